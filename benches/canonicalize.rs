@@ -0,0 +1,111 @@
+//! Cost of canonicalizing every scanned entry, against canonicalizing the
+//! scanned dir once
+//!
+//! Scanning used to canonicalize every item and every symlink in the tags
+//! dir. Now the dirs are canonicalized once and their entries joined to
+//! them, and symlinks are resolved with one `readlink` (see `scan_items`
+//! and `link_target`). This times both ways on a tree made for it:
+//!
+//! ```text
+//! $ cargo bench --bench canonicalize -- /mnt/nfs/scratch 20000
+//! ```
+//!
+//! The dir (default: the system temp dir) should be on the filesystem to
+//! measure, like an NFS mount. The items are put five dirs deep, as the
+//! cost of canonicalizing grows with the number of path components.
+//!
+//! On a local ext4 disk (Linux 6.18, one core), with 20,000 items and as
+//! many links: items 230 ms canonicalizing each, 13 ms joining; links
+//! 360 ms canonicalizing each, 80 ms with `readlink`. These were not taken
+//! on NFS, where a lookup can be a round trip to the server.
+
+use std::env;
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Make `n` items, and a tag with a relative link to each
+fn make_tree(base: &Path, n: usize) -> (PathBuf, PathBuf) {
+    let deep = base.join("linkorgasm-bench/a/b/c/d/e");
+    let (items, tag) = (deep.join("items"), deep.join("tags/tag"));
+    if items.is_dir() && fs::read_dir(&items).unwrap().count() == n {
+        return (items, tag);
+    }
+    let _ = fs::remove_dir_all(base.join("linkorgasm-bench"));
+    fs::create_dir_all(&items).unwrap();
+    fs::create_dir_all(&tag).unwrap();
+    for i in 0..n {
+        let name = format!("item{}", i);
+        fs::write(items.join(&name), b"").unwrap();
+        symlink(Path::new("../../items").join(&name), tag.join(&name)).unwrap();
+    }
+    (items, tag)
+}
+
+/// `base` joined with a relative path, without looking anything up
+fn lexical_path(base: &Path, rel: &Path) -> PathBuf {
+    let mut path = base.to_path_buf();
+    for c in rel.components() {
+        match c {
+            Component::ParentDir => {
+                path.pop();
+            }
+            Component::CurDir => {}
+            c => path.push(c),
+        }
+    }
+    path
+}
+
+fn time(f: impl FnOnce() -> usize) -> (Duration, usize) {
+    let start = Instant::now();
+    let n = f();
+    (start.elapsed(), n)
+}
+
+fn main() {
+    // cargo bench passes `--bench` to harness-less benches
+    let args: Vec<String> = env::args().skip(1).filter(|a| a != "--bench").collect();
+    let base = args.first().map_or_else(env::temp_dir, PathBuf::from);
+    let n = args
+        .get(1)
+        .map_or(20_000, |n| n.parse().expect("invalid count"));
+    let (items, tag) = make_tree(&base, n);
+
+    let each = time(|| {
+        fs::read_dir(&items)
+            .unwrap()
+            .map(|e| e.unwrap().path().canonicalize().unwrap())
+            .count()
+    });
+    let once = time(|| {
+        let root = items.canonicalize().unwrap();
+        fs::read_dir(&items)
+            .unwrap()
+            .map(|e| root.join(e.unwrap().file_name()))
+            .count()
+    });
+    println!(
+        "items: {:?} canonicalizing each, {:?} joining ({} items)",
+        each.0, once.0, n
+    );
+
+    let each = time(|| {
+        fs::read_dir(&tag)
+            .unwrap()
+            .map(|e| e.unwrap().path().canonicalize().unwrap())
+            .count()
+    });
+    let once = time(|| {
+        let root = tag.canonicalize().unwrap();
+        fs::read_dir(&tag)
+            .unwrap()
+            .map(|e| lexical_path(&root, &fs::read_link(e.unwrap().path()).unwrap()))
+            .count()
+    });
+    println!(
+        "links: {:?} canonicalizing each, {:?} with readlink ({} links)",
+        each.0, once.0, n
+    );
+}
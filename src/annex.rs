@@ -0,0 +1,46 @@
+//! git-annex awareness
+//!
+//! Files in a git-annex repository are symlinks into the object store
+//! (`.git/annex/objects/xx/yy/KEY/KEY`), and dangling when the content
+//! isn't present. Canonicalizing them gives a different path in every
+//! clone, or fails. With the `git-annex` setting, such items are indexed by
+//! their path in the work tree (where tag links point too), kept when
+//! their content is missing, and symlinks in tag dirs are matched to items
+//! by annex key, so links made in another clone of the repo still count.
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::lexical_path;
+
+/// Symlinks followed at most to find an annex object
+const MAX_HOPS: usize = 8;
+
+/// The annex key of an object store path, `None` for other paths
+fn object_key(p: &Path) -> Option<String> {
+    let parts: Vec<Component> = p.components().collect();
+    let in_store = parts.windows(3).any(|w| {
+        w[0].as_os_str() == ".git" && w[1].as_os_str() == "annex" && w[2].as_os_str() == "objects"
+    });
+    if !in_store {
+        return None;
+    }
+    p.file_name().and_then(|name| name.to_str()).map(str::to_owned)
+}
+
+/// The annex key of the file a symlink leads to, `None` if it doesn't lead
+/// into an annex object store
+///
+/// Symlinks are followed without requiring their targets to exist, so
+/// this works for files whose content isn't present.
+pub fn key_of(link: &Path) -> Option<String> {
+    let mut path: PathBuf = link.to_path_buf();
+    for _ in 0..MAX_HOPS {
+        let target = fs::read_link(&path).ok()?;
+        path = lexical_path(path.parent()?, &target);
+        if let Some(key) = object_key(&path) {
+            return Some(key);
+        }
+    }
+    None
+}
@@ -0,0 +1,196 @@
+//! `linkorgasm apply --from PLAN`
+//!
+//! Applies a declarative plan of tag changes without starting the UI, so
+//! tagging can be scripted. A plan looks like this:
+//!
+//! ```toml
+//! items = "/data/music"
+//! tags = "/data/tags"
+//! create = ["genre/jazz"]
+//!
+//! [[add]]
+//! item = "Kind of Blue.flac"
+//! tags = ["genre/jazz", "favorites"]
+//!
+//! [[remove]]
+//! item = "Kind of Blue.flac"
+//! tags = ["unsorted"]
+//! ```
+//!
+//! Item paths are relative to the items dir, tag names to the tags dir.
+//! `--items` and `--tags` on the command line override the plan.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+use serde::Deserialize;
+
+use crate::cli;
+use crate::exit;
+use crate::{create_tag, load_headless, tag_dir, tag_item, untag_item, AppState};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Plan {
+    /// items dir
+    items: Option<PathBuf>,
+    /// tags dir
+    tags: Option<PathBuf>,
+    /// tags to create
+    create: Vec<String>,
+    /// tags to add to items
+    add: Vec<Assignment>,
+    /// tags to remove from items
+    remove: Vec<Assignment>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Assignment {
+    item: PathBuf,
+    tags: Vec<String>,
+}
+
+/// What applying the plan did
+#[derive(Default)]
+struct Summary {
+    created: usize,
+    added: usize,
+    removed: usize,
+    unchanged: usize,
+    errors: usize,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} tags created, {} links added, {} links removed, {} unchanged, {} errors",
+            self.created, self.added, self.removed, self.unchanged, self.errors
+        )
+    }
+}
+
+fn load_plan(path: &Path) -> Result<Plan, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("cannot read plan: {}", e))?;
+    toml::from_str(&text).map_err(|e| format!("cannot parse plan: {}", e))
+}
+
+/// Look up a known item by its path relative to the items dir
+fn find_item(state: &AppState, items: &Path, item: &Path) -> Result<PathBuf, String> {
+    items
+        .join(item)
+        .canonicalize()
+        .ok()
+        .filter(|p| state.items_all.contains_key(p.as_path()))
+        .ok_or_else(|| format!("unknown item: {}", item.display()))
+}
+
+/// Look up a known tag by its name
+pub fn find_tag(state: &AppState, tag: &str) -> Result<PathBuf, String> {
+    tag_dir(state, tag)
+        .canonicalize()
+        .ok()
+        .filter(|p| state.tags.contains_key(p.as_path()))
+        .ok_or_else(|| format!("unknown tag: {}", tag))
+}
+
+/// Add or remove the tags of one assignment
+fn assign(state: &mut AppState, items: &Path, a: &Assignment, add: bool, summary: &mut Summary) {
+    let ip = match find_item(state, items, &a.item) {
+        Ok(ip) => ip,
+        Err(e) => {
+            eprintln!("{}", e);
+            summary.errors += 1;
+            return;
+        }
+    };
+    for name in a.tags.iter() {
+        let tp = match find_tag(state, name) {
+            Ok(tp) => tp,
+            Err(e) => {
+                eprintln!("{}", e);
+                summary.errors += 1;
+                continue;
+            }
+        };
+        if state.tags[tp.as_path()].items.contains_key(ip.as_path()) == add {
+            summary.unchanged += 1;
+            continue;
+        }
+        let res = if add {
+            tag_item(state, &tp, &ip)
+        } else {
+            untag_item(state, &tp, &ip)
+        };
+        match res {
+            Ok(()) if add => summary.added += 1,
+            Ok(()) => summary.removed += 1,
+            Err(e) => {
+                eprintln!("{}: {}: {}", a.item.display(), name, e);
+                summary.errors += 1;
+            }
+        }
+    }
+}
+
+/// Entry point for `linkorgasm apply`, returns the exit code
+pub fn run(m: &ArgMatches) -> i32 {
+    let plan = match load_plan(Path::new(m.value_of_os("from").unwrap())) {
+        Ok(plan) => plan,
+        Err(e) => {
+            eprintln!("{}", e);
+            return exit::USAGE;
+        }
+    };
+
+    let items = cli::items_dir(m).or(plan.items.clone());
+    let tags = cli::tags_dir(m).or(plan.tags.clone());
+    let (items, tags) = match (items, tags) {
+        (Some(items), Some(tags)) => (items, tags),
+        _ => {
+            eprintln!("both an items dir and a tags dir are required");
+            return exit::USAGE;
+        }
+    };
+
+    let mut state = match load_headless(m, Some(&items), &tags) {
+        Ok(state) => state,
+        Err(e) => return exit::load_failed(&tags, &e),
+    };
+    // item paths in a list are relative to the current directory
+    let items = if items == Path::new("-") {
+        PathBuf::new()
+    } else {
+        items
+    };
+
+    let mut summary = Summary::default();
+    for name in plan.create.iter() {
+        let known = find_tag(&state, name).is_ok();
+        match create_tag(&mut state, name) {
+            Ok(_) if known => summary.unchanged += 1,
+            Ok(_) => summary.created += 1,
+            Err(e) => {
+                eprintln!("{}: {}", name, e);
+                summary.errors += 1;
+            }
+        }
+    }
+    for a in plan.add.iter() {
+        assign(&mut state, &items, a, true, &mut summary);
+    }
+    for a in plan.remove.iter() {
+        assign(&mut state, &items, a, false, &mut summary);
+    }
+
+    for op in state.fs.log() {
+        println!("{}", op);
+    }
+    if !cli::is_quiet(m) {
+        println!("{}", summary);
+    }
+    exit::from_errors(summary.errors)
+}
@@ -0,0 +1,203 @@
+//! `linkorgasm export-archive --tag NAME --out FILE`
+//!
+//! Packs the items of a tag and its nested tags into an archive, for
+//! handing a curated set to someone else. The files themselves are packed,
+//! not the symlinks to them. With `--structure`, the items of nested tags
+//! are put in subdirectories named like them; otherwise every item is put
+//! once at the top, with a number appended to clashing names.
+//!
+//! The archive is made by `tar` or `zip`, from a staging dir of symlinks
+//! they follow. The staging dir is a new private dir in the temp dir, and
+//! only what was put into it is removed afterwards.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use clap::ArgMatches;
+use hashbrown::HashSet;
+
+use crate::apply::find_tag;
+use crate::cli;
+use crate::exit;
+use crate::fsops;
+use crate::{load_readonly, AppState, SharedPath};
+
+/// Archive formats, by the name given to `--format`
+pub static FORMATS: &[&str] = &["tar", "tar.gz", "tar.xz", "zip"];
+
+/// Paths in the archive of the items to pack, relative to its top dir
+///
+/// `tp` is the canonical path of a known tag.
+fn layout(state: &AppState, tp: &Path, structure: bool) -> Vec<(PathBuf, SharedPath)> {
+    let mut tags: Vec<&SharedPath> = state.tags.keys().filter(|t| t.starts_with(tp)).collect();
+    tags.sort();
+
+    let mut out = Vec::new();
+    let mut packed = HashSet::new();
+    let mut names = HashSet::new();
+    for t in tags {
+        let mut items: Vec<(&SharedPath, &OsString)> = state.tags[*t].items.iter().collect();
+        items.sort();
+        let dir = if structure {
+            t.strip_prefix(tp).unwrap().to_path_buf()
+        } else {
+            PathBuf::new()
+        };
+        for (ip, _) in items {
+            if !structure && !packed.insert(ip.clone()) {
+                continue;
+            }
+            let path = unique_name(&mut names, &dir, &state.items_all[ip].filename);
+            out.push((path, ip.clone()));
+        }
+    }
+    out
+}
+
+/// `dir/name`, with a number appended if that path is taken already
+fn unique_name(names: &mut HashSet<PathBuf>, dir: &Path, name: &OsString) -> PathBuf {
+    let mut path = dir.join(name);
+    let stem = Path::new(name).file_stem().unwrap_or_default().to_owned();
+    let ext = Path::new(name).extension().map(|e| e.to_owned());
+    let mut n = 1;
+    while names.contains(&path) {
+        n += 1;
+        let mut numbered = stem.clone();
+        numbered.push(format!("-{}", n));
+        if let Some(ref ext) = ext {
+            numbered.push(".");
+            numbered.push(ext);
+        }
+        path = dir.join(numbered);
+    }
+    names.insert(path.clone());
+    path
+}
+
+/// Command packing `top` in the current dir into `out`
+fn pack_command(format: &str, top: &Path, out: &Path) -> Command {
+    if format == "zip" {
+        let mut cmd = Command::new("zip");
+        cmd.arg("-r").arg("-q").arg(out).arg(top);
+        return cmd;
+    }
+    let flags = match format {
+        "tar.gz" => "-chzf",
+        "tar.xz" => "-chJf",
+        _ => "-chf",
+    };
+    let mut cmd = Command::new("tar");
+    cmd.arg(flags).arg(out).arg(top);
+    cmd
+}
+
+/// Stage the items as symlinks under `staging/top`, then pack them
+fn pack(
+    items: &[(PathBuf, SharedPath)],
+    staging: &Path,
+    top: &Path,
+    format: &str,
+    out: &Path,
+) -> io::Result<()> {
+    for (path, ip) in items {
+        let link = staging.join(top).join(path);
+        fs::create_dir_all(link.parent().unwrap())?;
+        symlink(ip, &link)?;
+    }
+    let status = pack_command(format, top, out)
+        .current_dir(staging)
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("packing failed ({})", status),
+        ));
+    }
+    Ok(())
+}
+
+/// Remove the symlinks staged under `staging/top`, the dirs made for them,
+/// and the staging dir
+fn unstage(items: &[(PathBuf, SharedPath)], staging: &Path, top: &Path) -> io::Result<()> {
+    let mut dirs = Vec::new();
+    for (path, _) in items {
+        let link = staging.join(top).join(path);
+        if link.symlink_metadata().is_ok() {
+            fs::remove_file(&link)?;
+        }
+        let parents = link.ancestors().skip(1).take_while(|dir| *dir != staging);
+        dirs.extend(parents.map(Path::to_path_buf));
+    }
+    // nested dirs sort after their parents
+    dirs.sort();
+    dirs.dedup();
+    for dir in dirs.iter().rev() {
+        if dir.exists() {
+            fs::remove_dir(dir)?;
+        }
+    }
+    fs::remove_dir(staging)
+}
+
+/// Entry point for `linkorgasm export-archive`, returns the exit code
+pub fn run(m: &ArgMatches) -> i32 {
+    let (items, tags) = match (cli::items_dir(m), cli::tags_dir(m)) {
+        (Some(items), Some(tags)) => (items, tags),
+        _ => {
+            eprintln!("both an items dir and a tags dir are required");
+            return exit::USAGE;
+        }
+    };
+    let state = match load_readonly(m, Some(&items), &tags) {
+        Ok(state) => state,
+        Err(e) => return exit::load_failed(&tags, &e),
+    };
+    let name = m.value_of("tag").unwrap();
+    let tp = match find_tag(&state, name) {
+        Ok(tp) => tp,
+        Err(e) => {
+            eprintln!("{}", e);
+            return exit::USAGE;
+        }
+    };
+
+    let items = layout(&state, &tp, m.is_present("structure"));
+    if items.is_empty() {
+        eprintln!("tag has no items: {}", name);
+        return exit::FAILED;
+    }
+    // tar and zip run in the staging dir, so they need an absolute path
+    let cwd = env::current_dir().expect("cannot access current dir");
+    let out = cwd.join(m.value_of_os("out").unwrap());
+    if out.symlink_metadata().is_ok() {
+        eprintln!("{} already exists", out.display());
+        return exit::USAGE;
+    }
+    let format = m.value_of("format").unwrap();
+    let top = PathBuf::from(tp.file_name().unwrap());
+
+    let staging = match fsops::create_temp_dir("linkorgasm-archive") {
+        Ok(staging) => staging,
+        Err(e) => {
+            eprintln!("cannot create staging dir: {}", e);
+            return exit::FAILED;
+        }
+    };
+    let res = pack(&items, &staging, &top, format, &out);
+    if let Err(e) = unstage(&items, &staging, &top) {
+        eprintln!("cannot remove {}: {}", staging.display(), e);
+    }
+    if let Err(e) = res {
+        eprintln!("{}: {}", out.display(), e);
+        return exit::FAILED;
+    }
+    if !cli::is_quiet(m) {
+        println!("{} items packed into {}", items.len(), out.display());
+    }
+    exit::OK
+}
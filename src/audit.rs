@@ -0,0 +1,63 @@
+//! Audit log of changes to the tags tree
+//!
+//! Every change to a tags directory is appended with a timestamp to
+//! `.linkorgasm/audit.log` inside it. The file is only ever appended to, so
+//! it tells when an item gained or lost a tag, across sessions.
+
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use cursive::traits::*;
+use cursive::view::ScrollStrategy;
+use cursive::views::{Dialog, ScrollView, TextView};
+use cursive::Cursive;
+
+use crate::{do_app, ui_error, META_DIR};
+
+/// Name of the audit log in the metadata dir
+pub static AUDIT_FILE: &str = "audit.log";
+
+/// Number of entries shown by the viewer
+const VIEW_ENTRIES: usize = 1000;
+
+pub fn audit_path(tags_path: &Path) -> PathBuf {
+    tags_path.join(META_DIR).join(AUDIT_FILE)
+}
+
+/// Open the audit log of a tags dir for appending, creating it if needed
+pub fn open(tags_path: &Path) -> io::Result<File> {
+    fs::create_dir_all(tags_path.join(META_DIR))?;
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(audit_path(tags_path))
+}
+
+/// Show the latest entries of the audit log
+pub fn ui_view(siv: &mut Cursive) {
+    let path = do_app(siv, |_, state| audit_path(&state.tags_path));
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return ui_error(siv, e),
+    };
+    let lines: Vec<&str> = text.lines().collect();
+    let start = lines.len().saturating_sub(VIEW_ENTRIES);
+    let content = if lines.is_empty() {
+        tr!("(no changes recorded)")
+    } else {
+        lines[start..].join("\n")
+    };
+
+    let content = ScrollView::new(TextView::new(content).no_wrap())
+        .scroll_x(true)
+        .scroll_strategy(ScrollStrategy::StickToBottom);
+    siv.add_layer(
+        Dialog::around(content.max_height(30))
+            .title(tr!("Audit log"))
+            .button(tr!("Close"), |siv| {
+                siv.pop_layer();
+            }),
+    );
+}
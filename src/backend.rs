@@ -0,0 +1,53 @@
+//! Choice of the terminal backend
+//!
+//! Which backends are built in is chosen with the `termion-backend`
+//! (default), `crossterm-backend` and `ncurses-backend` cargo features; the
+//! one used is the `backend` setting or `--backend`, else the first built
+//! in. termion and crossterm are pure Rust and link statically (musl);
+//! ncurses needs the system library, but measures wide characters with the
+//! terminal's own tables.
+
+use cursive::Cursive;
+
+#[cfg(not(any(
+    feature = "termion-backend",
+    feature = "crossterm-backend",
+    feature = "ncurses-backend"
+)))]
+compile_error!("enable at least one of the termion-, crossterm- or ncurses-backend features");
+
+/// Names of the backends built in, the default first
+pub fn names() -> Vec<&'static str> {
+    let mut names = Vec::new();
+    if cfg!(feature = "termion-backend") {
+        names.push("termion");
+    }
+    if cfg!(feature = "crossterm-backend") {
+        names.push("crossterm");
+    }
+    if cfg!(feature = "ncurses-backend") {
+        names.push("ncurses");
+    }
+    names
+}
+
+/// Start the UI on the named backend, or on the default one
+pub fn init(name: Option<&str>) -> Result<Cursive, String> {
+    let name = name.unwrap_or(names()[0]);
+    let siv = match name {
+        #[cfg(feature = "termion-backend")]
+        "termion" => Cursive::termion().map_err(|e| e.to_string()),
+        #[cfg(feature = "crossterm-backend")]
+        "crossterm" => Cursive::crossterm().map_err(|e| e.to_string()),
+        #[cfg(feature = "ncurses-backend")]
+        "ncurses" => Cursive::ncurses().map_err(|e| e.to_string()),
+        _ => {
+            return Err(format!(
+                "unknown backend: {} (built in: {})",
+                name,
+                names().join(", ")
+            ))
+        }
+    };
+    siv.map_err(|e| format!("cannot start the {} backend: {}", name, e))
+}
@@ -0,0 +1,215 @@
+//! Directory browser for choosing the items and tags directories
+//!
+//! Lists the subdirectories of the current directory; Enter descends into
+//! the highlighted one (`../` goes up). The path can also be typed, with
+//! `~` and environment variables (`$HOME`, `${XDG_DATA_HOME}`) expanded.
+//! Typing a path that doesn't exist and pressing Enter chooses it, for
+//! tags directories that are yet to be created. Tab completes typed paths
+//! like a shell.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use cursive::event::Key;
+use cursive::traits::*;
+use cursive::views::{
+    Dialog, DummyView, EditView, LinearLayout, OnEventView, ScrollView, SelectView,
+};
+use cursive::Cursive;
+
+use crate::escape::display_name;
+use crate::ui_error;
+
+/// Expand a leading `~` and environment variables in a typed path
+///
+/// Unset variables are left as they are.
+pub fn expand(path: &str) -> PathBuf {
+    let mut out = String::new();
+    let mut rest = path;
+    if path == "~" || path.starts_with("~/") {
+        if let Some(home) = dirs::home_dir() {
+            out.push_str(&home.to_string_lossy());
+            rest = &path[1..];
+        }
+    }
+
+    let mut chars = rest.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            out.push(c);
+            continue;
+        }
+        let braced = chars.peek() == Some(&'{');
+        if braced {
+            chars.next();
+        }
+        let mut name = String::new();
+        let mut closed = false;
+        while let Some(&c) = chars.peek() {
+            if braced && c == '}' {
+                chars.next();
+                closed = true;
+                break;
+            }
+            if !braced && !(c.is_ascii_alphanumeric() || c == '_') {
+                break;
+            }
+            name.push(c);
+            chars.next();
+        }
+        match env::var(&name) {
+            Ok(value) if !name.is_empty() && (closed || !braced) => out.push_str(&value),
+            _ if braced => {
+                out.push_str("${");
+                out.push_str(&name);
+                if closed {
+                    out.push('}');
+                }
+            }
+            _ => {
+                out.push('$');
+                out.push_str(&name);
+            }
+        }
+    }
+    PathBuf::from(out)
+}
+
+/// Complete a typed path like a shell
+///
+/// Completes to the only matching entry (with a `/` for directories), or
+/// to the longest common prefix of several. Hidden entries only match a
+/// prefix starting with a dot.
+fn complete(text: &str) -> Option<String> {
+    let (dir, prefix) = match text.rfind('/') {
+        Some(i) => (&text[..=i], &text[i + 1..]),
+        None => ("", text),
+    };
+    let listed = if dir.is_empty() {
+        PathBuf::from(".")
+    } else {
+        expand(dir)
+    };
+    let matches: Vec<(String, bool)> = fs::read_dir(listed)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let name = e.file_name().into_string().ok()?;
+            let hidden = name.starts_with('.') && !prefix.starts_with('.');
+            if name.starts_with(prefix) && !hidden {
+                Some((name, e.path().is_dir()))
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    match matches.len() {
+        0 => None,
+        1 => {
+            let (ref name, is_dir) = matches[0];
+            Some(format!("{}{}{}", dir, name, if is_dir { "/" } else { "" }))
+        }
+        _ => {
+            let mut common = matches[0].0.clone();
+            for (name, _) in matches.iter().skip(1) {
+                let len = common
+                    .chars()
+                    .zip(name.chars())
+                    .take_while(|(a, b)| a == b)
+                    .map(|(a, _)| a.len_utf8())
+                    .sum();
+                common.truncate(len);
+            }
+            if common.len() > prefix.len() {
+                Some(format!("{}{}", dir, common))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// UI callback completing the path typed in the browser
+fn ui_complete(siv: &mut Cursive) {
+    siv.call_on_id("browsepath", |v: &mut EditView| {
+        if let Some(done) = complete(&v.get_content()) {
+            v.set_content(done);
+        }
+    });
+}
+
+/// Show the contents of a directory in the browser
+fn navigate(siv: &mut Cursive, dir: &Path) {
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let mut subdirs: Vec<PathBuf> = match fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_dir())
+            .collect(),
+        Err(e) => return ui_error(siv, format!("{}: {}", dir.display(), e)),
+    };
+    subdirs.sort();
+
+    siv.call_on_id("browsepath", |v: &mut EditView| {
+        v.set_content(dir.to_string_lossy());
+    });
+    siv.call_on_id("browselist", |v: &mut SelectView<PathBuf>| {
+        v.clear();
+        if let Some(parent) = dir.parent() {
+            v.add_item("../", parent.to_path_buf());
+        }
+        for p in subdirs {
+            let name = format!("{}/", display_name(p.file_name().unwrap(), false));
+            v.add_item(name, p);
+        }
+    });
+}
+
+/// Display UI Dialog for choosing a directory
+///
+/// Starts in `start` if it is a directory, in the current one otherwise.
+/// `submit` gets the chosen path, and has to close the dialog.
+pub fn ui_browse(siv: &mut Cursive, title: &str, start: &str, submit: fn(&mut Cursive, &Path)) {
+    let start = expand(start);
+    let start = if start.is_dir() {
+        start
+    } else {
+        env::current_dir().unwrap_or_default()
+    };
+
+    let path = EditView::new()
+        .on_submit(move |siv, text| {
+            let p = expand(text);
+            if p.is_dir() {
+                navigate(siv, &p);
+            } else {
+                submit(siv, &p);
+            }
+        })
+        .with_id("browsepath");
+    // Tab would move the focus otherwise
+    let path = OnEventView::new(path).on_pre_event(Key::Tab, ui_complete);
+    let list = SelectView::<PathBuf>::new()
+        .on_submit(|siv, p: &PathBuf| navigate(siv, p))
+        .with_id("browselist");
+    let layout = LinearLayout::vertical()
+        .child(path)
+        .child(DummyView)
+        .child(ScrollView::new(list).fixed_height(15));
+
+    siv.add_layer(
+        Dialog::around(layout)
+            .title(title)
+            .button(tr!("Choose"), move |siv| {
+                let text = siv
+                    .call_on_id("browsepath", |v: &mut EditView| v.get_content())
+                    .unwrap();
+                submit(siv, &expand(&text));
+            })
+            .fixed_width(60),
+    );
+    navigate(siv, &start);
+}
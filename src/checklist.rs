@@ -0,0 +1,269 @@
+//! Checkboxes in front of list rows
+//!
+//! `Check` is the state of a checkbox, drawn as a styled marker: checked in
+//! the title color, mixed (only some of the selected items) in the
+//! secondary color. `CheckList` is a `SelectView` that keeps the state of
+//! each row apart from its label, for the tags view and the tag editor;
+//! the items view puts the markers into the labels of its `LazyList`.
+//!
+//! Both draw labels with `print_label`: the `SelectView` of cursive paints
+//! colored spans on the view background, which hides the cursor on rows
+//! with colored tags.
+
+use std::cmp::{max, min};
+use std::rc::Rc;
+
+use cursive::direction::Direction;
+use cursive::event::{Callback, Event, EventResult, Key, MouseButton, MouseEvent};
+use cursive::theme::{ColorStyle, Effect, Style};
+use cursive::utils::markup::StyledString;
+use cursive::{Cursive, Printer, Rect, Vec2, View};
+
+/// Width of a marker, with the space after it
+const MARKER_WIDTH: usize = 4;
+
+/// State of a checkbox
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Check {
+    Off,
+    On,
+    /// some of the items in question, but not all
+    Mixed,
+}
+
+impl Check {
+    /// State for a tag that `on` of the items in question have and `off`
+    /// haven't
+    pub fn from_counts(on: usize, off: usize) -> Check {
+        match (on, off) {
+            (0, _) => Check::Off,
+            (_, 0) => Check::On,
+            (_, _) => Check::Mixed,
+        }
+    }
+
+    /// The marker, followed by a space; `reversed` for rows to point out
+    pub fn marker(self, reversed: bool) -> StyledString {
+        let (text, color) = match self {
+            Check::Off => ("[ ]", ColorStyle::primary()),
+            Check::On => ("[X]", ColorStyle::title_primary()),
+            Check::Mixed => ("[-]", ColorStyle::secondary()),
+        };
+        let style = if reversed {
+            Style::from(color).combine(Effect::Reverse)
+        } else {
+            Style::from(color)
+        };
+        let mut marker = StyledString::styled(text, style);
+        marker.append_plain(" ");
+        marker
+    }
+}
+
+impl From<bool> for Check {
+    fn from(on: bool) -> Check {
+        if on {
+            Check::On
+        } else {
+            Check::Off
+        }
+    }
+}
+
+/// Print a styled label on a row drawn in `style`
+///
+/// Spans keep their colors and effects, but not their background, so the
+/// cursor stays visible on them.
+pub fn print_label(printer: &Printer, pos: Vec2, label: &StyledString, style: ColorStyle) {
+    let mut x = pos.x;
+    for span in label.spans() {
+        let mut span_style = *span.attr;
+        span_style.color = Some(match span.attr.color {
+            Some(color) => ColorStyle::new(color.front, style.back),
+            None => style,
+        });
+        printer.with_style(span_style, |printer| printer.print((x, pos.y), span.content));
+        x += span.width;
+    }
+}
+
+/// Style of a row: highlighted if the cursor is on it
+pub fn row_style(printer: &Printer, cursor: bool) -> ColorStyle {
+    if !cursor {
+        ColorStyle::primary()
+    } else if printer.focused {
+        ColorStyle::highlight()
+    } else {
+        ColorStyle::highlight_inactive()
+    }
+}
+
+struct Row<T> {
+    label: StyledString,
+    check: Check,
+    value: Rc<T>,
+}
+
+/// A list of rows with checkboxes
+///
+/// Its methods are named like those of `SelectView`. Rows start unchecked.
+pub struct CheckList<T> {
+    rows: Vec<Row<T>>,
+    focus: usize,
+    /// width of the widest label
+    width: usize,
+    on_select: Option<Rc<dyn Fn(&mut Cursive, &T)>>,
+}
+
+impl<T: 'static> CheckList<T> {
+    pub fn new() -> Self {
+        CheckList {
+            rows: Vec::new(),
+            focus: 0,
+            width: 0,
+            on_select: None,
+        }
+    }
+
+    /// Set the callback run when the cursor moves to another row
+    pub fn on_select<F: Fn(&mut Cursive, &T) + 'static>(mut self, f: F) -> Self {
+        self.on_select = Some(Rc::new(f));
+        self
+    }
+
+    pub fn clear(&mut self) {
+        self.rows.clear();
+        self.focus = 0;
+        self.width = 0;
+    }
+
+    pub fn add_item<S: Into<StyledString>>(&mut self, label: S, value: T) {
+        let label = label.into();
+        self.width = max(self.width, label.width());
+        self.rows.push(Row {
+            label,
+            check: Check::Off,
+            value: Rc::new(value),
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Label and value of a row
+    pub fn get_item(&self, i: usize) -> Option<(&StyledString, &T)> {
+        self.rows.get(i).map(|row| (&row.label, &*row.value))
+    }
+
+    /// Replace the label of a row, keeping its checkbox
+    pub fn set_label<S: Into<StyledString>>(&mut self, i: usize, label: S) {
+        let label = label.into();
+        self.width = max(self.width, label.width());
+        self.rows[i].label = label;
+    }
+
+    pub fn check(&self, i: usize) -> Check {
+        self.rows[i].check
+    }
+
+    pub fn set_check(&mut self, i: usize, check: Check) {
+        self.rows[i].check = check;
+    }
+
+    pub fn selected_id(&self) -> Option<usize> {
+        if self.rows.is_empty() {
+            None
+        } else {
+            Some(self.focus)
+        }
+    }
+
+    pub fn selection(&self) -> Option<Rc<T>> {
+        self.selected_id().map(|i| Rc::clone(&self.rows[i].value))
+    }
+
+    /// Move the cursor to a row
+    ///
+    /// The returned callback runs `on_select`.
+    pub fn set_selection(&mut self, i: usize) -> Callback {
+        if self.rows.is_empty() {
+            return Callback::dummy();
+        }
+        self.focus = min(i, self.rows.len() - 1);
+        self.select_cb().unwrap_or_else(Callback::dummy)
+    }
+
+    fn select_cb(&self) -> Option<Callback> {
+        let f = Rc::clone(self.on_select.as_ref()?);
+        let value = Rc::clone(&self.rows[self.focus].value);
+        Some(Callback::from_fn(move |siv| f(siv, &value)))
+    }
+}
+
+impl<T: 'static> Default for CheckList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: 'static> View for CheckList<T> {
+    fn draw(&self, printer: &Printer) {
+        for (y, row) in self.rows.iter().enumerate() {
+            let style = row_style(printer, y == self.focus);
+            printer.with_color(style, |printer| {
+                print_label(printer, Vec2::new(0, y), &row.check.marker(false), style);
+                print_label(printer, Vec2::new(MARKER_WIDTH, y), &row.label, style);
+                let width = MARKER_WIDTH + row.label.width();
+                if width < printer.size.x {
+                    printer.print_hline((width, y), printer.size.x - width, " ");
+                }
+            });
+        }
+    }
+
+    fn required_size(&mut self, _: Vec2) -> Vec2 {
+        Vec2::new(MARKER_WIDTH + self.width, self.rows.len())
+    }
+
+    fn take_focus(&mut self, _: Direction) -> bool {
+        !self.rows.is_empty()
+    }
+
+    fn important_area(&self, size: Vec2) -> Rect {
+        Rect::from_size((0, self.focus), (size.x, 1))
+    }
+
+    fn on_event(&mut self, event: Event) -> EventResult {
+        if self.rows.is_empty() {
+            return EventResult::Ignored;
+        }
+        let last = self.rows.len() - 1;
+        let old = self.focus;
+        match event {
+            Event::Key(Key::Up) if self.focus > 0 => self.focus -= 1,
+            Event::Key(Key::Down) if self.focus < last => self.focus += 1,
+            Event::Key(Key::PageUp) => self.focus = self.focus.saturating_sub(10),
+            Event::Key(Key::PageDown) => self.focus = min(self.focus + 10, last),
+            Event::Key(Key::Home) => self.focus = 0,
+            Event::Key(Key::End) => self.focus = last,
+            Event::Mouse {
+                event: MouseEvent::Press(MouseButton::Left),
+                position,
+                offset,
+            } => match position.checked_sub(offset) {
+                Some(pos) if pos.y <= last => self.focus = pos.y,
+                _ => return EventResult::Ignored,
+            },
+            _ => return EventResult::Ignored,
+        }
+        if self.focus == old {
+            return EventResult::Consumed(None);
+        }
+        EventResult::Consumed(self.select_cb())
+    }
+}
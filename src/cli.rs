@@ -0,0 +1,538 @@
+//! Command line interface
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::{crate_version, App, Arg, ArgMatches, SubCommand};
+
+use crate::archive;
+use crate::completions;
+use crate::config::{self, Config, Identity, Profile};
+#[cfg(feature = "daemon")]
+use crate::daemon;
+use crate::fsops::FsOps;
+use crate::identity;
+use crate::normalize;
+use crate::search;
+use crate::serve;
+use crate::stats;
+
+/// Build the argument parser
+pub fn build_cli() -> App<'static, 'static> {
+    let app = App::new("linkorgasm")
+        .version(crate_version!())
+        .about("Tool for organizing files with symlinks")
+        .arg(
+            Arg::with_name("items")
+                .long("items")
+                .global(true)
+                .value_name("DIR")
+                .help("Items directory, or '-' to read newline-separated item paths from stdin"),
+        )
+        .arg(
+            Arg::with_name("exclude")
+                .long("exclude")
+                .global(true)
+                .value_name("PATTERN")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Skip items matching a gitignore-style pattern (repeatable)"),
+        )
+        .arg(
+            Arg::with_name("include")
+                .long("include")
+                .global(true)
+                .value_name("GLOB")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Only scan items whose file name matches a glob (repeatable)"),
+        )
+        .arg(
+            Arg::with_name("tags")
+                .long("tags")
+                .global(true)
+                .value_name("DIR")
+                .help("Tags directory"),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .global(true)
+                .value_name("NAME")
+                .help("Use the directories and settings of a [profile.NAME] in the config file"),
+        )
+        .arg(
+            Arg::with_name("identity")
+                .long("identity")
+                .global(true)
+                .value_name("MODE")
+                .possible_values(identity::MODES)
+                .help("Tell items apart by canonical path or by inode (hard links are one item)"),
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .global(true)
+                .help("Log changes to the tags directory instead of making them"),
+        )
+        .arg(
+            Arg::with_name("read-only")
+                .long("read-only")
+                .global(true)
+                .help("Browse and query only; refuse all changes to the tags directory"),
+        )
+        .arg(
+            Arg::with_name("dry-run-log")
+                .long("dry-run-log")
+                .global(true)
+                .value_name("FILE")
+                .requires("dry-run")
+                .help("Also append the changes logged by --dry-run to FILE"),
+        )
+        .arg(
+            Arg::with_name("log-file")
+                .long("log-file")
+                .global(true)
+                .value_name("FILE")
+                .help("Append scan timings, filesystem operations and errors to FILE"),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .global(true)
+                .multiple(true)
+                .help("Log more to the --log-file (-v: every operation, -vv: everything)"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .global(true)
+                .help("Subcommands print only results and errors, no summaries or warnings"),
+        )
+        .arg(
+            Arg::with_name("theme")
+                .long("theme")
+                .value_name("THEME")
+                .help("Color theme: 'dark', 'light', a theme name from the config dir, or a path"),
+        )
+        .arg(
+            Arg::with_name("backend")
+                .long("backend")
+                .global(true)
+                .value_name("NAME")
+                .help("Terminal backend: 'termion', 'crossterm' or 'ncurses' (if built in)"),
+        )
+        .arg(
+            Arg::with_name("filter")
+                .long("filter")
+                .value_name("QUERY")
+                .help("Start showing only items matching a query ('untagged': items without tags)"),
+        )
+        .arg(
+            Arg::with_name("select-tag")
+                .long("select-tag")
+                .value_name("TAG")
+                .help("Start with the tags view cursor on a tag"),
+        )
+        .arg(
+            Arg::with_name("tutorial")
+                .long("tutorial")
+                .conflicts_with_all(&["items", "tags", "profile"])
+                .help("Learn the basics in a sandbox with sample files"),
+        )
+        .arg(
+            Arg::with_name("print-selection")
+                .long("print-selection")
+                .help("Quit with Enter and print the selected item paths to stdout"),
+        )
+        .subcommand(
+            SubCommand::with_name("self-check")
+                .about("Verify state files on disk and migrate them to the current format"),
+        )
+        .subcommand(
+            SubCommand::with_name("apply")
+                .about("Apply a plan of tag changes non-interactively")
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .value_name("PLAN")
+                        .required(true)
+                        .help("Plan file (TOML)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("gc")
+                .about("Remove dangling symlinks from the tags directory")
+                .arg(
+                    Arg::with_name("empty-tags")
+                        .long("empty-tags")
+                        .help("Also remove tag directories left empty"),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .help("Remove dangling symlinks even if most of them are"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("relink")
+                .about("Point symlinks in the tags directory at a moved items directory")
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .value_name("OLD")
+                        .required(true)
+                        .help("Old location of the items directory"),
+                )
+                .arg(
+                    Arg::with_name("to")
+                        .long("to")
+                        .value_name("NEW")
+                        .required(true)
+                        .help("New location of the items directory"),
+                )
+                .arg(
+                    Arg::with_name("absolute")
+                        .long("absolute")
+                        .help("Write absolute symlinks, whatever the configured link-style"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("normalize-links")
+                .about("Rewrite the symlinks in the tags directory in one link style")
+                .arg(
+                    Arg::with_name("style")
+                        .long("style")
+                        .value_name("STYLE")
+                        .possible_values(normalize::STYLES)
+                        .help("Link style to use instead of the configured link-style"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("materialize")
+                .about("Make the symlinks of every tag match its manifest (with tag-manifests)"),
+        )
+        .subcommand(
+            SubCommand::with_name("export-html")
+                .about("Write a static HTML index of the items of a tag or query")
+                .arg(
+                    Arg::with_name("tag")
+                        .long("tag")
+                        .value_name("NAME")
+                        .required_unless("query")
+                        .conflicts_with("query")
+                        .help("Tag whose items to list"),
+                )
+                .arg(
+                    Arg::with_name("query")
+                        .long("query")
+                        .value_name("QUERY")
+                        .help("Query selecting the items to list, like the 'f' filter"),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .value_name("DIR")
+                        .required(true)
+                        .help("Directory to write index.html to (created if missing)"),
+                )
+                .arg(
+                    Arg::with_name("thumbnails")
+                        .long("thumbnails")
+                        .help("Show images as thumbnails (made with ImageMagick if installed)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export-archive")
+                .about("Pack the items of a tag into a tar or zip archive")
+                .arg(
+                    Arg::with_name("tag")
+                        .long("tag")
+                        .value_name("NAME")
+                        .required(true)
+                        .help("Tag whose items (and those of its nested tags) to pack"),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .value_name("FILE")
+                        .required(true)
+                        .help("Archive to create"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .possible_values(archive::FORMATS)
+                        .default_value("tar.gz")
+                        .help("Archive format and compression"),
+                )
+                .arg(
+                    Arg::with_name("structure")
+                        .long("structure")
+                        .help("Put the items of nested tags in subdirectories named like them"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("pick-tags")
+                .about("Edit the tags of a single file in a checklist, for file manager bindings")
+                .arg(
+                    Arg::with_name("file")
+                        .value_name("FILE")
+                        .required(true)
+                        .help("File or directory to tag"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("completions")
+                .about("Print a shell completion script (with tag names for --tag)")
+                .arg(
+                    Arg::with_name("shell")
+                        .value_name("SHELL")
+                        .required(true)
+                        .possible_values(completions::SHELLS)
+                        .help("Shell to complete for"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("query")
+                .about("Print the paths of the items matching a query")
+                .arg(
+                    Arg::with_name("expr")
+                        .value_name("QUERY")
+                        .required(true)
+                        .help("Query, like in the items view ('f'), e.g. 'jazz -live year>=2018'"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .possible_values(search::FORMATS)
+                        .default_value("plain")
+                        .help("One path per line, a JSON array, or NUL-separated paths"),
+                )
+                .arg(
+                    Arg::with_name("null")
+                        .short("0")
+                        .help("Separate paths with NUL bytes (same as --format null)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("stats")
+                .about("Report item, tag and broken link counts and the collection size")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .possible_values(stats::FORMATS)
+                        .default_value("plain")
+                        .help("Report for humans, or as a JSON object"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("maintain")
+                .about("Repair and remove broken links, refresh descriptions and report stats")
+                .arg(
+                    Arg::with_name("repair-by-name")
+                        .long("repair-by-name")
+                        .help("Relink dangling links to the one item like their target (by name)"),
+                )
+                .arg(
+                    Arg::with_name("remove-dangling")
+                        .long("remove-dangling")
+                        .help("Remove dangling links, unless most links are dangling"),
+                )
+                .arg(
+                    Arg::with_name("empty-tags")
+                        .long("empty-tags")
+                        .help("Also remove tag directories left empty"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .possible_values(stats::FORMATS)
+                        .default_value("plain")
+                        .help("Summary for humans, or as a JSON object"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about("Serve a web page to filter, tag and untag the items from a browser")
+                .arg(
+                    Arg::with_name("listen")
+                        .long("listen")
+                        .value_name("ADDR:PORT")
+                        .default_value(serve::DEFAULT_LISTEN)
+                        .help("Address to listen on; without --token, keep it local"),
+                )
+                .arg(
+                    Arg::with_name("token")
+                        .long("token")
+                        .value_name("TOKEN")
+                        .env("LINKORGASM_TOKEN")
+                        .hide_env_values(true)
+                        .help("Require this token, as bearer token or basic auth password"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("describe")
+                .about("Describe the images with the describe-command, for text: queries")
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .help("Also describe images that haven't changed since"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("snapshot")
+                .about("Record the items of every tag in a snapshot file, as a backup")
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .value_name("FILE")
+                        .help("Snapshot to create, instead of one in .linkorgasm/snapshots"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("restore")
+                .about("Recreate the tags and links of a snapshot")
+                .arg(
+                    Arg::with_name("snapshot")
+                        .value_name("SNAPSHOT")
+                        .help("Snapshot file (the latest one of the tags directory by default)"),
+                )
+                .arg(
+                    Arg::with_name("exact")
+                        .long("exact")
+                        .help("Also remove the links and tags the snapshot doesn't have"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("List the tags and links added and removed between two snapshots")
+                .arg(
+                    Arg::with_name("old")
+                        .value_name("OLD")
+                        .required(true)
+                        .help("Snapshot to compare from"),
+                )
+                .arg(
+                    Arg::with_name("new")
+                        .value_name("NEW")
+                        .help("Snapshot to compare to (the tags directory as it is by default)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("list-tags")
+                .about("Print the names of all tags (of the last used tags directory by default)"),
+        );
+    #[cfg(feature = "ipc")]
+    let app = app.arg(
+        Arg::with_name("socket")
+            .long("socket")
+            .value_name("PATH")
+            .help("Accept JSON-RPC requests (query, tag, untag, rescan) on a Unix socket"),
+    );
+    #[cfg(feature = "daemon")]
+    let app = app.subcommand(
+        SubCommand::with_name("daemon")
+            .about("Keep watching: auto-tag new items and keep an index for a quick start")
+            .arg(
+                Arg::with_name("interval")
+                    .long("interval")
+                    .value_name("SECS")
+                    .default_value(daemon::DEFAULT_INTERVAL)
+                    .help("Time between scans of the items and tags directories"),
+            )
+            .arg(
+                Arg::with_name("remove-dangling")
+                    .long("remove-dangling")
+                    .help("Also remove dangling symlinks, unless most links are dangling"),
+            ),
+    );
+    #[cfg(feature = "gui")]
+    let app = app.subcommand(
+        SubCommand::with_name("gui")
+            .about("Browse and tag the items in a window, with thumbnails of pictures"),
+    );
+    #[cfg(feature = "dbus")]
+    let app = app.arg(
+        Arg::with_name("dbus")
+            .long("dbus")
+            .help("Offer the tag store as a D-Bus service on the session bus"),
+    );
+    app
+}
+
+/// Override config settings with command line options
+pub fn apply_to_config(config: &mut Config, m: &ArgMatches) {
+    if let Some(name) = m.value_of("profile") {
+        config.use_profile(name);
+    }
+    if let Some(excludes) = m.values_of("exclude") {
+        config.exclude.extend(excludes.map(str::to_owned));
+    }
+    if let Some(includes) = m.values_of("include") {
+        config.include.extend(includes.map(str::to_owned));
+    }
+    if let Some(name) = m.value_of("theme") {
+        config.theme = Some(name.to_owned());
+    }
+    if let Some(name) = m.value_of("backend") {
+        config.backend = Some(name.to_owned());
+    }
+    match m.value_of("identity") {
+        Some("path") => config.identity = Identity::Path,
+        Some("inode") => config.identity = Identity::Inode,
+        _ => {}
+    }
+}
+
+/// Set up filesystem operations for `--dry-run`, `--dry-run-log` and
+/// `--read-only`
+pub fn fs_ops(m: &ArgMatches) -> io::Result<FsOps> {
+    let mut fs = FsOps::new(
+        m.is_present("dry-run"),
+        m.value_of_os("dry-run-log").map(Path::new),
+    )?;
+    fs.set_read_only(m.is_present("read-only"));
+    Ok(fs)
+}
+
+/// The profile selected with `--profile`
+fn profile(m: &ArgMatches) -> Option<Profile> {
+    let name = m.value_of("profile")?;
+    config::load().profile.remove(name)
+}
+
+/// Check that the profile selected with `--profile` exists
+pub fn check_profile(m: &ArgMatches) -> Result<(), String> {
+    match m.value_of("profile") {
+        Some(name) if !config::load().profile.contains_key(name) => {
+            Err(format!("no such profile in the config file: {}", name))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Whether `--quiet` was given
+pub fn is_quiet(m: &ArgMatches) -> bool {
+    m.is_present("quiet")
+}
+
+/// Items directory from `--items`, or from the profile
+pub fn items_dir(m: &ArgMatches) -> Option<PathBuf> {
+    m.value_of_os("items")
+        .map(PathBuf::from)
+        .or_else(|| profile(m)?.items)
+}
+
+/// Tags directory from `--tags`, or from the profile
+pub fn tags_dir(m: &ArgMatches) -> Option<PathBuf> {
+    m.value_of_os("tags")
+        .map(PathBuf::from)
+        .or_else(|| profile(m)?.tags)
+}
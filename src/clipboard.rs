@@ -0,0 +1,27 @@
+//! Copying to the system clipboard
+//!
+//! Uses the OSC 52 terminal escape sequence, so it works without any
+//! clipboard daemon and even over SSH, as long as the terminal supports it.
+
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+
+use hashbrown::HashSet;
+
+use crate::SharedPath;
+
+/// Copy given paths to the clipboard, newline-separated
+pub fn yank_paths(paths: &HashSet<SharedPath>) -> io::Result<()> {
+    let mut paths: Vec<_> = paths.iter().collect();
+    paths.sort();
+
+    let mut data = Vec::new();
+    for p in paths {
+        data.extend_from_slice(p.as_os_str().as_bytes());
+        data.push(b'\n');
+    }
+
+    let mut out = io::stdout();
+    write!(out, "\x1b]52;c;{}\x07", base64::encode(&data))?;
+    out.flush()
+}
@@ -0,0 +1,37 @@
+//! Tag colors
+//!
+//! A tag's color is stored in a `.color` file inside the tag directory, so
+//! it is shared by everyone using the same tags tree. The file contains a
+//! color in cursive's syntax: a name like `red` or `light blue`, or a hex
+//! value like `#ff8800`.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use cursive::theme::Color;
+
+use crate::fsops::FsOps;
+
+/// Name of the file holding the color in a tag directory
+pub static COLOR_FILE: &str = ".color";
+
+/// Read the color of a tag
+pub fn read(tag_dir: &Path) -> Option<Color> {
+    fs::read_to_string(tag_dir.join(COLOR_FILE))
+        .ok()
+        .and_then(|s| Color::parse(s.trim()))
+}
+
+/// Set the color of a tag, or remove it if `spec` is empty
+pub fn write(ops: &mut FsOps, tag_dir: &Path, spec: &str) -> io::Result<()> {
+    let path = tag_dir.join(COLOR_FILE);
+    if spec.is_empty() {
+        match ops.remove_file(&path) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            r => r,
+        }
+    } else {
+        ops.write_file(&path, &format!("{}\n", spec))
+    }
+}
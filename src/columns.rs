@@ -0,0 +1,82 @@
+//! Metadata columns in the items view
+
+use std::time::SystemTime;
+
+use chrono::{DateTime, Local};
+use unicode_width::UnicodeWidthStr;
+
+use crate::Item;
+
+/// Sets of columns to cycle through, after the name
+pub static COLUMN_SETS: &[&[Column]] = &[
+    &[],
+    &[Column::Size],
+    &[Column::Size, Column::Modified],
+    &[Column::Size, Column::Modified, Column::TagCount],
+];
+
+/// Names longer than this are not padded further
+const MAX_NAME_WIDTH: usize = 60;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Column {
+    Size,
+    Modified,
+    TagCount,
+}
+
+/// Format a file size for humans
+pub fn format_size(size: u64) -> String {
+    const UNITS: &[&str] = &["K", "M", "G", "T", "P"];
+    if size < 1024 {
+        return format!("{}B", size);
+    }
+    let mut size = size as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", size, UNITS[unit])
+}
+
+fn format_mtime(mtime: Option<SystemTime>) -> String {
+    match mtime {
+        Some(mtime) => DateTime::<Local>::from(mtime)
+            .format("%Y-%m-%d %H:%M")
+            .to_string(),
+        None => "-".to_owned(),
+    }
+}
+
+/// Width to pad names to, given all names shown
+pub fn name_width<'a>(names: impl Iterator<Item = &'a str>) -> usize {
+    names
+        .map(UnicodeWidthStr::width)
+        .max()
+        .unwrap_or(0)
+        .min(MAX_NAME_WIDTH)
+}
+
+/// Pad a name to the given width, if any columns follow it
+pub fn pad_name(name: String, width: usize, columns: &[Column]) -> String {
+    if columns.is_empty() {
+        return name;
+    }
+    let pad = width.saturating_sub(name.width());
+    name + &" ".repeat(pad)
+}
+
+/// Render the columns for an item
+pub fn format_columns(item: &Item, columns: &[Column]) -> String {
+    let mut s = String::new();
+    for column in columns {
+        s += &match column {
+            Column::Size if item.is_dir => format!("  {:>7}", "-"),
+            Column::Size => format!("  {:>7}", format_size(item.size)),
+            Column::Modified => format!("  {:>16}", format_mtime(item.mtime)),
+            Column::TagCount => format!("  {:>3} tags", item.tags.len()),
+        };
+    }
+    s
+}
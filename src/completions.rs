@@ -0,0 +1,131 @@
+//! Shell completions
+//!
+//! `linkorgasm completions SHELL` prints a completion script for bash, zsh
+//! or fish. Besides the subcommands and options, the scripts complete tag
+//! names for `--tag` by calling `linkorgasm list-tags`, which reads them
+//! from the tags dir given on the command line being completed, or from
+//! the most recently used one.
+
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use clap::{ArgMatches, Shell};
+
+use crate::{cli, config, exit, recent, scan_tags, sorted_tags, AppState};
+
+/// Shells completion scripts are made for
+pub static SHELLS: &[&str] = &["bash", "zsh", "fish"];
+
+/// Completion of `--tag` values in bash, wrapping the generated function
+static BASH_TAGS: &str = r#"
+_linkorgasm_tags() {
+    local i tags=()
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        if [[ "${COMP_WORDS[i]}" == --tags ]]; then
+            tags=(--tags "${COMP_WORDS[i+1]}")
+        fi
+    done
+    linkorgasm list-tags "${tags[@]}" 2>/dev/null
+}
+
+_linkorgasm_dynamic() {
+    if [[ "${COMP_WORDS[COMP_CWORD-1]}" == --tag ]]; then
+        local IFS=$'\n'
+        COMPREPLY=($(compgen -W "$(_linkorgasm_tags)" -- "${COMP_WORDS[COMP_CWORD]}"))
+        return 0
+    fi
+    _linkorgasm "$@"
+}
+
+complete -F _linkorgasm_dynamic -o bashdefault -o default linkorgasm
+"#;
+
+/// Completion of `--tag` values in zsh, run instead of the generated function
+static ZSH_TAGS: &str = r#"
+_linkorgasm_dynamic() {
+    if [[ "${words[CURRENT-1]}" == --tag ]]; then
+        local i
+        local -a tags dir
+        for ((i = 2; i < CURRENT; i++)); do
+            [[ "${words[i]}" == --tags ]] && dir=(--tags "${words[i+1]}")
+        done
+        tags=(${(f)"$(linkorgasm list-tags "${dir[@]}" 2>/dev/null)"})
+        compadd -a tags
+        return
+    fi
+    _linkorgasm "$@"
+}
+
+_linkorgasm_dynamic "$@""#;
+
+/// Completion of `--tag` values in fish, added to the generated ones
+static FISH_TAGS: &str = r#"
+function __linkorgasm_tags
+    set -l words (commandline -opc)
+    set -l dir
+    for i in (seq (count $words))
+        if test "$words[$i]" = --tags; and test $i -lt (count $words)
+            set dir --tags $words[(math $i + 1)]
+        end
+    end
+    linkorgasm list-tags $dir 2>/dev/null
+end
+complete -c linkorgasm -l tag -x -a '(__linkorgasm_tags)'
+"#;
+
+/// Entry point for `linkorgasm completions`, returns the exit code
+pub fn run(m: &ArgMatches) -> i32 {
+    let name = m.value_of("shell").unwrap();
+    let shell: Shell = name.parse().unwrap();
+    let mut script = Vec::new();
+    cli::build_cli().gen_completions_to("linkorgasm", shell, &mut script);
+    let mut script = String::from_utf8(script).expect("completion script is not UTF-8");
+
+    match name {
+        "bash" => script.push_str(BASH_TAGS),
+        "zsh" => {
+            // the generated script ends by calling its completion function
+            let call = "_linkorgasm \"$@\"";
+            match script.rfind(call) {
+                Some(i) => script.replace_range(i..i + call.len(), ZSH_TAGS.trim_start()),
+                None => script.push_str(ZSH_TAGS),
+            }
+        }
+        _ => script.push_str(FISH_TAGS),
+    }
+    let stdout = io::stdout();
+    if let Err(e) = stdout.lock().write_all(script.as_bytes()) {
+        eprintln!("{}", e);
+        return exit::FAILED;
+    }
+    exit::OK
+}
+
+/// Entry point for `linkorgasm list-tags`, returns the exit code
+///
+/// Prints the names of all tags, one per line, without locking the tags
+/// dir, so it can run while it is in use.
+pub fn list_tags(m: &ArgMatches) -> i32 {
+    let tags: Option<PathBuf> = cli::tags_dir(m).or_else(recent::last_tags);
+    let tags = match tags {
+        Some(ref tags) if tags.is_dir() => tags,
+        _ => {
+            eprintln!("a tags dir is required");
+            return exit::USAGE;
+        }
+    };
+    let mut config = config::load();
+    cli::apply_to_config(&mut config, m);
+    let mut state = AppState::new(config);
+    state.tags_path = tags.to_path_buf();
+    scan_tags(&mut state, tags);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for (_, tag) in sorted_tags(&state) {
+        if writeln!(out, "{}", tag.name).is_err() {
+            return exit::FAILED;
+        }
+    }
+    exit::OK
+}
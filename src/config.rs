@@ -0,0 +1,284 @@
+//! User configuration
+//!
+//! Loaded from `config.toml` in the config directory
+//! (`~/.config/linkorgasm/` on Linux). Every setting is optional.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use serde::Deserialize;
+
+use crate::exit;
+use crate::hooks::Hooks;
+use crate::linkstyle::LinkStyle;
+
+/// How the items and tags panes are arranged
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// side by side
+    Horizontal,
+    /// items above tags, for narrow terminals
+    Vertical,
+}
+
+/// Type icons in front of item names
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Icons {
+    Off,
+    /// a letter for each type
+    Ascii,
+    /// glyphs of a Nerd Font
+    Nerd,
+}
+
+/// What to do with symlinks in the items directory
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemSymlinks {
+    /// the item is the file linked to
+    Follow,
+    /// the symlink is an item of its own
+    Keep,
+    /// symlinks are not items
+    Skip,
+}
+
+/// What tells items apart
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Identity {
+    /// the canonical path
+    Path,
+    /// the device and inode (see `identity`)
+    Inode,
+}
+
+/// A named set of directories and settings, selected with `--profile`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Profile {
+    /// items directory
+    pub items: Option<PathBuf>,
+    /// tags directory
+    pub tags: Option<PathBuf>,
+    /// added to the `exclude` patterns
+    pub exclude: Vec<String>,
+    /// added to the `include` globs
+    pub include: Vec<String>,
+    /// added to the `auto-tag` rules, replacing those for the same glob
+    pub auto_tag: BTreeMap<String, Vec<String>>,
+    /// added to the `open-with` commands, replacing those for the same extensions
+    pub open_with: BTreeMap<String, String>,
+}
+
+/// Config file format version understood by this binary
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    /// format version the file was written for
+    pub version: Option<u32>,
+    /// command to open tag directories with
+    pub file_manager: String,
+    /// gitignore-style patterns for files to skip when scanning items
+    pub exclude: Vec<String>,
+    /// glob patterns for file names to scan; if empty, scan everything
+    pub include: Vec<String>,
+    /// whether directories in the items directory are items too
+    pub include_dirs: bool,
+    /// whether new symlinks are relative or absolute
+    pub link_style: LinkStyle,
+    /// escape all names for display like `ls -b`, not just unprintable ones
+    pub escape_names: bool,
+    /// sort names case-insensitively, with numbers in numeric order
+    pub natural_sort: bool,
+    /// color theme: `dark`, `light`, or a theme file
+    pub theme: Option<String>,
+    /// terminal backend: `termion`, `crossterm` or `ncurses`, if built in
+    pub backend: Option<String>,
+    /// language of the UI, like `de`; by default from the environment
+    pub language: Option<String>,
+    /// arrangement of the panes
+    pub layout: Layout,
+    /// share of the main window used by the items pane (percent)
+    pub split: usize,
+    /// type icons in the items view
+    pub icons: Icons,
+    /// names of the tags toggled by the number keys '1' to '0'
+    pub pinned_tags: Vec<String>,
+    /// tags created in a new tags directory, if the user asks for them
+    pub tag_template: Vec<String>,
+    /// commands to open items with ('o'), keyed by comma-separated extensions
+    pub open_with: BTreeMap<String, String>,
+    /// commands printing the text of files, by file extension, for the
+    /// `text:` query term
+    pub text_commands: BTreeMap<String, String>,
+    /// command describing an image, for `linkorgasm describe`
+    pub describe_command: Option<String>,
+    /// extra tags directories, keyed by the label prefixed to their tag names
+    pub tag_roots: BTreeMap<String, PathBuf>,
+    /// commands run around tagging
+    pub hooks: Hooks,
+    /// tags given to new items by `linkorgasm daemon`, keyed by file name glob
+    pub auto_tag: BTreeMap<String, Vec<String>>,
+    /// how deep tags can be nested; deeper dirs are skipped
+    pub max_tag_depth: usize,
+    /// glob patterns for names of dirs in the tags tree that are not tags
+    pub tag_exclude: Vec<String>,
+    /// whether tag dirs list their items in manifests the symlinks follow
+    pub tag_manifests: bool,
+    /// whether the items dir is a git-annex repository (see `annex`)
+    pub git_annex: bool,
+    /// what symlinks in the items dir stand for
+    pub item_symlinks: ItemSymlinks,
+    /// what tells items apart
+    pub identity: Identity,
+    /// key that quits: a character, `ctrl-` and a character, or `esc`
+    pub quit_key: String,
+    /// whether quitting asks for confirmation first
+    pub confirm_quit: bool,
+    /// whether the tags dir is shared and tag changes are journaled
+    pub journal: bool,
+    /// seconds between checks for changes made to the items and tags dirs
+    /// from outside, 0 for none
+    pub watch_interval: u64,
+    /// whether tag changes apply to the highlighted item when nothing is
+    /// selected
+    pub implicit_selection: bool,
+    /// profiles, keyed by name (`[profile.NAME]` tables)
+    pub profile: BTreeMap<String, Profile>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            version: None,
+            file_manager: "xdg-open".to_owned(),
+            exclude: Vec::new(),
+            include: Vec::new(),
+            include_dirs: true,
+            link_style: LinkStyle::Auto,
+            escape_names: false,
+            natural_sort: false,
+            theme: None,
+            backend: None,
+            language: None,
+            layout: Layout::Horizontal,
+            split: 50,
+            icons: Icons::Off,
+            pinned_tags: Vec::new(),
+            tag_template: Vec::new(),
+            open_with: BTreeMap::new(),
+            text_commands: vec![("pdf".to_owned(), "pdftotext -q {} -".to_owned())]
+                .into_iter()
+                .collect(),
+            describe_command: None,
+            tag_roots: BTreeMap::new(),
+            hooks: Hooks::default(),
+            auto_tag: BTreeMap::new(),
+            max_tag_depth: 32,
+            tag_exclude: vec![".*".to_owned(), "lost+found".to_owned(), "@eaDir".to_owned()],
+            tag_manifests: false,
+            git_annex: false,
+            item_symlinks: ItemSymlinks::Follow,
+            identity: Identity::Path,
+            quit_key: "q".to_owned(),
+            confirm_quit: false,
+            journal: false,
+            watch_interval: 0,
+            implicit_selection: true,
+            profile: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Add the settings of a profile, `false` if there is no such profile
+    pub fn use_profile(&mut self, name: &str) -> bool {
+        let profile = match self.profile.get(name) {
+            Some(profile) => profile.clone(),
+            None => return false,
+        };
+        self.exclude.extend(profile.exclude);
+        self.include.extend(profile.include);
+        self.auto_tag.extend(profile.auto_tag);
+        self.open_with.extend(profile.open_with);
+        true
+    }
+}
+
+/// Directory holding the config file and other user files
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|p| p.join("linkorgasm"))
+}
+
+/// Path to the config file
+pub fn config_path() -> Option<PathBuf> {
+    config_dir().map(|p| p.join("config.toml"))
+}
+
+/// Report a config file that can't be used and exit
+fn unusable(path: &Path, why: String) -> ! {
+    eprintln!("{}: {}", path.display(), why);
+    process::exit(exit::USAGE)
+}
+
+thread_local! {
+    /// The config file as read by the first `load`
+    static LOADED: RefCell<Option<Config>> = RefCell::new(None);
+}
+
+/// Load the config file, or use defaults if there is none
+///
+/// The file is read once, later calls (like for `--profile` and then for
+/// the settings) get a copy of what was read.
+pub fn load() -> Config {
+    LOADED.with(|loaded| loaded.borrow_mut().get_or_insert_with(read).clone())
+}
+
+/// Read the config file, or use defaults if there is none
+///
+/// A config file that can't be parsed, or has a version this binary can't
+/// read, is reported and nothing is done, rather than using the defaults.
+fn read() -> Config {
+    let path = match config_path() {
+        Some(path) => path,
+        None => return Config::default(),
+    };
+    let config: Config = match fs::read_to_string(&path) {
+        Ok(s) => match toml::from_str(&s) {
+            Ok(config) => config,
+            Err(e) => unusable(&path, format!("cannot parse config file: {}", e)),
+        },
+        Err(_) => Config::default(),
+    };
+    match config.version {
+        Some(v) if v > VERSION => unusable(
+            &path,
+            format!(
+                "config file version {} is newer than supported ({}), please upgrade linkorgasm",
+                v, VERSION
+            ),
+        ),
+        Some(v) if v < VERSION => unusable(
+            &path,
+            format!(
+                "config file version {} is outdated, run `linkorgasm self-check` to migrate it",
+                v
+            ),
+        ),
+        _ => config,
+    }
+}
+
+/// Upgrade a config document by one version
+///
+/// There has only ever been one format so far, so there is nothing to
+/// change.
+pub fn migrate(_doc: &mut toml::Value, _from: u32) {}
@@ -0,0 +1,559 @@
+//! Checking the index against the items and tags dirs on disk
+//!
+//! Sync tools (or other people) may add and remove files in the items dir
+//! and symlinks in tag dirs while linkorgasm is running. This re-reads
+//! both, lists the items added and removed and the links that differ from
+//! the index, and lets the user take them (reload from disk), look at the
+//! items involved, or write the tags as shown back to disk. With
+//! `watch-interval`, the check runs every few seconds and shows the list
+//! when something new changed. Links to things that aren't items are left
+//! to the foreign links review.
+//!
+//! The disk is read on a thread of its own, compared with a copy of the
+//! index; only what it found goes back to the UI thread, where anything
+//! the index (or the disk) has caught up with since is dropped.
+
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::hash::Hash;
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+use cursive::traits::Identifiable;
+use cursive::views::{Dialog, ScrollView, TextView};
+use cursive::Cursive;
+use hashbrown::{HashMap, HashSet};
+
+use crate::config::Config;
+use crate::handle::AppHandle;
+use crate::journal;
+use crate::linkstyle;
+use crate::manifest;
+use crate::{
+    apply_filter, do_app, item_key, lexical_path, link_target, rescan_tag, rescan_tags, run_filter,
+    scan_items, ui_error, ui_refresh_itemview, ui_select_item, AppState, Filter, SharedPath,
+};
+
+/// Register the selection is saved in before "Investigate" replaces it
+static INVESTIGATE_REGISTER: &str = "before investigating";
+
+/// A difference between the index and the disk
+#[derive(Debug, Clone)]
+pub enum Mismatch {
+    /// a link to the item is in the tag dir (with this name), but not in
+    /// the index
+    Added(PathBuf, PathBuf, OsString),
+    /// the index has the link, but the tag dir doesn't
+    Removed(PathBuf, PathBuf),
+    /// the tag dir is gone
+    TagGone(PathBuf),
+    /// a file in the items dir that isn't an item yet
+    ItemAdded(PathBuf),
+    /// an item whose file is gone
+    ItemRemoved(PathBuf),
+}
+
+impl Mismatch {
+    /// Canonical path of the tag the mismatch is in, if it is in one
+    fn tag(&self) -> Option<&Path> {
+        match self {
+            Mismatch::Added(tp, _, _) | Mismatch::Removed(tp, _) | Mismatch::TagGone(tp) => {
+                Some(tp)
+            }
+            Mismatch::ItemAdded(_) | Mismatch::ItemRemoved(_) => None,
+        }
+    }
+
+    /// Whether an item was added or removed
+    fn is_item(&self) -> bool {
+        self.tag().is_none()
+    }
+}
+
+/// A copy of what the index holds, to compare with the disk off the UI
+/// thread
+struct Indexed {
+    config: Config,
+    items_path: Option<PathBuf>,
+    items: HashSet<PathBuf>,
+    /// the items of every tag, by the canonical path of the tag
+    tags: Vec<(PathBuf, HashSet<PathBuf>)>,
+}
+
+impl Indexed {
+    fn of(state: &AppState) -> Indexed {
+        let mut tags: Vec<(PathBuf, HashSet<PathBuf>)> = state
+            .tags
+            .iter()
+            .map(|(tp, tag)| {
+                let items = tag.items.keys().map(|ip| ip.to_path_buf()).collect();
+                (tp.to_path_buf(), items)
+            })
+            .collect();
+        tags.sort_by(|a, b| a.0.cmp(&b.0));
+        Indexed {
+            config: state.config.clone(),
+            items_path: state.items_path.clone(),
+            items: state.items_all.keys().map(|ip| ip.to_path_buf()).collect(),
+            tags,
+        }
+    }
+}
+
+/// Whether the file of an item is gone
+fn is_gone(ip: &Path) -> bool {
+    ip.symlink_metadata().is_err()
+}
+
+/// Items linked from a tag dir on disk, with the names of their links,
+/// `None` if it can't be read
+///
+/// `item` gives the item a symlink leads to, if it is one.
+fn items_on_disk<T: Eq + Hash>(
+    tp: &Path,
+    item: impl Fn(&Path) -> Option<T>,
+) -> Option<HashMap<T, OsString>> {
+    let entries = fs::read_dir(tp).ok()?;
+    let mut items = HashMap::default();
+    for entry in entries.filter_map(|e| e.ok()) {
+        let is_link = entry.file_type().map(|t| t.is_symlink());
+        if !is_link.unwrap_or(false) {
+            continue;
+        }
+        if let Some(ip) = item(&entry.path()) {
+            items.insert(ip, entry.file_name());
+        }
+    }
+    Some(items)
+}
+
+/// Items of the index linked from a tag dir on disk, see `items_on_disk`
+fn indexed_on_disk(state: &AppState, tp: &Path) -> Option<HashMap<SharedPath, OsString>> {
+    items_on_disk(tp, |link| {
+        link_target(state, link, tp).and_then(|c| item_key(state, &c))
+    })
+}
+
+/// The items dir scanned again with the same settings, `None` without one
+fn scan_fresh(state: &mut AppState) -> Option<AppState> {
+    let items = state.items_path.clone().filter(|p| p.is_dir())?;
+    let mut fresh = AppState::new(mem::take(&mut state.config));
+    scan_items(&mut fresh, &items);
+    state.config = mem::take(&mut fresh.config);
+    Some(fresh)
+}
+
+/// Compare the items and every tag of a copy of the index with the disk
+///
+/// Runs off the UI thread, with a state of its own for the items on disk.
+fn compare(ix: &Indexed) -> Vec<Mismatch> {
+    let mut disk = AppState::new(ix.config.clone());
+    let mut mismatches = Vec::new();
+    if let Some(items) = ix.items_path.as_ref().filter(|p| p.is_dir()) {
+        scan_items(&mut disk, items);
+        let mut added: Vec<&SharedPath> = disk
+            .items_all
+            .keys()
+            .filter(|ip| !ix.items.contains(&***ip))
+            .collect();
+        added.sort();
+        for ip in added {
+            mismatches.push(Mismatch::ItemAdded(ip.to_path_buf()));
+        }
+    }
+    let mut removed: Vec<&PathBuf> = ix.items.iter().filter(|ip| is_gone(ip)).collect();
+    removed.sort();
+    for ip in removed {
+        mismatches.push(Mismatch::ItemRemoved(ip.clone()));
+    }
+
+    // the item a link leads to, if the index has it
+    let indexed = |link: &Path, tp: &Path| {
+        if let Ok(target) = fs::read_link(link) {
+            let target = lexical_path(tp, &target);
+            if ix.items.contains(&target) {
+                return Some(target);
+            }
+        }
+        link_target(&disk, link, tp).filter(|c| ix.items.contains(c))
+    };
+    for (tp, items) in ix.tags.iter() {
+        let on_disk = match items_on_disk(tp, |link| indexed(link, tp)) {
+            Some(on_disk) => on_disk,
+            None => {
+                mismatches.push(Mismatch::TagGone(tp.clone()));
+                continue;
+            }
+        };
+        let mut added: Vec<(&PathBuf, &OsString)> = on_disk
+            .iter()
+            .filter(|(ip, _)| !items.contains(*ip))
+            .collect();
+        added.sort();
+        let mut removed: Vec<&PathBuf> = items
+            .iter()
+            .filter(|ip| !on_disk.contains_key(*ip))
+            .collect();
+        removed.sort();
+        for (ip, name) in added {
+            mismatches.push(Mismatch::Added(tp.clone(), ip.clone(), name.clone()));
+        }
+        for ip in removed {
+            mismatches.push(Mismatch::Removed(tp.clone(), ip.clone()));
+        }
+    }
+    mismatches
+}
+
+/// The mismatches that are still there
+///
+/// The index may have changed since they were found (they were found on a
+/// copy of it), and the disk since they were shown.
+fn recheck(state: &AppState, mismatches: Vec<Mismatch>) -> Vec<Mismatch> {
+    let mut on_disk: HashMap<PathBuf, Option<HashMap<SharedPath, OsString>>> = HashMap::default();
+    let has_link = |tp: &Path, ip: &Path| {
+        state
+            .tags
+            .get(tp)
+            .map_or(false, |tag| tag.items.contains_key(ip))
+    };
+    mismatches
+        .into_iter()
+        .filter(|m| {
+            let links = match m.tag() {
+                Some(tp) => on_disk
+                    .entry(tp.to_path_buf())
+                    .or_insert_with(|| indexed_on_disk(state, tp))
+                    .as_ref(),
+                None => None,
+            };
+            match m {
+                Mismatch::Added(tp, ip, name) => {
+                    let link = links.and_then(|links| links.get(ip.as_path()));
+                    state.tags.contains_key(tp.as_path()) && !has_link(tp, ip) && link == Some(name)
+                }
+                Mismatch::Removed(tp, ip) => {
+                    has_link(tp, ip)
+                        && links.map_or(false, |links| !links.contains_key(ip.as_path()))
+                }
+                Mismatch::TagGone(tp) => state.tags.contains_key(tp.as_path()) && links.is_none(),
+                Mismatch::ItemAdded(ip) => {
+                    !state.items_all.contains_key(ip.as_path()) && !is_gone(ip)
+                }
+                Mismatch::ItemRemoved(ip) => {
+                    state.items_all.contains_key(ip.as_path()) && is_gone(ip)
+                }
+            }
+        })
+        .collect()
+}
+
+/// Take the items added to and removed from the items dir into the index
+fn reload_items(state: &mut AppState) {
+    let gone: Vec<SharedPath> = state.items_all.keys().filter(|ip| is_gone(ip)).cloned().collect();
+    for ip in gone.iter() {
+        let item = state.items_all.remove(ip).unwrap();
+        for tp in item.tags.iter() {
+            if let Some(tag) = state.tags.get_mut(tp) {
+                tag.items.remove(ip);
+            }
+        }
+        state.items_vis.remove(ip);
+        state.sel.remove(ip);
+        for sel in state.registers.values_mut() {
+            sel.remove(ip);
+        }
+    }
+    let items_all = &state.items_all;
+    state.annex_keys.retain(|_, ips| {
+        ips.retain(|ip| items_all.contains_key(&**ip));
+        !ips.is_empty()
+    });
+    state.inodes.retain(|_, ip| items_all.contains_key(&**ip));
+
+    if let Some(fresh) = scan_fresh(state) {
+        let AppState {
+            items_all,
+            annex_keys,
+            inodes,
+            ..
+        } = fresh;
+        for (ip, item) in items_all {
+            state.items_all.entry(ip).or_insert(item);
+        }
+        for (key, ips) in annex_keys {
+            let known = state.annex_keys.entry(key).or_insert_with(Vec::new);
+            for ip in ips {
+                if !known.contains(&ip) {
+                    known.push(ip);
+                }
+            }
+        }
+        for (id, ip) in inodes {
+            state.inodes.entry(id).or_insert(ip);
+        }
+    }
+}
+
+/// Reload what differs from disk
+///
+/// If items were added or removed, all tags are reloaded, as links to new
+/// items were not known.
+fn accept(state: &mut AppState, mismatches: &[Mismatch]) {
+    if mismatches.iter().any(Mismatch::is_item) {
+        reload_items(state);
+        rescan_tags(state);
+        return;
+    }
+    let mut tags: Vec<&Path> = mismatches.iter().filter_map(Mismatch::tag).collect();
+    tags.dedup();
+    for tp in tags {
+        rescan_tag(state, tp);
+    }
+}
+
+/// Put back the link of an item the index has in a tag
+fn relink(state: &mut AppState, tp: &Path, ip: &Path) -> io::Result<()> {
+    let name = state.tags[tp].items[ip].clone();
+    let is_dir = state.items_all[ip].is_dir;
+    let target = linkstyle::target(state.config.link_style, tp, ip);
+    state.fs.symlink(&target, &tp.join(name), is_dir)?;
+    manifest::add(state, tp, ip)?;
+    journal::record(state, "tag", tp, ip);
+    Ok(())
+}
+
+/// Remove a link the index doesn't have from a tag dir
+fn unlink(state: &mut AppState, tp: &Path, ip: &Path, name: &OsStr) -> io::Result<()> {
+    let is_dir = state.items_all[ip].is_dir;
+    state.fs.remove_symlink(&tp.join(name), is_dir)?;
+    manifest::remove(state, tp, ip)?;
+    journal::record(state, "untag", tp, ip);
+    Ok(())
+}
+
+/// Make a tag dir that is gone again, with the links of its items
+fn recreate(state: &mut AppState, tp: &Path) -> io::Result<()> {
+    state.fs.create_dir(tp)?;
+    let mut items: Vec<SharedPath> = state.tags[tp].items.keys().cloned().collect();
+    items.sort();
+    for ip in items.iter().filter(|ip| !is_gone(ip)) {
+        relink(state, tp, ip)?;
+    }
+    Ok(())
+}
+
+/// Write the tags as shown back to disk, returns the errors
+///
+/// Items added to or removed from the items dir are taken as they are.
+/// What changed again since the mismatches were found is left alone.
+fn reapply(state: &mut AppState, mismatches: &[Mismatch]) -> Vec<String> {
+    let mismatches = recheck(state, mismatches.to_vec());
+    let mut errors = Vec::new();
+    for m in mismatches.iter() {
+        let res = match m {
+            Mismatch::Added(tp, ip, name) => unlink(state, tp, ip, name),
+            Mismatch::Removed(tp, ip) if !is_gone(ip) => relink(state, tp, ip),
+            Mismatch::TagGone(tp) => recreate(state, tp),
+            _ => Ok(()),
+        };
+        if let Err(e) = res {
+            errors.push(format!("{}: {}", describe(state, m), e));
+        }
+    }
+    if mismatches.iter().any(Mismatch::is_item) {
+        reload_items(state);
+    }
+    errors
+}
+
+/// One line of the report
+fn describe(state: &AppState, m: &Mismatch) -> String {
+    let tag_name = |tp: &Path| {
+        state
+            .tags
+            .get(tp)
+            .map_or_else(|| tp.display().to_string(), |t| t.name.clone())
+    };
+    match m {
+        Mismatch::Added(tp, ip, _) => format!("+ {}: {}", tag_name(tp), ip.display()),
+        Mismatch::Removed(tp, ip) => format!("- {}: {}", tag_name(tp), ip.display()),
+        Mismatch::TagGone(tp) => format!("tag {} is gone", tag_name(tp)),
+        Mismatch::ItemAdded(ip) => format!("+ {}", ip.display()),
+        Mismatch::ItemRemoved(ip) => format!("- {}", ip.display()),
+    }
+}
+
+/// The report, with the items and the links in sections
+fn report(state: &AppState, mismatches: &[Mismatch]) -> String {
+    let section = |title: String, lines: Vec<String>| {
+        if lines.is_empty() {
+            String::new()
+        } else {
+            format!("{}\n{}\n\n", title, lines.join("\n"))
+        }
+    };
+    let lines = |f: fn(&Mismatch) -> bool| {
+        mismatches
+            .iter()
+            .filter(|m| f(m))
+            .map(|m| describe(state, m))
+            .collect::<Vec<String>>()
+    };
+    let mut text = section(
+        tr!("Items added:"),
+        lines(|m| matches!(m, Mismatch::ItemAdded(_))),
+    );
+    text += &section(
+        tr!("Items removed:"),
+        lines(|m| matches!(m, Mismatch::ItemRemoved(_))),
+    );
+    text += &section(
+        tr!("Tags ('+' links added, '-' removed):"),
+        lines(|m| !m.is_item()),
+    );
+    text.trim_end().to_owned()
+}
+
+/// Show the changes and what can be done about them
+fn ui_show(siv: &mut Cursive, mismatches: Vec<Mismatch>, report: String) {
+    let mismatches = Rc::new(mismatches);
+    let text = tr!(
+        "Changed on disk since it was scanned:\n\n{}\n\n\
+         Accept reloads them, Investigate selects the items involved (the \
+         selection is saved as \"{}\" first), and Re-apply mine writes the \
+         tags as shown back to disk.",
+        report,
+        INVESTIGATE_REGISTER
+    );
+    let (accepted, investigated, reapplied) =
+        (Rc::clone(&mismatches), Rc::clone(&mismatches), mismatches);
+    siv.add_layer(
+        Dialog::around(ScrollView::new(TextView::new(text).with_id("changes")))
+            .title(tr!("Tags directory changed"))
+            .button(tr!("Accept"), move |siv| {
+                siv.pop_layer();
+                do_app(siv, |siv, state| {
+                    accept(state, &accepted);
+                    run_filter(state);
+                    ui_refresh_itemview(siv, state);
+                });
+            })
+            .button(tr!("Investigate"), move |siv| {
+                siv.pop_layer();
+                let first = do_app(siv, |siv, state| {
+                    let mut involved: Vec<SharedPath> = investigated
+                        .iter()
+                        .filter_map(|m| match m {
+                            Mismatch::Added(_, ip, _)
+                            | Mismatch::Removed(_, ip)
+                            | Mismatch::ItemRemoved(ip) => item_key(state, ip),
+                            _ => None,
+                        })
+                        .collect();
+                    involved.sort();
+                    involved.dedup();
+                    if !state.sel.is_empty() {
+                        let sel = mem::take(&mut state.sel);
+                        state.registers.insert(INVESTIGATE_REGISTER.to_owned(), sel);
+                    }
+                    state.sel = involved.iter().cloned().collect::<HashSet<_>>();
+                    apply_filter(siv, state, Filter::All);
+                    involved.into_iter().next()
+                });
+                if let Some(ip) = first {
+                    ui_select_item(siv, &ip);
+                }
+            })
+            .button(tr!("Re-apply mine"), move |siv| {
+                siv.pop_layer();
+                do_app(siv, |siv, state| {
+                    if state.fs.is_read_only() {
+                        return ui_error(siv, tr!("The tags directory is read-only."));
+                    }
+                    let errors = reapply(state, &reapplied);
+                    run_filter(state);
+                    ui_refresh_itemview(siv, state);
+                    if !errors.is_empty() {
+                        ui_error(siv, errors.join("\n"));
+                    }
+                });
+            })
+            .dismiss_button(tr!("Close")),
+    );
+}
+
+/// Show the mismatches found on a copy of the index that are still there
+fn ui_found(siv: &mut Cursive, state: &mut AppState, found: Vec<Mismatch>) {
+    let mismatches = recheck(state, found);
+    if mismatches.is_empty() {
+        siv.add_layer(Dialog::info(tr!("The tags directory matches what is shown.")));
+        return;
+    }
+    let report = report(state, &mismatches);
+    ui_show(siv, mismatches, report);
+}
+
+/// Whether the changes are shown already
+fn is_shown(siv: &mut Cursive) -> bool {
+    siv.call_on_id("changes", |_: &mut TextView| ()).is_some()
+}
+
+/// UI callback to check the index and offer what can be done about changes
+///
+/// The disk is read on another thread, the UI keeps running meanwhile.
+pub fn ui_check(siv: &mut Cursive) {
+    let ix = do_app(siv, |_, state| Indexed::of(state));
+    let app = AppHandle::new(siv);
+    thread::spawn(move || {
+        let found = compare(&ix);
+        app.post(move |siv, state| ui_found(siv, state, found));
+    });
+}
+
+/// Check for changes every `interval` while the UI runs
+///
+/// The disk is read on another thread, and the changes are shown when they
+/// differ from the ones last found, and they aren't shown already.
+pub fn ui_start_watch(siv: &Cursive, interval: Duration) {
+    let app = AppHandle::new(siv);
+    thread::spawn(move || {
+        let mut last = String::new();
+        loop {
+            thread::sleep(interval);
+            let ix = app.call(|siv, state| {
+                if is_shown(siv) {
+                    None
+                } else {
+                    Some(Indexed::of(state))
+                }
+            });
+            let ix = match ix {
+                Some(Some(ix)) => ix,
+                Some(None) => continue,
+                None => break,
+            };
+            let found = compare(&ix);
+            let seen = last.clone();
+            let found = app.call(move |siv, state| {
+                if is_shown(siv) {
+                    return seen;
+                }
+                let mismatches = recheck(state, found);
+                let report = report(state, &mismatches);
+                if !mismatches.is_empty() && report != seen {
+                    ui_show(siv, mismatches, report.clone());
+                }
+                report
+            });
+            match found {
+                Some(report) => last = report,
+                None => break,
+            }
+        }
+    });
+}
@@ -0,0 +1,157 @@
+//! Contents of items, for the `text:` query term
+//!
+//! Searched on demand: text and code items are read as they are, items
+//! with a command in `text-commands` (like `pdftotext` for PDFs) are
+//! converted by it, others have no text. Descriptions written by
+//! `linkorgasm describe` are searched too. The texts are kept, lowercased,
+//! until their files change, so only the first search through a
+//! collection is slow. The output of text commands is also kept in the
+//! user's cache dir, by command and file path, size and modification time,
+//! so they run only once per file across runs.
+
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
+
+use hashbrown::HashMap;
+use log::debug;
+
+use crate::config::Config;
+use crate::describe;
+use crate::filetype::{self, Kind};
+use crate::{AppState, Item, SharedPath};
+
+/// Files bigger than this are not searched
+const MAX_SIZE: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug)]
+struct Entry {
+    /// modification times of the item and its description
+    mtimes: (Option<SystemTime>, Option<SystemTime>),
+    /// `None` for items without text
+    text: Option<String>,
+}
+
+/// Texts of the items searched so far
+#[derive(Debug, Default)]
+pub struct Cache {
+    texts: RefCell<HashMap<SharedPath, Entry>>,
+}
+
+/// Command configured in `text-commands` for a file name's extension
+fn text_command<'a>(config: &'a Config, filename: &Path) -> Option<&'a str> {
+    let ext = filename.extension()?.to_string_lossy().to_lowercase();
+    config
+        .text_commands
+        .iter()
+        .find(|(exts, _)| {
+            exts.split(',')
+                .any(|e| e.trim().trim_start_matches('.').to_lowercase() == ext)
+        })
+        .map(|(_, cmd)| cmd.as_str())
+}
+
+/// Output of a text command, the path replacing `{}` or appended
+///
+/// The command is split into words like a shell would, but run without
+/// one.
+pub fn run(cmdline: &str, path: &Path) -> Option<Vec<u8>> {
+    let words = shell_words::split(cmdline).ok()?;
+    let mut words = words.iter();
+    let mut cmd = Command::new(words.next()?);
+    let mut placed = false;
+    for word in words {
+        if word == "{}" {
+            cmd.arg(path);
+            placed = true;
+        } else {
+            cmd.arg(word);
+        }
+    }
+    if !placed {
+        cmd.arg(path);
+    }
+    let out = cmd
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if out.status.success() {
+        Some(out.stdout)
+    } else {
+        None
+    }
+}
+
+/// File the output of a text command for a file is kept in
+///
+/// It is named by everything the output depends on, so a changed file or
+/// command gets a new one.
+fn cache_path(cmdline: &str, path: &Path) -> Option<PathBuf> {
+    let meta = fs::metadata(path).ok()?;
+    let mut hasher = DefaultHasher::new();
+    (cmdline, path, meta.len(), meta.modified().ok()).hash(&mut hasher);
+    let name = format!("{:016x}.txt", hasher.finish());
+    dirs::cache_dir().map(|p| p.join("linkorgasm").join("text").join(name))
+}
+
+/// Output of a text command, from the cache dir if it ran on the file before
+fn cached_run(cmdline: &str, path: &Path) -> Option<Vec<u8>> {
+    let cache = cache_path(cmdline, path);
+    if let Some(bytes) = cache.as_ref().and_then(|p| fs::read(p).ok()) {
+        return Some(bytes);
+    }
+    let out = run(cmdline, path)?;
+    if let Some(cache) = cache {
+        // a reader never sees a half-written text
+        let tmp = cache.with_extension("tmp");
+        let res = fs::create_dir_all(cache.parent().unwrap())
+            .and_then(|()| fs::write(&tmp, &out))
+            .and_then(|()| fs::rename(&tmp, &cache));
+        if let Err(e) = res {
+            debug!("cannot cache text of {}: {}", path.display(), e);
+        }
+    }
+    Some(out)
+}
+
+/// Text of a file, lowercased
+fn extract(config: &Config, ip: &Path, item: &Item) -> Option<String> {
+    if item.is_dir || item.size > MAX_SIZE {
+        return None;
+    }
+    let bytes = match text_command(config, Path::new(&item.filename)) {
+        Some(cmdline) => cached_run(cmdline, ip)?,
+        None => match filetype::of(item) {
+            Kind::Text | Kind::Code => fs::read(ip).ok()?,
+            _ => return None,
+        },
+    };
+    Some(String::from_utf8_lossy(&bytes).to_lowercase())
+}
+
+/// Whether the text of an item contains a lowercase word
+pub fn contains(state: &AppState, ip: &SharedPath, item: &Item, word: &str) -> bool {
+    let modified = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+    let sidecar = describe::sidecar_path(state, ip);
+    let mtimes = (modified(&**ip), sidecar.as_deref().and_then(|p| modified(p)));
+    let mut texts = state.content.texts.borrow_mut();
+    let fresh = match texts.get(ip) {
+        Some(entry) => entry.mtimes == mtimes,
+        None => false,
+    };
+    if !fresh {
+        let mut text = extract(&state.config, ip, item);
+        if mtimes.1.is_some() {
+            let description = sidecar.and_then(|p| fs::read(p).ok()).unwrap_or_default();
+            let description = String::from_utf8_lossy(&description).to_lowercase();
+            text = Some(text.unwrap_or_default() + "\n" + &description);
+        }
+        texts.insert(ip.clone(), Entry { mtimes, text });
+    }
+    texts[ip].text.as_ref().map_or(false, |text| text.contains(word))
+}
@@ -0,0 +1,263 @@
+//! `linkorgasm daemon`
+//!
+//! Keeps the tags tree tidy without the UI: the items dir is watched for
+//! changes (and both dirs are scanned every `--interval` seconds anyway),
+//! new items are tagged according to the `auto-tag` config rules, and with
+//! `--remove-dangling`, dangling symlinks are removed from the tags tree.
+//! What is done is printed with a timestamp. After every scan, the items
+//! are written to a warm index the UI starts from (see `index`).
+//!
+//! The tags dir is only locked while a scan is processed. If another
+//! instance (like the UI) holds the lock, the scan is skipped; items that
+//! show up meanwhile are still new to the next scan.
+
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use chrono::Local;
+use clap::ArgMatches;
+use globset::{Glob, GlobMatcher};
+use hashbrown::HashSet;
+
+use crate::cli;
+use crate::config::{self, Config};
+use crate::exit;
+use crate::gc::{check_dangling, remove_dangling};
+use crate::index;
+use crate::{find_or_create_tag, load_exclusive, tag_item, AppState, SharedPath};
+
+/// Default time between scans, in seconds
+pub static DEFAULT_INTERVAL: &str = "60";
+
+/// Time to let a burst of changes (like copying many files) settle before
+/// scanning
+const SETTLE: Duration = Duration::from_secs(2);
+
+/// Auto-tagging rule: items whose file name matches get the tags
+struct Rule {
+    glob: GlobMatcher,
+    tags: Vec<String>,
+}
+
+/// Compile the `auto-tag` rules from the config
+fn rules(config: &Config) -> Result<Vec<Rule>, String> {
+    config
+        .auto_tag
+        .iter()
+        .map(|(pattern, tags)| {
+            let glob = Glob::new(pattern).map_err(|e| format!("auto-tag: {}", e))?;
+            Ok(Rule {
+                glob: glob.compile_matcher(),
+                tags: tags.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Print a line with a timestamp
+fn log(msg: impl std::fmt::Display) {
+    println!("{} {}", Local::now().format("%Y-%m-%d %H:%M:%S"), msg);
+}
+
+/// Tag a new item according to the rules, logging only failures if `quiet`
+fn auto_tag(state: &mut AppState, rules: &[Rule], ip: &Path, quiet: bool) {
+    let filename = state.items_all[ip].filename.clone();
+    for rule in rules.iter().filter(|r| r.glob.is_match(&filename)) {
+        for name in rule.tags.iter() {
+            let tp = match find_or_create_tag(state, name) {
+                Ok(tp) => tp,
+                Err(e) => {
+                    log(format_args!("cannot create tag {}: {}", name, e));
+                    continue;
+                }
+            };
+            if state.items_all[ip].tags.contains(&tp) {
+                continue;
+            }
+            match tag_item(state, &tp, ip) {
+                Ok(()) if quiet => {}
+                Ok(()) => log(format_args!("tagged {} with {}", ip.display(), name)),
+                Err(e) => log(format_args!("cannot tag {} with {}: {}", ip.display(), name, e)),
+            }
+        }
+    }
+}
+
+/// Process one scan, returns the items it found
+fn process(
+    m: &ArgMatches,
+    items: &Path,
+    tags: &Path,
+    rules: &[Rule],
+    known: Option<&HashSet<PathBuf>>,
+) -> Result<HashSet<PathBuf>, String> {
+    let mut state = load_exclusive(m, Some(items), tags)?;
+    let quiet = cli::is_quiet(m);
+
+    if let Some(known) = known {
+        let mut new: Vec<SharedPath> = state
+            .items_all
+            .keys()
+            .filter(|ip| !known.contains(&***ip))
+            .cloned()
+            .collect();
+        new.sort();
+        for ip in new.iter() {
+            auto_tag(&mut state, rules, ip, quiet);
+        }
+    }
+
+    if m.is_present("remove-dangling") {
+        match check_dangling(&state) {
+            Ok(()) => {
+                let (removed, errors) = remove_dangling(&mut state);
+                if removed > 0 && !quiet {
+                    log(format_args!("removed {} dangling symlinks", removed));
+                }
+                for (link, e) in errors {
+                    log(format_args!("cannot remove {}: {}", link.display(), e));
+                }
+            }
+            Err(e) => log(e),
+        }
+    }
+    if state.fs.is_dry_run() {
+        for op in state.fs.log() {
+            log(op);
+        }
+    }
+    if let Err(e) = index::store(&state) {
+        log(format_args!("cannot write the items index: {}", e));
+    }
+
+    Ok(state.items_all.keys().map(|ip| ip.to_path_buf()).collect())
+}
+
+/// Entry point for `linkorgasm daemon`, runs until killed
+pub fn run(m: &ArgMatches) -> i32 {
+    let (items, tags) = match (cli::items_dir(m), cli::tags_dir(m)) {
+        (Some(items), Some(tags)) if items != Path::new("-") => (items, tags),
+        _ => {
+            eprintln!("both an items dir and a tags dir are required");
+            return exit::USAGE;
+        }
+    };
+    let interval = match m.value_of("interval").unwrap().parse::<u64>() {
+        Ok(secs) if secs > 0 => Duration::from_secs(secs),
+        _ => {
+            eprintln!("invalid interval: {}", m.value_of("interval").unwrap());
+            return exit::USAGE;
+        }
+    };
+    let rules = match rules(&config::load()) {
+        Ok(rules) => rules,
+        Err(e) => {
+            eprintln!("{}", e);
+            return exit::USAGE;
+        }
+    };
+
+    let watch = match Watch::new(&items) {
+        Ok(watch) => Some(watch),
+        Err(e) => {
+            let why = format!("{} (only scanning every interval)", e);
+            log(format_args!("cannot watch {}: {}", items.display(), why));
+            None
+        }
+    };
+
+    // items there at startup are not new
+    let mut known = None;
+    loop {
+        match process(m, &items, &tags, &rules, known.as_ref()) {
+            Ok(found) => known = Some(found),
+            Err(e) => log(format_args!("scan skipped: {}", e)),
+        }
+        match watch {
+            Some(ref watch) => watch.wait(interval),
+            None => thread::sleep(interval),
+        }
+    }
+}
+
+/// Watch on the items dir, to scan as soon as something changes there
+#[cfg(target_os = "linux")]
+struct Watch {
+    /// the inotify instance
+    fd: libc::c_int,
+}
+
+#[cfg(target_os = "linux")]
+impl Watch {
+    fn new(dir: &Path) -> std::io::Result<Watch> {
+        use std::ffi::CString;
+        use std::io;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = CString::new(dir.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let watch = Watch { fd };
+        let mask = libc::IN_CREATE
+            | libc::IN_DELETE
+            | libc::IN_MOVED_FROM
+            | libc::IN_MOVED_TO
+            | libc::IN_CLOSE_WRITE
+            | libc::IN_DELETE_SELF
+            | libc::IN_MOVE_SELF;
+        if unsafe { libc::inotify_add_watch(fd, path.as_ptr(), mask) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(watch)
+    }
+
+    /// Wait until something changed in the dir, or `timeout` passed
+    fn wait(&self, timeout: Duration) {
+        let mut pfd = libc::pollfd {
+            fd: self.fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ms = timeout.as_millis().min(libc::c_int::max_value() as u128) as libc::c_int;
+        if unsafe { libc::poll(&mut pfd, 1, ms) } > 0 {
+            thread::sleep(SETTLE);
+            self.drain();
+        }
+    }
+
+    /// Read and forget the pending events
+    fn drain(&self) {
+        let mut buf = [0u8; 4096];
+        let ptr = buf.as_mut_ptr() as *mut libc::c_void;
+        while unsafe { libc::read(self.fd, ptr, buf.len()) } > 0 {}
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Watch {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+/// Without inotify, the dir is only scanned every interval
+#[cfg(not(target_os = "linux"))]
+struct Watch;
+
+#[cfg(not(target_os = "linux"))]
+impl Watch {
+    fn new(_dir: &Path) -> std::io::Result<Watch> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "watching is only supported on Linux",
+        ))
+    }
+
+    fn wait(&self, timeout: Duration) {
+        thread::sleep(timeout);
+    }
+}
@@ -0,0 +1,125 @@
+//! D-Bus service for desktop integration
+//!
+//! With `--dbus`, the running instance owns the name
+//! `org.linkorgasm.Linkorgasm` on the session bus and serves the
+//! `org.linkorgasm.Linkorgasm1` interface at `/org/linkorgasm/Linkorgasm`,
+//! so file manager scripts and desktop search tools can use the tag store:
+//!
+//! - `TagFile(s path, s tag)`, `UntagFile(s path, s tag)`
+//! - `GetTags(s path) -> as`: names of the tags of an item
+//! - `ListTags() -> as`: names of all tags
+//! - `Query(s query) -> as`: paths of the items matching a query
+//! - `Rescan()`: reload all tags from disk
+//! - signal `TagChanged(s path, s tag, b tagged)`, for every change
+//!
+//! Methods are handled like requests on the control socket (see `ipc`).
+//!
+//! Only compiled with the `dbus` feature.
+
+use std::path::Path;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+use cursive::Cursive;
+use serde_json::{json, Value};
+use zbus::{blocking, dbus_interface, fdo};
+
+use crate::handle::AppHandle;
+use crate::ipc::request_ui;
+use crate::AppState;
+
+static BUS_NAME: &str = "org.linkorgasm.Linkorgasm";
+static OBJECT_PATH: &str = "/org/linkorgasm/Linkorgasm";
+static INTERFACE: &str = "org.linkorgasm.Linkorgasm1";
+
+/// Connection to the session bus, held for the whole session
+pub struct Bus {
+    /// changes to send as signals: item, tag name and whether it was tagged
+    changes: Sender<(String, String, bool)>,
+}
+
+struct Service {
+    app: AppHandle,
+}
+
+impl Service {
+    /// Call a control method on the UI thread
+    fn call(&self, method: &str, params: Value) -> fdo::Result<Value> {
+        request_ui(&self.app, method.to_owned(), params)
+            .map_err(|(_, msg)| fdo::Error::Failed(msg))
+    }
+
+    /// Call a control method returning a list of strings
+    fn call_list(&self, method: &str, params: Value) -> fdo::Result<Vec<String>> {
+        let value = self.call(method, params)?;
+        Ok(value
+            .as_array()
+            .map(|a| a.iter().filter_map(|v| v.as_str().map(str::to_owned)).collect())
+            .unwrap_or_default())
+    }
+}
+
+#[dbus_interface(name = "org.linkorgasm.Linkorgasm1")]
+impl Service {
+    fn tag_file(&self, path: String, tag: String) -> fdo::Result<()> {
+        self.call("tag", json!({ "item": path, "tag": tag })).map(|_| ())
+    }
+
+    fn untag_file(&self, path: String, tag: String) -> fdo::Result<()> {
+        self.call("untag", json!({ "item": path, "tag": tag })).map(|_| ())
+    }
+
+    fn get_tags(&self, path: String) -> fdo::Result<Vec<String>> {
+        self.call_list("tags", json!({ "item": path }))
+    }
+
+    fn list_tags(&self) -> fdo::Result<Vec<String>> {
+        self.call_list("tags", Value::Null)
+    }
+
+    fn query(&self, query: String) -> fdo::Result<Vec<String>> {
+        self.call_list("query", json!({ "query": query }))
+    }
+
+    fn rescan(&self) -> fdo::Result<()> {
+        self.call("rescan", Value::Null).map(|_| ())
+    }
+}
+
+/// Register the service on the session bus
+///
+/// Signals are sent from a thread of their own, so the UI never waits for
+/// the bus.
+pub fn start(siv: &Cursive) -> zbus::Result<Bus> {
+    let service = Service {
+        app: AppHandle::new(siv),
+    };
+    let conn = blocking::ConnectionBuilder::session()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, service)?
+        .build()?;
+
+    let (changes, rx) = mpsc::channel::<(String, String, bool)>();
+    thread::spawn(move || {
+        for change in rx {
+            let _ = conn.emit_signal(None::<()>, OBJECT_PATH, INTERFACE, "TagChanged", &change);
+        }
+    });
+    Ok(Bus { changes })
+}
+
+/// Announce that an item was tagged or untagged
+///
+/// `tp` and `ip` are canonical paths of a known tag and item. Nothing is
+/// announced in dry-run mode, as the tags dir isn't changed.
+pub fn notify(state: &AppState, tp: &Path, ip: &Path, tagged: bool) {
+    let bus = match state.dbus {
+        Some(ref bus) => bus,
+        None => return,
+    };
+    if state.fs.is_dry_run() {
+        return;
+    }
+    let item = ip.to_string_lossy().into_owned();
+    let _ = bus.changes.send((item, state.tags[tp].name.clone(), tagged));
+}
@@ -0,0 +1,76 @@
+//! Deleting items to the trash
+//!
+//! Selected items are moved to the XDG trash ('D'), so they can be
+//! restored with a file manager. Their symlinks in the tag dirs are removed.
+//!
+//! Only compiled with the `trash` feature.
+
+use std::io;
+use std::path::Path;
+
+use cursive::views::{Dialog, ScrollView, TextView};
+use cursive::Cursive;
+
+use crate::manifest;
+use crate::{do_app, ui_error, ui_fill_itemview, ui_mark_tagsview, AppState, SharedPath};
+
+/// Move an item to the trash, then remove its symlinks and forget it
+///
+/// `ip` is the canonical path of a known item.
+pub fn delete_item(state: &mut AppState, ip: &Path) -> io::Result<()> {
+    state.fs.trash(ip)?;
+    let item = state.items_all.remove(ip).unwrap();
+    state.items_vis.remove(ip);
+    state.sel.remove(ip);
+    for sel in state.registers.values_mut() {
+        sel.remove(ip);
+    }
+    for tp in item.tags.iter() {
+        let tag = state.tags.get_mut(tp).unwrap();
+        if let Some(name) = tag.items.remove(ip) {
+            state.fs.remove_symlink(&tp.join(name), item.is_dir)?;
+            manifest::remove(state, tp, ip)?;
+        }
+    }
+    Ok(())
+}
+
+/// UI callback for the delete dialog
+fn ui_submit_delete(siv: &mut Cursive) {
+    siv.pop_layer();
+    do_app(siv, |siv, state| {
+        let mut sel: Vec<SharedPath> = state.sel.iter().cloned().collect();
+        sel.sort();
+        for ip in sel.iter() {
+            if let Err(e) = delete_item(state, ip) {
+                ui_fill_itemview(siv, state);
+                ui_mark_tagsview(siv, state);
+                return ui_error(siv, format!("{}: {}", ip.display(), e));
+            }
+        }
+        ui_fill_itemview(siv, state);
+        ui_mark_tagsview(siv, state);
+    });
+}
+
+/// Ask for confirmation to delete the selected items, listing them
+pub fn ui_build_delete(siv: &mut Cursive) {
+    let names: Vec<String> = do_app(siv, |_, state| {
+        let mut sel: Vec<&SharedPath> = state.sel.iter().collect();
+        sel.sort();
+        sel.iter().map(|ip| ip.display().to_string()).collect()
+    });
+    if names.is_empty() {
+        return siv.add_layer(Dialog::info(tr!("No items are selected.")));
+    }
+
+    let content = ScrollView::new(TextView::new(names.join("\n")));
+    siv.add_layer(
+        Dialog::around(content)
+            .title(tr!("Move {} items to the trash?", names.len()))
+            .button(tr!("Delete"), ui_submit_delete)
+            .button(tr!("Cancel"), |siv| {
+                siv.pop_layer();
+            }),
+    );
+}
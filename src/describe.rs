@@ -0,0 +1,118 @@
+//! `linkorgasm describe`: descriptions of images by a command
+//!
+//! Runs the `describe-command` (like `tesseract {} -` for the text in
+//! scans, or an image captioner) on each image item and stores its output
+//! as a sidecar file in `.linkorgasm/descriptions` in the tags dir, under
+//! the item's path in the items dir. The `text:` query term searches these
+//! along with the contents of items, so photos can be found by what they
+//! show. Images are only described again when they changed, unless
+//! `--force` is given.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+
+use crate::cli;
+use crate::content;
+use crate::exit;
+use crate::filetype::{self, Kind};
+use crate::{load_headless, AppState, SharedPath, META_DIR};
+
+/// Dir of the sidecars in the metadata dir
+pub static DESCRIPTIONS_DIR: &str = "descriptions";
+
+/// Sidecar of an item, `None` for items outside the items dir
+pub fn sidecar_path(state: &AppState, ip: &Path) -> Option<PathBuf> {
+    let rel = ip.strip_prefix(state.items_path.as_ref()?).ok()?;
+    let mut name = rel.as_os_str().to_owned();
+    name.push(".txt");
+    Some(state.tags_path.join(META_DIR).join(DESCRIPTIONS_DIR).join(name))
+}
+
+/// Whether an item changed since its sidecar was written, or has none
+fn is_stale(sidecar: &Path, ip: &Path) -> bool {
+    let modified = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+    match (modified(sidecar), modified(ip)) {
+        (Some(described), Some(changed)) => changed > described,
+        _ => true,
+    }
+}
+
+/// Run the command on an item and write its output to the sidecar
+fn describe(state: &mut AppState, cmdline: &str, ip: &Path) -> Result<(), String> {
+    let sidecar = match sidecar_path(state, ip) {
+        Some(sidecar) => sidecar,
+        None => return Err("not in the items directory".to_owned()),
+    };
+    let text = content::run(cmdline, ip).ok_or_else(|| format!("`{}` failed", cmdline))?;
+    if let Some(dir) = sidecar.parent() {
+        state.fs.create_meta_dir(dir).map_err(|e| e.to_string())?;
+    }
+    state.fs.write_file(&sidecar, text).map_err(|e| e.to_string())
+}
+
+/// Describe the images that changed since they were described (all with
+/// `force`), returns how many were and the errors
+pub fn describe_images(state: &mut AppState, cmdline: &str, force: bool) -> (usize, Vec<String>) {
+    let mut images: Vec<SharedPath> = state
+        .items_all
+        .iter()
+        .filter(|(_, item)| filetype::of(item) == Kind::Image)
+        .map(|(ip, _)| ip.clone())
+        .collect();
+    images.sort();
+    let mut described = 0;
+    let mut errors = Vec::new();
+    for ip in images {
+        match sidecar_path(state, &ip) {
+            Some(ref sidecar) if force || is_stale(sidecar, &ip) => {}
+            _ => continue,
+        }
+        match describe(state, cmdline, &ip) {
+            Ok(()) => described += 1,
+            Err(e) => errors.push(format!("{}: {}", ip.display(), e)),
+        }
+    }
+    (described, errors)
+}
+
+/// Entry point for `linkorgasm describe`, returns the exit code
+pub fn run(m: &ArgMatches) -> i32 {
+    let (items, tags) = match (cli::items_dir(m), cli::tags_dir(m)) {
+        (Some(items), Some(tags)) => (items, tags),
+        _ => {
+            eprintln!("both an items dir and a tags dir are required");
+            return exit::USAGE;
+        }
+    };
+    let mut state = match load_headless(m, Some(&items), &tags) {
+        Ok(state) => state,
+        Err(e) => return exit::load_failed(&tags, &e),
+    };
+    let cmdline = match state.config.describe_command.clone() {
+        Some(cmdline) => cmdline,
+        None => {
+            eprintln!("no describe-command is configured");
+            return exit::USAGE;
+        }
+    };
+
+    let (described, errors) = describe_images(&mut state, &cmdline, m.is_present("force"));
+    for e in errors.iter() {
+        eprintln!("{}", e);
+    }
+
+    for op in state.fs.log() {
+        println!("{}", op);
+    }
+    let verb = if state.fs.is_dry_run() {
+        "to describe"
+    } else {
+        "described"
+    };
+    if !cli::is_quiet(m) {
+        println!("{} images {}, {} errors", described, verb, errors.len());
+    }
+    exit::from_errors(errors.len())
+}
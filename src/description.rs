@@ -0,0 +1,100 @@
+//! Tag descriptions
+//!
+//! A tag's description is stored in a `.description` file inside the tag
+//! directory, so the criteria for what goes into a tag can be written down
+//! for everyone using the same tags tree. It is shown below the tags view
+//! while the tag is highlighted.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use cursive::traits::*;
+use cursive::views::{Dialog, TextArea, TextView};
+use cursive::Cursive;
+
+use crate::checklist::CheckList;
+use crate::fsops::FsOps;
+use crate::{do_app, ui_error};
+
+/// Name of the file holding the description in a tag directory
+pub static DESCRIPTION_FILE: &str = ".description";
+
+/// Read the description of a tag, `None` if it has none
+pub fn read(tag_dir: &Path) -> Option<String> {
+    fs::read_to_string(tag_dir.join(DESCRIPTION_FILE))
+        .ok()
+        .map(|s| s.trim_end().to_owned())
+        .filter(|s| !s.is_empty())
+}
+
+/// Set the description of a tag, or remove it if `text` is blank
+pub fn write(ops: &mut FsOps, tag_dir: &Path, text: &str) -> io::Result<()> {
+    let path = tag_dir.join(DESCRIPTION_FILE);
+    let text = text.trim_end();
+    if text.is_empty() {
+        match ops.remove_file(&path) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            r => r,
+        }
+    } else {
+        ops.write_file(&path, &format!("{}\n", text))
+    }
+}
+
+/// Show the description of a tag below the tags view
+///
+/// Called while the app state is taken, so it only reads the file.
+pub fn ui_show(siv: &mut Cursive, tag_dir: &Path) {
+    let text = read(tag_dir).unwrap_or_default();
+    siv.call_on_id("tagdescription", |v: &mut TextView| v.set_content(text));
+}
+
+/// Show the description of the highlighted tag (none if no tag is)
+pub fn ui_show_highlighted(siv: &mut Cursive) {
+    let tp = siv
+        .call_on_id("tagsview", |v: &mut CheckList<PathBuf>| v.selection())
+        .and_then(|tp| tp);
+    match tp {
+        Some(tp) => ui_show(siv, &tp),
+        None => {
+            siv.call_on_id("tagdescription", |v: &mut TextView| v.set_content(""));
+        }
+    }
+}
+
+/// Display UI Dialog for editing the highlighted tag's description
+pub fn ui_build_edit(siv: &mut Cursive) {
+    let tp = siv
+        .call_on_id("tagsview", |v: &mut CheckList<PathBuf>| v.selection())
+        .and_then(|tp| tp);
+    let tp = match tp {
+        Some(tp) => tp,
+        None => return,
+    };
+    let current = read(&tp).unwrap_or_default();
+    let area = TextArea::new()
+        .content(current)
+        .with_id("tagdescriptionedit")
+        .min_width(50)
+        .min_height(8);
+    siv.add_layer(
+        Dialog::around(area)
+            .title(tr!("Tag description (empty for none)"))
+            .button(tr!("Save"), move |siv| {
+                let text = siv
+                    .call_on_id("tagdescriptionedit", |v: &mut TextArea| {
+                        v.get_content().to_owned()
+                    })
+                    .unwrap();
+                siv.pop_layer();
+                do_app(siv, |siv, state| {
+                    if let Err(e) = write(&mut state.fs, &tp, &text) {
+                        ui_error(siv, e);
+                    }
+                });
+                ui_show(siv, &tp);
+            })
+            .dismiss_button(tr!("Cancel")),
+    );
+}
@@ -0,0 +1,103 @@
+//! Display escaping for file names
+//!
+//! File names are arbitrary bytes, but the UI shows strings. Names that are
+//! not valid UTF-8 or contain control characters are escaped like `ls -b`
+//! does (C-style escapes, octal for everything else), so that different
+//! names never render as the same mojibake. With the `escape-names` config
+//! setting, backslashes are escaped too and all names go through the same
+//! treatment, which makes the display unambiguous.
+
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::str;
+
+/// Get the string to show in the UI for a file name or path
+pub fn display_name(name: &OsStr, escape_all: bool) -> String {
+    let bytes = name.as_bytes();
+    match str::from_utf8(bytes) {
+        Ok(s) if !escape_all && !s.chars().any(char::is_control) => s.to_owned(),
+        _ => escape_bytes(bytes, escape_all),
+    }
+}
+
+/// Escape a name, and with `backslashes` the backslashes in it too
+fn escape_bytes(mut bytes: &[u8], backslashes: bool) -> String {
+    let mut out = String::new();
+    loop {
+        match str::from_utf8(bytes) {
+            Ok(s) => {
+                escape_str(&mut out, s, backslashes);
+                return out;
+            }
+            Err(e) => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+                escape_str(&mut out, str::from_utf8(valid).unwrap(), backslashes);
+                let bad = e.error_len().unwrap_or_else(|| rest.len());
+                for b in &rest[..bad] {
+                    write!(out, "\\{:03o}", b).unwrap();
+                }
+                bytes = &rest[bad..];
+            }
+        }
+    }
+}
+
+fn escape_str(out: &mut String, s: &str, backslashes: bool) {
+    for c in s.chars() {
+        match c {
+            '\\' if backslashes => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_control() => {
+                let mut buf = [0; 4];
+                for b in c.encode_utf8(&mut buf).bytes() {
+                    write!(out, "\\{:03o}", b).unwrap();
+                }
+            }
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(bytes: &[u8]) -> &OsStr {
+        OsStr::from_bytes(bytes)
+    }
+
+    #[test]
+    fn plain_names_are_unchanged() {
+        assert_eq!(display_name(name(b"caf\xc3\xa9.txt"), false), "café.txt");
+    }
+
+    #[test]
+    fn invalid_utf8_is_octal() {
+        assert_eq!(display_name(name(b"a\xffb\xc3"), false), "a\\377b\\303");
+        assert_eq!(display_name(name(b"\xe9t\xe9"), false), "\\351t\\351");
+    }
+
+    #[test]
+    fn control_chars_are_escaped() {
+        assert_eq!(display_name(name(b"a\nb\tc\rd"), false), "a\\nb\\tc\\rd");
+        assert_eq!(display_name(name(b"bell\x07"), false), "bell\\007");
+        // C1 controls are two bytes in UTF-8
+        assert_eq!(display_name(name(b"\xc2\x85"), false), "\\302\\205");
+    }
+
+    #[test]
+    fn backslashes_are_escaped_only_with_escape_all() {
+        assert_eq!(display_name(name(b"a\\b"), false), "a\\b");
+        assert_eq!(display_name(name(b"a\\b\n"), false), "a\\b\\n");
+        assert_eq!(display_name(name(b"a\\b"), true), "a\\\\b");
+        assert_eq!(display_name(name(b"a\\b\xff"), true), "a\\\\b\\377");
+    }
+
+    #[test]
+    fn escape_all_keeps_printable_names() {
+        assert_eq!(display_name(name(b"caf\xc3\xa9 1.txt"), true), "café 1.txt");
+    }
+}
@@ -0,0 +1,59 @@
+//! Exit codes of the subcommands
+//!
+//! - `OK` (0): everything was done
+//! - `FAILED` (1): something failed and was reported, the rest was done
+//! - `USAGE` (2): bad arguments (like a missing directory or an invalid
+//!   query), nothing was done
+//! - `INCONSISTENT` (3): the command succeeded, but the tags directory has
+//!   dangling symlinks or tag dirs that can't be read; only reported by
+//!   the commands checking for those (`stats`, `gc`, `maintain`)
+//!
+//! A failure counts before an inconsistency.
+
+use std::path::Path;
+
+use crate::AppState;
+
+pub const OK: i32 = 0;
+pub const FAILED: i32 = 1;
+pub const USAGE: i32 = 2;
+pub const INCONSISTENT: i32 = 3;
+
+/// `FAILED` if there were errors, `OK` otherwise
+pub fn from_errors(errors: usize) -> i32 {
+    if errors > 0 {
+        FAILED
+    } else {
+        OK
+    }
+}
+
+/// Whether the tags dir has dangling symlinks or unreadable tag dirs
+pub fn is_inconsistent(state: &AppState) -> bool {
+    state.foreign.iter().any(|f| f.target.is_none()) || !state.skipped_tags.is_empty()
+}
+
+/// Exit code of a command checking the tags dir, after it ran with
+/// `errors` errors
+pub fn checked(state: &AppState, errors: usize) -> i32 {
+    if errors > 0 {
+        FAILED
+    } else if is_inconsistent(state) {
+        INCONSISTENT
+    } else {
+        OK
+    }
+}
+
+/// Report that the library couldn't be loaded, returns the exit code
+///
+/// A tags dir that doesn't exist is a bad argument, other errors (like a
+/// lock held by another instance) are failures.
+pub fn load_failed(tags: &Path, e: &str) -> i32 {
+    eprintln!("{}", e);
+    if tags.is_dir() {
+        FAILED
+    } else {
+        USAGE
+    }
+}
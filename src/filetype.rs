@@ -0,0 +1,98 @@
+//! Types of items, like image or audio
+//!
+//! Guessed from the extension, grouped like the MIME types of the files;
+//! directories are a type of their own. Used for the icons of the items
+//! view and the `type:` query term.
+
+use std::path::Path;
+
+use crate::Item;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Dir,
+    Image,
+    Video,
+    Audio,
+    Document,
+    Archive,
+    Code,
+    Text,
+    Other,
+}
+
+/// Names of the types in queries
+static KIND_NAMES: &[(&str, Kind)] = &[
+    ("dir", Kind::Dir),
+    ("image", Kind::Image),
+    ("video", Kind::Video),
+    ("audio", Kind::Audio),
+    ("document", Kind::Document),
+    ("archive", Kind::Archive),
+    ("code", Kind::Code),
+    ("text", Kind::Text),
+    ("other", Kind::Other),
+];
+
+/// Extensions of each type, lowercase
+static EXTENSIONS: &[(Kind, &[&str])] = &[
+    (
+        Kind::Image,
+        &[
+            "jpg", "jpeg", "png", "gif", "webp", "bmp", "tif", "tiff", "svg", "heic", "avif",
+            "raw", "cr2", "nef", "psd", "xcf", "ico",
+        ],
+    ),
+    (
+        Kind::Video,
+        &["mp4", "mkv", "webm", "avi", "mov", "wmv", "flv", "m4v", "mpg", "mpeg", "ts"],
+    ),
+    (
+        Kind::Audio,
+        &["mp3", "flac", "ogg", "opus", "wav", "m4a", "aac", "wma", "aiff", "mid"],
+    ),
+    (
+        Kind::Document,
+        &[
+            "pdf", "epub", "djvu", "doc", "docx", "odt", "rtf", "xls", "xlsx", "ods", "ppt",
+            "pptx", "odp",
+        ],
+    ),
+    (
+        Kind::Archive,
+        &["zip", "tar", "gz", "tgz", "bz2", "xz", "zst", "7z", "rar", "iso", "deb", "rpm"],
+    ),
+    (
+        Kind::Code,
+        &[
+            "rs", "c", "h", "cpp", "hpp", "py", "js", "go", "java", "rb", "sh", "pl",
+            "lua", "html", "css", "json", "toml", "yaml", "yml", "xml",
+        ],
+    ),
+    (Kind::Text, &["txt", "md", "rst", "org", "tex", "log", "csv", "nfo"]),
+];
+
+/// Type of an item
+pub fn of(item: &Item) -> Kind {
+    if item.is_dir {
+        return Kind::Dir;
+    }
+    let ext = match Path::new(&item.filename).extension() {
+        Some(ext) => ext.to_string_lossy().to_lowercase(),
+        None => return Kind::Other,
+    };
+    EXTENSIONS
+        .iter()
+        .find(|(_, exts)| exts.contains(&ext.as_str()))
+        .map_or(Kind::Other, |(kind, _)| *kind)
+}
+
+impl Kind {
+    /// A type by its name in queries, like `image`
+    pub fn from_name(name: &str) -> Option<Kind> {
+        KIND_NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, kind)| *kind)
+    }
+}
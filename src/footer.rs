@@ -0,0 +1,119 @@
+//! One-line footer with the keys of the focused pane
+//!
+//! The panes are wrapped in `Tracked`, which notes whether they are drawn
+//! focused. The footer is drawn after them and shows the main keys of the
+//! pane that was, or those of dialogs if neither was (a dialog is on top).
+//! The help screen ('h') lists all keys.
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use cursive::theme::{ColorStyle, Effect};
+use cursive::utils::markup::StyledString;
+use cursive::view::{View, ViewWrapper};
+use cursive::{wrap_impl, Printer, Vec2};
+
+/// A pane of the main window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pane {
+    Items,
+    Tags,
+}
+
+/// The pane drawn focused in the current frame, if any
+pub type Focus = Rc<Cell<Option<Pane>>>;
+
+/// Keys of the items view, with what they do
+static ITEMS_KEYS: &[(&str, &str)] = &[
+    ("space", "select"),
+    ("enter", "edit tags"),
+    ("f", "query"),
+    ("u", "untagged"),
+    ("r", "show all"),
+    ("t", "to tags"),
+    ("tab", "tags pane"),
+    ("h", "help"),
+];
+
+/// Keys of the tags view, with what they do
+static TAGS_KEYS: &[(&str, &str)] = &[
+    ("space", "toggle on selection"),
+    ("+", "new tag"),
+    ("d", "describe"),
+    ("c", "color"),
+    ("s", "sort"),
+    ("i", "to items"),
+    ("tab", "items pane"),
+    ("h", "help"),
+];
+
+/// Keys of dialogs, with what they do
+static DIALOG_KEYS: &[(&str, &str)] = &[
+    ("tab/arrows", "move between fields and buttons"),
+    ("enter", "press button"),
+];
+
+/// Marks the focus for the footer when drawn focused
+pub struct Tracked<V> {
+    view: V,
+    pane: Pane,
+    focus: Focus,
+}
+
+impl<V: View> Tracked<V> {
+    pub fn new(view: V, pane: Pane, focus: Focus) -> Self {
+        Tracked { view, pane, focus }
+    }
+}
+
+impl<V: View> ViewWrapper for Tracked<V> {
+    wrap_impl!(self.view: V);
+
+    fn wrap_draw(&self, printer: &Printer) {
+        if printer.focused {
+            self.focus.set(Some(self.pane));
+        }
+        self.view.draw(printer);
+    }
+}
+
+/// The footer line
+pub struct Footer {
+    focus: Focus,
+    /// the quit key, as configured
+    quit_key: String,
+}
+
+impl Footer {
+    pub fn new(focus: Focus, quit_key: String) -> Self {
+        Footer { focus, quit_key }
+    }
+}
+
+impl View for Footer {
+    fn draw(&self, printer: &Printer) {
+        // the panes set it again while drawing the next frame
+        let pane = self.focus.replace(None);
+        let keys = match pane {
+            Some(Pane::Items) => ITEMS_KEYS,
+            Some(Pane::Tags) => TAGS_KEYS,
+            None => DIALOG_KEYS,
+        };
+        let mut keys: Vec<(&str, &str)> = keys.to_vec();
+        if pane.is_some() {
+            keys.push((&self.quit_key, "quit"));
+        }
+        let mut line = StyledString::new();
+        for (key, action) in keys {
+            line.append_styled(key, Effect::Bold);
+            line.append_plain(format!(" {}  ", tr!(action)));
+        }
+        printer.with_color(ColorStyle::secondary(), |printer| {
+            printer.print_styled((0, 0), (&line).into());
+        });
+    }
+
+    fn required_size(&mut self, _: Vec2) -> Vec2 {
+        Vec2::new(1, 1)
+    }
+}
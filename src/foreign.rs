@@ -0,0 +1,141 @@
+//! Review of foreign and dangling symlinks found in tag dirs
+//!
+//! These are symlinks that don't point to a known item: either their
+//! target doesn't exist anymore, or it is outside the items directory.
+
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use cursive::traits::*;
+use cursive::views::{Dialog, ScrollView, SelectView};
+use cursive::Cursive;
+
+use hashbrown::HashSet;
+
+use crate::escape::display_name;
+use crate::{
+    do_app, item_key, item_stat, tag_key, ui_error, ui_refresh_itemview, AppState, Item,
+    SharedPath,
+};
+
+#[derive(Debug)]
+pub struct ForeignLink {
+    /// path to the symlink
+    pub link: PathBuf,
+    /// canonical path of the tag dir containing the symlink
+    pub tag: PathBuf,
+    /// canonical path of the target (`None` if dangling)
+    pub target: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy)]
+enum Action {
+    /// add the target as an item (tagged with the link's tag)
+    Adopt,
+    /// delete the symlink
+    Delete,
+    /// leave it alone
+    Ignore,
+}
+
+/// Add the target of a foreign link to the items index, or the tag to
+/// the item if the target is known already (another link adopted it)
+fn adopt(state: &mut AppState, f: &ForeignLink) -> Result<(), String> {
+    let target = match f.target {
+        Some(ref target) => target,
+        None => return Err("cannot adopt a dangling symlink".to_owned()),
+    };
+    let ip: SharedPath = item_key(state, target).unwrap_or_else(|| target.as_path().into());
+    let tp = tag_key(state, &f.tag);
+    let escape = state.config.escape_names;
+    let item = state.items_all.entry(Rc::clone(&ip)).or_insert_with(|| {
+        let filename = target.file_name().unwrap().to_owned();
+        let (size, mtime) = item_stat(target);
+        Item {
+            name: display_name(&filename, escape),
+            filename,
+            is_dir: target.is_dir(),
+            size,
+            mtime,
+            tags: HashSet::default(),
+        }
+    });
+    item.tags.extend(tp);
+    state.items_vis.insert(Rc::clone(&ip));
+    if let Some(tag) = state.tags.get_mut(f.tag.as_path()) {
+        tag.items.insert(ip, f.link.file_name().unwrap().to_owned());
+    }
+    Ok(())
+}
+
+/// UI callback for the review dialog buttons
+fn resolve(siv: &mut Cursive, action: Action) {
+    let link = siv
+        .call_on_id("foreignview", |v: &mut SelectView<PathBuf>| v.selection())
+        .unwrap();
+    let link = match link {
+        Some(link) => link,
+        None => return,
+    };
+
+    do_app(siv, |siv, state| {
+        let idx = match state.foreign.iter().position(|f| f.link == *link) {
+            Some(idx) => idx,
+            None => return,
+        };
+        let f = state.foreign.remove(idx);
+        let result = match action {
+            Action::Adopt => adopt(state, &f),
+            Action::Delete => state.fs.remove_file(&f.link).map_err(|e| e.to_string()),
+            Action::Ignore => Ok(()),
+        };
+        if let Err(e) = result {
+            state.foreign.insert(idx, f);
+            return ui_error(siv, e);
+        }
+
+        siv.call_on_id("foreignview", |v: &mut SelectView<PathBuf>| {
+            if let Some(i) = v.selected_id() {
+                v.remove_item(i);
+            }
+        });
+        if state.foreign.is_empty() {
+            siv.pop_layer();
+        }
+        if let Action::Adopt = action {
+            ui_refresh_itemview(siv, state);
+        }
+    });
+}
+
+/// Show dialog for reviewing foreign symlinks, if there are any
+pub fn ui_review(siv: &mut Cursive, state: &mut AppState) {
+    if state.foreign.is_empty() {
+        return;
+    }
+
+    let escape_all = state.config.escape_names;
+    let mut list = SelectView::<PathBuf>::new();
+    for f in state.foreign.iter() {
+        let target = match f.target {
+            Some(ref target) => display_name(target.as_os_str(), escape_all),
+            None => "(dangling)".to_owned(),
+        };
+        list.add_item(
+            format!("{} -> {}", display_name(f.link.as_os_str(), escape_all), target),
+            f.link.clone(),
+        );
+    }
+    list.sort_by_label();
+
+    siv.add_layer(
+        Dialog::around(ScrollView::new(list.with_id("foreignview")).scroll_x(true))
+            .title(tr!("Symlinks to unknown items"))
+            .button(tr!("Adopt"), |siv| resolve(siv, Action::Adopt))
+            .button(tr!("Delete"), |siv| resolve(siv, Action::Delete))
+            .button(tr!("Ignore"), |siv| resolve(siv, Action::Ignore))
+            .button(tr!("Close"), |siv| {
+                siv.pop_layer();
+            }),
+    );
+}
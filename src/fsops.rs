@@ -0,0 +1,311 @@
+//! Filesystem mutations
+//!
+//! Every change to the tags tree (and moves of items) goes through `FsOps`.
+//!
+//! With `--dry-run`, operations are logged (to a panel in the main window,
+//! and optionally to a file) instead of executed, so a big reorganization
+//! can be rehearsed. The in-memory indexes are updated either way.
+//!
+//! Changes that are made are recorded in the tags dir's audit log.
+//!
+//! In read-only mode (`--read-only`, or a tags dir the user can't write
+//! to), every change is refused up front, with an error saying so. That
+//! includes linkorgasm's own files in `.linkorgasm` (manifests, inodes,
+//! descriptions); the lock, audit log and journal aren't opened at all.
+
+use std::env;
+use std::ffi::{CString, OsString};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use cursive::views::TextView;
+use cursive::Cursive;
+use log::{debug, warn};
+
+use crate::audit;
+
+#[derive(Default)]
+pub struct FsOps {
+    dry_run: bool,
+    /// refuse all changes
+    read_only: bool,
+    /// operations that were not executed
+    log: Vec<String>,
+    /// number of log entries already shown in the panel
+    shown: usize,
+    /// file the log is also appended to
+    log_file: Option<File>,
+    /// audit logs of the tags dir and the extra tag roots, by the
+    /// canonical path of their root; entries are relative to it
+    audits: Vec<(PathBuf, File)>,
+}
+
+#[cfg(unix)]
+fn make_symlink(target: &Path, link: &Path, _is_dir: bool) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+/// Windows distinguishes between symlinks to files and to directories.
+#[cfg(windows)]
+fn make_symlink(target: &Path, link: &Path, is_dir: bool) -> io::Result<()> {
+    if is_dir {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
+#[cfg(unix)]
+fn remove_symlink(link: &Path, _is_dir: bool) -> io::Result<()> {
+    fs::remove_file(link)
+}
+
+#[cfg(windows)]
+fn remove_symlink(link: &Path, is_dir: bool) -> io::Result<()> {
+    if is_dir {
+        fs::remove_dir(link)
+    } else {
+        fs::remove_file(link)
+    }
+}
+
+impl FsOps {
+    pub fn new(dry_run: bool, log_path: Option<&Path>) -> io::Result<FsOps> {
+        let log_file = match log_path {
+            Some(path) => Some(OpenOptions::new().create(true).append(true).open(path)?),
+            None => None,
+        };
+        Ok(FsOps {
+            dry_run,
+            log_file,
+            ..FsOps::default()
+        })
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Operations logged so far (only in dry-run mode)
+    pub fn log(&self) -> &[String] {
+        &self.log
+    }
+
+    /// Start recording changes in the audit logs of a tags dir and of
+    /// extra tag `roots`
+    ///
+    /// A change goes to the log of the root it was made in; changes outside
+    /// of all of them (like moved items) go to the tags dir's. Nothing is
+    /// recorded (or created) in dry-run and read-only mode.
+    pub fn open_audit(&mut self, tags_path: &Path, roots: &[PathBuf]) -> io::Result<()> {
+        self.audits.clear();
+        if self.dry_run || self.read_only {
+            return Ok(());
+        }
+        for root in std::iter::once(tags_path).chain(roots.iter().map(PathBuf::as_path)) {
+            self.audits.push((root.canonicalize()?, audit::open(root)?));
+        }
+        Ok(())
+    }
+
+    /// Add an entry for a change that was made to the audit log
+    fn record(&mut self, action: &str, path: &Path, target: Option<&Path>) {
+        // the innermost root, if a root is inside another
+        let i = self
+            .audits
+            .iter()
+            .enumerate()
+            .filter(|(_, (root, _))| path.starts_with(root))
+            .max_by_key(|(_, (root, _))| root.as_os_str().len())
+            .map_or(0, |(i, _)| i);
+        let (root, file) = match self.audits.get_mut(i) {
+            Some((root, file)) => (&*root, file),
+            None => return,
+        };
+        let path = path.strip_prefix(root).unwrap_or(path);
+        let target = match target {
+            Some(target) => format!(" -> {}", target.display()),
+            None => String::new(),
+        };
+        // the change itself was made, so it must not be reported as failed
+        let _ = writeln!(
+            file,
+            "{} {} {}{}",
+            Local::now().to_rfc3339(),
+            action,
+            path.display(),
+            target
+        );
+    }
+
+    /// Execute an operation, or just log its description in dry-run mode
+    fn run(&mut self, desc: String, op: impl FnOnce() -> io::Result<()>) -> io::Result<()> {
+        if self.read_only && !self.dry_run {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("read-only mode, not done: {}", desc),
+            ));
+        }
+        if !self.dry_run {
+            debug!("{}", desc);
+            return op().map_err(|e| {
+                warn!("{}: {}", desc, e);
+                e
+            });
+        }
+        if let Some(ref mut file) = self.log_file {
+            writeln!(file, "{}", desc)?;
+        }
+        self.log.push(desc);
+        Ok(())
+    }
+
+    /// Create a symlink to an item
+    pub fn symlink(&mut self, target: &Path, link: &Path, is_dir: bool) -> io::Result<()> {
+        let desc = format!("ln -s {} {}", target.display(), link.display());
+        self.run(desc, || make_symlink(target, link, is_dir))?;
+        self.record("link-add", link, Some(target));
+        Ok(())
+    }
+
+    /// Remove a symlink to an item
+    pub fn remove_symlink(&mut self, link: &Path, is_dir: bool) -> io::Result<()> {
+        let desc = format!("rm {}", link.display());
+        self.run(desc, || remove_symlink(link, is_dir))?;
+        self.record("link-remove", link, None);
+        Ok(())
+    }
+
+    /// Point a symlink somewhere else, by making the new link next to it
+    /// and renaming it over the old one, so the link is never missing
+    pub fn replace_symlink(&mut self, target: &Path, link: &Path, is_dir: bool) -> io::Result<()> {
+        let desc = format!("ln -sf {} {}", target.display(), link.display());
+        self.run(desc, || {
+            let mut name = OsString::from(".");
+            name.push(link.file_name().unwrap_or_default());
+            name.push(".linkorgasm-tmp");
+            let tmp = link.with_file_name(name);
+            make_symlink(target, &tmp, is_dir)?;
+            fs::rename(&tmp, link).map_err(|e| {
+                let _ = remove_symlink(&tmp, is_dir);
+                e
+            })
+        })?;
+        self.record("link-replace", link, Some(target));
+        Ok(())
+    }
+
+    /// Create a directory and its missing parents
+    pub fn create_dir(&mut self, path: &Path) -> io::Result<()> {
+        let desc = format!("mkdir -p {}", path.display());
+        self.run(desc, || fs::DirBuilder::new().recursive(true).create(path))?;
+        self.record("tag-create", path, None);
+        Ok(())
+    }
+
+    /// Create a dir for linkorgasm's own files (like `.linkorgasm`) and its
+    /// missing parents, unless it exists
+    pub fn create_meta_dir(&mut self, path: &Path) -> io::Result<()> {
+        if path.is_dir() {
+            return Ok(());
+        }
+        let desc = format!("mkdir -p {}", path.display());
+        self.run(desc, || fs::create_dir_all(path))
+    }
+
+    /// Remove an empty directory
+    pub fn remove_dir(&mut self, path: &Path) -> io::Result<()> {
+        let desc = format!("rmdir {}", path.display());
+        self.run(desc, || fs::remove_dir(path))?;
+        self.record("tag-delete", path, None);
+        Ok(())
+    }
+
+    /// Rename or move an item
+    pub fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        let desc = format!("mv {} {}", from.display(), to.display());
+        self.run(desc, || fs::rename(from, to))?;
+        self.record("item-move", from, Some(to));
+        Ok(())
+    }
+
+    /// Move an item to the trash
+    #[cfg(feature = "trash")]
+    pub fn trash(&mut self, path: &Path) -> io::Result<()> {
+        let desc = format!("trash {}", path.display());
+        self.run(desc, || {
+            trash::delete(path).map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))
+        })?;
+        self.record("item-trash", path, None);
+        Ok(())
+    }
+
+    pub fn write_file(&mut self, path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+        let desc = format!("write {}", path.display());
+        self.run(desc, || fs::write(path, contents))?;
+        self.record("file-write", path, None);
+        Ok(())
+    }
+
+    pub fn remove_file(&mut self, path: &Path) -> io::Result<()> {
+        let desc = format!("rm {}", path.display());
+        self.run(desc, || fs::remove_file(path))?;
+        self.record("file-remove", path, None);
+        Ok(())
+    }
+}
+
+/// Whether the user may create and remove entries in a directory
+pub fn is_writable(dir: &Path) -> bool {
+    let path = match CString::new(dir.as_os_str().as_bytes()) {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+    unsafe { libc::access(path.as_ptr(), libc::W_OK | libc::X_OK) == 0 }
+}
+
+/// Create a private dir (mode 0700) with a new random name in the temp dir,
+/// with `mkdtemp`
+///
+/// It never is a dir that existed before, so nobody else can have put
+/// anything into it.
+pub fn create_temp_dir(prefix: &str) -> io::Result<PathBuf> {
+    let template = env::temp_dir().join(format!("{}-XXXXXX", prefix));
+    let template = CString::new(template.into_os_string().into_vec())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let raw = template.into_raw();
+    let res = unsafe { libc::mkdtemp(raw) };
+    // mkdtemp replaced the Xs in place
+    let path = unsafe { CString::from_raw(raw) };
+    if res.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(OsString::from_vec(path.into_bytes()).into())
+}
+
+/// Append operations logged since the last call to the dry-run panel
+pub fn ui_show_log(siv: &mut Cursive, fs: &mut FsOps) {
+    if fs.shown == fs.log.len() {
+        return;
+    }
+    let new = &fs.log[fs.shown..];
+    let shown = siv.call_on_id("dryrunlog", |v: &mut TextView| {
+        for line in new {
+            v.append(format!("{}\n", line));
+        }
+    });
+    if shown.is_some() {
+        fs.shown = fs.log.len();
+    }
+}
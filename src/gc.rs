@@ -0,0 +1,235 @@
+//! Tidying up the tags tree
+//!
+//! After heavy untagging, tag directories may be left without any symlinks,
+//! and after deleting items, tag directories fill with dangling symlinks.
+//! These can be found and deleted here.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+use cursive::views::{Dialog, ScrollView, TextView};
+use cursive::Cursive;
+
+use crate::cli;
+use crate::color::COLOR_FILE;
+use crate::description::DESCRIPTION_FILE;
+use crate::exit;
+use crate::journal;
+use crate::manifest::{self, MANIFEST_FILE};
+use crate::{do_app, load_exclusive, ui_error, ui_refresh_tagsview, AppState};
+
+/// Whether a tag has no items and contains nothing but empty tags
+///
+/// `.color` and `.description` files and empty manifests don't count,
+/// anything else (like foreign symlinks, unrelated files or manifests
+/// listing items that aren't here) keeps the tag.
+fn is_empty_tag(state: &AppState, tp: &Path) -> bool {
+    match state.tags.get(tp) {
+        Some(tag) if tag.items.is_empty() => {}
+        _ => return false,
+    }
+    let entries = match fs::read_dir(tp) {
+        Ok(entries) => entries,
+        Err(_) => return false,
+    };
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(_) => return false,
+        };
+        if entry.file_name() == COLOR_FILE || entry.file_name() == DESCRIPTION_FILE {
+            continue;
+        }
+        if entry.file_name() == MANIFEST_FILE && manifest::is_empty(tp) {
+            continue;
+        }
+        match entry.file_type() {
+            Ok(t) if t.is_dir() => {
+                if !is_empty_tag(state, &entry.path()) {
+                    return false;
+                }
+            }
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Canonical paths of all empty tags, nested ones before their parents
+pub fn empty_tags(state: &AppState) -> Vec<PathBuf> {
+    let mut empty: Vec<PathBuf> = state
+        .tags
+        .keys()
+        .filter(|tp| is_empty_tag(state, tp))
+        .map(|tp| tp.to_path_buf())
+        .collect();
+    empty.sort_by(|a, b| b.components().count().cmp(&a.components().count()));
+    empty
+}
+
+/// Delete an empty tag dir and remove it from the tags index
+pub fn remove_tag(state: &mut AppState, tp: &Path) -> io::Result<()> {
+    for file in [COLOR_FILE, DESCRIPTION_FILE, MANIFEST_FILE].iter() {
+        let path = tp.join(file);
+        if path.exists() {
+            state.fs.remove_file(&path)?;
+        }
+    }
+    state.fs.remove_dir(tp)?;
+    journal::record_tag(state, "tag-delete", tp);
+    state.tags.remove(tp);
+    Ok(())
+}
+
+/// Why deleting the dangling symlinks looks like a mistake, if it does
+///
+/// For commands running with nobody watching: if the items dir is missing
+/// (like an unmounted disk), no items are found and every link dangles,
+/// and deleting them all would empty the tags tree. Without an items dir,
+/// only the share of dangling links tells.
+pub fn check_dangling(state: &AppState) -> Result<(), String> {
+    let dangling = state.foreign.iter().filter(|f| f.target.is_none()).count();
+    if dangling == 0 {
+        return Ok(());
+    }
+    if state.items_path.is_some() && state.items_all.is_empty() {
+        return Err(format!(
+            "no items found, not removing {} dangling symlinks (is the items dir mounted?)",
+            dangling
+        ));
+    }
+    let linked: usize = state.tags.values().map(|tag| tag.items.len()).sum();
+    let links = linked + state.foreign.len();
+    if dangling * 2 > links {
+        return Err(format!(
+            "{} of {} symlinks are dangling, not removing them (is the items dir mounted?)",
+            dangling, links
+        ));
+    }
+    Ok(())
+}
+
+/// Delete the dangling symlinks found in the tags tree
+///
+/// Returns how many were deleted, and the ones that couldn't be.
+pub fn remove_dangling(state: &mut AppState) -> (usize, Vec<(PathBuf, io::Error)>) {
+    let dangling: Vec<PathBuf> = state
+        .foreign
+        .iter()
+        .filter(|f| f.target.is_none())
+        .map(|f| f.link.clone())
+        .collect();
+    let mut removed = 0;
+    let mut errors = Vec::new();
+    for link in dangling {
+        match state.fs.remove_file(&link) {
+            Ok(()) => removed += 1,
+            Err(e) => errors.push((link, e)),
+        }
+    }
+    state.foreign.retain(|f| f.target.is_some());
+    (removed, errors)
+}
+
+/// UI callback for deleting the empty tags
+fn ui_submit_empty_tags(siv: &mut Cursive) {
+    siv.pop_layer();
+    do_app(siv, |siv, state| {
+        for tp in empty_tags(state) {
+            if let Err(e) = remove_tag(state, &tp) {
+                ui_refresh_tagsview(siv, state);
+                return ui_error(siv, format!("{}: {}", tp.display(), e));
+            }
+        }
+        ui_refresh_tagsview(siv, state);
+    });
+}
+
+/// Show the empty tags and offer to delete them
+pub fn ui_empty_tags(siv: &mut Cursive) {
+    let names: Vec<String> = do_app(siv, |_, state| {
+        let mut names: Vec<String> = empty_tags(state)
+            .iter()
+            .map(|tp| state.tags[tp.as_path()].name.clone())
+            .collect();
+        names.sort();
+        names
+    });
+    if names.is_empty() {
+        return siv.add_layer(Dialog::info(tr!("There are no empty tags.")));
+    }
+
+    let content = ScrollView::new(TextView::new(names.join("\n")));
+    siv.add_layer(
+        Dialog::around(content)
+            .title(tr!("Delete {} empty tags?", names.len()))
+            .button(tr!("Delete"), ui_submit_empty_tags)
+            .button(tr!("Cancel"), |siv| {
+                siv.pop_layer();
+            }),
+    );
+}
+
+/// Entry point for `linkorgasm gc`, returns the exit code
+pub fn run(m: &ArgMatches) -> i32 {
+    let tags = match cli::tags_dir(m) {
+        Some(tags) => tags,
+        None => {
+            eprintln!("a tags dir is required");
+            return exit::USAGE;
+        }
+    };
+    let items = cli::items_dir(m);
+    let mut state = match load_exclusive(m, items.as_deref(), &tags) {
+        Ok(state) => state,
+        Err(e) => return exit::load_failed(&tags, &e),
+    };
+
+    let mut errors = 0;
+    let links = match check_dangling(&state) {
+        Err(e) if !m.is_present("force") => {
+            eprintln!("{}, use --force to remove them anyway", e);
+            errors += 1;
+            0
+        }
+        _ => {
+            let (links, failed) = remove_dangling(&mut state);
+            errors += failed.len();
+            for (link, e) in failed {
+                eprintln!("{}: {}", link.display(), e);
+            }
+            links
+        }
+    };
+
+    let mut empty = 0;
+    if m.is_present("empty-tags") {
+        for tp in empty_tags(&state) {
+            match remove_tag(&mut state, &tp) {
+                Ok(()) => empty += 1,
+                Err(e) => {
+                    eprintln!("{}: {}", tp.display(), e);
+                    errors += 1;
+                }
+            }
+        }
+    }
+
+    for op in state.fs.log() {
+        println!("{}", op);
+    }
+    let verb = if state.fs.is_dry_run() {
+        "to remove"
+    } else {
+        "removed"
+    };
+    if !cli::is_quiet(m) {
+        println!(
+            "{} dangling symlinks and {} empty tags {}, {} errors",
+            links, empty, verb, errors
+        );
+    }
+    exit::checked(&state, errors)
+}
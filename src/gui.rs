@@ -0,0 +1,362 @@
+//! `linkorgasm gui`: graphical frontend
+//!
+//! A window with the tags on the left and the items as a grid of
+//! thumbnails, for collections of pictures. It works on the same state as
+//! the terminal UI, in the same process, and tags through the same code, so
+//! the tags dir stays the plain symlink store (hooks, manifests, the audit
+//! log and `--dry-run` all apply). Files dropped on the window are selected
+//! if they are items.
+//!
+//! Built with the `gui` feature (not enabled by default), as it pulls in
+//! `eframe` and `image`.
+
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+use clap::ArgMatches;
+use eframe::egui;
+use hashbrown::{HashMap, HashSet};
+
+use crate::cli;
+use crate::i18n;
+use crate::{
+    item_key, load_headless, natsort, query, sorted_tags, toggle_tag_path, AppState, SharedPath,
+};
+
+/// Size of the thumbnails, in pixels
+const THUMB: u32 = 128;
+
+/// Height of an item's name under its thumbnail, in pixels
+const LABEL: f32 = 24.0;
+
+/// Thumbnails asked for and not decoded yet, so scrolling past a big dir
+/// doesn't queue up all of it
+const LOADS_IN_FLIGHT: usize = 32;
+
+/// Thumbnails kept, the ones not shown for the longest are dropped first
+const MAX_THUMBS: usize = 1024;
+
+/// A thumbnail and the last frame it was shown in
+struct Thumb {
+    /// `None` for items that aren't images
+    texture: Option<egui::TextureHandle>,
+    shown: u64,
+}
+
+/// Decodes thumbnails on a thread of its own
+struct Loader {
+    requests: mpsc::Sender<PathBuf>,
+    done: mpsc::Receiver<(PathBuf, Option<egui::ColorImage>)>,
+}
+
+impl Loader {
+    fn start(ctx: &egui::Context) -> Loader {
+        let (requests, todo) = mpsc::channel::<PathBuf>();
+        let (finished, done) = mpsc::channel();
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            for path in todo {
+                let image = decode(&path);
+                if finished.send((path, image)).is_err() {
+                    break;
+                }
+                ctx.request_repaint();
+            }
+        });
+        Loader { requests, done }
+    }
+}
+
+/// State of the window
+struct Gui {
+    state: AppState,
+    thumbs: HashMap<SharedPath, Thumb>,
+    /// items whose thumbnail is being decoded
+    loading: HashSet<SharedPath>,
+    loader: Loader,
+    /// frames drawn so far
+    frame: u64,
+    /// items matching the query, in display order
+    visible: Vec<(SharedPath, String)>,
+    /// whether `visible` needs to be found again
+    stale: bool,
+    /// text of the query field
+    query: String,
+    /// the parsed query, if the field isn't empty
+    filter: Option<query::Query>,
+    /// last error, shown in the status line
+    status: Option<String>,
+}
+
+/// The pixels of a thumbnail of an image item
+fn decode(path: &Path) -> Option<egui::ColorImage> {
+    image::ImageFormat::from_path(path).ok()?;
+    let img = image::open(path).ok()?.thumbnail(THUMB, THUMB).to_rgba8();
+    let size = [img.width() as usize, img.height() as usize];
+    Some(egui::ColorImage::from_rgba_unmultiplied(size, img.as_raw()))
+}
+
+impl Gui {
+    fn new(state: AppState, ctx: &egui::Context) -> Gui {
+        Gui {
+            state,
+            thumbs: HashMap::default(),
+            loading: HashSet::default(),
+            loader: Loader::start(ctx),
+            frame: 0,
+            visible: Vec::new(),
+            stale: true,
+            query: String::new(),
+            filter: None,
+            status: None,
+        }
+    }
+
+    /// Items matching the query, in display order
+    fn find_visible(&self) -> Vec<(SharedPath, String)> {
+        let state = &self.state;
+        let mut items: Vec<(SharedPath, String)> = state
+            .items_all
+            .iter()
+            .filter(|(ip, item)| {
+                self.filter.as_ref().map_or(true, |q| q.matches(state, ip, item))
+            })
+            .map(|(ip, item)| (ip.clone(), item.name.clone()))
+            .collect();
+        let natural = state.config.natural_sort;
+        items.sort_by(|a, b| natsort::compare_names(natural, &a.1, &b.1));
+        items
+    }
+
+    /// Upload the thumbnails decoded since the last frame
+    fn take_decoded(&mut self, ctx: &egui::Context) {
+        while let Ok((path, image)) = self.loader.done.try_recv() {
+            let ip = match item_key(&self.state, &path) {
+                Some(ip) => ip,
+                None => continue,
+            };
+            self.loading.remove(&ip);
+            let texture = image.map(|image| {
+                ctx.load_texture(
+                    path.to_string_lossy(),
+                    image,
+                    egui::TextureOptions::default(),
+                )
+            });
+            let shown = self.frame;
+            self.thumbs.insert(ip, Thumb { texture, shown });
+        }
+    }
+
+    /// Drop the thumbnails not shown for the longest, down to `MAX_THUMBS`
+    fn evict_thumbs(&mut self) {
+        if self.thumbs.len() <= MAX_THUMBS {
+            return;
+        }
+        let mut shown: Vec<u64> = self.thumbs.values().map(|t| t.shown).collect();
+        shown.sort_unstable();
+        let oldest = shown[shown.len() - MAX_THUMBS];
+        self.thumbs.retain(|_, t| t.shown >= oldest);
+    }
+
+    /// The thumbnail of an item, asking for it if it isn't decoded yet
+    fn thumb(&mut self, ip: &SharedPath) -> Option<egui::TextureHandle> {
+        if let Some(thumb) = self.thumbs.get_mut(ip) {
+            thumb.shown = self.frame;
+            return thumb.texture.clone();
+        }
+        if !self.loading.contains(ip)
+            && self.loading.len() < LOADS_IN_FLIGHT
+            && self.loader.requests.send(ip.to_path_buf()).is_ok()
+        {
+            self.loading.insert(ip.clone());
+        }
+        None
+    }
+
+    /// Select the dropped files that are items
+    fn take_dropped(&mut self, ctx: &egui::Context) {
+        let dropped: Vec<PathBuf> = ctx.input(|i| {
+            i.raw
+                .dropped_files
+                .iter()
+                .filter_map(|f| f.path.clone())
+                .collect()
+        });
+        for path in dropped {
+            match path.canonicalize().ok().and_then(|p| item_key(&self.state, &p)) {
+                Some(ip) => {
+                    self.state.sel.insert(ip);
+                }
+                None => self.status = Some(tr!("not an item: {}", path.display())),
+            }
+        }
+    }
+
+    fn ui_query(&mut self, ui: &mut egui::Ui, visible: &[(SharedPath, String)]) {
+        ui.horizontal(|ui| {
+            ui.label(tr!("Query"));
+            if ui.text_edit_singleline(&mut self.query).changed() {
+                self.filter = None;
+                self.stale = true;
+                if !self.query.trim().is_empty() {
+                    match query::Query::parse(&self.query) {
+                        Ok(q) => self.filter = Some(q),
+                        Err(e) => self.status = Some(tr!("bad query: {}", e)),
+                    }
+                }
+            }
+            if ui.button(tr!("Select all")).clicked() {
+                self.state.sel.extend(visible.iter().map(|(ip, _)| ip.clone()));
+            }
+            if ui.button(tr!("Clear selection")).clicked() {
+                self.state.sel.clear();
+            }
+        });
+    }
+
+    /// The tags, checked if all selected items have them
+    fn ui_tags(&mut self, ui: &mut egui::Ui) {
+        let tags: Vec<(SharedPath, String, usize)> = sorted_tags(&self.state)
+            .into_iter()
+            .map(|(tp, tag)| {
+                let n = self.state.sel.iter().filter(|ip| tag.items.contains_key(*ip)).count();
+                (tp.clone(), tag.name.clone(), n)
+            })
+            .collect();
+        let selected = self.state.sel.len();
+        ui.heading(tr!("Tags"));
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (tp, name, n) in tags {
+                let mixed = n > 0 && n < selected;
+                let mut on = selected > 0 && n == selected;
+                let label = if mixed {
+                    tr!("{} (some selected)", name)
+                } else {
+                    name
+                };
+                let check = egui::Checkbox::new(&mut on, label);
+                if ui.add_enabled(selected > 0 && !mixed, check).changed() {
+                    // the query may be about tags
+                    self.stale = true;
+                    if let Err(failure) = toggle_tag_path(&mut self.state, &tp) {
+                        let errors: Vec<String> = failure
+                            .errors
+                            .iter()
+                            .map(|(ip, e)| format!("{}: {}", ip.display(), e))
+                            .collect();
+                        self.status = Some(errors.join("\n"));
+                    }
+                }
+            }
+        });
+    }
+
+    /// The items as a grid, clicking one toggles its selection
+    ///
+    /// Only the rows scrolled into view are laid out, and only their
+    /// thumbnails are decoded.
+    fn ui_items(&mut self, ui: &mut egui::Ui, visible: &[(SharedPath, String)]) {
+        let size = egui::vec2(THUMB as f32, THUMB as f32 + LABEL);
+        let spacing = ui.spacing().item_spacing;
+        let columns = ((ui.available_width() + spacing.x) / (size.x + spacing.x)).max(1.0);
+        let columns = columns as usize;
+        let rows = (visible.len() + columns - 1) / columns;
+        egui::ScrollArea::vertical().show_rows(ui, size.y, rows, |ui, range| {
+            for row in range {
+                let start = row * columns;
+                let end = (start + columns).min(visible.len());
+                ui.horizontal(|ui| {
+                    for (ip, name) in &visible[start..end] {
+                        self.ui_item(ui, size, ip, name);
+                    }
+                });
+            }
+        });
+    }
+
+    fn ui_item(&mut self, ui: &mut egui::Ui, size: egui::Vec2, ip: &SharedPath, name: &str) {
+        let selected = self.state.sel.contains(ip);
+        let thumb = self.thumb(ip);
+        let clicked = ui
+            .allocate_ui(size, |ui| {
+                ui.vertical_centered(|ui| {
+                    let image = thumb.map(|t| {
+                        let button = egui::ImageButton::new(t.id(), t.size_vec2());
+                        ui.add(button.selected(selected)).clicked()
+                    });
+                    let label = ui.selectable_label(selected, name).clicked();
+                    image.unwrap_or(false) || label
+                })
+                .inner
+            })
+            .inner;
+        if clicked && !self.state.sel.remove(ip) {
+            self.state.sel.insert(ip.clone());
+        }
+    }
+}
+
+impl eframe::App for Gui {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.frame += 1;
+        self.take_dropped(ctx);
+        self.take_decoded(ctx);
+        if mem::take(&mut self.stale) {
+            self.visible = self.find_visible();
+        }
+        // taken out for the frame, as drawing it needs `self` too
+        let visible = mem::take(&mut self.visible);
+        egui::TopBottomPanel::top("query").show(ctx, |ui| self.ui_query(ui, &visible));
+        egui::TopBottomPanel::bottom("status").show(ctx, |ui| {
+            ui.label(tr!(
+                "{} of {} items, {} selected",
+                visible.len(),
+                self.state.items_all.len(),
+                self.state.sel.len()
+            ));
+            if let Some(ref status) = self.status {
+                ui.colored_label(egui::Color32::RED, status);
+            }
+        });
+        egui::SidePanel::left("tags").show(ctx, |ui| self.ui_tags(ui));
+        egui::CentralPanel::default().show(ctx, |ui| self.ui_items(ui, &visible));
+        self.visible = visible;
+        self.evict_thumbs();
+    }
+}
+
+/// Entry point for `linkorgasm gui`, returns the exit code
+pub fn run(m: &ArgMatches) -> i32 {
+    let (items, tags) = match (cli::items_dir(m), cli::tags_dir(m)) {
+        (Some(items), Some(tags)) => (items, tags),
+        _ => {
+            eprintln!("both an items dir and a tags dir are required");
+            return 1;
+        }
+    };
+    let state = match load_headless(m, Some(&items), &tags) {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    if let Err(e) = i18n::init(state.config.language.as_ref().map(String::as_str)) {
+        eprintln!("{}", e);
+    }
+    let res = eframe::run_native(
+        "linkorgasm",
+        eframe::NativeOptions::default(),
+        Box::new(move |cc| Box::new(Gui::new(state, &cc.egui_ctx))),
+    );
+    match res {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("cannot open the window: {}", e);
+            1
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! Passing work to the UI thread
+//!
+//! The app state lives in the user data of `Cursive` and is only used on
+//! the UI thread, taken out for the length of a `do_app`. Other threads
+//! (the control socket, D-Bus, jobs, background scans, ...) get an
+//! `AppHandle` and send it closures instead, which the UI thread runs with
+//! the app state between two events, redrawing afterwards.
+//!
+//! Code running while the state is taken (like `on_select` callbacks of
+//! views filled from `do_app`) can `defer` work to run once it is back.
+
+use std::sync::mpsc;
+
+use cursive::{CbSink, Cursive};
+
+use crate::{do_app, AppState};
+
+/// A way to run code on the UI thread, from any thread
+#[derive(Clone)]
+pub struct AppHandle {
+    sink: CbSink,
+}
+
+impl AppHandle {
+    pub fn new(siv: &Cursive) -> Self {
+        AppHandle {
+            sink: siv.cb_sink().clone(),
+        }
+    }
+
+    /// Run `f` with the app state on the UI thread
+    ///
+    /// Returns `false` if the UI is gone (quitting).
+    pub fn post<F>(&self, f: F) -> bool
+    where
+        F: FnOnce(&mut Cursive, &mut AppState) + Send + 'static,
+    {
+        self.post_ui(move |siv| do_app(siv, f))
+    }
+
+    /// Run `f` on the UI thread, for changes to views only
+    pub fn post_ui<F>(&self, f: F) -> bool
+    where
+        F: FnOnce(&mut Cursive) + Send + 'static,
+    {
+        self.sink.send(Box::new(f)).is_ok()
+    }
+
+    /// Run `f` with the app state on the UI thread and wait for its result
+    ///
+    /// `None` if the UI is gone. Blocks until the UI thread gets to it, so
+    /// it must not be called from the UI thread.
+    pub fn call<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut Cursive, &mut AppState) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let posted = self.post(move |siv, state| {
+            let _ = tx.send(f(siv, state));
+        });
+        if !posted {
+            return None;
+        }
+        rx.recv().ok()
+    }
+}
+
+/// Run `f` with the app state once the current callback is done
+///
+/// For code that may run while the state is taken.
+pub fn defer<F>(siv: &mut Cursive, f: F)
+where
+    F: FnOnce(&mut Cursive, &mut AppState) + Send + 'static,
+{
+    AppHandle::new(siv).post(f);
+}
@@ -0,0 +1,184 @@
+//! Hook commands run around tagging
+//!
+//! The commands in the `[hooks]` config table are run before and after a
+//! symlink is added to (`pre-tag`, `post-tag`) or removed from (`pre-untag`,
+//! `post-untag`) a tag dir, to integrate with indexing, sync or backup
+//! tools. `{tag}` and `{item}` are replaced by the canonical paths of the
+//! tag dir and the item.
+//!
+//! Commands are split into words like a shell would (so quotes and
+//! backslashes work) but run without one, so the substituted paths need no
+//! quoting. Their output is discarded. Pre hooks are waited for, up to
+//! `timeout` seconds (10 by default): one failing or taking longer refuses
+//! the change of that item, and the other items of a batch are changed
+//! anyway. Post hooks are started and not waited for, as the change has
+//! been made; their failures are only logged. Hooks are not run in dry-run
+//! mode.
+
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+use serde::Deserialize;
+
+use crate::AppState;
+
+/// How long a pre hook may run by default, in seconds
+const TIMEOUT: u64 = 10;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Hooks {
+    pub pre_tag: Option<String>,
+    pub post_tag: Option<String>,
+    pub pre_untag: Option<String>,
+    pub post_untag: Option<String>,
+    /// seconds a pre hook may run before it is killed
+    pub timeout: Option<u64>,
+}
+
+/// A pre hook refusing the change of an item
+#[derive(Debug)]
+struct Refused(String);
+
+impl fmt::Display for Refused {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for Refused {}
+
+fn refused(msg: String) -> io::Error {
+    warn!("{}", msg);
+    io::Error::new(io::ErrorKind::Other, Refused(msg))
+}
+
+/// Whether an error from changing a link is a pre hook refusing it
+pub fn is_refusal(e: &io::Error) -> bool {
+    e.get_ref().map_or(false, |e| e.is::<Refused>())
+}
+/// Replace the placeholders in a word of a hook command
+fn expand(word: &str, tag: &Path, item: &Path) -> OsString {
+    let mut out = OsString::new();
+    let mut rest = word;
+    while let Some(i) = rest.find('{') {
+        out.push(&rest[..i]);
+        rest = &rest[i..];
+        if rest.starts_with("{tag}") {
+            out.push(tag);
+            rest = &rest["{tag}".len()..];
+        } else if rest.starts_with("{item}") {
+            out.push(item);
+            rest = &rest["{item}".len()..];
+        } else {
+            out.push("{");
+            rest = &rest[1..];
+        }
+    }
+    out.push(rest);
+    out
+}
+
+/// The command of a hook for a tag and an item, if it is configured
+fn command(
+    state: &AppState,
+    hook: &Option<String>,
+    tag: &Path,
+    item: &Path,
+) -> Result<Option<Command>, String> {
+    let hook = match *hook {
+        Some(ref hook) if !state.fs.is_dry_run() => hook,
+        _ => return Ok(None),
+    };
+    let words = shell_words::split(hook).map_err(|e| format!("hook `{}`: {}", hook, e))?;
+    let mut words = words.iter();
+    let program = match words.next() {
+        Some(program) => program,
+        None => return Ok(None),
+    };
+    debug!("running hook `{}` for {} in {}", hook, item.display(), tag.display());
+    let mut cmd = Command::new(expand(program, tag, item));
+    cmd.args(words.map(|w| expand(w, tag, item)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    Ok(Some(cmd))
+}
+
+/// Wait for a child to exit, killing it after `timeout`
+///
+/// Returns `None` if it was killed.
+fn wait_timeout(child: &mut Child, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if start.elapsed() >= timeout {
+            let _ = child.kill();
+            child.wait()?;
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Run a pre hook for a tag and an item, if it is configured
+///
+/// An error means the hook refused the change (see `is_refusal`).
+pub fn pre(state: &AppState, hook: &Option<String>, tag: &Path, item: &Path) -> io::Result<()> {
+    let mut cmd = match command(state, hook, tag, item) {
+        Ok(Some(cmd)) => cmd,
+        Ok(None) => return Ok(()),
+        Err(e) => return Err(refused(e)),
+    };
+    let hook = hook.as_ref().unwrap();
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| refused(format!("hook `{}`: {}", hook, e)))?;
+    let timeout = Duration::from_secs(state.config.hooks.timeout.unwrap_or(TIMEOUT));
+    match wait_timeout(&mut child, timeout) {
+        Ok(Some(status)) if status.success() => Ok(()),
+        Ok(Some(status)) => Err(refused(format!("hook `{}` failed ({})", hook, status))),
+        Ok(None) => Err(refused(format!(
+            "hook `{}` took longer than {} seconds",
+            hook,
+            timeout.as_secs()
+        ))),
+        Err(e) => Err(refused(format!("hook `{}`: {}", hook, e))),
+    }
+}
+
+/// Start a post hook for a tag and an item, if it is configured
+///
+/// It is waited for on a thread of its own, only to log a failure.
+pub fn post(state: &AppState, hook: &Option<String>, tag: &Path, item: &Path) {
+    let mut cmd = match command(state, hook, tag, item) {
+        Ok(Some(cmd)) => cmd,
+        Ok(None) => return,
+        Err(e) => {
+            warn!("{}", e);
+            return;
+        }
+    };
+    let hook = hook.clone().unwrap();
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("hook `{}`: {}", hook, e);
+            return;
+        }
+    };
+    thread::spawn(move || match child.wait() {
+        Ok(status) if !status.success() => warn!("hook `{}` failed ({})", hook, status),
+        Ok(_) => {}
+        Err(e) => warn!("hook `{}`: {}", hook, e),
+    });
+}
@@ -0,0 +1,220 @@
+//! Static HTML galleries
+//!
+//! `linkorgasm export-html` writes an `index.html` listing the items of a
+//! tag or of a query result. It links to the files where they are, by paths
+//! relative to the output dir, so a curated subset of a library can be
+//! shared without copying files. With `--thumbnails`, images are shown as
+//! thumbnails, made with ImageMagick's `convert` if it is installed.
+
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use clap::ArgMatches;
+
+use crate::cli;
+use crate::exit;
+use crate::escape::display_name;
+use crate::query::Query;
+use crate::{load_readonly, tag_dir, AppState, SharedPath};
+
+/// File extensions shown as images (lowercase)
+static IMAGE_EXTS: &[&str] = &["jpg", "jpeg", "png", "gif", "webp", "svg", "bmp"];
+
+/// Size of the thumbnails, in pixels
+const THUMB_SIZE: u32 = 256;
+
+/// Escape text for HTML
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Percent-encode a relative path for use in a URL
+fn url_path(p: &Path) -> String {
+    let mut out = String::new();
+    for &b in p.as_os_str().as_bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~/".contains(&b) {
+            out.push(b as char);
+        } else {
+            write!(out, "%{:02X}", b).unwrap();
+        }
+    }
+    out
+}
+
+/// Path of `to` relative to the dir `from`, both canonical
+fn relative(from: &Path, to: &Path) -> PathBuf {
+    let from: Vec<Component> = from.components().collect();
+    let to: Vec<Component> = to.components().collect();
+    let common = from.iter().zip(to.iter()).take_while(|(a, b)| a == b).count();
+    let mut p = PathBuf::new();
+    for _ in common..from.len() {
+        p.push("..");
+    }
+    for c in to[common..].iter() {
+        p.push(c.as_os_str());
+    }
+    p
+}
+
+/// Whether an item is shown as an image
+fn is_image(p: &Path) -> bool {
+    p.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .map_or(false, |e| IMAGE_EXTS.contains(&e.as_str()))
+}
+
+/// Make a thumbnail of an image with `convert`, returns whether it worked
+fn make_thumbnail(image: &Path, thumb: &Path) -> bool {
+    Command::new("convert")
+        .arg(image)
+        .arg("-auto-orient")
+        .arg("-thumbnail")
+        .arg(format!("{}x{}", THUMB_SIZE, THUMB_SIZE))
+        .arg(thumb)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_or(false, |s| s.success())
+}
+
+/// Write the gallery to `out` (a canonical dir)
+fn write_gallery(
+    state: &AppState,
+    out: &Path,
+    title: &str,
+    items: &[SharedPath],
+    thumbnails: bool,
+) -> io::Result<()> {
+    let thumbs = out.join("thumbs");
+    if thumbnails {
+        fs::create_dir_all(&thumbs)?;
+    }
+
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>").unwrap();
+    writeln!(html, "<html>\n<head>\n<meta charset=\"utf-8\">").unwrap();
+    writeln!(html, "<title>{}</title>", escape(title)).unwrap();
+    writeln!(
+        html,
+        "<style>\nbody {{ font-family: sans-serif; }}\n\
+         figure {{ display: inline-block; width: {0}px; margin: 8px; vertical-align: top; }}\n\
+         figure img {{ max-width: {0}px; max-height: {0}px; }}\n\
+         figcaption {{ font-size: small; word-wrap: break-word; }}\n</style>",
+        THUMB_SIZE
+    )
+    .unwrap();
+    writeln!(html, "</head>\n<body>\n<h1>{}</h1>", escape(title)).unwrap();
+    writeln!(html, "<p>{} items</p>", items.len()).unwrap();
+
+    for (n, ip) in items.iter().enumerate() {
+        let item = &state.items_all[ip];
+        let href = url_path(&relative(out, ip));
+        let name = escape(&display_name(&item.filename, false));
+        if thumbnails && is_image(ip) {
+            let thumb = thumbs.join(format!("{}.jpg", n + 1));
+            let src = if make_thumbnail(ip, &thumb) {
+                url_path(&relative(out, &thumb))
+            } else {
+                href.clone()
+            };
+            writeln!(
+                html,
+                "<figure><a href=\"{0}\"><img src=\"{1}\" alt=\"{2}\" loading=\"lazy\"></a>\
+                 <figcaption>{2}</figcaption></figure>",
+                href, src, name
+            )
+            .unwrap();
+        } else {
+            let slash = if item.is_dir { "/" } else { "" };
+            writeln!(html, "<p><a href=\"{}\">{}{}</a></p>", href, name, slash).unwrap();
+        }
+    }
+    writeln!(html, "</body>\n</html>").unwrap();
+
+    fs::write(out.join("index.html"), html)
+}
+
+/// Run the `export-html` subcommand, returns the exit code
+pub fn run(m: &ArgMatches) -> i32 {
+    let (items, tags) = match (cli::items_dir(m), cli::tags_dir(m)) {
+        (Some(items), Some(tags)) => (items, tags),
+        _ => {
+            eprintln!("both an items dir and a tags dir are required");
+            return exit::USAGE;
+        }
+    };
+    let state = match load_readonly(m, Some(&items), &tags) {
+        Ok(state) => state,
+        Err(e) => return exit::load_failed(&tags, &e),
+    };
+
+    let tag = m.value_of("tag");
+    let (title, mut selected): (String, Vec<SharedPath>) = if let Some(name) = tag {
+        let tag = tag_dir(&state, name)
+            .canonicalize()
+            .ok()
+            .and_then(|tp| state.tags.get(tp.as_path()));
+        match tag {
+            Some(tag) => (tag.name.clone(), tag.items.keys().cloned().collect()),
+            None => {
+                eprintln!("unknown tag: {}", name);
+                return exit::USAGE;
+            }
+        }
+    } else {
+        let q = m.value_of("query").unwrap();
+        let query = match Query::parse(q) {
+            Ok(query) => query,
+            Err(e) => {
+                eprintln!("invalid query: {}", e);
+                return exit::USAGE;
+            }
+        };
+        let matching = state
+            .items_all
+            .iter()
+            .filter(|(ip, i)| query.matches(&state, ip, i))
+            .map(|(p, _)| p.clone())
+            .collect();
+        (q.to_owned(), matching)
+    };
+    selected.sort();
+
+    let out = Path::new(m.value_of_os("out").unwrap());
+    let out = match fs::create_dir_all(out).and_then(|()| out.canonicalize()) {
+        Ok(out) => out,
+        Err(e) => {
+            eprintln!("{}: {}", out.display(), e);
+            return exit::FAILED;
+        }
+    };
+    let thumbnails = m.is_present("thumbnails");
+    if let Err(e) = write_gallery(&state, &out, &title, &selected, thumbnails) {
+        eprintln!("{}: {}", out.display(), e);
+        return exit::FAILED;
+    }
+    if !cli::is_quiet(m) {
+        println!(
+            "{} items written to {}",
+            selected.len(),
+            out.join("index.html").display()
+        );
+    }
+    exit::OK
+}
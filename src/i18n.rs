@@ -0,0 +1,152 @@
+//! Translations of UI strings
+//!
+//! Works like gettext: UI strings are written in English and looked up in
+//! the catalog of the user's language, falling back to the English text
+//! when there is no translation. The language is the `language` setting,
+//! or else taken from `LC_ALL`, `LC_MESSAGES` or `LANG` (`de_AT.UTF-8`
+//! tries `de_AT`, then `de`).
+//!
+//! Catalogs are TOML files, `locales/LANG.toml` in the config dir or
+//! built in from the `locales` dir of the source tree, with the
+//! translations under `[messages]`, keyed by the English text, and an
+//! optional translated `help` text. Messages take their arguments in `{}`
+//! placeholders, filled in order, or by position with `{0}`, `{1}`, ...
+//!
+//! Only strings shown in the UI are translated; output of subcommands and
+//! logs stay in English, as scripts parse them.
+
+use std::cell::RefCell;
+use std::env;
+use std::fmt::Display;
+use std::fs;
+
+use hashbrown::HashMap;
+use serde::Deserialize;
+
+use crate::config;
+
+/// Catalogs built into the binary, by language
+static BUILTIN: &[(&str, &str)] = &[("de", include_str!("../locales/de.toml"))];
+
+/// Translate a UI string, filling in its arguments like `format!`
+macro_rules! tr {
+    ($msg:expr) => {
+        $crate::i18n::tr($msg)
+    };
+    ($msg:expr, $($arg:expr),+ $(,)?) => {
+        $crate::i18n::fill(&$crate::i18n::tr($msg), &[$(&$arg as &dyn std::fmt::Display),+])
+    };
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Catalog {
+    /// translated help screen
+    help: Option<String>,
+    /// translations, keyed by the English text
+    messages: HashMap<String, String>,
+}
+
+thread_local! {
+    static CATALOG: RefCell<Catalog> = RefCell::new(Catalog::default());
+}
+
+/// Languages to try for a locale name like `de_AT.UTF-8`, most specific first
+fn candidates(locale: &str) -> Vec<String> {
+    let name = locale.split(|c| c == '.' || c == '@').next().unwrap_or("");
+    let mut langs = vec![name.to_owned()];
+    if let Some(i) = name.find('_') {
+        langs.push(name[..i].to_owned());
+    }
+    langs.retain(|l| !l.is_empty() && l != "C" && l != "POSIX");
+    langs
+}
+
+/// The language configured or set in the environment
+fn locale(configured: Option<&str>) -> Option<String> {
+    if let Some(lang) = configured {
+        return Some(lang.to_owned());
+    }
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|v| !v.is_empty())
+}
+
+/// Text of the catalog for a language, from the config dir or built in
+fn catalog_text(lang: &str) -> Option<String> {
+    let user = config::config_dir()
+        .map(|dir| dir.join("locales").join(format!("{}.toml", lang)))
+        .and_then(|path| fs::read_to_string(path).ok());
+    user.or_else(|| {
+        BUILTIN
+            .iter()
+            .find(|(l, _)| *l == lang)
+            .map(|(_, text)| (*text).to_owned())
+    })
+}
+
+/// Load the catalog of the user's language
+///
+/// Returns an error for a catalog that can't be parsed; the UI is in
+/// English then.
+pub fn init(language: Option<&str>) -> Result<(), String> {
+    let locale = match locale(language) {
+        Some(locale) => locale,
+        None => return Ok(()),
+    };
+    for lang in candidates(&locale) {
+        if let Some(text) = catalog_text(&lang) {
+            let catalog: Catalog = toml::from_str(&text)
+                .map_err(|e| format!("cannot read the translations for {}: {}", lang, e))?;
+            CATALOG.with(|c| *c.borrow_mut() = catalog);
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+/// The translation of a UI string, or the string itself
+pub fn tr(msg: &str) -> String {
+    CATALOG.with(|c| {
+        c.borrow()
+            .messages
+            .get(msg)
+            .cloned()
+            .unwrap_or_else(|| msg.to_owned())
+    })
+}
+
+/// The translated help screen, or the English one
+pub fn help(english: &str) -> String {
+    CATALOG.with(|c| c.borrow().help.clone().unwrap_or_else(|| english.to_owned()))
+}
+
+/// Fill the `{}` and `{N}` placeholders of a message with its arguments
+pub fn fill(msg: &str, args: &[&dyn Display]) -> String {
+    let mut out = String::with_capacity(msg.len());
+    let mut next = 0;
+    let mut rest = msg;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+        let index = match &rest[1..end] {
+            "" => {
+                next += 1;
+                Some(next - 1)
+            }
+            n => n.parse::<usize>().ok(),
+        };
+        match index.and_then(|i| args.get(i)) {
+            Some(arg) => out.push_str(&arg.to_string()),
+            None => out.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
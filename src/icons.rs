@@ -0,0 +1,28 @@
+//! Type icons in the items view
+//!
+//! With the `icons` setting, each item gets an icon for its type in front
+//! of its name: a Nerd Font glyph, or a letter for terminals without such
+//! a font. See `filetype` for the types.
+
+use crate::config::Icons;
+use crate::filetype::{self, Kind};
+use crate::Item;
+
+/// Icon of an item, `None` if icons are off
+pub fn icon(icons: Icons, item: &Item) -> Option<&'static str> {
+    if icons == Icons::Off {
+        return None;
+    }
+    let (letter, glyph) = match filetype::of(item) {
+        Kind::Dir => ("d", "\u{f07b}"),
+        Kind::Image => ("i", "\u{f1c5}"),
+        Kind::Video => ("v", "\u{f1c8}"),
+        Kind::Audio => ("a", "\u{f1c7}"),
+        Kind::Document => ("o", "\u{f1c1}"),
+        Kind::Archive => ("z", "\u{f1c6}"),
+        Kind::Code => ("c", "\u{f1c9}"),
+        Kind::Text => ("t", "\u{f15c}"),
+        Kind::Other => ("-", "\u{f15b}"),
+    };
+    Some(if icons == Icons::Nerd { glyph } else { letter })
+}
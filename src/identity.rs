@@ -0,0 +1,251 @@
+//! Identifying items by device and inode
+//!
+//! Items are known by their canonical path, so hard links to the same file
+//! are separate items, and an item renamed outside of linkorgasm loses its
+//! tags. With `identity = "inode"` (or `--identity inode`), hard links are
+//! one item (the first one found), and the inodes of all items are kept in
+//! `.linkorgasm/inodes` in the tags dir, with their sizes and modification
+//! times. A dangling symlink in a tag dir whose target had the inode of an
+//! item that is now elsewhere, with the same size and modification time (a
+//! reused inode has other ones), is taken to be a link to that item, and
+//! pointed at its new path after the scan.
+//!
+//! The inodes file starts with a header line naming its format version
+//! (see `selfcheck`). An inodes file of a newer version is neither used nor
+//! overwritten.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hashbrown::{HashMap, HashSet};
+use log::warn;
+
+use crate::config::Identity;
+use crate::{lexical_path, linkstyle, selfcheck, AppState, META_DIR};
+
+/// Names of the identity modes, as given to `--identity`
+pub static MODES: &[&str] = &["path", "inode"];
+
+/// Name of the file with the inodes of the items in the metadata dir
+pub static INODES_FILE: &str = "inodes";
+
+/// Format version of the inodes file understood by this binary
+pub const VERSION: u32 = 1;
+
+/// First word of the header line
+pub static HEADER: &str = "linkorgasm-inodes";
+
+/// Device and inode of a file
+pub type FileId = (u64, u64);
+
+/// What is known of an item from the last scan
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Known {
+    pub id: FileId,
+    pub size: u64,
+    /// modification time, in seconds since the epoch
+    pub mtime: u64,
+}
+
+/// Device and inode of an item, from its metadata
+pub fn file_id(meta: &fs::Metadata) -> FileId {
+    (meta.dev(), meta.ino())
+}
+
+pub fn inodes_path(tags_path: &Path) -> PathBuf {
+    tags_path.join(META_DIR).join(INODES_FILE)
+}
+
+/// Upgrade the lines of an inodes file by one version, from the given one
+///
+/// There is only version 1 so far.
+pub fn migrate(_lines: &mut Vec<u8>, _from: u32) {}
+
+/// The version of an inodes file newer than this binary understands
+fn too_new(bytes: &[u8]) -> Option<u32> {
+    match selfcheck::split_header(bytes, HEADER) {
+        (Some(v), _) if v > VERSION => Some(v),
+        _ => None,
+    }
+}
+
+fn mtime_secs(mtime: Option<SystemTime>) -> Option<u64> {
+    Some(mtime?.duration_since(UNIX_EPOCH).ok()?.as_secs())
+}
+
+/// Read what was known of the items at the last scan, by path
+///
+/// Lines are `DEV INO SIZE MTIME PATH`. A missing or broken file, or one
+/// of a newer version, gives nothing.
+pub fn load(tags_path: &Path) -> HashMap<PathBuf, Known> {
+    let file = inodes_path(tags_path);
+    let bytes = match fs::read(&file) {
+        Ok(bytes) => bytes,
+        Err(_) => return HashMap::default(),
+    };
+    if let Some(v) = too_new(&bytes) {
+        warn!(
+            "{} has version {}, but this binary only understands up to {}",
+            file.display(),
+            v,
+            VERSION
+        );
+        return HashMap::default();
+    }
+    let (_, lines) = selfcheck::split_header(&bytes, HEADER);
+    let mut known = HashMap::default();
+    for line in lines.split(|&b| b == b'\n') {
+        let mut fields = line.splitn(5, |&b| b == b' ');
+        let mut number = || {
+            fields
+                .next()
+                .and_then(|f| std::str::from_utf8(f).ok()?.parse().ok())
+        };
+        let (dev, ino, size, mtime) = (number(), number(), number(), number());
+        let path = fields.next().map(|f| PathBuf::from(OsStr::from_bytes(f)));
+        if let (Some(dev), Some(ino), Some(size), Some(mtime), Some(path)) =
+            (dev, ino, size, mtime, path)
+        {
+            known.insert(
+                path,
+                Known {
+                    id: (dev, ino),
+                    size,
+                    mtime,
+                },
+            );
+        }
+    }
+    known
+}
+
+/// Write what is known of the current items, unless nothing changed
+///
+/// The entries of the last scan are kept for files that weren't scanned
+/// this time but are still there (the scan may have been partial), and for
+/// the targets of links still dangling, unless their inode is an item's now.
+/// An inodes file of a newer version is left alone.
+fn save(state: &mut AppState) -> io::Result<()> {
+    let file = inodes_path(&state.tags_path);
+    if let Some(v) = fs::read(&file).ok().as_deref().and_then(too_new) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "inodes file has version {}, but this binary only understands up to {}",
+                v, VERSION
+            ),
+        ));
+    }
+    let mut known: Vec<(PathBuf, Known)> = state
+        .inodes
+        .iter()
+        .filter_map(|(id, ip)| {
+            let item = &state.items_all[ip];
+            let mtime = mtime_secs(item.mtime)?;
+            Some((
+                ip.to_path_buf(),
+                Known {
+                    id: *id,
+                    size: item.size,
+                    mtime,
+                },
+            ))
+        })
+        .collect();
+    let dangling: HashSet<PathBuf> = state
+        .foreign
+        .iter()
+        .filter(|f| f.target.is_none())
+        .filter_map(|f| Some(lexical_path(&f.tag, &fs::read_link(&f.link).ok()?)))
+        .collect();
+    for (path, k) in state.known_paths.iter() {
+        let current =
+            state.items_all.contains_key(path.as_path()) || state.inodes.contains_key(&k.id);
+        if !current && (dangling.contains(path) || path.symlink_metadata().is_ok()) {
+            known.push((path.clone(), *k));
+        }
+    }
+    known.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut old: Vec<(&PathBuf, &Known)> = state.known_paths.iter().collect();
+    old.sort_by(|a, b| a.0.cmp(b.0));
+    if known.iter().map(|(p, k)| (p, k)).eq(old) {
+        return Ok(());
+    }
+
+    let mut contents = selfcheck::header(HEADER, VERSION).into_bytes();
+    for (path, k) in known {
+        let (dev, ino) = k.id;
+        contents.extend(format!("{} {} {} {} ", dev, ino, k.size, k.mtime).as_bytes());
+        contents.extend(path.as_os_str().as_bytes());
+        contents.push(b'\n');
+    }
+    state.fs.create_meta_dir(&state.tags_path.join(META_DIR))?;
+    state.fs.write_file(&file, contents)
+}
+
+/// The item a dangling symlink in the tag dir `cdir` stood for, if it has
+/// been moved since the last scan
+///
+/// The item must have the inode, size and modification time the target
+/// had.
+pub fn moved_target(state: &AppState, link: &Path, cdir: &Path) -> Option<PathBuf> {
+    if state.config.identity != Identity::Inode {
+        return None;
+    }
+    let target = lexical_path(cdir, &fs::read_link(link).ok()?);
+    let known = state.known_paths.get(&target)?;
+    let ip = state.inodes.get(&known.id)?;
+    let item = state.items_all.get(ip)?;
+    if item.size != known.size || mtime_secs(item.mtime) != Some(known.mtime) {
+        return None;
+    }
+    Some(ip.to_path_buf())
+}
+
+/// Point the links to moved items at their new paths, then save the inodes
+///
+/// Returns the errors. Nothing is saved in dry-run and read-only mode.
+pub fn repair(state: &mut AppState) -> Vec<String> {
+    if state.config.identity != Identity::Inode {
+        return Vec::new();
+    }
+    let mut moved = Vec::new();
+    for (tp, tag) in state.tags.iter() {
+        for (ip, name) in tag.items.iter() {
+            let link = tp.join(name);
+            let target = match fs::read_link(&link) {
+                Ok(target) => lexical_path(tp, &target),
+                Err(_) => continue,
+            };
+            if target != **ip && link.metadata().is_err() {
+                moved.push((tp.clone(), ip.clone(), link));
+            }
+        }
+    }
+    moved.sort();
+
+    let mut errors = Vec::new();
+    for (tp, ip, link) in moved.iter() {
+        let is_dir = state.items_all[ip].is_dir;
+        let target = linkstyle::target(state.config.link_style, tp, ip);
+        let res = state
+            .fs
+            .remove_symlink(link, is_dir)
+            .and_then(|_| state.fs.symlink(&target, link, is_dir));
+        if let Err(e) = res {
+            errors.push(format!("{}: {}", link.display(), e));
+        }
+    }
+    if !state.fs.is_dry_run() && !state.fs.is_read_only() {
+        if let Err(e) = save(state) {
+            let path = inodes_path(&state.tags_path);
+            errors.push(format!("{}: {}", path.display(), e));
+        }
+    }
+    errors
+}
@@ -0,0 +1,194 @@
+//! Index of the items kept warm by `linkorgasm daemon`
+//!
+//! After every scan, the daemon writes what it found in the items dir to a
+//! file in the user's cache dir. The UI starts from that file instead of
+//! scanning the items dir, as long as the daemon that wrote it is still
+//! running (and so watching for changes), the items dir hasn't changed
+//! since, and the items were scanned with the same settings. Anything else
+//! means a scan, as without the daemon.
+
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::OsString;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use hashbrown::{HashMap, HashSet};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::escape;
+use crate::identity::FileId;
+use crate::{AppState, Item, SharedPath};
+
+/// Version of the index format
+const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Index {
+    version: u32,
+    /// process id of the daemon that wrote it
+    pid: u32,
+    /// canonical path of the items dir
+    dir: PathBuf,
+    /// modification time of the items dir when it was scanned
+    dir_mtime: Option<SystemTime>,
+    /// the settings deciding which files are items
+    settings: String,
+    items: Vec<Entry>,
+    /// files skipped by the scan, and why
+    skipped: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    path: OsString,
+    filename: OsString,
+    is_dir: bool,
+    size: u64,
+    mtime: Option<SystemTime>,
+    id: Option<FileId>,
+    annex_key: Option<String>,
+}
+
+/// Path to the index of an items dir
+fn index_path(dir: &Path) -> Option<PathBuf> {
+    let mut hasher = DefaultHasher::new();
+    dir.hash(&mut hasher);
+    let name = format!("index-{:016x}.json", hasher.finish());
+    dirs::cache_dir().map(|p| p.join("linkorgasm").join(name))
+}
+
+/// The settings that change what a scan of the items dir finds
+fn settings(config: &Config) -> String {
+    format!(
+        "{:?} {:?} {} {:?} {:?} {}",
+        config.exclude,
+        config.include,
+        config.include_dirs,
+        config.item_symlinks,
+        config.identity,
+        config.git_annex
+    )
+}
+
+fn dir_mtime(dir: &Path) -> Option<SystemTime> {
+    fs::metadata(dir).and_then(|m| m.modified()).ok()
+}
+
+/// Whether a process is running
+fn is_running(pid: u32) -> bool {
+    unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+}
+
+/// Write the index of the items scanned from the items dir
+pub fn store(state: &AppState) -> io::Result<()> {
+    let dir = match state.items_path {
+        Some(ref dir) => dir,
+        None => return Ok(()),
+    };
+    let path = match index_path(dir) {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let ids: HashMap<&SharedPath, FileId> = state.inodes.iter().map(|(id, ip)| (ip, *id)).collect();
+    let keys: HashMap<&SharedPath, &String> = state
+        .annex_keys
+        .iter()
+        .flat_map(|(key, ips)| ips.iter().map(move |ip| (ip, key)))
+        .collect();
+    let items = state
+        .items_all
+        .iter()
+        .map(|(ip, item)| Entry {
+            path: ip.as_os_str().to_owned(),
+            filename: item.filename.clone(),
+            is_dir: item.is_dir,
+            size: item.size,
+            mtime: item.mtime,
+            id: ids.get(ip).copied(),
+            annex_key: keys.get(ip).map(|key| (*key).clone()),
+        })
+        .collect();
+    let index = Index {
+        version: VERSION,
+        pid: process::id(),
+        dir: dir.clone(),
+        dir_mtime: dir_mtime(dir),
+        settings: settings(&state.config),
+        items,
+        skipped: state.skipped_items.clone(),
+    };
+    let json = serde_json::to_vec(&index).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // a reader never sees a half-written index
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, json)?;
+    fs::rename(&tmp, &path)
+}
+
+/// Add the items of the items dir `p` from a warm index, instead of
+/// scanning it
+///
+/// Returns whether there was a usable index; if not, nothing was added.
+pub fn load(state: &mut AppState, p: &Path) -> bool {
+    let dir = match p.canonicalize() {
+        Ok(dir) => dir,
+        Err(_) => return false,
+    };
+    let index: Index = match index_path(&dir)
+        .and_then(|path| fs::read(path).ok())
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+    {
+        Some(index) => index,
+        None => return false,
+    };
+    if index.version != VERSION
+        || index.dir != dir
+        || index.dir_mtime.is_none()
+        || index.dir_mtime != dir_mtime(&dir)
+        || index.settings != settings(&state.config)
+        || !is_running(index.pid)
+    {
+        return false;
+    }
+
+    state.items_path = Some(dir);
+    for entry in index.items {
+        let ip: SharedPath = PathBuf::from(entry.path).into();
+        if let Some(key) = entry.annex_key {
+            state
+                .annex_keys
+                .entry(key)
+                .or_insert_with(Vec::new)
+                .push(Rc::clone(&ip));
+        }
+        if let Some(id) = entry.id {
+            state.inodes.insert(id, Rc::clone(&ip));
+        }
+        state.items_all.insert(
+            ip,
+            Item {
+                name: escape::display_name(&entry.filename, state.config.escape_names),
+                filename: entry.filename,
+                is_dir: entry.is_dir,
+                size: entry.size,
+                mtime: entry.mtime,
+                tags: HashSet::default(),
+            },
+        );
+    }
+    state.skipped_items.extend(index.skipped);
+    info!(
+        "loaded {} items from the daemon's index",
+        state.items_all.len()
+    );
+    true
+}
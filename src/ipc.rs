@@ -0,0 +1,261 @@
+//! Control interface over a Unix socket
+//!
+//! With `--socket PATH`, the running instance accepts JSON-RPC 2.0
+//! requests on a Unix socket, one JSON object per line, and answers each
+//! with one line. File managers and editors can thus query and change tags
+//! while the UI shows the changes right away. Methods:
+//!
+//! - `query {"query": Q}`: paths of the items matching a query (all items
+//!   without one)
+//! - `tags {"item": P}`: names of the tags of an item (all tags without one)
+//! - `tag {"item": P, "tag": NAME}`: tag an item, creating a missing tag
+//! - `untag {"item": P, "tag": NAME}`: untag an item
+//! - `rescan {"tag": NAME}`: reload a tag from disk (all tags without one)
+//!
+//! Requests are handled one at a time by the UI thread. Until the tags
+//! directory is chosen and scanned, they are answered with an error.
+//!
+//! Only compiled with the `ipc` feature.
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use cursive::Cursive;
+use serde_json::{json, Value};
+
+use crate::checklist::CheckList;
+use crate::handle::AppHandle;
+use crate::query::Query;
+use crate::{
+    find_or_create_tag, rescan_tag, rescan_tags, run_filter, sorted_tags, tag_item,
+    ui_refresh_itemview, untag_item, AppState, SharedPath,
+};
+
+/// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+/// error code for failed operations (not part of JSON-RPC)
+const FAILED: i64 = 1;
+
+/// Result of a method, or its error code and message
+pub type CallResult = Result<Value, (i64, String)>;
+
+/// Listening socket, removed when dropped
+pub struct Server {
+    path: PathBuf,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Listen on a Unix socket and serve requests in the background
+///
+/// A socket file left behind by an instance that is gone is replaced.
+pub fn start(siv: &Cursive, path: &Path) -> io::Result<Server> {
+    if path.symlink_metadata().is_ok() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is in use by another instance", path.display()),
+            ));
+        }
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    let app = AppHandle::new(siv);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            let app = app.clone();
+            thread::spawn(move || serve(stream, app));
+        }
+    });
+    Ok(Server {
+        path: path.to_path_buf(),
+    })
+}
+
+/// Answer the requests of one client until it disconnects
+fn serve(stream: UnixStream, app: AppHandle) {
+    let reader = match stream.try_clone() {
+        Ok(reader) => BufReader::new(reader),
+        Err(_) => return,
+    };
+    let mut out = stream;
+    for line in reader.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(request) => dispatch(&app, request),
+            Err(e) => Some(error_response(Value::Null, PARSE_ERROR, e.to_string())),
+        };
+        // notifications (requests without an id) get no response
+        if let Some(response) = response {
+            if writeln!(out, "{}", response).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Response reporting a failed request
+fn error_response(id: Value, code: i64, message: String) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+/// Handle a request, returns the response
+fn dispatch(app: &AppHandle, request: Value) -> Option<Value> {
+    let id = request.get("id").cloned();
+    let method = match request.get("method").and_then(Value::as_str) {
+        Some(method) => method.to_owned(),
+        None => {
+            let msg = "missing method".to_owned();
+            return Some(error_response(id.unwrap_or(Value::Null), INVALID_REQUEST, msg));
+        }
+    };
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+    let result = request_ui(app, method, params);
+
+    let id = id?;
+    Some(match result {
+        Ok(value) => json!({ "jsonrpc": "2.0", "id": id, "result": value }),
+        Err((code, message)) => error_response(id, code, message),
+    })
+}
+
+/// Have the UI thread call a method and wait for the result
+pub fn request_ui(app: &AppHandle, method: String, params: Value) -> CallResult {
+    app.call(move |siv, state| call(siv, state, &method, &params))
+        .unwrap_or_else(|| Err((INTERNAL_ERROR, "linkorgasm is quitting".to_owned())))
+}
+
+/// A string parameter, `None` if it is not given
+fn str_param<'p>(params: &'p Value, name: &str) -> Result<Option<&'p str>, (i64, String)> {
+    match params.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s)),
+        Some(_) => Err((INVALID_PARAMS, format!("{} must be a string", name))),
+    }
+}
+
+/// A string parameter that must be given
+fn required<'p>(params: &'p Value, name: &str) -> Result<&'p str, (i64, String)> {
+    str_param(params, name)?.ok_or_else(|| (INVALID_PARAMS, format!("missing {}", name)))
+}
+
+/// Key of a known item, given by any path to it
+fn find_item(state: &AppState, item: &str) -> Result<SharedPath, (i64, String)> {
+    crate::find_item(state, item).map_err(|e| (FAILED, e))
+}
+
+/// Key of a known tag, by name
+fn find_tag(state: &AppState, name: &str) -> Result<SharedPath, (i64, String)> {
+    crate::find_tag(state, name).map_err(|e| (FAILED, e))
+}
+
+/// Sorted paths as a JSON array
+fn paths(mut paths: Vec<&SharedPath>) -> Value {
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|p| Value::from(p.to_string_lossy().into_owned()))
+        .collect()
+}
+
+/// Handle a request, on the UI thread
+fn call(siv: &mut Cursive, state: &mut AppState, method: &str, params: &Value) -> CallResult {
+    // the main UI is built once the tags dir is scanned
+    if siv.call_on_id("tagsview", |_: &mut CheckList<PathBuf>| ()).is_none() {
+        return Err((FAILED, "no tags directory loaded yet".to_owned()));
+    }
+    let failed = |e: io::Error| (FAILED, e.to_string());
+    match method {
+        "query" => {
+            let query = match str_param(params, "query")? {
+                Some(q) => Some(Query::parse(q).map_err(|e| (FAILED, e.to_string()))?),
+                None => None,
+            };
+            let matching = state
+                .items_all
+                .iter()
+                .filter(|(ip, i)| query.as_ref().map_or(true, |q| q.matches(state, ip, i)))
+                .map(|(p, _)| p)
+                .collect();
+            Ok(paths(matching))
+        }
+        "tags" => {
+            let names: Vec<String> = match str_param(params, "item")? {
+                Some(item) => {
+                    let ip = find_item(state, item)?;
+                    let mut names: Vec<String> = state.items_all[&ip]
+                        .tags
+                        .iter()
+                        .map(|tp| state.tags[tp].name.clone())
+                        .collect();
+                    names.sort();
+                    names
+                }
+                None => sorted_tags(state)
+                    .into_iter()
+                    .map(|(_, t)| t.name.clone())
+                    .collect(),
+            };
+            Ok(Value::from(names))
+        }
+        "tag" => {
+            let ip = find_item(state, required(params, "item")?)?;
+            let name = required(params, "tag")?;
+            let tp = find_or_create_tag(state, name).map_err(|e| (FAILED, e))?;
+            if !state.items_all[&ip].tags.contains(&tp) {
+                tag_item(state, &tp, &ip).map_err(failed)?;
+            }
+            run_filter(state);
+            ui_refresh_itemview(siv, state);
+            Ok(Value::Null)
+        }
+        "untag" => {
+            let ip = find_item(state, required(params, "item")?)?;
+            let tp = find_tag(state, required(params, "tag")?)?;
+            if state.items_all[&ip].tags.contains(&tp) {
+                untag_item(state, &tp, &ip).map_err(failed)?;
+            }
+            run_filter(state);
+            ui_refresh_itemview(siv, state);
+            Ok(Value::Null)
+        }
+        "rescan" => {
+            match str_param(params, "tag")? {
+                Some(name) => {
+                    let tp = find_tag(state, name)?;
+                    rescan_tag(state, &tp);
+                }
+                None => rescan_tags(state),
+            }
+            run_filter(state);
+            ui_refresh_itemview(siv, state);
+            Ok(Value::Null)
+        }
+        _ => Err((METHOD_NOT_FOUND, format!("unknown method: {}", method))),
+    }
+}
@@ -0,0 +1,268 @@
+//! Tracking of spawned commands ("jobs")
+//!
+//! Commands started from the UI run in the background with their output
+//! captured, so it doesn't garble the screen. The jobs panel ('j') lists
+//! them with their status, shows their output and can kill them. Output
+//! of commands run for it ('E') is shown as soon as they are done.
+//!
+//! Only compiled with the `preview` feature.
+
+use std::fmt;
+use std::io::{self, Read};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use cursive::traits::*;
+use cursive::view::ScrollStrategy;
+use cursive::views::{Dialog, ScrollView, SelectView, TextView};
+use cursive::Cursive;
+
+use crate::handle::AppHandle;
+use crate::{do_app, ui_error};
+
+/// Captured output kept per job, in bytes; the rest is dropped
+const MAX_OUTPUT: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Running,
+    /// exited with the given code
+    Exited(i32),
+    /// terminated by the given signal
+    Killed(i32),
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Status::Running => write!(f, "running"),
+            Status::Exited(code) => write!(f, "exited ({})", code),
+            Status::Killed(sig) => write!(f, "killed ({})", sig),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Job {
+    id: usize,
+    /// command line, for display
+    label: String,
+    child: Child,
+    status: Status,
+    /// stdout and stderr, interleaved as they arrive
+    output: Arc<Mutex<Vec<u8>>>,
+}
+
+#[derive(Debug, Default)]
+pub struct Jobs {
+    jobs: Vec<Job>,
+    next_id: usize,
+}
+
+/// Copy everything from a pipe into the shared output buffer
+fn capture(
+    mut pipe: impl Read + Send + 'static,
+    output: Arc<Mutex<Vec<u8>>>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut buf = [0; 4096];
+        loop {
+            let n = match pipe.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            let mut output = output.lock().unwrap();
+            let room = MAX_OUTPUT.saturating_sub(output.len());
+            output.extend_from_slice(&buf[..n.min(room)]);
+        }
+    })
+}
+
+impl Jobs {
+    /// Start a command in the background, capturing its output
+    pub fn spawn(&mut self, cmd: Command, label: String) -> io::Result<()> {
+        self.start(cmd, label).map(|_| ())
+    }
+
+    /// Like `spawn`, but show the output once the command closes it
+    pub fn spawn_shown(
+        &mut self,
+        cmd: Command,
+        label: String,
+        app: AppHandle,
+    ) -> io::Result<()> {
+        let (id, readers) = self.start(cmd, label)?;
+        thread::spawn(move || {
+            for reader in readers.into_iter() {
+                let _ = reader.join();
+            }
+            app.post_ui(move |siv| ui_show_output(siv, id));
+        });
+        Ok(())
+    }
+
+    /// Spawn a job, returns its id and the threads reading its output
+    fn start(
+        &mut self,
+        mut cmd: Command,
+        label: String,
+    ) -> io::Result<(usize, Vec<JoinHandle<()>>)> {
+        let mut child = cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let readers = vec![
+            capture(child.stdout.take().unwrap(), Arc::clone(&output)),
+            capture(child.stderr.take().unwrap(), Arc::clone(&output)),
+        ];
+
+        self.next_id += 1;
+        self.jobs.push(Job {
+            id: self.next_id,
+            label,
+            child,
+            status: Status::Running,
+            output,
+        });
+        Ok((self.next_id, readers))
+    }
+
+    /// Update the status of running jobs that have finished
+    pub fn poll(&mut self) {
+        for job in self.jobs.iter_mut() {
+            if job.status != Status::Running {
+                continue;
+            }
+            if let Ok(Some(status)) = job.child.try_wait() {
+                job.status = match status.code() {
+                    Some(code) => Status::Exited(code),
+                    None => Status::Killed(status.signal().unwrap_or(0)),
+                };
+            }
+        }
+    }
+
+    /// Kill a running job
+    pub fn kill(&mut self, id: usize) -> io::Result<()> {
+        if let Some(job) = self.jobs.iter_mut().find(|j| j.id == id) {
+            if job.status == Status::Running {
+                job.child.kill()?;
+                job.child.wait()?;
+            }
+        }
+        self.poll();
+        Ok(())
+    }
+
+    /// Command line, status and output of a job (lossily decoded)
+    fn output(&self, id: usize) -> Option<(String, Status, String)> {
+        self.jobs.iter().find(|j| j.id == id).map(|j| {
+            let output = j.output.lock().unwrap();
+            let output = String::from_utf8_lossy(&output).into_owned();
+            (j.label.clone(), j.status, output)
+        })
+    }
+
+    /// Labels for the jobs panel, newest first
+    fn rows(&self) -> Vec<(String, usize)> {
+        self.jobs
+            .iter()
+            .rev()
+            .map(|j| (format!("{:>3}  {:<12} {}", j.id, j.status, j.label), j.id))
+            .collect()
+    }
+}
+
+/// Id of the highlighted job in the jobs panel
+fn selected_job(siv: &mut Cursive) -> Option<usize> {
+    siv.call_on_id("jobsview", |v: &mut SelectView<usize>| v.selection())
+        .and_then(|id| id)
+        .map(|id| *id)
+}
+
+/// Refresh the statuses in the jobs panel
+fn ui_refresh(siv: &mut Cursive) {
+    let rows = do_app(siv, |_, state| {
+        state.jobs.poll();
+        state.jobs.rows()
+    });
+    siv.call_on_id("jobsview", |v: &mut SelectView<usize>| {
+        let focus = v.selected_id().unwrap_or(0);
+        v.clear();
+        v.add_all(rows);
+        v.set_selection(focus);
+    });
+}
+
+/// UI callback to kill the highlighted job
+fn ui_kill(siv: &mut Cursive) {
+    if let Some(id) = selected_job(siv) {
+        if let Err(e) = do_app(siv, |_, state| state.jobs.kill(id)) {
+            ui_error(siv, e);
+        }
+        ui_refresh(siv);
+    }
+}
+
+/// UI callback to show the output of the highlighted job
+fn ui_output(siv: &mut Cursive) {
+    if let Some(id) = selected_job(siv) {
+        ui_show_output(siv, id);
+    }
+}
+
+/// Show the output of a job
+fn ui_show_output(siv: &mut Cursive, id: usize) {
+    let job = do_app(siv, |_, state| {
+        state.jobs.poll();
+        state.jobs.output(id)
+    });
+    let (label, status, output) = match job {
+        Some(job) => job,
+        None => return,
+    };
+    let output = if output.is_empty() {
+        "(no output)".to_owned()
+    } else {
+        output
+    };
+
+    let content = ScrollView::new(TextView::new(output).no_wrap())
+        .scroll_x(true)
+        .scroll_strategy(ScrollStrategy::StickToBottom);
+    siv.add_layer(
+        Dialog::around(content.max_height(30))
+            .title(format!("{}: {}", label, status))
+            .button(tr!("Close"), |siv| {
+                siv.pop_layer();
+            }),
+    );
+}
+
+/// Show the jobs panel
+pub fn ui_view(siv: &mut Cursive) {
+    let rows = do_app(siv, |_, state| {
+        state.jobs.poll();
+        state.jobs.rows()
+    });
+    if rows.is_empty() {
+        return siv.add_layer(Dialog::info(tr!("No commands have been run.")));
+    }
+
+    let mut list = SelectView::<usize>::new();
+    list.add_all(rows);
+    siv.add_layer(
+        Dialog::around(ScrollView::new(list.with_id("jobsview")).max_height(20))
+            .title(tr!("Jobs"))
+            .button(tr!("Output"), ui_output)
+            .button(tr!("Kill"), ui_kill)
+            .button(tr!("Refresh"), ui_refresh)
+            .button(tr!("Close"), |siv| {
+                siv.pop_layer();
+            }),
+    );
+}
@@ -0,0 +1,274 @@
+//! Journal of tag changes, for tags trees shared between users
+//!
+//! With `journal = true`, every tag and untag is appended to
+//! `.linkorgasm/journal` in the tags dir, one line per change: time,
+//! client (host and PID), `tag` or `untag`, tag name and item. Before a
+//! batch of tag changes, and every few seconds in the UI, a client replays
+//! the lines the others appended since it last looked: the tags they
+//! touched are reloaded from disk, and tags they created are added. So
+//! nobody acts on a stale view of someone else's tags, and all clients end
+//! up showing the same tags tree.
+//!
+//! Tags created and deleted are journaled too, with an empty item, so the
+//! others add or drop them.
+//!
+//! Journal clients lock the tags dir shared (see `lock`), so they can run
+//! side by side. The first client in finds nobody with lines left to read,
+//! so it empties the journal. Nothing is journaled in dry-run and
+//! read-only mode.
+//!
+//! Each line is appended with a single write, which keeps the lines of
+//! clients on one machine from mixing. On NFS, appends from different
+//! machines aren't atomic and lines may get mangled; a mangled line is
+//! skipped, so a change made then shows up only on the next reload.
+//!
+//! The journal starts with a header line naming its format version (see
+//! `selfcheck`). A journal of a newer version is not touched.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use chrono::Local;
+use cursive::Cursive;
+use hashbrown::HashSet;
+
+use crate::handle::AppHandle;
+use crate::selfcheck;
+use crate::{
+    create_tag, rescan_tag, run_filter, tag_dir, ui_refresh_itemview, AppState, SharedPath,
+    META_DIR,
+};
+
+/// Name of the journal in the metadata dir
+pub static JOURNAL_FILE: &str = "journal";
+
+/// Format version of the journal understood by this binary
+pub const VERSION: u32 = 1;
+
+/// First word of the header line
+pub static HEADER: &str = "linkorgasm-journal";
+
+/// Time between replays in the UI
+pub const SYNC_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The journal of the tags dir of a session
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    /// opened for appending
+    file: File,
+    /// how far it has been read
+    offset: u64,
+    /// this client, as written to the lines
+    client: String,
+}
+
+pub fn journal_path(tags_path: &Path) -> PathBuf {
+    tags_path.join(META_DIR).join(JOURNAL_FILE)
+}
+
+/// Upgrade the lines of a journal by one version, from the given one
+///
+/// There is only version 1 so far.
+pub fn migrate(_lines: &mut Vec<u8>, _from: u32) {}
+
+/// Host name and PID, telling the clients apart
+fn client_id() -> String {
+    let mut buf = [0u8; 256];
+    let res = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    let host = if res == 0 {
+        let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+        String::from_utf8_lossy(&buf[..len]).into_owned()
+    } else {
+        "localhost".to_owned()
+    };
+    format!("{}:{}", host, process::id())
+}
+
+/// Escape tabs, newlines and backslashes in a field
+fn escape_field(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape_field(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Start journaling the changes to a tags dir, if configured
+///
+/// Changes made before are on disk already, so reading starts at the end.
+pub fn open(state: &mut AppState, tags_path: &Path) -> io::Result<()> {
+    if !state.config.journal || state.fs.is_dry_run() || state.fs.is_read_only() {
+        return Ok(());
+    }
+    fs::create_dir_all(tags_path.join(META_DIR))?;
+    let path = journal_path(tags_path);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(&path)?;
+    if let Some(ref mut lock) = state.lock {
+        if lock.is_alone() {
+            file.set_len(0)?;
+            lock.share()?;
+        }
+    }
+    let mut start = Vec::new();
+    (&mut file).take(64).read_to_end(&mut start)?;
+    if start.is_empty() {
+        file.write_all(selfcheck::header(HEADER, VERSION).as_bytes())?;
+    } else if let (Some(v), _) = selfcheck::split_header(&start, HEADER) {
+        if v > VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "journal has version {}, but this binary only understands up to {}",
+                    v, VERSION
+                ),
+            ));
+        }
+    }
+    let offset = file.metadata()?.len();
+    state.journal = Some(Journal {
+        path,
+        file,
+        offset,
+        client: client_id(),
+    });
+    Ok(())
+}
+
+/// Append a change to an item's tags that was made
+pub fn record(state: &mut AppState, op: &str, tp: &Path, ip: &Path) {
+    let item = match state.items_path {
+        Some(ref items) => ip.strip_prefix(items).unwrap_or(ip),
+        None => ip,
+    };
+    let item = escape_field(&item.to_string_lossy());
+    append(state, op, tp, &item);
+}
+
+/// Append the creation (`tag-create`) or deletion (`tag-delete`) of a tag
+///
+/// A deleted tag must still be in the tags index.
+pub fn record_tag(state: &mut AppState, op: &str, tp: &Path) {
+    append(state, op, tp, "");
+}
+
+/// Append a line for a change of the tag `tp`
+fn append(state: &mut AppState, op: &str, tp: &Path, item: &str) {
+    let name = match state.tags.get(tp) {
+        Some(tag) => escape_field(&tag.name),
+        None => return,
+    };
+    let journal = match state.journal {
+        Some(ref mut journal) => journal,
+        None => return,
+    };
+    let line = format!(
+        "{}\t{}\t{}\t{}\t{}\n",
+        Local::now().to_rfc3339(),
+        journal.client,
+        op,
+        name,
+        item
+    );
+    // one write, so lines of clients appending at once don't mix (see
+    // above for NFS); the change itself was made, so it must not be
+    // reported as failed
+    let _ = journal.file.write_all(line.as_bytes());
+}
+
+/// Names of the tags changed by other clients since the last call
+fn read_new(journal: &mut Journal) -> io::Result<Vec<String>> {
+    let mut file = File::open(&journal.path)?;
+    file.seek(SeekFrom::Start(journal.offset))?;
+    let mut new = Vec::new();
+    file.read_to_end(&mut new)?;
+    // a line being written is read next time
+    let complete = match new.iter().rposition(|&b| b == b'\n') {
+        Some(end) => end + 1,
+        None => return Ok(Vec::new()),
+    };
+    journal.offset += complete as u64;
+    let text = String::from_utf8_lossy(&new[..complete]);
+    let names = text
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            match fields.as_slice() {
+                [_, client, _, name, _] if *client != journal.client => {
+                    Some(unescape_field(name))
+                }
+                _ => None,
+            }
+        })
+        .collect();
+    Ok(names)
+}
+
+/// Reload the tags changed by other clients, returns how many there were
+pub fn sync(state: &mut AppState) -> usize {
+    let names = match state.journal.as_mut().map(read_new) {
+        Some(Ok(names)) => names,
+        _ => return 0,
+    };
+    let names: HashSet<String> = names.into_iter().collect();
+    for name in names.iter() {
+        let known = state
+            .tags
+            .iter()
+            .find(|(_, tag)| tag.name == *name)
+            .map(|(tp, _)| tp.clone());
+        let tp: Option<SharedPath> = match known {
+            Some(tp) => Some(tp),
+            // created by another client; this only adds it to the index
+            None if tag_dir(state, name).is_dir() => {
+                create_tag(state, name).ok().map(|tp| tp.into())
+            }
+            None => None,
+        };
+        if let Some(tp) = tp {
+            rescan_tag(state, &tp);
+        }
+    }
+    names.len()
+}
+
+/// Replay the changes of other clients every few seconds while the UI runs
+pub fn ui_start_sync(siv: &Cursive) {
+    let app = AppHandle::new(siv);
+    thread::spawn(move || loop {
+        thread::sleep(SYNC_INTERVAL);
+        let running = app.post(|siv, state| {
+            if sync(state) > 0 {
+                run_filter(state);
+                ui_refresh_itemview(siv, state);
+            }
+        });
+        if !running {
+            break;
+        }
+    });
+}
@@ -0,0 +1,41 @@
+//! Key/value tags
+//!
+//! A tag like `year=2020` is stored as the nested dir `year=/2020`: the
+//! parent dir, whose name ends with `=`, holds one tag per value of the key.
+//! It stays browsable in a file manager, while the tags view groups the
+//! values under their key and queries can compare them (`year>=2018`).
+
+use std::path::PathBuf;
+
+/// Split a tag name given by the user into key and value
+fn parse(name: &str) -> Option<(&str, &str)> {
+    if name.contains('/') {
+        return None;
+    }
+    let eq = name.find('=')?;
+    let (key, value) = (&name[..eq], &name[eq + 1..]);
+    if key.is_empty() || value.is_empty() {
+        return None;
+    }
+    Some((key, value))
+}
+
+/// Path of a tag dir (relative to the tags dir) for a tag name
+///
+/// `key=value` maps to `key=/value`, other names are paths already.
+pub fn tag_path(name: &str) -> PathBuf {
+    match parse(name) {
+        Some((key, value)) => PathBuf::from(format!("{}=", key)).join(value),
+        None => PathBuf::from(name),
+    }
+}
+
+/// Key and value of a tag, from its path relative to the tags dir
+pub fn from_rel_name(rel: &str) -> Option<(String, String)> {
+    let sep = rel.find("=/")?;
+    let (key, value) = (&rel[..sep], &rel[sep + 2..]);
+    if key.is_empty() || key.contains('/') || value.is_empty() || value.contains('/') {
+        return None;
+    }
+    Some((key.to_owned(), value.to_owned()))
+}
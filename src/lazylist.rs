@@ -0,0 +1,326 @@
+//! A list view that only renders what is visible
+//!
+//! `SelectView` keeps a styled label for every row and measures all of them
+//! on every layout, which gets sluggish with 100k items. `LazyList` keeps
+//! plain names (for sorting, and shown until a row gets its label) and
+//! styled labels only for the rows around the visible window. When the
+//! cursor moves to rows without labels, the `on_page` callback is run to
+//! fill them in with `set_label`. The `on_select` callback is run when the
+//! user moves the cursor.
+//!
+//! Its methods are named like those of `SelectView`. Values are stored as
+//! given and cloned by `selection`, so they should be cheap to clone (like
+//! an `Rc`). Header rows (`add_header`) have no value and are skipped by
+//! the cursor.
+
+use std::cmp::{max, min, Ordering};
+use std::ops::Range;
+use std::rc::Rc;
+
+use cursive::direction::Direction;
+use cursive::event::{Callback, Event, EventResult, Key, MouseButton, MouseEvent};
+use cursive::theme::Effect;
+use cursive::utils::markup::StyledString;
+use cursive::{Cursive, Printer, Vec2, View};
+use unicode_width::UnicodeWidthStr;
+
+use crate::checklist;
+
+/// Rows labeled beyond the visible window, in each direction
+const MARGIN: usize = 50;
+
+/// Rows assumed to be visible before the first layout
+const MIN_ROWS: usize = 100;
+
+pub struct LazyList<T> {
+    /// plain name and value of each row, `None` for headers
+    items: Vec<(String, Option<T>)>,
+    /// styled labels, only for rows that have been paged in
+    labels: Vec<Option<StyledString>>,
+    focus: usize,
+    /// first visible row
+    offset: usize,
+    /// number of visible rows, from the last layout
+    height: usize,
+    /// width of the widest name
+    names_width: usize,
+    /// width of the widest row shown so far
+    width: usize,
+    on_page: Option<Rc<dyn Fn(&mut Cursive)>>,
+    on_select: Option<Rc<dyn Fn(&mut Cursive)>>,
+}
+
+impl<T: Clone + 'static> LazyList<T> {
+    pub fn new() -> Self {
+        LazyList {
+            items: Vec::new(),
+            labels: Vec::new(),
+            focus: 0,
+            offset: 0,
+            height: 0,
+            names_width: 0,
+            width: 0,
+            on_page: None,
+            on_select: None,
+        }
+    }
+
+    /// Set the callback filling in labels for the rows in `window`
+    pub fn on_page<F: Fn(&mut Cursive) + 'static>(mut self, f: F) -> Self {
+        self.on_page = Some(Rc::new(f));
+        self
+    }
+
+    /// Set the callback run when the user moves the cursor to another row
+    pub fn on_select<F: Fn(&mut Cursive) + 'static>(mut self, f: F) -> Self {
+        self.on_select = Some(Rc::new(f));
+        self
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.labels.clear();
+        self.focus = 0;
+        self.offset = 0;
+        self.names_width = 0;
+        self.width = 0;
+    }
+
+    pub fn add_item(&mut self, name: String, value: T) {
+        self.names_width = max(self.names_width, name.width());
+        self.width = max(self.width, self.names_width);
+        self.items.push((name, Some(value)));
+        self.labels.push(None);
+        // the cursor starts on the first row that isn't a header
+        if self.is_header(self.focus) {
+            self.focus = self.items.len() - 1;
+        }
+    }
+
+    /// Add a row that only shows a title, like the name of a group
+    pub fn add_header(&mut self, title: String) {
+        let label = StyledString::styled(title.clone(), Effect::Bold);
+        self.names_width = max(self.names_width, title.width());
+        self.width = max(self.width, self.names_width);
+        self.items.push((title, None));
+        self.labels.push(Some(label));
+    }
+
+    fn is_header(&self, i: usize) -> bool {
+        self.items[i].1.is_none()
+    }
+
+    /// Sort the rows by name with a comparison function, dropping their labels
+    ///
+    /// Only for lists without headers.
+    pub fn sort_by<F: FnMut(&str, &str) -> Ordering>(&mut self, mut compare: F) {
+        self.items.sort_by(|a, b| compare(&a.0, &b.0));
+        self.invalidate();
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Name and value of a row, `None` for headers
+    pub fn get_item(&self, i: usize) -> Option<(&str, &T)> {
+        match self.items.get(i) {
+            Some((name, Some(value))) => Some((name.as_str(), value)),
+            _ => None,
+        }
+    }
+
+    pub fn selected_id(&self) -> Option<usize> {
+        if self.items.is_empty() || self.is_header(self.focus) {
+            None
+        } else {
+            Some(self.focus)
+        }
+    }
+
+    pub fn selection(&self) -> Option<T> {
+        self.selected_id().and_then(|i| self.items[i].1.clone())
+    }
+
+    /// Move the cursor to a row
+    ///
+    /// The returned callback pages in labels, if needed.
+    pub fn set_selection(&mut self, i: usize) -> Callback {
+        if self.items.is_empty() {
+            return Callback::dummy();
+        }
+        self.focus = min(i, self.items.len() - 1);
+        self.skip_headers(true);
+        self.scroll_to_focus();
+        match self.on_page {
+            Some(ref f) if self.needs_labels() => {
+                let f = Rc::clone(f);
+                Callback::from_fn(move |siv| f(siv))
+            }
+            _ => Callback::dummy(),
+        }
+    }
+
+    /// Rows that should have labels: the visible ones and a margin
+    pub fn window(&self) -> Range<usize> {
+        let rows = max(self.height, MIN_ROWS);
+        let start = self.offset.saturating_sub(MARGIN);
+        let end = min(self.offset + rows + MARGIN, self.items.len());
+        start..end
+    }
+
+    pub fn has_label(&self, i: usize) -> bool {
+        self.labels[i].is_some()
+    }
+
+    pub fn set_label(&mut self, i: usize, label: StyledString) {
+        self.width = max(self.width, label.width());
+        self.labels[i] = Some(label);
+    }
+
+    /// Drop all labels (but those of headers), so they are paged in again
+    pub fn invalidate(&mut self) {
+        for (label, (_, value)) in self.labels.iter_mut().zip(self.items.iter()) {
+            if value.is_some() {
+                *label = None;
+            }
+        }
+        self.width = self.names_width;
+    }
+
+    /// Move the cursor off a header, in the direction it was moving if
+    /// there is an item that way
+    fn skip_headers(&mut self, down: bool) {
+        let below = (self.focus..self.items.len()).find(|&i| !self.is_header(i));
+        let above = (0..=self.focus).rev().find(|&i| !self.is_header(i));
+        let next = if down { below.or(above) } else { above.or(below) };
+        if let Some(i) = next {
+            self.focus = i;
+        }
+    }
+
+    fn needs_labels(&self) -> bool {
+        self.window().any(|i| self.labels[i].is_none())
+    }
+
+    fn scroll_to_focus(&mut self) {
+        if self.focus < self.offset {
+            self.offset = self.focus;
+        } else if self.height > 0 && self.focus >= self.offset + self.height {
+            self.offset = self.focus + 1 - self.height;
+        }
+    }
+
+    /// Result of a cursor movement from the row `old`: page in labels, if
+    /// needed, and run `on_select` if the cursor is on another row
+    fn moved(&mut self, down: bool, old: usize) -> EventResult {
+        self.skip_headers(down);
+        self.scroll_to_focus();
+        let page = match self.on_page {
+            Some(ref f) if self.needs_labels() => Some(Rc::clone(f)),
+            _ => None,
+        };
+        let select = match self.on_select {
+            Some(ref f) if self.focus != old => Some(Rc::clone(f)),
+            _ => None,
+        };
+        if page.is_none() && select.is_none() {
+            return EventResult::Consumed(None);
+        }
+        EventResult::with_cb(move |siv| {
+            if let Some(ref f) = page {
+                f(siv);
+            }
+            if let Some(ref f) = select {
+                f(siv);
+            }
+        })
+    }
+}
+
+impl<T: Clone + 'static> Default for LazyList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone + 'static> View for LazyList<T> {
+    fn draw(&self, printer: &Printer) {
+        let end = min(self.offset + printer.size.y, self.items.len());
+        for (y, i) in (self.offset..end).enumerate() {
+            let style = checklist::row_style(printer, i == self.focus);
+            printer.with_color(style, |printer| {
+                let width = match self.labels[i] {
+                    Some(ref label) => {
+                        checklist::print_label(printer, Vec2::new(0, y), label, style);
+                        label.width()
+                    }
+                    None => {
+                        let name = &self.items[i].0;
+                        printer.print((0, y), name);
+                        name.width()
+                    }
+                };
+                if width < printer.size.x {
+                    printer.print_hline((width, y), printer.size.x - width, " ");
+                }
+            });
+        }
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        Vec2::new(self.width, min(self.items.len(), constraint.y))
+    }
+
+    fn layout(&mut self, size: Vec2) {
+        self.height = size.y;
+        // don't leave empty rows at the bottom when the list fits
+        if self.offset + self.height > self.items.len() {
+            self.offset = self.items.len().saturating_sub(self.height);
+        }
+        self.scroll_to_focus();
+    }
+
+    fn take_focus(&mut self, _: Direction) -> bool {
+        self.items.iter().any(|(_, value)| value.is_some())
+    }
+
+    fn on_event(&mut self, event: Event) -> EventResult {
+        if self.items.is_empty() {
+            return EventResult::Ignored;
+        }
+        let last = self.items.len() - 1;
+        let page = max(self.height, 1);
+        let old = self.focus;
+        match event {
+            Event::Key(Key::Up) if self.focus > 0 => self.focus -= 1,
+            Event::Key(Key::Down) if self.focus < last => self.focus += 1,
+            Event::Key(Key::PageUp) => self.focus = self.focus.saturating_sub(page),
+            Event::Key(Key::PageDown) => self.focus = min(self.focus + page, last),
+            Event::Key(Key::Home) => self.focus = 0,
+            Event::Key(Key::End) => self.focus = last,
+            Event::Mouse {
+                event: MouseEvent::WheelUp,
+                ..
+            } => self.focus = self.focus.saturating_sub(3),
+            Event::Mouse {
+                event: MouseEvent::WheelDown,
+                ..
+            } => self.focus = min(self.focus + 3, last),
+            Event::Mouse {
+                event: MouseEvent::Press(MouseButton::Left),
+                position,
+                offset,
+            } => match position.checked_sub(offset) {
+                Some(pos) if self.offset + pos.y <= last => self.focus = self.offset + pos.y,
+                _ => return EventResult::Ignored,
+            },
+            _ => return EventResult::Ignored,
+        }
+        self.moved(self.focus >= old, old)
+    }
+}
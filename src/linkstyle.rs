@@ -0,0 +1,79 @@
+//! Relative or absolute symlinks
+//!
+//! Symlinks are relative by default (`../../music/song.flac`), so a tags
+//! dir keeps working when it is moved together with the items. A relative
+//! link that crosses a mount point breaks, though, when one side is
+//! mounted elsewhere (a different filesystem, or a bind mount). With the
+//! `link-style = "auto"` default, such links are made absolute instead.
+
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use log::info;
+use serde::Deserialize;
+
+use crate::tag_target_path;
+
+/// How symlinks in tag dirs point at items
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkStyle {
+    /// relative, unless that crosses a mount point
+    Auto,
+    /// always relative to the tag dir
+    Relative,
+    /// always the canonical path of the item
+    Absolute,
+}
+
+/// Device of a path, `None` if it can't be accessed
+fn device(p: &Path) -> Option<u64> {
+    fs::metadata(p).ok().map(|m| m.dev())
+}
+
+/// Whether the way from `tag` to `item` through their common ancestor
+/// leaves the filesystem of that ancestor
+///
+/// `tag` and `item` are canonical paths. Paths that can't be accessed (as
+/// in dry-run mode) don't count.
+fn crosses_mount(tag: &Path, item: &Path) -> bool {
+    let common: PathBuf = tag
+        .components()
+        .zip(item.components())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a)
+        .collect();
+    let dev = match device(&common) {
+        Some(dev) => dev,
+        None => return false,
+    };
+    let item_dir = item.parent().unwrap_or(item);
+    [tag, item_dir].iter().any(|end| {
+        end.ancestors()
+            .take_while(|dir| *dir != common)
+            .any(|dir| device(dir).map_or(false, |d| d != dev))
+    })
+}
+
+/// Target for a new symlink in the tag dir `tag` to `item`
+///
+/// `tag` and `item` are canonical paths.
+pub fn target(style: LinkStyle, tag: &Path, item: &Path) -> PathBuf {
+    match style {
+        LinkStyle::Relative => tag_target_path(tag, item),
+        LinkStyle::Absolute => item.to_path_buf(),
+        LinkStyle::Auto => {
+            if crosses_mount(tag, item) {
+                info!(
+                    "absolute link for {} in {}: relative one would cross a mount point",
+                    item.display(),
+                    tag.display()
+                );
+                item.to_path_buf()
+            } else {
+                tag_target_path(tag, item)
+            }
+        }
+    }
+}
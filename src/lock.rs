@@ -0,0 +1,117 @@
+//! Instance locking for the tags directory
+//!
+//! Two sessions on the same tags tree would clobber each other's symlinks
+//! and work on stale indexes, so a session holds an advisory lock on
+//! `.linkorgasm/lock` in the tags dir. The lock is released by the OS when
+//! the process exits, even if it crashes. The file contains the PID of the
+//! holder, for messages.
+//!
+//! Sessions that keep a journal (see `journal`) take the lock shared
+//! instead: they can work side by side, but not with sessions without it,
+//! nor with commands rewriting links in bulk (like `gc` or `relink`),
+//! which aren't journaled and always lock exclusively. The first journal
+//! client in holds the lock exclusively until it has emptied the journal.
+//!
+//! The extra tag roots from the config are locked the same way, each with
+//! its own lock, as other instances may use them with other tags dirs.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use crate::META_DIR;
+
+/// Name of the lock file in the metadata dir
+pub static LOCK_FILE: &str = "lock";
+
+/// A held lock, released on drop
+pub struct Lock {
+    file: File,
+    /// whether it is to be shared once the journal is emptied
+    shared: bool,
+    /// whether it is held exclusively, so nobody else uses the tags dir
+    alone: bool,
+}
+
+impl Lock {
+    /// Whether nobody else holds the lock
+    pub fn is_alone(&self) -> bool {
+        self.alone
+    }
+
+    /// Let other journal clients in, if the lock was asked for shared
+    pub fn share(&mut self) -> io::Result<()> {
+        if !self.shared || !self.alone {
+            return Ok(());
+        }
+        if unsafe { libc::flock(self.file.as_raw_fd(), libc::LOCK_SH) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.alone = false;
+        Ok(())
+    }
+}
+
+fn lock_path(tags_path: &Path) -> PathBuf {
+    tags_path.join(META_DIR).join(LOCK_FILE)
+}
+
+/// Lock a tags dir for this instance, `shared` with other journal clients
+///
+/// A shared lock is taken exclusively if nobody else holds it, until
+/// `share` is called. Fails with `WouldBlock` if another instance holds
+/// the lock.
+pub fn acquire(tags_path: &Path, shared: bool) -> io::Result<Lock> {
+    fs::create_dir_all(tags_path.join(META_DIR))?;
+    // don't truncate yet, the PID belongs to the holder until we get the lock
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .open(lock_path(tags_path))?;
+    let fd = file.as_raw_fd();
+    if unsafe { libc::flock(fd, libc::LOCK_EX | libc::LOCK_NB) } < 0 {
+        let e = io::Error::last_os_error();
+        if !shared || e.kind() != io::ErrorKind::WouldBlock {
+            return Err(e);
+        }
+        if unsafe { libc::flock(fd, libc::LOCK_SH | libc::LOCK_NB) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // the other holders' PIDs stay
+        return Ok(Lock {
+            file,
+            shared,
+            alone: false,
+        });
+    }
+    file.set_len(0)?;
+    writeln!(file, "{}", std::process::id())?;
+    Ok(Lock {
+        file,
+        shared,
+        alone: true,
+    })
+}
+
+/// Lock extra tag roots like the tags dir, see `acquire`
+///
+/// Returns the locks, or the root that couldn't be locked and why.
+pub fn acquire_roots(roots: &[PathBuf], shared: bool) -> Result<Vec<Lock>, (PathBuf, io::Error)> {
+    let mut locks = Vec::new();
+    for root in roots {
+        let mut lock = acquire(root, shared).map_err(|e| (root.clone(), e))?;
+        // the journal is in the tags dir, there is nothing to empty here
+        lock.share().map_err(|e| (root.clone(), e))?;
+        locks.push(lock);
+    }
+    Ok(locks)
+}
+
+/// Describe the instance holding the lock of a tags dir
+pub fn holder(tags_path: &Path) -> String {
+    match fs::read_to_string(lock_path(tags_path)) {
+        Ok(ref pid) if !pid.trim().is_empty() => format!("pid {}", pid.trim()),
+        _ => "unknown pid".to_owned(),
+    }
+}
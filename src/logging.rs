@@ -0,0 +1,66 @@
+//! Log file for debugging
+//!
+//! The UI takes over the terminal, so diagnostics go to the file given with
+//! `--log-file`: scan timings, filesystem operations and errors. `-v` adds
+//! debug messages (like every operation), `-vv` everything. Without
+//! `--log-file`, nothing is logged.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::Local;
+use log::{Level, Log, Metadata, Record};
+
+struct FileLogger {
+    file: Mutex<File>,
+    level: Level,
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let mut file = self.file.lock().unwrap();
+        // logging must never break the program
+        let _ = writeln!(
+            file,
+            "{} {:5} {}: {}",
+            Local::now().to_rfc3339(),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+    }
+
+    fn flush(&self) {
+        let _ = self.file.lock().unwrap().flush();
+    }
+}
+
+/// Start logging to a file (appending), at a level set by the `-v` count
+pub fn init(path: Option<&Path>, verbosity: u64) -> io::Result<()> {
+    let path = match path {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let level = match verbosity {
+        0 => Level::Info,
+        1 => Level::Debug,
+        _ => Level::Trace,
+    };
+    log::set_boxed_logger(Box::new(FileLogger {
+        file: Mutex::new(file),
+        level,
+    }))
+    .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+    log::set_max_level(level.to_level_filter());
+    Ok(())
+}
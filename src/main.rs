@@ -1,73 +1,428 @@
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
 use std::fs;
-use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::io::{self, BufRead, Write};
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
 
+use clap::ArgMatches;
+use cursive::event::{Event, Key};
+use cursive::theme::{Color, Effect};
 use cursive::traits::*;
+use cursive::utils::markup::StyledString;
+use cursive::view::{ScrollStrategy, SizeConstraint};
 use cursive::views::{
-    Dialog, DummyView, EditView, LinearLayout, OnEventView, ScrollView, SelectView, TextView,
+    BoxView, Dialog, DummyView, EditView, IdView, LinearLayout, OnEventView, Panel, ScrollView,
+    TextView,
 };
 use cursive::Cursive;
 
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use hashbrown::{HashMap, HashSet};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+// first, so that its `tr!` macro can be used in the other modules
+#[macro_use]
+mod i18n;
+
+mod annex;
+mod apply;
+mod archive;
+mod audit;
+mod backend;
+mod browser;
+mod checklist;
+mod cli;
+mod clipboard;
+mod color;
+mod columns;
+mod completions;
+mod config;
+mod consistency;
+mod content;
+#[cfg(feature = "daemon")]
+mod daemon;
+#[cfg(feature = "dbus")]
+mod dbus;
+#[cfg(feature = "trash")]
+mod delete;
+mod describe;
+mod description;
+mod escape;
+mod exit;
+mod filetype;
+mod footer;
+mod foreign;
+mod fsops;
+mod gc;
+#[cfg(feature = "gui")]
+mod gui;
+mod handle;
+mod hooks;
+mod html;
+mod icons;
+mod identity;
+#[cfg(feature = "daemon")]
+mod index;
+#[cfg(feature = "ipc")]
+mod ipc;
+#[cfg(feature = "preview")]
+mod jobs;
+mod journal;
+mod kv;
+mod lazylist;
+mod linkstyle;
+mod lock;
+mod logging;
+mod maintain;
+mod manifest;
+mod natsort;
+mod normalize;
+mod picktags;
+mod playlist;
+#[cfg(feature = "plugins")]
+mod plugins;
+#[cfg(feature = "preview")]
+mod preview;
+mod query;
+mod recent;
+mod registers;
+mod relink;
+mod rename;
+mod rest;
+mod search;
+mod selfcheck;
+mod serve;
+mod session;
+mod snapshot;
+mod stats;
+mod tageditor;
+mod theme;
+mod tty;
+mod tutorial;
+
+use crate::checklist::{Check, CheckList};
+use crate::config::{Config, Identity, ItemSymlinks, Layout};
+use crate::lazylist::LazyList;
 
 static HELP_TEXT: &str = r"
 The main window is split in two panes: items (left) and tags (right).
-Use arrow keys and TAB to navigate.
+Use arrow keys and TAB to navigate. The line at the bottom shows the main
+keys of the focused pane.
 
 You can select items using the spacebar and the tags view will update
-to show which tags apply to all currently selected items.
+to show which tags apply to all currently selected items ([X]) and
+which to only some of them ([-]).
 
 In the tags view, use the spacebar to toggle the status of a tag for
-all currently selected items.
+all currently selected items. The items stay selected, so more tags can
+be toggled for them right away. With no items selected, it toggles the
+tag for the highlighted item (unless `implicit-selection` is off).
+
+The checkboxes of the items carrying the tag under the tags view cursor
+are shown reversed, so its items can be seen without filtering.
+
+Directories are items too and are shown with a trailing '/'.
 
 Commands when in the items view:
 space   => select/deselect item
 'e'     => open selected items (you will be asked for command to use)
+           (not available in builds without the `preview` feature)
+'o'     => open selected items with the commands configured for their
+           extensions (`open-with`), asking for one for the others
+           (needs the `preview` feature, too)
+'E'     => run a command (e.g. `sha256sum`) on all selected items at once
+           and show its output (needs the `preview` feature, too)
+'y'     => copy paths of selected items to the clipboard
+'P'     => write the shown items (e.g. a query result) to an .m3u8 playlist
+F2      => rename the highlighted item (its symlinks are updated)
+'M'     => move selected items to another directory (symlinks updated)
+'D'     => move selected items to the trash (their symlinks are removed)
+           (not available in builds without the `trash` feature)
+':'     => run a plugin script from the config dir's `plugins` dir
+           (only available in builds with the `plugins` feature)
+'s'     => save the selection under a name
+'l'     => recall a saved selection (replace or merge into the selection)
+'v'     => list the selected items with their number and combined size
+enter   => edit all tags of the highlighted item in a checklist
+           (space checks/unchecks a tag, changes are made on Apply)
+'t'     => move the tags view cursor to the highlighted item's tags
+           (press again to cycle through them)
+'m'     => cycle metadata columns (size, modification time, tag count)
+'p'     => cycle item names: file name, path in items dir, canonical path
+'G'     => group the items under their tags (items with several tags are
+           listed under each), or list them plainly again
+'1'-'0' => toggle the tag pinned to the number key on each selected item
 'r'     => reset filters (show all items)
-'0'     => hide items that have tags (filter to show only untagged)
+'u'     => hide items that have tags (filter to show only untagged;
+           '0' does the same while no tag is pinned to it)
+'x'     => show only items with given file extensions
+'f'     => show only items matching a query, e.g. `jazz -live year>=2018`
+           (tag names, '-' to exclude a tag, comparisons of key=value tags,
+           `size>10M`, `mtime<2022-01-01`, `type:image`, `text:word`)
 
 Commands when in the tags view:
 space   => toggle tag on each selected item
-'+'     => create a new tag
+'+'     => create a new tag (`key=value` creates a key/value tag)
+'c'     => set the tag's color (items carrying the tag are tinted with it)
+'d'     => edit the tag's description, shown below the tags view while
+           the tag is highlighted (kept in a `.description` file)
+'R'     => re-read the items and tags directories, list the items and links
+           added or removed by others since they were scanned, and accept
+           the changes, select the items involved, or re-apply yours
+'s'     => cycle the order of tags: by name, by number of items, or most
+           recently toggled first
+'i'     => move the items view cursor to the tag's items
+           (press again to cycle through them)
+'a'     => show the log of changes made to the tags directory
+'g'     => delete tags that have no items (and only empty nested tags)
+'P'     => write the tag's items to an .m3u8 playlist
+'1'-'0' => pin the tag to the number key (see the items view)
+'o'     => open the tag directory in the file manager
+           (not available in builds without the `preview` feature)
 
 Global commands:
 'h'/'?' => show this help screen
-'q'     => quit
+'<'/'>' => shrink/grow the items pane
+'j'     => show commands started with 'e'/'E': their status and output,
+           and kill them (not available without the `preview` feature)
+'q'     => quit (the session is saved and can be restored next time); the key
+           is set with `quit-key`, and `confirm-quit` asks first
+enter   => with --print-selection: quit and print selected items
 ";
 
+/// Canonical path of an item or tag
+///
+/// The indexes (visible items, selection, tag members, the items view) all
+/// hold clones of the keys of `items_all` and `tags`, so each path is
+/// stored once even in libraries with hundreds of thousands of items.
+type SharedPath = Rc<Path>;
+
 #[derive(Debug)]
 struct Item {
     /// string to display in UI
     name: String,
     /// file name
     filename: OsString,
+    /// whether the item is a directory
+    is_dir: bool,
+    /// size in bytes
+    size: u64,
+    /// last modification time
+    mtime: Option<SystemTime>,
     /// tags for this item
-    tags: HashSet<PathBuf>,
+    tags: HashSet<SharedPath>,
 }
 
 #[derive(Debug)]
 struct Tag {
     /// string to display in UI
     name: String,
+    /// color for the tag and the items carrying it
+    color: Option<Color>,
     /// set of items tagged with this tag
     /// key is the canonical path of the item
-    /// value is a path to the symlink in the tag dir
-    items: HashMap<PathBuf, PathBuf>,
+    /// value is the file name of the symlink in the tag dir
+    items: HashMap<SharedPath, OsString>,
+    /// key and value, for key/value tags (`key=/value` dirs)
+    kv: Option<(String, String)>,
+    /// label of the extra tag root holding it, `None` for the tags dir
+    root: Option<String>,
+    /// when it was last toggled this session (see `AppState::use_clock`)
+    last_used: u64,
+}
+
+impl Tag {
+    /// Tag without items, `rel_name` is its path relative to its tag root
+    ///
+    /// Tags in an extra root are named `label:name`.
+    fn new(root: Option<&str>, rel_name: String, color: Option<Color>) -> Tag {
+        let kv = kv::from_rel_name(&rel_name);
+        let name = match kv {
+            Some((ref key, ref value)) => format!("{}={}", key, value),
+            None => rel_name,
+        };
+        let name = match root {
+            Some(label) => format!("{}:{}", label, name),
+            None => name,
+        };
+        Tag {
+            name,
+            color,
+            items: HashMap::default(),
+            kv,
+            root: root.map(str::to_owned),
+            last_used: 0,
+        }
+    }
+}
+
+/// Which items are shown in the items view
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Filter {
+    /// all items
+    All,
+    /// only items without tags
+    Untagged,
+    /// only items with one of the given (lowercase) file extensions
+    Extensions(Vec<String>),
+    /// only items matching a query (see `query`)
+    Query(String),
+}
+
+/// Order of the tags in the tags view (within each tag root)
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TagSort {
+    /// by name
+    Name,
+    /// most items first
+    Count,
+    /// most recently toggled first, then the unused ones by name
+    Recent,
+}
+
+/// What to show as the name of an item
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NameMode {
+    /// file name (or the path as given, for items read from a list)
+    Name,
+    /// path relative to the items directory
+    Relative,
+    /// canonical path
+    Canonical,
 }
 
 #[derive(Debug)]
 struct AppState {
     /// all items (indexed by canonical path)
-    items_all: HashMap<PathBuf, Item>,
+    items_all: HashMap<SharedPath, Item>,
     /// items shown in UI
-    items_vis: HashSet<PathBuf>,
+    items_vis: HashSet<SharedPath>,
+    /// filter deciding which items are shown
+    filter: Filter,
     /// all tags (indexed by canonical path)
-    tags: HashMap<PathBuf, Tag>,
+    tags: HashMap<SharedPath, Tag>,
     /// root of tags dir
     tags_path: PathBuf,
+    /// canonical path of the items dir (if items were scanned from one)
+    items_path: Option<PathBuf>,
     /// current selection (set of items selected in UI)
-    sel: HashSet<PathBuf>,
+    sel: HashSet<SharedPath>,
+    /// named selections saved during the session
+    registers: HashMap<String, HashSet<SharedPath>>,
+    /// item or tag last jumped from, and how many times in a row
+    jump: Option<(PathBuf, usize)>,
+    /// user configuration
+    config: Config,
+    /// whether Enter picks the selection (`--print-selection`)
+    print_selection: bool,
+    /// filter the main UI starts with (`--filter`)
+    start_filter: Option<Filter>,
+    /// tag the tags view cursor starts on (`--select-tag`)
+    start_tag: Option<String>,
+    /// whether the user quit by picking the selection (`--print-selection`)
+    picked: bool,
+    /// symlinks in tag dirs that don't point to a known item
+    foreign: Vec<foreign::ForeignLink>,
+    /// items that are git-annex files, by annex key (see `annex`); files
+    /// with the same content share a key
+    annex_keys: HashMap<String, Vec<SharedPath>>,
+    /// items by device and inode, with `identity = "inode"`
+    inodes: HashMap<identity::FileId, SharedPath>,
+    /// paths the items had at the last scan, with `identity = "inode"`
+    known_paths: HashMap<PathBuf, identity::Known>,
+    /// tag dirs skipped by the last scan, and why
+    skipped_tags: Vec<String>,
+    /// files skipped when scanning items (like dangling symlinks), and why
+    skipped_items: Vec<String>,
+    /// share of the main window used by the items pane (percent)
+    split: usize,
+    /// metadata columns shown in items view (index into `COLUMN_SETS`)
+    columns: usize,
+    /// what item names in the items view show
+    name_mode: NameMode,
+    /// whether the items view groups the items under their tags
+    group_by_tag: bool,
+    /// tag under the tags view cursor, whose items are marked
+    hover_tag: Option<PathBuf>,
+    /// order of the tags view
+    tag_sort: TagSort,
+    /// counts tag changes, to order tags by when they were last used
+    use_clock: u64,
+    /// width item names are padded to when columns are shown
+    name_width: usize,
+    /// executes (or, in dry-run mode, logs) changes to the tags tree
+    fs: fsops::FsOps,
+    /// lock on the tags dir, held for the whole session
+    lock: Option<lock::Lock>,
+    /// locks on the extra tag roots, likewise
+    root_locks: Vec<lock::Lock>,
+    /// journal of tag changes, if the tags dir is shared
+    journal: Option<journal::Journal>,
+    /// contents of the items searched by queries
+    content: content::Cache,
+    /// tags toggled by the number keys (see `PIN_KEYS`)
+    pinned: Vec<Option<PathBuf>>,
+    /// commands spawned from the UI
+    #[cfg(feature = "preview")]
+    jobs: jobs::Jobs,
+    /// progress through the tutorial, if running in its sandbox
+    tutorial: Option<tutorial::Tutorial>,
+    /// D-Bus service, if registered (`--dbus`)
+    #[cfg(feature = "dbus")]
+    dbus: Option<dbus::Bus>,
+}
+
+impl AppState {
+    fn new(config: Config) -> AppState {
+        AppState {
+            items_all: HashMap::default(),
+            items_vis: HashSet::default(),
+            filter: Filter::All,
+            tags: HashMap::default(),
+            tags_path: PathBuf::new(),
+            items_path: None,
+            sel: HashSet::default(),
+            registers: HashMap::default(),
+            jump: None,
+            split: config.split.clamp(10, 90),
+            config,
+            print_selection: false,
+            start_filter: None,
+            start_tag: None,
+            picked: false,
+            foreign: Vec::new(),
+            annex_keys: HashMap::default(),
+            inodes: HashMap::default(),
+            known_paths: HashMap::default(),
+            skipped_tags: Vec::new(),
+            skipped_items: Vec::new(),
+            columns: 0,
+            name_mode: NameMode::Name,
+            group_by_tag: false,
+            hover_tag: None,
+            tag_sort: TagSort::Name,
+            use_clock: 0,
+            name_width: 0,
+            fs: fsops::FsOps::default(),
+            lock: None,
+            root_locks: Vec::new(),
+            journal: None,
+            content: content::Cache::default(),
+            pinned: vec![None; PIN_KEYS.len()],
+            #[cfg(feature = "preview")]
+            jobs: jobs::Jobs::default(),
+            tutorial: None,
+            #[cfg(feature = "dbus")]
+            dbus: None,
+        }
+    }
 }
 
 fn do_app<F, R>(siv: &mut Cursive, f: F) -> R
@@ -76,26 +431,359 @@ where
 {
     let mut state = siv.take_user_data().unwrap();
     let r = f(siv, &mut state);
+    fsops::ui_show_log(siv, &mut state.fs);
+    tutorial::ui_check(siv, &mut state);
     siv.set_user_data(state);
     r
 }
 
+/// Name of the file with ignore patterns in the items directory
+static IGNORE_FILE: &str = ".linkorgasmignore";
+
+/// Name of the dir in the tags directory holding linkorgasm's own files
+///
+/// It is not a tag.
+static META_DIR: &str = ".linkorgasm";
+
+/// Build matcher for files that should not be scanned as items
+///
+/// Combines the ignore file in the items directory (gitignore syntax) with
+/// the exclude patterns from config and command line. Invalid patterns are
+/// left out, with a warning.
+fn scan_ignore(state: &AppState, root: &Path) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(root);
+    let file = root.join(IGNORE_FILE);
+    if file.exists() {
+        // the valid lines are added anyway
+        if let Some(e) = builder.add(&file) {
+            warn!("ignored patterns in {}: {}", file.display(), e);
+        }
+    }
+    for pattern in state.config.exclude.iter() {
+        if let Err(e) = builder.add_line(None, pattern) {
+            warn!("ignored exclude pattern: {}", e);
+        }
+    }
+    builder.build().unwrap_or_else(|e| {
+        warn!("ignored exclude patterns: {}", e);
+        Gitignore::empty()
+    })
+}
+
+/// Build matcher for the file names that should be scanned as items
+///
+/// `None` if there are no include patterns, meaning everything is included.
+/// Invalid patterns are left out, with a warning.
+fn scan_include(state: &AppState) -> Option<GlobSet> {
+    if state.config.include.is_empty() {
+        return None;
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in state.config.include.iter() {
+        match Glob::new(pattern) {
+            Ok(glob) => {
+                builder.add(glob);
+            }
+            Err(e) => warn!("ignored include pattern: {}", e),
+        }
+    }
+    match builder.build() {
+        Ok(include) => Some(include),
+        Err(e) => {
+            warn!("ignored include patterns: {}", e);
+            None
+        }
+    }
+}
+
+/// Build matcher for the names of dirs in the tags tree that are not tags
+///
+/// Sync and version control tools keep their metadata in dirs like `.git`
+/// or `.stversions`, which would otherwise show up as tags. Invalid
+/// patterns are left out, with a warning.
+fn tag_exclude(state: &AppState) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in state.config.tag_exclude.iter() {
+        match Glob::new(pattern) {
+            Ok(glob) => {
+                builder.add(glob);
+            }
+            Err(e) => warn!("ignored tag-exclude pattern: {}", e),
+        }
+    }
+    builder.build().unwrap_or_else(|e| {
+        warn!("ignored tag-exclude patterns: {}", e);
+        GlobSet::empty()
+    })
+}
+
+/// Whether a dir named `name` in the tags tree is not a tag
+///
+/// The meta dir at the top is never a tag, whatever the patterns.
+fn is_excluded_tag(exclude: &GlobSet, name: &OsStr, top: bool) -> bool {
+    (top && name == META_DIR) || exclude.is_match(name)
+}
+
+/// Collect size and modification time of an item
+fn item_stat(path: &Path) -> (u64, Option<SystemTime>) {
+    match fs::metadata(path) {
+        Ok(meta) => (meta.len(), meta.modified().ok()),
+        Err(_) => (0, None),
+    }
+}
+
+/// Canonical path of a file that may be a symlink, without following it
+///
+/// That is its canonical dir plus its own name.
+fn own_canonical_path(p: &Path) -> Option<PathBuf> {
+    let dir = match p.parent() {
+        Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+        Some(dir) => dir,
+        None => return None,
+    };
+    Some(dir.canonicalize().ok()?.join(p.file_name()?))
+}
+
+/// Leave a file out of the items, with a warning
+fn skip_item(state: &mut AppState, path: &Path, why: impl std::fmt::Display) {
+    let msg = format!("skipped item {}: {}", path.display(), why);
+    warn!("{}", msg);
+    state.skipped_items.push(msg);
+}
+
 /// Add files from given directory to items index
 fn scan_items(state: &mut AppState, p: impl AsRef<Path>) {
+    let p = p.as_ref();
+    let start = Instant::now();
+    let root = p.canonicalize().expect("cannot access all dir");
+    state.items_path = Some(root.clone());
+    let ignore = scan_ignore(state, p);
+    let include = scan_include(state);
+    // items found through a symlink, and the symlink
+    let mut linked: HashMap<PathBuf, PathBuf> = HashMap::default();
     for entry in fs::read_dir(p).expect("cannot access all dir") {
         let entry = entry.expect("error scanning all dir");
         let path = entry.path();
-        let cpath = path.canonicalize().unwrap();
+        let ftype = entry.file_type().expect("error scanning all dir");
+        if state.config.git_annex && entry.file_name() == ".git" {
+            continue;
+        }
+        let annex_key = if state.config.git_annex && ftype.is_symlink() {
+            annex::key_of(&path)
+        } else {
+            None
+        };
+        // entries of a canonical dir are canonical, unless they are symlinks
+        let (cpath, meta) = if annex_key.is_some() {
+            // the content may be missing
+            (root.join(entry.file_name()), fs::metadata(&path).ok())
+        } else if ftype.is_symlink() {
+            let resolved = match state.config.item_symlinks {
+                ItemSymlinks::Skip => continue,
+                ItemSymlinks::Keep => {
+                    fs::metadata(&path).map(|m| (root.join(entry.file_name()), m))
+                }
+                ItemSymlinks::Follow => path
+                    .canonicalize()
+                    .and_then(|cpath| fs::metadata(&cpath).map(|m| (cpath, m))),
+            };
+            match resolved {
+                Ok((cpath, meta)) => (cpath, Some(meta)),
+                Err(e) => {
+                    if !ignore.matched(&path, false).is_ignore() {
+                        skip_item(state, &path, format!("dangling symlink ({})", e));
+                    }
+                    continue;
+                }
+            }
+        } else {
+            (root.join(entry.file_name()), entry.metadata().ok())
+        };
+        let is_dir = meta.as_ref().map_or(false, |m| m.is_dir());
+        if entry.file_name() == IGNORE_FILE || ignore.matched(&path, is_dir).is_ignore() {
+            continue;
+        }
+        if is_dir && !state.config.include_dirs {
+            continue;
+        }
+        if let Some(ref include) = include {
+            if !include.is_match(entry.file_name()) {
+                continue;
+            }
+        }
+        if state.items_all.contains_key(cpath.as_path()) {
+            let link = if ftype.is_symlink() {
+                None
+            } else {
+                linked.remove(&cpath)
+            };
+            match link {
+                // the file itself is the item, not a symlink listed before it
+                Some(link) => {
+                    let filename = entry.file_name();
+                    let item = state.items_all.get_mut(cpath.as_path()).unwrap();
+                    item.name = escape::display_name(&filename, state.config.escape_names);
+                    item.filename = filename;
+                    skip_item(state, &link, format!("same file as {}", cpath.display()));
+                }
+                None => skip_item(state, &path, format!("same file as {}", cpath.display())),
+            }
+            continue;
+        }
+        let id = match state.config.identity {
+            Identity::Inode => meta.as_ref().map(identity::file_id),
+            Identity::Path => None,
+        };
+        if let Some(other) = id.and_then(|id| state.inodes.get(&id)) {
+            let why = format!("hard link to {}", other.display());
+            skip_item(state, &path, why);
+            continue;
+        }
         let filename = entry.file_name();
+        let (size, mtime) = meta.map_or((0, None), |m| (m.len(), m.modified().ok()));
+        if ftype.is_symlink() && annex_key.is_none() {
+            linked.insert(cpath.clone(), path.clone());
+        }
+        let ip: SharedPath = cpath.into();
+        if let Some(key) = annex_key {
+            state
+                .annex_keys
+                .entry(key)
+                .or_insert_with(Vec::new)
+                .push(Rc::clone(&ip));
+        }
+        if let Some(id) = id {
+            state.inodes.insert(id, Rc::clone(&ip));
+        }
         state.items_all.insert(
-            cpath,
+            ip,
             Item {
-                name: filename.to_string_lossy().to_string(),
+                name: escape::display_name(&filename, state.config.escape_names),
                 filename: filename,
+                is_dir,
+                size,
+                mtime,
+                tags: HashSet::default(),
+            },
+        );
+    }
+    info!(
+        "scanned {} items in {} ({:?})",
+        state.items_all.len(),
+        p.display(),
+        start.elapsed()
+    );
+}
+
+/// Add files from a newline-separated list of paths to items index
+///
+/// Paths that cannot be resolved are skipped.
+fn scan_items_list(state: &mut AppState, list: impl BufRead) {
+    let start = Instant::now();
+    let include = scan_include(state);
+    // items found through a symlink, and the symlink
+    let mut linked: HashMap<PathBuf, PathBuf> = HashMap::default();
+    for line in list.split(b'\n') {
+        let line = line.expect("error reading items list");
+        if line.is_empty() {
+            continue;
+        }
+        let path = Path::new(OsStr::from_bytes(&line));
+        let annex_key = if state.config.git_annex {
+            annex::key_of(path)
+        } else {
+            None
+        };
+        let is_link = path
+            .symlink_metadata()
+            .map_or(false, |m| m.file_type().is_symlink());
+        let cpath = match annex_key {
+            Some(_) => own_canonical_path(path),
+            None if is_link => {
+                let resolved = match state.config.item_symlinks {
+                    ItemSymlinks::Skip => continue,
+                    ItemSymlinks::Keep => path.metadata().map(|_| own_canonical_path(path)),
+                    ItemSymlinks::Follow => path.canonicalize().map(Some),
+                };
+                match resolved {
+                    Ok(cpath) => cpath,
+                    Err(e) => {
+                        skip_item(state, path, format!("dangling symlink ({})", e));
+                        continue;
+                    }
+                }
+            }
+            None => path.canonicalize().ok(),
+        };
+        let (cpath, filename) = match (cpath, path.file_name()) {
+            (Some(cpath), Some(filename)) => (cpath, filename.to_owned()),
+            _ => continue,
+        };
+        if state.items_all.contains_key(cpath.as_path()) {
+            let link = if is_link { None } else { linked.remove(&cpath) };
+            match link {
+                // the file itself is the item, not a symlink listed before it
+                Some(link) => {
+                    let name = escape::display_name(path.as_os_str(), state.config.escape_names);
+                    let item = state.items_all.get_mut(cpath.as_path()).unwrap();
+                    item.name = name;
+                    item.filename = filename;
+                    skip_item(state, &link, format!("same file as {}", cpath.display()));
+                }
+                None => skip_item(state, path, format!("same file as {}", cpath.display())),
+            }
+            continue;
+        }
+        let id = match state.config.identity {
+            Identity::Inode => fs::metadata(&cpath).ok().map(|m| identity::file_id(&m)),
+            Identity::Path => None,
+        };
+        if let Some(other) = id.and_then(|id| state.inodes.get(&id)) {
+            let why = format!("hard link to {}", other.display());
+            skip_item(state, path, why);
+            continue;
+        }
+        let is_dir = cpath.is_dir();
+        if is_dir && !state.config.include_dirs {
+            continue;
+        }
+        if let Some(ref include) = include {
+            if !include.is_match(&filename) {
+                continue;
+            }
+        }
+        let (size, mtime) = item_stat(&cpath);
+        if is_link && annex_key.is_none() {
+            linked.insert(cpath.clone(), path.to_path_buf());
+        }
+        let ip: SharedPath = cpath.into();
+        if let Some(key) = annex_key {
+            state
+                .annex_keys
+                .entry(key)
+                .or_insert_with(Vec::new)
+                .push(Rc::clone(&ip));
+        }
+        if let Some(id) = id {
+            state.inodes.insert(id, Rc::clone(&ip));
+        }
+        state.items_all.insert(
+            ip,
+            Item {
+                name: escape::display_name(path.as_os_str(), state.config.escape_names),
+                filename,
+                is_dir,
+                size,
+                mtime,
                 tags: HashSet::default(),
             },
         );
     }
+    info!(
+        "read {} items from a list ({:?})",
+        state.items_all.len(),
+        start.elapsed()
+    );
 }
 
 /// Scan tag directory
@@ -104,276 +792,1362 @@ fn scan_items(state: &mut AppState, p: impl AsRef<Path>) {
 ///
 /// Adds subdirectories to tags index.
 /// Detects symlinks that point to a known item and adds item to the tag info.
-fn scan_tags(state: &mut AppState, mut parent: Option<&mut Tag>, p: impl AsRef<Path>) {
+/// Symlinks to directory items are links, not subdirectories.
+/// Other symlinks (dangling or to unknown targets) are collected for review.
+///
+/// The extra tag roots from the config are scanned too, unless they are
+/// unavailable (like an unmounted network share).
+fn scan_tags(state: &mut AppState, p: impl AsRef<Path>) {
     let p = p.as_ref();
-    for entry in fs::read_dir(p).expect("cannot access tags dir") {
-        let entry = entry.expect("error scanning tags dir");
-        let path = entry.path();
-        let cpath = path.canonicalize().unwrap();
-        if path.is_dir() {
-            let mut tag = Tag {
-                name: path
-                    .strip_prefix(&state.tags_path)
-                    .unwrap()
-                    .to_string_lossy()
-                    .to_string(),
-                items: HashMap::default(),
-            };
-            scan_tags(state, Some(&mut tag), &path);
-            state.tags.insert(cpath, tag);
-        } else if let Some(item) = state.items_all.get_mut(&cpath) {
-            if let Some(ref mut parent) = parent {
-                parent.items.insert(cpath, path);
-                item.tags.insert(p.canonicalize().unwrap());
+    let start = Instant::now();
+    state.skipped_tags.clear();
+    if state.config.identity == Identity::Inode {
+        state.known_paths = identity::load(p);
+    }
+    let mut visited = HashSet::default();
+    let exclude = tag_exclude(state);
+    let found = p
+        .canonicalize()
+        .and_then(|cdir| Ok((cdir, fs::metadata(p)?)));
+    let (cdir, md) = match found {
+        Ok((cdir, md)) => (SharedPath::from(cdir), md),
+        Err(e) => {
+            skip_tag_dir(state, p, e);
+            return;
+        }
+    };
+    visited.insert((md.dev(), md.ino()));
+    let mut walk = TagWalk {
+        root: p,
+        label: None,
+        exclude: &exclude,
+        visited: &mut visited,
+    };
+    scan_tag_dir(state, None, p, &cdir, &mut walk, 0);
+
+    let roots: Vec<(String, PathBuf)> = state
+        .config
+        .tag_roots
+        .iter()
+        .map(|(label, root)| (label.clone(), root.clone()))
+        .collect();
+    for (label, root) in roots.iter() {
+        if let (Ok(cdir), Ok(md)) = (root.canonicalize(), fs::metadata(root)) {
+            if !cdir.is_dir() {
+                continue;
             }
+            if !visited.insert((md.dev(), md.ino())) {
+                skip_tag_dir(state, root, "already scanned as part of another tags dir");
+                continue;
+            }
+            let mut walk = TagWalk {
+                root,
+                label: Some(label.as_str()),
+                exclude: &exclude,
+                visited: &mut visited,
+            };
+            scan_tag_dir(state, None, root, &cdir.into(), &mut walk, 0);
         }
     }
+    info!(
+        "scanned {} tags in {} ({:?})",
+        state.tags.len(),
+        p.display(),
+        start.elapsed()
+    );
 }
 
-fn apply_filter(siv: &mut Cursive, state: &mut AppState, filter: impl FnOnce(&mut AppState)) {
-    filter(state);
-    ui_refresh_itemview(siv, state);
+/// The extra tag roots that are there and can be written to, each once and
+/// without the tags dir `tags`, for locks and audit logs
+fn writable_roots(state: &AppState, tags: &Path) -> Vec<PathBuf> {
+    let mut seen: Vec<PathBuf> = tags.canonicalize().into_iter().collect();
+    let mut roots = Vec::new();
+    for root in state.config.tag_roots.values() {
+        let croot = match root.canonicalize() {
+            Ok(croot) if croot.is_dir() => croot,
+            _ => continue,
+        };
+        if seen.contains(&croot) || !fsops::is_writable(root) {
+            continue;
+        }
+        seen.push(croot);
+        roots.push(root.clone());
+    }
+    roots
 }
 
-fn itemview_filter_reset(state: &mut AppState) {
-    state.items_vis.clear();
-    state.sel.clear();
-    for i in state.items_all.keys() {
-        state.items_vis.insert(i.clone());
-    }
+/// Where a scan of the tags tree is, for `scan_tag_dir`
+struct TagWalk<'a> {
+    /// the tag root being scanned
+    root: &'a Path,
+    /// its label, if it is an extra one
+    label: Option<&'a str>,
+    /// names of dirs that are not tags
+    exclude: &'a GlobSet,
+    /// device and inode of every dir scanned so far
+    visited: &'a mut HashSet<(u64, u64)>,
 }
 
-fn itemview_filter_untagged(state: &mut AppState) {
-    state.items_vis.clear();
-    state.sel.clear();
-    for (p, i) in state.items_all.iter() {
-        if i.tags.is_empty() {
-            state.items_vis.insert(p.clone());
+/// Leave a dir out of the tags, with a warning
+fn skip_tag_dir(state: &mut AppState, dir: &Path, why: impl std::fmt::Display) {
+    let msg = format!("skipped tag dir {}: {}", dir.display(), why);
+    warn!("{}", msg);
+    state.skipped_tags.push(msg);
+}
+
+/// Scan a dir of the tags tree, `cdir` is its canonical path
+///
+/// Paths on network filesystems are expensive to canonicalize, so that is
+/// done once for the tags dir: subdirectories (which are not symlinks) of a
+/// canonical dir are canonical too. Symlinks to dirs are never followed,
+/// but bind mounts can still make the tree loop, so a dir seen before
+/// (by device and inode) is skipped, as are dirs more than `max-tag-depth`
+/// levels deep.
+fn scan_tag_dir(
+    state: &mut AppState,
+    mut parent: Option<&mut Tag>,
+    dir: &Path,
+    cdir: &SharedPath,
+    walk: &mut TagWalk,
+    depth: usize,
+) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => return skip_tag_dir(state, dir, e),
+    };
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                // the rest of the dir can't be read either
+                skip_tag_dir(state, dir, e);
+                break;
+            }
+        };
+        let path = entry.path();
+        let ftype = match entry.file_type() {
+            Ok(ftype) => ftype,
+            Err(e) => {
+                warn!("skipped {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        if ftype.is_dir() && is_excluded_tag(walk.exclude, &entry.file_name(), parent.is_none()) {
+            continue;
+        }
+        if ftype.is_dir() {
+            if depth >= state.config.max_tag_depth {
+                let why = format!("deeper than max-tag-depth ({})", state.config.max_tag_depth);
+                skip_tag_dir(state, &path, why);
+                continue;
+            }
+            match entry.metadata() {
+                Ok(md) if walk.visited.insert((md.dev(), md.ino())) => {}
+                Ok(_) => {
+                    skip_tag_dir(state, &path, "already scanned (a filesystem loop?)");
+                    continue;
+                }
+                Err(e) => {
+                    skip_tag_dir(state, &path, e);
+                    continue;
+                }
+            }
+            let cpath: SharedPath = cdir.join(entry.file_name()).into();
+            let mut tag = Tag::new(
+                walk.label,
+                escape::display_name(
+                    path.strip_prefix(walk.root).unwrap().as_os_str(),
+                    state.config.escape_names,
+                ),
+                color::read(&path),
+            );
+            scan_tag_dir(state, Some(&mut tag), &path, &cpath, walk, depth + 1);
+            state.tags.insert(cpath, tag);
+        } else if let Some(ref mut parent) = parent {
+            if !ftype.is_symlink() {
+                continue;
+            }
+            let cpath = link_target(state, &path, cdir);
+            if let Some(ip) = cpath.as_ref().and_then(|c| item_key(state, c)) {
+                parent.items.insert(Rc::clone(&ip), entry.file_name());
+                let item = state.items_all.get_mut(&ip).unwrap();
+                item.tags.insert(Rc::clone(cdir));
+            } else {
+                state.foreign.push(foreign::ForeignLink {
+                    link: path,
+                    tag: cdir.to_path_buf(),
+                    target: cpath,
+                });
+            }
         }
     }
 }
 
-/// Refresh UI after an update to the items index
+/// Reload the links of one tag from its dir
 ///
-/// Also refreshes tags view to prevent it being obsolete.
-fn ui_refresh_itemview(siv: &mut Cursive, state: &mut AppState) {
-    state.sel.clear();
-
-    siv.call_on_id("itemview", |v: &mut SelectView<PathBuf>| {
-        v.clear();
-        for p in state.items_vis.iter() {
-            let i = &state.items_all[p];
-            v.add_item(i.name.clone(), p.clone());
+/// On a shared filesystem, others may change a tag dir after it was
+/// scanned. Links to unknown items are left alone. A tag dir that is gone
+/// is dropped from the tags index, along with its subtags.
+fn rescan_tag(state: &mut AppState, tp: &Path) {
+    let tkey = match tag_key(state, tp) {
+        Some(tkey) => tkey,
+        None => return,
+    };
+    let entries = match fs::read_dir(tp) {
+        Ok(entries) => entries,
+        Err(_) => {
+            let gone: Vec<SharedPath> = state
+                .tags
+                .keys()
+                .filter(|t| t.starts_with(tp))
+                .cloned()
+                .collect();
+            for t in gone.iter() {
+                let tag = state.tags.remove(t).unwrap();
+                for ip in tag.items.keys() {
+                    state.items_all.get_mut(ip).unwrap().tags.remove(t);
+                }
+            }
+            return;
         }
-        v.sort_by_label();
-    });
+    };
 
-    ui_mark_itemview(siv, state);
-    ui_refresh_tagsview(siv, state);
-}
-
-/// Refresh UI after an update to the tags index
-fn ui_refresh_tagsview(siv: &mut Cursive, state: &mut AppState) {
-    siv.call_on_id("tagsview", |v: &mut SelectView<PathBuf>| {
-        v.clear();
-        for (p, t) in state.tags.iter() {
-            v.add_item(t.name.clone(), p.clone());
+    let old = mem::take(&mut state.tags.get_mut(tp).unwrap().items);
+    for ip in old.keys() {
+        state.items_all.get_mut(ip).unwrap().tags.remove(tp);
+    }
+    let mut items = HashMap::default();
+    for entry in entries.filter_map(|e| e.ok()) {
+        let is_link = entry.file_type().map(|t| t.is_symlink());
+        if !is_link.unwrap_or(false) {
+            continue;
         }
-        v.sort_by_label();
-    });
+        let cpath = link_target(state, &entry.path(), tp);
+        if let Some(ip) = cpath.as_ref().and_then(|c| item_key(state, c)) {
+            let item = state.items_all.get_mut(&ip).unwrap();
+            item.tags.insert(Rc::clone(&tkey));
+            items.insert(ip, entry.file_name());
+        }
+    }
+    state.tags.get_mut(tp).unwrap().items = items;
+}
 
-    ui_mark_tagsview(siv, state);
+/// Reload all tags from the tags dir
+fn rescan_tags(state: &mut AppState) {
+    state.tags.clear();
+    state.foreign.clear();
+    state.skipped_tags.clear();
+    for item in state.items_all.values_mut() {
+        item.tags.clear();
+    }
+    let tags_path = state.tags_path.clone();
+    scan_tags(state, tags_path);
 }
 
-/// Generate/update checkbox states in items view
+/// Canonical path of the target of a symlink in the tag dir `cdir`
 ///
-/// Our "checkboxes" are just prefixes to the string displayed.
-fn ui_mark_itemview(siv: &mut Cursive, state: &mut AppState) {
-    siv.call_on_id("itemview", |v: &mut SelectView<PathBuf>| {
-        for i in 0..v.len() {
-            let (s, p) = v.get_item_mut(i).unwrap();
-            let item = state.items_all.get(p).unwrap();
-
-            *s = format!(
-                "{} {}",
-                if state.sel.contains(p) { "[X]" } else { "[ ]" },
-                item.name
-            )
-            .into();
+/// Links made by linkorgasm point straight at an item, which a single
+/// `readlink` finds, without looking up every path component like
+/// `canonicalize`. Links to git-annex files are matched by annex key.
+/// Anything else is canonicalized. A dangling link is `None`, unless it is
+/// to an item that moved (see `identity`).
+fn link_target(state: &AppState, link: &Path, cdir: &Path) -> Option<PathBuf> {
+    if let Ok(target) = fs::read_link(link) {
+        let target = lexical_path(cdir, &target);
+        if state.items_all.contains_key(target.as_path()) {
+            return Some(target);
         }
-    });
+    }
+    if !state.annex_keys.is_empty() {
+        if let Some(ips) = annex::key_of(link).and_then(|key| state.annex_keys.get(&key)) {
+            return annex_item(ips, link).map(|ip| ip.to_path_buf());
+        }
+    }
+    link.canonicalize()
+        .ok()
+        .or_else(|| identity::moved_target(state, link, cdir))
 }
 
-/// Generate/update checkbox states in tags view
+/// Which of the items with the annex key of a link it is for
 ///
-/// Our "checkboxes" are just prefixes to the string displayed.
-fn ui_mark_tagsview(siv: &mut Cursive, state: &mut AppState) {
-    siv.call_on_id("tagsview", |v: &mut SelectView<PathBuf>| {
-        for i in 0..v.len() {
-            let (s, p) = v.get_item_mut(i).unwrap();
-            let t = state.tags.get(p).unwrap();
-
-            let mut oncount = 0;
-            let mut offcount = 0;
+/// That is the one at the work tree path the link points to (which may be
+/// in another clone of the repo), or else the first by path.
+fn annex_item<'a>(ips: &'a [SharedPath], link: &Path) -> Option<&'a SharedPath> {
+    let target = fs::read_link(link).ok();
+    let name = target.as_ref().and_then(|target| target.file_name());
+    ips.iter()
+        .filter(|ip| name.is_some() && ip.file_name() == name)
+        .min()
+        .or_else(|| ips.iter().min())
+}
 
-            for item in state.sel.iter() {
-                if t.items.contains_key(item) {
-                    oncount += 1;
-                } else {
-                    offcount += 1;
-                }
-            }
+/// The shared path under which a known item is indexed
+fn item_key(state: &AppState, ip: &Path) -> Option<SharedPath> {
+    state.items_all.get_key_value(ip).map(|(k, _)| Rc::clone(k))
+}
 
-            *s = format!(
-                "{} {}",
-                match (oncount, offcount) {
-                    (0, _) => "[ ]",
-                    (_, 0) => "[X]",
-                    (_, _) => "[?]",
-                },
-                t.name
-            )
-            .into();
-        }
-    });
+/// The shared path under which a known tag is indexed
+fn tag_key(state: &AppState, tp: &Path) -> Option<SharedPath> {
+    state.tags.get_key_value(tp).map(|(k, _)| Rc::clone(k))
 }
 
-/// Generate target path for a new symlink
+/// Make a path absolute and resolve `.` and `..` without touching the disk
 ///
-/// `tag` and `item` are canonical paths.
-fn tag_target_path(tag: &Path, item: &Path) -> PathBuf {
-    let mut t = tag.iter();
-    let mut i = item.iter();
-
-    let mut p = PathBuf::from("../");
-    loop {
-        let tnext = t.next();
-        let inext = i.next();
-
-        if tnext != inext {
-            for _ in 0..t.count() {
-                p.push("../");
-            }
-            p.push(inext.unwrap());
-            for x in i {
-                p.push(x);
+/// Only gives the canonical path if `base` is canonical and no component
+/// of `p` is a symlink.
+fn lexical_path(base: &Path, p: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for c in base.join(p).components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
             }
-            break;
+            c => out.push(c.as_os_str()),
         }
     }
-
-    p
+    out
 }
 
-/// UI callback to select/deselect item
-fn toggle_sel(siv: &mut Cursive, state: &mut AppState) {
-    let p = siv
-        .call_on_id("itemview", |v: &mut SelectView<PathBuf>| {
-            v.selection().unwrap()
-        })
-        .unwrap();
+/// Make a filter the active one and show its result
+fn apply_filter(siv: &mut Cursive, state: &mut AppState, filter: Filter) {
+    state.filter = filter;
+    run_filter(state);
+    ui_refresh_itemview(siv, state);
+}
 
-    if state.sel.contains(p.as_ref()) {
-        state.sel.remove(p.as_ref());
-    } else {
-        state.sel.insert(p.to_path_buf());
+/// Recompute visible items from the active filter
+///
+/// Selected items stay selected, as long as they are still shown.
+fn run_filter(state: &mut AppState) {
+    match state.filter.clone() {
+        Filter::All => itemview_filter_reset(state),
+        Filter::Untagged => itemview_filter_untagged(state),
+        Filter::Extensions(exts) => itemview_filter_ext(state, &exts),
+        Filter::Query(q) => match query::Query::parse(&q) {
+            Ok(q) => itemview_filter_query(state, &q),
+            Err(_) => itemview_filter_reset(state),
+        },
     }
+    prune_selection(state);
 }
 
-/// UI callback to tag/untag selected items
-fn toggle_tag(siv: &mut Cursive, state: &mut AppState) {
-    let tp = siv
-        .call_on_id("tagsview", |v: &mut SelectView<PathBuf>| {
-            v.selection().unwrap()
-        })
-        .unwrap();
-    let tp = tp.as_path();
-    let tag = state.tags.get_mut(tp).unwrap();
+/// Drop items from the selection that are not shown (anymore)
+fn prune_selection(state: &mut AppState) {
+    let vis = &state.items_vis;
+    state.sel.retain(|p| vis.contains(p));
+}
 
-    // check for mixed state and abort if needed
-    {
-        let mut iter = state.sel.iter();
-        let first = if let Some(x) = iter.next() {
-            tag.items.contains_key(x)
-        } else {
-            false /* actually irrelevant */
-        };
-        for i in iter {
-            let contains = tag.items.contains_key(i);
-            if (first && !contains) || (!first && contains) {
-                return;
-            }
-        }
+fn itemview_filter_reset(state: &mut AppState) {
+    state.items_vis.clear();
+    for i in state.items_all.keys() {
+        state.items_vis.insert(i.clone());
     }
+}
 
-    for ip in state.sel.iter() {
-        let item = state.items_all.get_mut(ip).unwrap();
-        if let Some(p) = tag.items.get(ip) {
-            fs::remove_file(p).expect("could not delete symlink");
-            tag.items.remove(ip);
-            item.tags.remove(tp);
-        } else {
-            let target = tag_target_path(&tp, &ip);
-            let mut link = tp.to_path_buf();
-            link.push(&item.filename);
-
-            std::os::unix::fs::symlink(&target, &link).expect("could not create symlink");
-            tag.items.insert(ip.to_owned(), link);
-            item.tags.insert(tp.to_owned());
+fn itemview_filter_untagged(state: &mut AppState) {
+    state.items_vis.clear();
+    for (p, i) in state.items_all.iter() {
+        if i.tags.is_empty() {
+            state.items_vis.insert(p.clone());
         }
     }
 }
 
-/// Open selected files with provided command
-fn cmdexec(siv: &mut Cursive, state: &mut AppState, cmd: &str) {
-    siv.pop_layer();
-    for item in state.sel.iter() {
-        let mut cmd = Command::new(cmd);
-        cmd.args(&[item]);
-        if let Err(e) = cmd.spawn() {
-            siv.add_layer(
-                Dialog::text(format!("{}", e))
-                    .title("ERROR")
-                    .button("Ok", |siv| {
-                        siv.pop_layer();
-                    }),
-            )
+/// Show only items with one of the given file extensions (case-insensitive)
+fn itemview_filter_ext(state: &mut AppState, exts: &[String]) {
+    state.items_vis.clear();
+    for (p, i) in state.items_all.iter() {
+        let ext = Path::new(&i.filename)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase());
+        if let Some(ext) = ext {
+            if exts.contains(&ext) {
+                state.items_vis.insert(p.clone());
+            }
         }
     }
 }
 
-/// Display UI Dialog for providing command to open items with
-fn ui_build_cmdexec(siv: &mut Cursive) {
-    siv.add_layer(ui_input_dialog(
-        "Open selection with:",
-        "cmd",
-        "",
-        |siv, x| do_app(siv, |siv, state| cmdexec(siv, state, x))
-    ));
-}
-
+/// Show only items matching a query
+fn itemview_filter_query(state: &mut AppState, q: &query::Query) {
+    state.items_vis.clear();
+    for (p, i) in state.items_all.iter() {
+        if q.matches(state, p, i) {
+            state.items_vis.insert(p.clone());
+        }
+    }
+}
+
+/// UI callback for the query filter dialog
+fn ui_submit_filter_query(siv: &mut Cursive, q: &str) {
+    if let Err(e) = query::Query::parse(q) {
+        return ui_error(siv, e);
+    }
+    siv.pop_layer();
+    do_app(siv, |siv, state| {
+        apply_filter(siv, state, Filter::Query(q.to_owned()))
+    });
+}
+
+/// UI callback for the extension filter dialog
+fn ui_submit_filter_ext(siv: &mut Cursive, exts: &str) {
+    let exts: Vec<String> = exts
+        .split(',')
+        .map(|e| e.trim().trim_start_matches('.').to_lowercase())
+        .filter(|e| !e.is_empty())
+        .collect();
+    siv.pop_layer();
+    do_app(siv, |siv, state| {
+        apply_filter(siv, state, Filter::Extensions(exts))
+    });
+}
+
+/// Get the name to show for an item, according to the name mode
+fn item_display_name(state: &AppState, p: &Path, item: &Item) -> String {
+    let path = match state.name_mode {
+        NameMode::Name => return item.name.clone(),
+        NameMode::Relative => state
+            .items_path
+            .as_ref()
+            .and_then(|root| p.strip_prefix(root).ok())
+            .unwrap_or(p),
+        NameMode::Canonical => p,
+    };
+    escape::display_name(path.as_os_str(), state.config.escape_names)
+}
+
+/// Refresh UI after an update to the items index
+///
+/// The selection is kept, except for items that are no longer shown.
+/// Also refreshes tags view to prevent it being obsolete.
+fn ui_refresh_itemview(siv: &mut Cursive, state: &mut AppState) {
+    prune_selection(state);
+    ui_fill_itemview(siv, state);
+    ui_refresh_tagsview(siv, state);
+}
+
+/// (Re)build the list of items in the items view
+///
+/// The cursor stays on the same item, if it is still shown.
+fn ui_fill_itemview(siv: &mut Cursive, state: &mut AppState) {
+    let cursor = siv
+        .call_on_id("itemview", |v: &mut LazyList<SharedPath>| v.selection())
+        .and_then(|p| p);
+    let mut names: Vec<(String, SharedPath)> = state
+        .items_vis
+        .iter()
+        .map(|p| {
+            let i = &state.items_all[p];
+            let name = item_display_name(state, p, i);
+            (format!("{}{}", name, if i.is_dir { "/" } else { "" }), p.clone())
+        })
+        .collect();
+    if state.name_mode == NameMode::Name {
+        disambiguate(&mut names, state.config.escape_names);
+    }
+    state.name_width = columns::name_width(names.iter().map(|(name, _)| name.as_str()));
+
+    let natural = state.config.natural_sort;
+    if state.group_by_tag {
+        names.sort_by(|a, b| natsort::compare_names(natural, &a.0, &b.0));
+        let groups = item_groups(state, &names);
+        siv.call_on_id("itemview", |v: &mut LazyList<SharedPath>| {
+            v.clear();
+            for (title, rows) in groups {
+                v.add_header(title);
+                for i in rows {
+                    v.add_item(names[i].0.clone(), Rc::clone(&names[i].1));
+                }
+            }
+        });
+    } else {
+        siv.call_on_id("itemview", |v: &mut LazyList<SharedPath>| {
+            v.clear();
+            for (name, p) in names.drain(..) {
+                v.add_item(name, p);
+            }
+            v.sort_by(|a, b| natsort::compare_names(natural, a, b));
+        });
+    }
+    if let Some(p) = cursor {
+        ui_select_item(siv, &p);
+    }
+
+    ui_mark_itemview(siv, state);
+}
+
+/// Shown items clustered by tag, for the grouped items view
+///
+/// `names` are the rows of the items view, in order. Returns a header for
+/// each tag with shown items (in the order of the tags view), then one for
+/// the untagged items, with the indices of their rows. Items with several
+/// tags are listed under each of them.
+fn item_groups(state: &AppState, names: &[(String, SharedPath)]) -> Vec<(String, Vec<usize>)> {
+    let rows: HashMap<&Path, usize> = names
+        .iter()
+        .enumerate()
+        .map(|(i, (_, p))| (&**p, i))
+        .collect();
+    let mut groups = Vec::new();
+    for (_, tag) in tagsview_order(state) {
+        let mut members: Vec<usize> = tag
+            .items
+            .keys()
+            .filter_map(|ip| rows.get(&**ip).cloned())
+            .collect();
+        if members.is_empty() {
+            continue;
+        }
+        members.sort();
+        groups.push((format!("{} ({})", tag.name, members.len()), members));
+    }
+    let untagged: Vec<usize> = names
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, p))| state.items_all[p].tags.is_empty())
+        .map(|(i, _)| i)
+        .collect();
+    if !untagged.is_empty() {
+        groups.push((format!("untagged ({})", untagged.len()), untagged));
+    }
+    groups
+}
+
+/// Append parent dirs to names shared by several items
+///
+/// As few trailing components of the parent dirs are used as it takes to
+/// tell the items apart, e.g. `intro.mp3 (live/1998)`.
+fn disambiguate(names: &mut [(String, SharedPath)], escape_all: bool) {
+    let mut groups: HashMap<&str, Vec<usize>> = HashMap::default();
+    for (i, (name, _)) in names.iter().enumerate() {
+        groups.entry(name.as_str()).or_insert_with(Vec::new).push(i);
+    }
+
+    let mut suffixes = Vec::new();
+    for group in groups.values().filter(|g| g.len() > 1) {
+        let parents: Vec<Vec<&OsStr>> = group
+            .iter()
+            .map(|&i| names[i].1.parent().map_or(Vec::new(), |p| p.iter().collect()))
+            .collect();
+        let longest = parents.iter().map(Vec::len).max().unwrap_or(0);
+        let suffix = |parent: &[&OsStr], depth: usize| -> PathBuf {
+            parent[parent.len().saturating_sub(depth)..].iter().collect()
+        };
+        let mut depth = 1;
+        while depth < longest {
+            let distinct: HashSet<PathBuf> = parents.iter().map(|p| suffix(p, depth)).collect();
+            if distinct.len() == group.len() {
+                break;
+            }
+            depth += 1;
+        }
+        for (&i, parent) in group.iter().zip(parents.iter()) {
+            suffixes.push((i, suffix(parent, depth)));
+        }
+    }
+
+    for (i, suffix) in suffixes {
+        let suffix = escape::display_name(suffix.as_os_str(), escape_all);
+        names[i].0 = format!("{} ({})", names[i].0, suffix);
+    }
+}
+
+/// Move the cursor of the items view to the row holding the given path
+fn ui_select_item(siv: &mut Cursive, value: &Path) {
+    let cb = siv.call_on_id("itemview", |v: &mut LazyList<SharedPath>| {
+        (0..v.len())
+            .find(|&i| v.get_item(i).map_or(false, |(_, p)| &**p == value))
+            .map(|i| v.set_selection(i))
+    });
+    if let Some(Some(cb)) = cb {
+        cb(siv);
+    }
+}
+
+/// Move the cursor of a list view to the row holding the given path
+fn ui_select_value(siv: &mut Cursive, id: &str, value: &Path) {
+    let cb = siv.call_on_id(id, |v: &mut CheckList<PathBuf>| {
+        (0..v.len())
+            .find(|&i| v.get_item(i).unwrap().1 == value)
+            .map(|i| v.set_selection(i))
+    });
+    if let Some(Some(cb)) = cb {
+        cb(siv);
+    }
+}
+
+/// All tags in the order of the tags view
+///
+/// Those of the tags dir come first, then each extra root as a section of
+/// its own.
+fn sorted_tags(state: &AppState) -> Vec<(&SharedPath, &Tag)> {
+    let mut tags: Vec<_> = state.tags.iter().collect();
+    let natural = state.config.natural_sort;
+    tags.sort_by(|a, b| {
+        a.1.root
+            .cmp(&b.1.root)
+            .then_with(|| natsort::compare_names(natural, &a.1.name, &b.1.name))
+    });
+    tags
+}
+
+/// All tags in the order chosen for the tags view
+fn tagsview_order(state: &AppState) -> Vec<(&SharedPath, &Tag)> {
+    let mut tags = sorted_tags(state);
+    // stable, so ties stay in name order
+    match state.tag_sort {
+        TagSort::Name => {}
+        TagSort::Count => tags.sort_by(|a, b| {
+            a.1.root
+                .cmp(&b.1.root)
+                .then_with(|| b.1.items.len().cmp(&a.1.items.len()))
+        }),
+        TagSort::Recent => tags.sort_by(|a, b| {
+            a.1.root
+                .cmp(&b.1.root)
+                .then_with(|| b.1.last_used.cmp(&a.1.last_used))
+        }),
+    }
+    tags
+}
+
+/// Refresh the tags view after tags were toggled
+///
+/// Only the marks change, unless the order depends on usage.
+fn ui_update_tagsview(siv: &mut Cursive, state: &mut AppState) {
+    if state.tag_sort == TagSort::Name {
+        ui_mark_tagsview(siv, state);
+    } else {
+        ui_refresh_tagsview(siv, state);
+    }
+}
+
+/// Refresh UI after an update to the tags index
+///
+/// The cursor stays on the same tag, if it still exists.
+fn ui_refresh_tagsview(siv: &mut Cursive, state: &mut AppState) {
+    let cursor = siv
+        .call_on_id("tagsview", |v: &mut CheckList<PathBuf>| v.selection())
+        .and_then(|tp| tp);
+    siv.call_on_id("tagsview", |v: &mut CheckList<PathBuf>| {
+        v.clear();
+        for (p, t) in tagsview_order(state) {
+            v.add_item(t.name.clone(), p.to_path_buf());
+        }
+    });
+    if let Some(tp) = cursor {
+        ui_select_value(siv, "tagsview", &tp);
+    }
+
+    ui_mark_tagsview(siv, state);
+    description::ui_show_highlighted(siv);
+    let tp = siv
+        .call_on_id("tagsview", |v: &mut CheckList<PathBuf>| v.selection())
+        .and_then(|tp| tp);
+    ui_hover_tag(siv, state, tp.map(|tp| (*tp).clone()));
+}
+
+/// Mark the items of the tag under the tags view cursor
+fn ui_hover_tag(siv: &mut Cursive, state: &mut AppState, tp: Option<PathBuf>) {
+    if state.hover_tag != tp {
+        state.hover_tag = tp;
+        ui_mark_itemview(siv, state);
+    }
+}
+
+/// Color to tint an item with: that of its first colored tag (by name)
+fn item_color(state: &AppState, item: &Item) -> Option<Color> {
+    item.tags
+        .iter()
+        .filter_map(|tp| state.tags.get(tp))
+        .filter_map(|t| t.color.map(|c| (&t.name, c)))
+        .min_by(|a, b| a.0.cmp(b.0))
+        .map(|(_, c)| c)
+}
+
+/// Build a label from a (possibly colored) name
+fn ui_label(name: String, color: Option<Color>) -> StyledString {
+    match color {
+        Some(color) => StyledString::styled(name, color),
+        None => StyledString::plain(name),
+    }
+}
+
+/// Generate/update checkbox states in items view
+///
+/// The checkboxes are part of the labels, and only the rows around the
+/// visible ones get labels, see `LazyList`.
+fn ui_mark_itemview(siv: &mut Cursive, state: &mut AppState) {
+    siv.call_on_id("itemview", |v: &mut LazyList<SharedPath>| v.invalidate());
+    ui_page_itemview(siv, state);
+}
+
+/// Label the rows of the items view that are about to be shown
+fn ui_page_itemview(siv: &mut Cursive, state: &mut AppState) {
+    let cols = columns::COLUMN_SETS[state.columns];
+    let hover = state.hover_tag.as_ref().and_then(|tp| state.tags.get(tp.as_path()));
+    siv.call_on_id("itemview", |v: &mut LazyList<SharedPath>| {
+        for i in v.window() {
+            if v.has_label(i) {
+                continue;
+            }
+            // headers come with their labels
+            let (name, p) = v.get_item(i).unwrap();
+            let item = &state.items_all[p];
+
+            let name = columns::pad_name(name.to_owned(), state.name_width, cols);
+            let color = item_color(state, item);
+            // the checkbox is shown reversed
+            let hovered = hover.map_or(false, |t| t.items.contains_key(p));
+            let mut label = Check::from(state.sel.contains(p)).marker(hovered);
+            if let Some(icon) = icons::icon(state.config.icons, item) {
+                label.append_plain(format!("{} ", icon));
+            }
+            label.append(ui_label(name, color));
+            label.append_plain(columns::format_columns(item, cols));
+            v.set_label(i, label);
+        }
+    });
+}
+
+/// Generate/update checkbox states in tags view
+///
+/// A tag is checked if all selected items have it, mixed if some have.
+fn ui_mark_tagsview(siv: &mut Cursive, state: &mut AppState) {
+    let implicit = implicit_item(siv, state);
+    let items: Vec<&SharedPath> = match implicit {
+        Some(ref ip) => vec![ip],
+        None => state.sel.iter().collect(),
+    };
+    siv.call_on_id("tagsview", |v: &mut CheckList<PathBuf>| {
+        for i in 0..v.len() {
+            let p = v.get_item(i).unwrap().1;
+            let t = state.tags.get(p.as_path()).unwrap();
+
+            let oncount = items.iter().filter(|ip| t.items.contains_key(**ip)).count();
+            let label = ui_label(tag_label(state, p, t), t.color);
+            v.set_check(i, Check::from_counts(oncount, items.len() - oncount));
+            v.set_label(i, label);
+        }
+    });
+}
+
+/// Name of a tag in the tags view: values of key/value tags are grouped
+/// under the key's dir, pinned tags show their number key
+fn tag_label(state: &AppState, tp: &Path, t: &Tag) -> String {
+    let name = match t.kv {
+        Some((_, ref value)) => format!("  {}", value),
+        None => t.name.clone(),
+    };
+    match state.pinned.iter().position(|pin| pin.as_deref() == Some(tp)) {
+        Some(n) => format!("{} ({})", name, PIN_KEYS.chars().nth(n).unwrap()),
+        None => name,
+    }
+}
+
+/// Generate relative target path for a new symlink
+///
+/// `tag` and `item` are canonical paths. See `linkstyle::target` for the
+/// configured style.
+fn tag_target_path(tag: &Path, item: &Path) -> PathBuf {
+    let mut t = tag.iter();
+    let mut i = item.iter();
+
+    let mut p = PathBuf::from("../");
+    loop {
+        let tnext = t.next();
+        let inext = i.next();
+
+        if tnext != inext {
+            for _ in 0..t.count() {
+                p.push("../");
+            }
+            p.push(inext.unwrap());
+            for x in i {
+                p.push(x);
+            }
+            break;
+        }
+    }
+
+    p
+}
+
+/// Show the selected items with their number and combined size
+///
+/// Directories count with their own size, not that of their contents.
+fn ui_show_selection(siv: &mut Cursive) {
+    let (paths, size, dirs) = do_app(siv, |_, state| {
+        let mut sel: Vec<&SharedPath> = state.sel.iter().collect();
+        sel.sort();
+        let items = sel.iter().map(|ip| &state.items_all[*ip]);
+        let size: u64 = items.clone().map(|i| i.size).sum();
+        let dirs = items.filter(|i| i.is_dir).count();
+        let paths: Vec<String> = sel.iter().map(|ip| ip.display().to_string()).collect();
+        (paths, size, dirs)
+    });
+    if paths.is_empty() {
+        return siv.add_layer(Dialog::info(tr!("No items are selected.")));
+    }
+
+    let mut text = paths.join("\n");
+    if dirs > 0 {
+        text.push_str("\n\n");
+        text.push_str(&tr!(
+            "{} directories are counted without their contents.",
+            dirs
+        ));
+    }
+    let content = ScrollView::new(TextView::new(text).no_wrap()).scroll_x(true);
+    siv.add_layer(
+        Dialog::around(content.max_height(20))
+            .title(tr!(
+                "{} items selected, {}",
+                paths.len(),
+                columns::format_size(size)
+            ))
+            .button(tr!("Close"), |siv| {
+                siv.pop_layer();
+            }),
+    );
+}
+
+/// UI callback to select/deselect item
+fn toggle_sel(siv: &mut Cursive, state: &mut AppState) {
+    let p = siv
+        .call_on_id("itemview", |v: &mut LazyList<SharedPath>| {
+            v.selection().unwrap()
+        })
+        .unwrap();
+
+    if state.sel.contains(&p) {
+        state.sel.remove(&p);
+    } else {
+        state.sel.insert(p);
+    }
+}
+
+/// The highlighted item, if nothing is selected and tag changes apply to
+/// it instead (`implicit-selection`)
+fn implicit_item(siv: &mut Cursive, state: &AppState) -> Option<SharedPath> {
+    if !state.sel.is_empty() || !state.config.implicit_selection {
+        return None;
+    }
+    siv.call_on_id("itemview", |v: &mut LazyList<SharedPath>| v.selection())
+        .and_then(|p| p)
+}
+
+/// Advance the jump cycle for the given source and get its position
+fn jump_next(state: &mut AppState, from: &Path, len: usize) -> usize {
+    let n = match state.jump {
+        Some((ref p, n)) if p == from => n + 1,
+        _ => 0,
+    };
+    state.jump = Some((from.to_path_buf(), n));
+    n % len
+}
+
+/// UI callback to move the tags view cursor through the highlighted item's tags
+fn ui_jump_to_tags(siv: &mut Cursive, state: &mut AppState) {
+    let ip = siv
+        .call_on_id("itemview", |v: &mut LazyList<SharedPath>| v.selection())
+        .unwrap();
+    let ip = match ip {
+        Some(ip) => ip,
+        None => return,
+    };
+
+    let mut tags: Vec<SharedPath> = state.items_all[&ip].tags.iter().cloned().collect();
+    if tags.is_empty() {
+        return;
+    }
+    tags.sort_by(|a, b| state.tags[a].name.cmp(&state.tags[b].name));
+    let tp = &tags[jump_next(state, &ip, tags.len())];
+    ui_select_value(siv, "tagsview", tp);
+}
+
+/// UI callback to move the items view cursor through the highlighted tag's items
+fn ui_jump_to_items(siv: &mut Cursive, state: &mut AppState) {
+    let tp = siv
+        .call_on_id("tagsview", |v: &mut CheckList<PathBuf>| v.selection())
+        .unwrap();
+    let tp = match tp {
+        Some(tp) => tp,
+        None => return,
+    };
+
+    let tag = &state.tags[tp.as_path()];
+    let rows: Vec<usize> = siv
+        .call_on_id("itemview", |v: &mut LazyList<SharedPath>| {
+            (0..v.len())
+                .filter(|&i| v.get_item(i).map_or(false, |(_, p)| tag.items.contains_key(p)))
+                .collect()
+        })
+        .unwrap();
+    if rows.is_empty() {
+        return;
+    }
+    let row = rows[jump_next(state, &tp, rows.len())];
+    let cb = siv
+        .call_on_id("itemview", |v: &mut LazyList<SharedPath>| v.set_selection(row))
+        .unwrap();
+    cb(siv);
+}
+
+/// Tag an item: create a symlink in the tag dir and update the indexes
+///
+/// `tp` and `ip` are canonical paths of a known tag and item.
+/// The `pre-tag` and `post-tag` hooks are run around it.
+fn tag_item(state: &mut AppState, tp: &Path, ip: &Path) -> io::Result<()> {
+    let (tkey, ikey) = (tag_key(state, tp).unwrap(), item_key(state, ip).unwrap());
+    if state.tags[tp].items.contains_key(ip) {
+        return Ok(());
+    }
+    hooks::pre(state, &state.config.hooks.pre_tag, tp, ip)?;
+
+    let tag = state.tags.get_mut(tp).unwrap();
+    let item = state.items_all.get_mut(ip).unwrap();
+    let target = linkstyle::target(state.config.link_style, tp, ip);
+    let link = tp.join(&item.filename);
+    state.fs.symlink(&target, &link, item.is_dir)?;
+    tag.items.insert(ikey, item.filename.clone());
+    item.tags.insert(tkey);
+    state.use_clock += 1;
+    tag.last_used = state.use_clock;
+    manifest::add(state, tp, ip)?;
+    journal::record(state, "tag", tp, ip);
+
+    #[cfg(feature = "dbus")]
+    dbus::notify(state, tp, ip, true);
+    hooks::post(state, &state.config.hooks.post_tag, tp, ip);
+    Ok(())
+}
+
+/// Untag an item: remove its symlink from the tag dir and update the indexes
+///
+/// `tp` and `ip` are canonical paths of a known tag and item.
+/// The `pre-untag` and `post-untag` hooks are run around it.
+fn untag_item(state: &mut AppState, tp: &Path, ip: &Path) -> io::Result<()> {
+    if !state.tags[tp].items.contains_key(ip) {
+        return Ok(());
+    }
+    hooks::pre(state, &state.config.hooks.pre_untag, tp, ip)?;
+
+    let tag = state.tags.get_mut(tp).unwrap();
+    let item = state.items_all.get_mut(ip).unwrap();
+    let name = &tag.items[ip];
+    state.fs.remove_symlink(&tp.join(name), item.is_dir)?;
+    tag.items.remove(ip);
+    item.tags.remove(tp);
+    state.use_clock += 1;
+    tag.last_used = state.use_clock;
+    manifest::remove(state, tp, ip)?;
+    journal::record(state, "untag", tp, ip);
+
+    #[cfg(feature = "dbus")]
+    dbus::notify(state, tp, ip, false);
+    hooks::post(state, &state.config.hooks.post_untag, tp, ip);
+    Ok(())
+}
+
+/// UI callback to tag/untag selected items
+fn toggle_tag(siv: &mut Cursive, state: &mut AppState) {
+    if state.fs.is_read_only() {
+        return ui_error(siv, tr!("The tags directory is read-only."));
+    }
+    let tp = siv
+        .call_on_id("tagsview", |v: &mut CheckList<PathBuf>| {
+            v.selection().unwrap()
+        })
+        .unwrap();
+    if let Err(failure) = toggle_tag_implicit(siv, state, &tp) {
+        ui_toggle_error(siv, state, &tp, &failure);
+    }
+}
+
+/// Tag/untag selected items, or the highlighted one if nothing is selected
+/// (see `implicit_item`)
+fn toggle_tag_implicit(
+    siv: &mut Cursive,
+    state: &mut AppState,
+    tp: &Path,
+) -> Result<(), ToggleFailure> {
+    let implicit = implicit_item(siv, state);
+    if let Some(ref ip) = implicit {
+        state.sel.insert(ip.clone());
+    }
+    let res = toggle_tag_path(state, tp);
+    if implicit.is_some() {
+        state.sel.clear();
+    }
+    res
+}
+
+/// Items a batch of tag changes failed for
+#[derive(Debug, Default)]
+struct ToggleFailure {
+    /// number of items the batch was to change
+    total: usize,
+    /// number of items that couldn't be changed
+    failed: usize,
+    /// items and what went wrong with them
+    errors: Vec<(SharedPath, String)>,
+    /// whether the tag dir was changed on disk, and has been reloaded
+    conflict: bool,
+    /// whether the changes made before a fatal error were undone
+    rolled_back: bool,
+}
+
+/// Whether an error from changing a link means the tag dir changed on disk
+fn is_conflict(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::AlreadyExists || e.kind() == io::ErrorKind::NotFound
+}
+
+/// Key of a known item, given by any path to it
+fn find_item(state: &AppState, item: &str) -> Result<SharedPath, String> {
+    Path::new(item)
+        .canonicalize()
+        .ok()
+        .and_then(|ip| item_key(state, &ip))
+        .ok_or_else(|| format!("unknown item: {}", item))
+}
+
+/// Key of a known tag, by name
+fn find_tag(state: &AppState, name: &str) -> Result<SharedPath, String> {
+    tag_dir(state, name)
+        .canonicalize()
+        .ok()
+        .and_then(|tp| tag_key(state, &tp))
+        .ok_or_else(|| format!("unknown tag: {}", name))
+}
+
+/// Key of a tag by name, creating the tag if it is missing
+fn find_or_create_tag(state: &mut AppState, name: &str) -> Result<SharedPath, String> {
+    let tp = create_tag(state, name).map_err(|e| e.to_string())?;
+    tag_key(state, &tp).ok_or_else(|| format!("unknown tag: {}", name))
+}
+
+/// Tag/untag selected items with the given tag
+///
+/// Does nothing if only some of the selected items carry the tag.
+/// Changes other journal clients made are replayed first.
+/// The items to change are collected first, then changed one by one.
+/// A link to create that already exists, or one to remove that is gone,
+/// means the tag dir changed on disk since it was scanned: that item is
+/// skipped and the tag reloaded afterwards. An item a pre hook refuses is
+/// skipped too. Any other error (like a full disk) stops the batch and
+/// undoes the changes made so far.
+fn toggle_tag_path(state: &mut AppState, tp: &Path) -> Result<(), ToggleFailure> {
+    journal::sync(state);
+    // removed by someone else
+    let tag = match state.tags.get(tp) {
+        Some(tag) => tag,
+        None => return Ok(()),
+    };
+
+    // check for mixed state and abort if needed
+    let mut iter = state.sel.iter();
+    let first = if let Some(x) = iter.next() {
+        tag.items.contains_key(x)
+    } else {
+        false /* actually irrelevant */
+    };
+    for i in iter {
+        let contains = tag.items.contains_key(i);
+        if (first && !contains) || (!first && contains) {
+            return Ok(());
+        }
+    }
+
+    let mut todo: Vec<SharedPath> = state.sel.iter().cloned().collect();
+    todo.sort();
+    let mut failure = ToggleFailure {
+        total: todo.len(),
+        ..ToggleFailure::default()
+    };
+    let mut done = Vec::new();
+    for ip in todo.iter() {
+        let res = if first {
+            untag_item(state, tp, ip)
+        } else {
+            tag_item(state, tp, ip)
+        };
+        match res {
+            Ok(()) => done.push(ip),
+            Err(e) => {
+                let conflict = is_conflict(&e);
+                failure.failed += 1;
+                failure.errors.push((Rc::clone(ip), e.to_string()));
+                if conflict {
+                    failure.conflict = true;
+                    continue;
+                }
+                if hooks::is_refusal(&e) {
+                    continue;
+                }
+                for ip in done.iter().rev() {
+                    let undo = if first {
+                        tag_item(state, tp, ip)
+                    } else {
+                        untag_item(state, tp, ip)
+                    };
+                    if let Err(e) = undo {
+                        failure
+                            .errors
+                            .push((Rc::clone(ip), format!("could not undo: {}", e)));
+                    }
+                }
+                failure.rolled_back = true;
+                break;
+            }
+        }
+    }
+
+    if failure.conflict {
+        rescan_tag(state, tp);
+    }
+    if failure.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(failure)
+    }
+}
+
+/// Report a failed batch of tag changes, item by item
+///
+/// The tags view is rebuilt, as the tag may have been reloaded.
+fn ui_toggle_error(
+    siv: &mut Cursive,
+    state: &mut AppState,
+    tp: &Path,
+    failure: &ToggleFailure,
+) {
+    ui_refresh_tagsview(siv, state);
+    ui_select_value(siv, "tagsview", tp);
+
+    let mut text = tr!(
+        "Could not change {} of {} items:",
+        failure.failed,
+        failure.total
+    );
+    text.push_str("\n\n");
+    for (ip, e) in failure.errors.iter() {
+        text.push_str(&format!("{}: {}\n", ip.display(), e));
+    }
+    if failure.conflict {
+        text.push('\n');
+        text.push_str(&tr!("The tag was changed on disk, it has been reloaded."));
+    }
+    if failure.rolled_back {
+        text.push('\n');
+        text.push_str(&tr!("The changes made before the error have been undone."));
+    }
+    siv.add_layer(
+        Dialog::around(ScrollView::new(TextView::new(text)).max_height(20))
+            .title(tr!("ERROR"))
+            .button(tr!("Ok"), |siv| {
+                siv.pop_layer();
+            }),
+    );
+}
+
+/// Number keys for pinned tags, in order
+static PIN_KEYS: &str = "1234567890";
+
+/// Find the tags pinned in the config
+///
+/// Unknown tag names are ignored.
+fn load_pinned(state: &mut AppState) {
+    state.pinned = state
+        .config
+        .pinned_tags
+        .iter()
+        .take(PIN_KEYS.len())
+        .map(|name| {
+            tag_dir(state, name)
+                .canonicalize()
+                .ok()
+                .filter(|tp| state.tags.contains_key(tp.as_path()))
+        })
+        .collect();
+    state.pinned.resize(PIN_KEYS.len(), None);
+}
+
+/// UI callback to toggle the tag pinned to a number key
+///
+/// Without a tag pinned to it, '0' still filters for untagged items, as it
+/// did before tags could be pinned.
+fn toggle_pinned(siv: &mut Cursive, state: &mut AppState, n: usize) {
+    let key = PIN_KEYS.chars().nth(n).unwrap();
+    let tp = match state.pinned[n] {
+        Some(ref tp) if state.tags.contains_key(tp.as_path()) => tp.clone(),
+        _ if key == '0' => return apply_filter(siv, state, Filter::Untagged),
+        _ => return ui_error(siv, tr!("No tag is pinned to '{}'.", key)),
+    };
+    if let Err(failure) = toggle_tag_implicit(siv, state, &tp) {
+        ui_toggle_error(siv, state, &tp, &failure);
+    }
+    ui_mark_itemview(siv, state);
+    ui_update_tagsview(siv, state);
+}
+
+/// UI callback to pin the highlighted tag to a number key
+fn pin_tag(siv: &mut Cursive, state: &mut AppState, n: usize) {
+    let tp = siv
+        .call_on_id("tagsview", |v: &mut CheckList<PathBuf>| v.selection())
+        .unwrap();
+    if let Some(tp) = tp {
+        for pin in state.pinned.iter_mut() {
+            if pin.as_ref() == Some(&*tp) {
+                *pin = None;
+            }
+        }
+        state.pinned[n] = Some((*tp).clone());
+        ui_mark_tagsview(siv, state);
+    }
+}
+
+/// Split a tag name into the label and path of its tag root, and the rest
+///
+/// Names starting with the label of an extra root and a colon are in that
+/// root, any other name is in the tags dir.
+fn split_root<'s, 'n>(
+    state: &'s AppState,
+    name: &'n str,
+) -> (Option<&'n str>, &'s Path, &'n str) {
+    if let Some(i) = name.find(':') {
+        if let Some(root) = state.config.tag_roots.get(&name[..i]) {
+            return (Some(&name[..i]), root, &name[i + 1..]);
+        }
+    }
+    (None, &state.tags_path, name)
+}
+
+/// Path of the tag dir for a tag name
+fn tag_dir(state: &AppState, name: &str) -> PathBuf {
+    let (_, root, rest) = split_root(state, name);
+    root.join(kv::tag_path(rest))
+}
+
+/// Create a tag dir (and any missing parents) and add them to the tags index
+///
+/// `name` is relative to its tag root, or `key=value` for a key/value tag,
+/// and starts with `label:` for an extra root.
+/// Returns the canonical path of the tag.
+fn create_tag(state: &mut AppState, name: &str) -> io::Result<PathBuf> {
+    let (label, root, name) = split_root(state, name);
+    let label = label.map(str::to_owned);
+    let root = root.to_path_buf();
+    let rel = kv::tag_path(name);
+    let valid = rel
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)));
+    // such a tag would be gone after the next scan
+    let exclude = tag_exclude(state);
+    let excluded = rel
+        .iter()
+        .enumerate()
+        .any(|(i, c)| is_excluded_tag(&exclude, c, i == 0));
+    if name.is_empty() || !valid || excluded {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid tag name: {}", name),
+        ));
+    }
+
+    let path = root.join(rel);
+    let created = !path.is_dir();
+    if created {
+        state.fs.create_dir(&path)?;
+    }
+
+    // in dry-run mode the dirs don't exist, so their canonical paths are guessed
+    let ctags = root.canonicalize()?;
+    let mut cpath = None;
+    for dir in path.ancestors() {
+        if dir == root {
+            break;
+        }
+        let rel = dir.strip_prefix(&root).unwrap();
+        let cdir = dir.canonicalize().unwrap_or_else(|_| ctags.join(rel));
+        if cpath.is_none() {
+            cpath = Some(cdir.clone());
+        }
+        if !state.tags.contains_key(cdir.as_path()) {
+            let tag = Tag::new(
+                label.as_ref().map(String::as_str),
+                escape::display_name(rel.as_os_str(), state.config.escape_names),
+                None,
+            );
+            state.tags.insert(cdir.into(), tag);
+        }
+    }
+
+    let cpath = cpath.unwrap();
+    if created {
+        journal::record_tag(state, "tag-create", &cpath);
+    }
+    Ok(cpath)
+}
+
 /// Create new tag with provided name
 fn new_tag(siv: &mut Cursive, state: &mut AppState, name: &str) {
     if !name.is_empty() {
         siv.pop_layer();
 
-        let mut path = state.tags_path.clone();
-        path.push(name);
+        if let Err(e) = create_tag(state, name) {
+            return ui_error(siv, e);
+        }
+
+        ui_refresh_tagsview(siv, state);
+    }
+}
+
+/// UI callback for the tag color dialog
+fn ui_submit_tag_color(siv: &mut Cursive, spec: &str) {
+    let tp = siv
+        .call_on_id("tagsview", |v: &mut CheckList<PathBuf>| v.selection())
+        .unwrap();
+    let tp = match tp {
+        Some(tp) => tp,
+        None => return,
+    };
 
-        fs::DirBuilder::new()
-            .recursive(true)
-            .create(&path)
-            .expect("could not create dir");
+    let spec = spec.trim();
+    let color = if spec.is_empty() {
+        None
+    } else {
+        match Color::parse(spec) {
+            Some(color) => Some(color),
+            None => return ui_error(siv, tr!("unknown color: {}", spec)),
+        }
+    };
 
-        state.tags.insert(
-            path.canonicalize().unwrap(),
-            Tag {
-                name: name.to_owned(),
-                items: HashMap::default(),
-            },
-        );
+    siv.pop_layer();
+    do_app(siv, |siv, state| {
+        if let Err(e) = color::write(&mut state.fs, &tp, spec) {
+            return ui_error(siv, e);
+        }
+        state.tags.get_mut(tp.as_path()).unwrap().color = color;
+        ui_mark_itemview(siv, state);
+        ui_mark_tagsview(siv, state);
+    });
+}
 
-        ui_refresh_tagsview(siv, state);
+/// Display UI Dialog for choosing the highlighted tag's color
+fn ui_build_tag_color(siv: &mut Cursive) {
+    let tp = siv
+        .call_on_id("tagsview", |v: &mut CheckList<PathBuf>| v.selection())
+        .unwrap();
+    if let Some(tp) = tp {
+        let current = fs::read_to_string(tp.join(color::COLOR_FILE)).unwrap_or_default();
+        siv.add_layer(ui_input_dialog(
+            "Tag color (empty for none):",
+            "tagcolor",
+            current.trim(),
+            ui_submit_tag_color,
+        ));
     }
 }
 
@@ -382,9 +2156,48 @@ fn ui_build_new_tag(siv: &mut Cursive) {
     siv.add_layer(ui_input_dialog("New tag:", "tagname", "", |siv, x| do_app(siv, |siv, state| new_tag(siv, state, x))));
 }
 
+/// Items pane of the main UI, wrapped for resizing
+type ItemPane = BoxView<ScrollView<OnEventView<IdView<LazyList<SharedPath>>>>>;
+
+/// Size the items pane according to the current split
+fn ui_resize_panes(siv: &mut Cursive, state: &mut AppState) {
+    let vertical = state.config.layout == Layout::Vertical;
+    let screen = siv.screen_size();
+    // leave room for the frame of the main window (and the footer and the
+    // read-only banner)
+    let lines = 1 + state.fs.is_read_only() as usize;
+    let total = if vertical { screen.y.saturating_sub(lines) } else { screen.x };
+    let total = total.saturating_sub(6);
+    let size = total * state.split / 100;
+    siv.call_on_id("itempane", |v: &mut ItemPane| {
+        if vertical {
+            v.set_height(SizeConstraint::Fixed(size));
+        } else {
+            v.set_width(SizeConstraint::Fixed(size));
+        }
+    });
+}
+
+/// UI callback to grow (or shrink, if negative) the items pane
+fn ui_grow_items_pane(siv: &mut Cursive, state: &mut AppState, by: isize) {
+    let split = state.split as isize + by;
+    state.split = split.clamp(10, 90) as usize;
+    ui_resize_panes(siv, state);
+}
+
 /// Initialise the main UI
 fn ui_build_main(siv: &mut Cursive) {
-    let itemview = SelectView::<PathBuf>::new().with_id("itemview");
+    let itemview = LazyList::<SharedPath>::new()
+        .on_page(|siv| do_app(siv, ui_page_itemview))
+        .on_select(|siv| {
+            // the tags view shows the tags of the highlighted item then
+            do_app(siv, |siv, state| {
+                if state.sel.is_empty() {
+                    ui_mark_tagsview(siv, state);
+                }
+            })
+        })
+        .with_id("itemview");
     let itemview = OnEventView::new(itemview)
         .on_event(' ', |siv| {
             do_app(siv, |siv, state| {
@@ -395,60 +2208,490 @@ fn ui_build_main(siv: &mut Cursive) {
         })
         .on_event('r', |siv| {
             do_app(siv, |siv, state| {
-                apply_filter(siv, state, itemview_filter_reset)
+                apply_filter(siv, state, Filter::All)
+            })
+        })
+        .on_event('u', |siv| {
+            do_app(siv, |siv, state| {
+                apply_filter(siv, state, Filter::Untagged)
             })
         })
-        .on_event('0', |siv| {
+        .on_event('x', |siv| {
+            siv.add_layer(ui_input_dialog(
+                "Show extensions (comma-separated):",
+                "filterext",
+                "",
+                ui_submit_filter_ext,
+            ))
+        })
+        .on_event('f', |siv| {
+            siv.add_layer(ui_input_dialog(
+                "Show items matching:",
+                "filterquery",
+                "",
+                ui_submit_filter_query,
+            ))
+        })
+        .on_event('m', |siv| {
+            do_app(siv, |siv, state| {
+                state.columns = (state.columns + 1) % columns::COLUMN_SETS.len();
+                ui_mark_itemview(siv, state);
+            })
+        })
+        .on_event('p', |siv| {
+            do_app(siv, |siv, state| {
+                state.name_mode = match state.name_mode {
+                    NameMode::Name => NameMode::Relative,
+                    NameMode::Relative => NameMode::Canonical,
+                    NameMode::Canonical => NameMode::Name,
+                };
+                ui_fill_itemview(siv, state);
+            })
+        })
+        .on_event('G', |siv| {
             do_app(siv, |siv, state| {
-                apply_filter(siv, state, itemview_filter_untagged)
+                state.group_by_tag = !state.group_by_tag;
+                ui_fill_itemview(siv, state);
             })
         })
-        .on_event('e', ui_build_cmdexec);
-    let itemview = ScrollView::new(itemview).scroll_x(true);
+        .on_event('t', |siv| do_app(siv, ui_jump_to_tags))
+        .on_event('s', registers::ui_build_save)
+        .on_event('l', registers::ui_build_recall)
+        .on_event(Key::F2, rename::ui_build_rename)
+        .on_event('M', rename::ui_build_move)
+        .on_event('P', playlist::ui_export_shown)
+        .on_event('v', ui_show_selection)
+        .on_event(Key::Enter, |siv| {
+            // Enter picks the selection in `--print-selection` mode
+            if do_app(siv, |_, state| state.print_selection) {
+                do_app(siv, pick_selection);
+            } else {
+                tageditor::ui_build(siv);
+            }
+        })
+        .on_event('y', |siv| {
+            do_app(siv, |siv, state| {
+                if let Err(e) = clipboard::yank_paths(&state.sel) {
+                    ui_error(siv, e);
+                }
+            })
+        });
+    #[cfg(feature = "preview")]
+    let itemview = itemview
+        .on_event('e', preview::ui_build_cmdexec)
+        .on_event('E', preview::ui_build_cmdrun)
+        .on_event('o', |siv| do_app(siv, preview::open_selection));
+    #[cfg(feature = "trash")]
+    let itemview = itemview.on_event('D', delete::ui_build_delete);
+    #[cfg(feature = "plugins")]
+    let itemview = itemview.on_event(':', plugins::ui_build);
+    let mut itemview = itemview;
+    for (n, key) in PIN_KEYS.chars().enumerate() {
+        itemview = itemview.on_event(key, move |siv| {
+            do_app(siv, |siv, state| toggle_pinned(siv, state, n))
+        });
+    }
+    // the list scrolls itself vertically, rendering only the visible rows
+    let itemview = ScrollView::new(itemview).scroll_x(true).scroll_y(false);
+    let itemview = BoxView::new(SizeConstraint::Free, SizeConstraint::Free, itemview)
+        .with_id("itempane");
 
-    let tagsview = SelectView::<PathBuf>::new().with_id("tagsview");
+    let tagsview = CheckList::<PathBuf>::new()
+        .on_select(|siv, tp| {
+            description::ui_show(siv, tp);
+            // may be called from `do_app`, by refreshes of the tags view
+            let tp = tp.clone();
+            handle::defer(siv, move |siv, state| ui_hover_tag(siv, state, Some(tp)));
+        })
+        .with_id("tagsview");
     let tagsview = OnEventView::new(tagsview)
         .on_event(' ', |siv| {
             do_app(siv, |siv, state| {
                 toggle_tag(siv, state);
-                ui_mark_tagsview(siv, state);
+                ui_mark_itemview(siv, state);
+                ui_update_tagsview(siv, state);
+            })
+        })
+        .on_event('+', ui_build_new_tag)
+        .on_event('c', ui_build_tag_color)
+        .on_event('d', description::ui_build_edit)
+        .on_event('R', consistency::ui_check)
+        .on_event('s', |siv| {
+            do_app(siv, |siv, state| {
+                state.tag_sort = match state.tag_sort {
+                    TagSort::Name => TagSort::Count,
+                    TagSort::Count => TagSort::Recent,
+                    TagSort::Recent => TagSort::Name,
+                };
+                ui_refresh_tagsview(siv, state);
             })
         })
-        .on_event('+', ui_build_new_tag);
-    let tagsview = ScrollView::new(tagsview);
+        .on_event('i', |siv| do_app(siv, ui_jump_to_items))
+        .on_event('a', audit::ui_view)
+        .on_event('g', gc::ui_empty_tags)
+        .on_event('P', playlist::ui_export_tag);
+    let mut tagsview = tagsview;
+    for (n, key) in PIN_KEYS.chars().enumerate() {
+        tagsview = tagsview.on_event(key, move |siv| {
+            do_app(siv, |siv, state| pin_tag(siv, state, n))
+        });
+    }
+    #[cfg(feature = "preview")]
+    let tagsview = tagsview.on_event('o', |siv| {
+        do_app(siv, |siv, state| preview::open_tagdir(siv, state))
+    });
+    let tagsview = LinearLayout::vertical()
+        .child(ScrollView::new(tagsview))
+        .child(TextView::new("").with_id("tagdescription"));
+
+    let focus = footer::Focus::default();
+    let itemview = footer::Tracked::new(itemview, footer::Pane::Items, focus.clone());
+    let tagsview = footer::Tracked::new(tagsview, footer::Pane::Tags, focus.clone());
 
-    let layout = LinearLayout::horizontal()
-        .child(itemview)
-        .child(DummyView)
-        .child(tagsview);
+    let vertical = do_app(siv, |_, state| state.config.layout == Layout::Vertical);
+    let layout = if vertical {
+        LinearLayout::vertical()
+    } else {
+        LinearLayout::horizontal()
+    };
+    let layout = layout.child(itemview).child(DummyView).child(tagsview);
+
+    let mut layout = LinearLayout::vertical().child(layout);
+    if do_app(siv, |_, state| state.fs.is_read_only()) {
+        let banner = tr!("Read-only: the tags directory can't be changed, only browsed");
+        let banner = TextView::new(StyledString::styled(banner, Effect::Reverse));
+        layout.insert_child(0, banner);
+    }
+    if do_app(siv, |_, state| state.fs.is_dry_run()) {
+        let log = ScrollView::new(TextView::new("").with_id("dryrunlog"))
+            .scroll_strategy(ScrollStrategy::StickToBottom);
+        layout.add_child(Panel::new(log).title(tr!("Dry run")).fixed_height(8));
+    }
+    let quit_key = do_app(siv, |_, state| state.config.quit_key.clone());
+    layout.add_child(footer::Footer::new(focus, quit_key));
 
-    siv.add_layer(Dialog::around(layout).title("linkorgasm"));
+    siv.add_layer(Dialog::around(layout).title("linkorgasm").full_screen());
 
     do_app(siv, |siv, state| {
-        apply_filter(siv, state, itemview_filter_reset)
+        ui_resize_panes(siv, state);
+        let filter = state.start_filter.take().unwrap_or(Filter::All);
+        apply_filter(siv, state, filter);
+        if let Some(name) = state.start_tag.take() {
+            let tp = state.tags.iter().find(|(_, t)| t.name == name).map(|(tp, _)| tp.clone());
+            match tp {
+                Some(tp) => {
+                    ui_select_value(siv, "tagsview", &tp);
+                    ui_hover_tag(siv, state, Some(tp.to_path_buf()));
+                }
+                None => ui_error(siv, tr!("no such tag: {}", name)),
+            }
+        }
+        foreign::ui_review(siv, state);
+        tutorial::ui_begin(siv, state);
     });
 }
 
 /// UI callback for items dir path dialog
-fn ui_submit_itemdir(siv: &mut Cursive, p: &str) {
-    do_app(siv, |_, state| scan_items(state, p));
+fn ui_submit_itemdir(siv: &mut Cursive, p: &Path) {
+    if !p.is_dir() {
+        return ui_error(siv, tr!("not a directory: {}", p.display()));
+    }
+    let tags_preset = do_app(siv, |_, state| {
+        scan_items(state, p);
+        state.tags_path.clone()
+    });
     siv.pop_layer();
-    siv.add_layer(ui_input_dialog(
-        "Tags directory:",
-        "tagdir",
-        "tags",
-        ui_submit_tagdir,
-    ));
+    if tags_preset.as_os_str().is_empty() {
+        ui_ask_tagdir(siv);
+    } else {
+        load_tagdir(siv, tags_preset);
+    }
+}
+
+/// Display UI Dialog asking for the items directory
+fn ui_ask_itemdir(siv: &mut Cursive) {
+    browser::ui_browse(siv, "Items directory", "all", ui_submit_itemdir);
+}
+
+/// Display UI Dialog asking for the tags directory
+fn ui_ask_tagdir(siv: &mut Cursive) {
+    browser::ui_browse(siv, "Tags directory", "tags", ui_submit_tagdir);
 }
 
 /// UI callback for tags dir path dialog
-fn ui_submit_tagdir(siv: &mut Cursive, p: &str) {
-    do_app(siv, |_, state| {
-        state.tags_path = PathBuf::from(p);
-        scan_tags(state, None, p);
-    });
+fn ui_submit_tagdir(siv: &mut Cursive, p: &Path) {
     siv.pop_layer();
+    load_tagdir(siv, p);
+}
+
+/// Lock the tags directory, then scan it and show the main UI
+///
+/// If another instance holds the lock, ask whether to go on anyway.
+/// Nothing is locked in dry-run and read-only mode, as the tags dir isn't
+/// changed; a tags dir the user can't write to is opened read-only.
+fn load_tagdir(siv: &mut Cursive, p: impl AsRef<Path>) {
+    let p = p.as_ref().to_path_buf();
+    if !p.is_dir() {
+        return ui_offer_tagdir(siv, p);
+    }
+    let locked: Result<(), (PathBuf, io::Error)> = do_app(siv, |_, state| {
+        if !fsops::is_writable(&p) {
+            state.fs.set_read_only(true);
+        }
+        if state.fs.is_dry_run() || state.fs.is_read_only() {
+            return Ok(());
+        }
+        let shared = state.config.journal;
+        state.lock = Some(lock::acquire(&p, shared).map_err(|e| (p.clone(), e))?);
+        state.root_locks = lock::acquire_roots(&writable_roots(state, &p), shared)?;
+        Ok(())
+    });
+    match locked {
+        Err((ref dir, ref e)) if e.kind() == io::ErrorKind::WouldBlock => {
+            let holder = if *dir == p {
+                lock::holder(&p)
+            } else {
+                format!("{}, {}", dir.display(), lock::holder(dir))
+            };
+            let msg = tr!(
+                "The tags directory is in use by another linkorgasm instance ({}).\n\
+                 Changes made by both will clobber each other.",
+                holder
+            );
+            siv.add_layer(
+                Dialog::text(msg)
+                    .title(tr!("Tags directory locked"))
+                    .button(tr!("Quit"), |siv| siv.quit())
+                    .button(tr!("Continue anyway"), move |siv| {
+                        siv.pop_layer();
+                        scan_tagdir(siv, &p);
+                    }),
+            );
+        }
+        Err((dir, e)) => {
+            scan_tagdir(siv, &p);
+            let e = format!("{}: {}", dir.display(), e);
+            ui_error(siv, tr!("cannot lock tags directory: {}", e));
+        }
+        Ok(()) => scan_tagdir(siv, &p),
+    }
+}
+
+/// Offer to create a tags directory that doesn't exist
+///
+/// It can be seeded with the tags from the `tag-template` config setting.
+fn ui_offer_tagdir(siv: &mut Cursive, p: PathBuf) {
+    if p.exists() {
+        ui_ask_tagdir(siv);
+        return ui_error(siv, tr!("not a directory: {}", p.display()));
+    }
+    let (dry_run, template) = do_app(siv, |_, state| {
+        (state.fs.is_dry_run(), !state.config.tag_template.is_empty())
+    });
+    if dry_run {
+        ui_ask_tagdir(siv);
+        return ui_error(
+            siv,
+            tr!("{} doesn't exist (and isn't created in dry-run mode)", p.display()),
+        );
+    }
+
+    let msg = tr!("The tags directory {} doesn't exist.", p.display());
+    let create = p.clone();
+    let dialog = Dialog::text(msg)
+        .title(tr!("New tags directory"))
+        .button(tr!("Create"), move |siv| {
+            siv.pop_layer();
+            create_tagdir(siv, &create, false);
+        });
+    let dialog = if template {
+        dialog.button(tr!("Create with starter tags"), move |siv| {
+            siv.pop_layer();
+            create_tagdir(siv, &p, true);
+        })
+    } else {
+        dialog
+    };
+    siv.add_layer(dialog.button(tr!("Choose another"), |siv| {
+        siv.pop_layer();
+        ui_ask_tagdir(siv);
+    }));
+}
+
+/// Create a new tags directory and load it, optionally with starter tags
+fn create_tagdir(siv: &mut Cursive, p: &Path, template: bool) {
+    if let Err(e) = fs::create_dir_all(p) {
+        ui_ask_tagdir(siv);
+        return ui_error(siv, tr!("cannot create {}: {}", p.display(), e));
+    }
+    load_tagdir(siv, p);
+    if !template {
+        return;
+    }
+    do_app(siv, |siv, state| {
+        let names = state.config.tag_template.clone();
+        for name in names.iter() {
+            if let Err(e) = create_tag(state, name) {
+                ui_error(siv, e);
+            }
+        }
+        ui_refresh_tagsview(siv, state);
+    });
+}
+
+/// Scan the tags directory and show the main UI
+fn scan_tagdir(siv: &mut Cursive, p: &Path) {
+    let (audit, errors) = do_app(siv, |_, state| {
+        state.tags_path = p.to_path_buf();
+        scan_tags(state, p);
+        load_pinned(state);
+        if let (Some(items), Ok(tags)) = (state.items_path.as_ref(), p.canonicalize()) {
+            if state.tutorial.is_none() {
+                recent::remember(items, &tags);
+            }
+        }
+        let roots = writable_roots(state, p);
+        let audit = state.fs.open_audit(p, &roots);
+        let mut errors = identity::repair(state);
+        if let Err(e) = journal::open(state, p) {
+            errors.push(format!("cannot open journal: {}", e));
+        }
+        (audit, errors)
+    });
     ui_build_main(siv);
+    if let Err(e) = audit {
+        ui_error(siv, tr!("cannot open audit log: {}", e));
+    }
+    if !errors.is_empty() {
+        ui_error(siv, errors.join("\n"));
+    }
+    let skipped = do_app(siv, |_, state| {
+        let skipped: Vec<&str> = state
+            .skipped_items
+            .iter()
+            .chain(state.skipped_tags.iter())
+            .map(String::as_str)
+            .collect();
+        skipped.join("\n")
+    });
+    if !skipped.is_empty() {
+        ui_error(siv, skipped);
+    }
+}
+
+/// Set up the state for a subcommand that runs without the UI
+///
+/// Scans the items (if given, `-` reads a list from stdin) and the tags
+/// dir, which is locked unless in dry-run or read-only mode (which it is
+/// in if the user can't write to it).
+fn load_headless(m: &ArgMatches, items: Option<&Path>, tags: &Path) -> Result<AppState, String> {
+    load_headless_with(m, tags, false, |state| add_items(state, items))
+}
+
+/// Like `load_headless`, but the lock is exclusive even with the journal
+///
+/// For commands rewriting links in bulk, which aren't journaled, so no
+/// journal client may run alongside.
+fn load_exclusive(m: &ArgMatches, items: Option<&Path>, tags: &Path) -> Result<AppState, String> {
+    load_headless_with(m, tags, true, |state| add_items(state, items))
+}
+
+/// Like `load_headless`, without locking or changing the tags dir
+///
+/// For commands that only read, like `list-tags`, so they can run while the
+/// tags dir is in use: there is no lock, audit log or journal, no links are
+/// repaired, and any write is refused as in read-only mode.
+fn load_readonly(m: &ArgMatches, items: Option<&Path>, tags: &Path) -> Result<AppState, String> {
+    let mut config = config::load();
+    cli::apply_to_config(&mut config, m);
+    let mut state = AppState::new(config);
+    state.fs.set_read_only(true);
+    add_items(&mut state, items);
+
+    if !tags.is_dir() {
+        return Err(format!("tags directory doesn't exist: {}", tags.display()));
+    }
+    state.tags_path = tags.to_path_buf();
+    scan_tags(&mut state, tags);
+    if !cli::is_quiet(m) {
+        for msg in state.skipped_items.iter().chain(state.skipped_tags.iter()) {
+            eprintln!("{}", msg);
+        }
+    }
+    Ok(state)
+}
+
+/// Scan the items given (`-` reads a list from stdin), if any
+fn add_items(state: &mut AppState, items: Option<&Path>) {
+    match items {
+        Some(items) if items == Path::new("-") => {
+            let stdin = io::stdin();
+            scan_items_list(state, stdin.lock());
+        }
+        Some(items) => scan_items(state, items),
+        None => {}
+    }
+}
+
+/// Like `load_headless`, with the items added by `add_items`, and the
+/// tags dir locked `exclusive`ly
+fn load_headless_with(
+    m: &ArgMatches,
+    tags: &Path,
+    exclusive: bool,
+    add_items: impl FnOnce(&mut AppState),
+) -> Result<AppState, String> {
+    let mut config = config::load();
+    cli::apply_to_config(&mut config, m);
+    let mut state = AppState::new(config);
+    state.fs = cli::fs_ops(m).map_err(|e| format!("cannot open dry-run log: {}", e))?;
+    add_items(&mut state);
+
+    if !tags.is_dir() {
+        return Err(format!("tags directory doesn't exist: {}", tags.display()));
+    }
+    if !fsops::is_writable(tags) {
+        state.fs.set_read_only(true);
+    }
+    if !state.fs.is_dry_run() && !state.fs.is_read_only() {
+        let shared = state.config.journal && !exclusive;
+        let locked = lock::acquire(tags, shared)
+            .map_err(|e| (tags.to_path_buf(), e))
+            .and_then(|lock| {
+                state.lock = Some(lock);
+                lock::acquire_roots(&writable_roots(&state, tags), shared)
+            });
+        match locked {
+            Ok(locks) => state.root_locks = locks,
+            Err((ref dir, ref e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                return Err(format!(
+                    "tags directory {} is in use by another linkorgasm instance ({})",
+                    dir.display(),
+                    lock::holder(dir)
+                ));
+            }
+            Err((dir, e)) => {
+                return Err(format!("cannot lock tags directory {}: {}", dir.display(), e))
+            }
+        }
+    }
+    state.tags_path = tags.to_path_buf();
+    scan_tags(&mut state, tags);
+    if !cli::is_quiet(m) {
+        for msg in state.skipped_items.iter().chain(state.skipped_tags.iter()) {
+            eprintln!("{}", msg);
+        }
+    }
+    let roots = writable_roots(&state, tags);
+    state
+        .fs
+        .open_audit(tags, &roots)
+        .map_err(|e| format!("cannot open audit log: {}", e))?;
+    journal::open(&mut state, tags).map_err(|e| format!("cannot open journal: {}", e))?;
+    for e in identity::repair(&mut state) {
+        eprintln!("{}", e);
+    }
+    Ok(state)
 }
 
 /// Helper to create a dialog asking the user for a text string
@@ -456,18 +2699,20 @@ fn ui_input_dialog(
     title: &str,
     id: &'static str,
     default: &str,
-    submit: fn(&mut Cursive, &str),
+    submit: impl Fn(&mut Cursive, &str) + 'static,
 ) -> Dialog {
+    let submit = Rc::new(submit);
+    let on_submit = Rc::clone(&submit);
     Dialog::new()
-        .title(title)
+        .title(tr!(title))
         .content(
             EditView::new()
-                .on_submit(submit)
+                .on_submit(move |siv, text| on_submit(siv, text))
                 .content(default)
                 .with_id(id)
                 .fixed_width(20),
         )
-        .button("Ok", move |siv| {
+        .button(tr!("Ok"), move |siv| {
             let text = siv
                 .call_on_id(id, |v: &mut EditView| v.get_content())
                 .unwrap();
@@ -475,41 +2720,279 @@ fn ui_input_dialog(
         })
 }
 
+/// UI callback to finish picking in `--print-selection` mode
+///
+/// Like fzf, picks the highlighted item if nothing is selected.
+fn pick_selection(siv: &mut Cursive, state: &mut AppState) {
+    if state.sel.is_empty() {
+        let p = siv
+            .call_on_id("itemview", |v: &mut LazyList<SharedPath>| v.selection())
+            .and_then(|p| p);
+        if let Some(p) = p {
+            state.sel.insert(p);
+        }
+    }
+    state.picked = true;
+    siv.quit();
+}
+
+/// Event of a key given like the `quit-key` setting
+fn quit_event(spec: &str) -> Result<Event, String> {
+    let mut chars = spec.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => return Ok(Event::Char(c)),
+        _ if spec == "esc" => return Ok(Event::Key(Key::Esc)),
+        _ => {}
+    }
+    let mut ctrl = spec.strip_prefix("ctrl-").map(str::chars);
+    match ctrl.as_mut().map(|c| (c.next(), c.next())) {
+        Some((Some(c), None)) => Ok(Event::CtrlChar(c)),
+        _ => Err(tr!("invalid quit-key (using 'q'): {}", spec)),
+    }
+}
+
+/// UI callback to quit, saving the session, after asking if so configured
+///
+/// Pressing the quit key again while asked quits, too.
+fn ui_quit(siv: &mut Cursive) {
+    let asking = siv.call_on_id("confirmquit", |_: &mut TextView| ()).is_some();
+    if asking || !do_app(siv, |_, state| state.config.confirm_quit) {
+        session::save(siv);
+        return siv.quit();
+    }
+    let text = TextView::new(tr!("Quit linkorgasm?")).with_id("confirmquit");
+    siv.add_layer(
+        Dialog::around(text)
+            .title(tr!("Quit"))
+            .button(tr!("Quit"), |siv| {
+                session::save(siv);
+                siv.quit();
+            })
+            .dismiss_button(tr!("Cancel")),
+    );
+}
+
+/// Show an error message
+fn ui_error(siv: &mut Cursive, msg: impl std::fmt::Display) {
+    let msg = msg.to_string();
+    error!("{}", msg);
+    siv.add_layer(
+        Dialog::text(msg)
+            .title(tr!("ERROR"))
+            .button(tr!("Ok"), |siv| {
+                siv.pop_layer();
+            }),
+    )
+}
+
 /// Show help
 fn ui_help(siv: &mut Cursive) {
-    let content = TextView::new(HELP_TEXT).no_wrap();
+    let content = TextView::new(i18n::help(HELP_TEXT)).no_wrap();
     let content = ScrollView::new(content).scroll_x(true);
     siv.add_layer(
         Dialog::new()
-            .title("HELP")
+            .title(tr!("HELP"))
             .content(content)
-            .button("Close", |siv| {
+            .button(tr!("Close"), |siv| {
                 siv.pop_layer();
             }),
     );
 }
 
 fn main() {
-    let mut siv = Cursive::default();
-
-    siv.set_user_data(AppState {
-        items_all: HashMap::default(),
-        items_vis: HashSet::default(),
-        tags: HashMap::default(),
-        tags_path: PathBuf::new(),
-        sel: HashSet::default(),
-    });
+    let matches = match cli::build_cli().get_matches_safe() {
+        Ok(matches) => matches,
+        // --help and --version
+        Err(e) if !e.use_stderr() => e.exit(),
+        Err(e) => {
+            eprintln!("{}", e.message);
+            std::process::exit(exit::USAGE);
+        }
+    };
+    let log_file = matches.value_of_os("log-file").map(Path::new);
+    if let Err(e) = logging::init(log_file, matches.occurrences_of("verbose")) {
+        eprintln!("cannot open log file: {}", e);
+        std::process::exit(1);
+    }
+    if let Err(e) = cli::check_profile(&matches) {
+        eprintln!("{}", e);
+        std::process::exit(exit::USAGE);
+    }
+    match matches.subcommand() {
+        ("self-check", Some(m)) => std::process::exit(selfcheck::run(m)),
+        ("apply", Some(m)) => std::process::exit(apply::run(m)),
+        ("gc", Some(m)) => std::process::exit(gc::run(m)),
+        ("describe", Some(m)) => std::process::exit(describe::run(m)),
+        ("maintain", Some(m)) => std::process::exit(maintain::run(m)),
+        ("snapshot", Some(m)) => std::process::exit(snapshot::run(m)),
+        ("restore", Some(m)) => std::process::exit(snapshot::restore(m)),
+        ("diff", Some(m)) => std::process::exit(snapshot::diff(m)),
+        ("relink", Some(m)) => std::process::exit(relink::run(m)),
+        ("normalize-links", Some(m)) => std::process::exit(normalize::run(m)),
+        ("materialize", Some(m)) => std::process::exit(manifest::run(m)),
+        ("export-html", Some(m)) => std::process::exit(html::run(m)),
+        ("export-archive", Some(m)) => std::process::exit(archive::run(m)),
+        #[cfg(feature = "daemon")]
+        ("daemon", Some(m)) => std::process::exit(daemon::run(m)),
+        ("pick-tags", Some(m)) => std::process::exit(picktags::run(m)),
+        #[cfg(feature = "gui")]
+        ("gui", Some(m)) => std::process::exit(gui::run(m)),
+        ("completions", Some(m)) => std::process::exit(completions::run(m)),
+        ("list-tags", Some(m)) => std::process::exit(completions::list_tags(m)),
+        ("query", Some(m)) => std::process::exit(search::run(m)),
+        ("stats", Some(m)) => std::process::exit(stats::run(m)),
+        ("serve", Some(m)) => std::process::exit(serve::run(m)),
+        _ => {}
+    }
+
+    // keep stdout free for the result and draw the UI on the terminal
+    let print_selection = matches.is_present("print-selection");
+    let stdout_saved = if print_selection {
+        Some(tty::redirect_to_tty(libc::STDOUT_FILENO).expect("cannot open terminal"))
+    } else {
+        None
+    };
+
+    let mut config = config::load();
+    cli::apply_to_config(&mut config, &matches);
+    let mut state = AppState::new(config);
+    state.fs = cli::fs_ops(&matches).expect("cannot open dry-run log");
+    state.start_filter = match matches.value_of("filter") {
+        Some("untagged") => Some(Filter::Untagged),
+        Some(q) => match query::Query::parse(q) {
+            Ok(_) => Some(Filter::Query(q.to_owned())),
+            Err(e) => {
+                eprintln!("invalid --filter query: {}", e);
+                std::process::exit(exit::USAGE);
+            }
+        },
+        None => None,
+    };
+    state.start_tag = matches.value_of("select-tag").map(str::to_owned);
+
+    let theme = state.config.theme.clone();
+    let journal = state.config.journal;
+    let watch_interval = state.config.watch_interval;
+    let quit_key = quit_event(&state.config.quit_key);
+    let i18n_result = i18n::init(state.config.language.as_ref().map(String::as_str));
 
-    siv.add_global_callback('q', |siv| siv.quit());
+    let items = cli::items_dir(&matches);
+    let tags = cli::tags_dir(&matches);
+    if let Some(ref items) = items {
+        if items == Path::new("-") {
+            let stdin = io::stdin();
+            scan_items_list(&mut state, stdin.lock());
+            // the UI needs the keyboard back
+            tty::redirect_to_tty(libc::STDIN_FILENO).expect("cannot open terminal");
+        } else {
+            #[cfg(feature = "daemon")]
+            let warm = index::load(&mut state, items);
+            #[cfg(not(feature = "daemon"))]
+            let warm = false;
+            if !warm {
+                scan_items(&mut state, items);
+            }
+        }
+    }
+    if let Some(ref tags) = tags {
+        state.tags_path = tags.clone();
+    }
+    state.print_selection = print_selection;
+
+    tty::install_panic_hook(matches.value_of_os("log-file").map(PathBuf::from));
+    let mut siv = match backend::init(state.config.backend.as_ref().map(String::as_str)) {
+        Ok(siv) => siv,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    siv.set_user_data(state);
+    let theme_result = theme.map(|name| theme::apply(&mut siv, &name));
+    if journal {
+        journal::ui_start_sync(&siv);
+    }
+    if watch_interval > 0 {
+        consistency::ui_start_watch(&siv, Duration::from_secs(watch_interval));
+    }
+    #[cfg(feature = "ipc")]
+    let ipc_server = matches
+        .value_of_os("socket")
+        .map(|p| ipc::start(&siv, Path::new(p)));
+
+    siv.add_global_callback(quit_key.clone().unwrap_or(Event::Char('q')), ui_quit);
     siv.add_global_callback('h', |siv| ui_help(siv));
     siv.add_global_callback('?', |siv| ui_help(siv));
+    siv.add_global_callback('<', |siv| {
+        do_app(siv, |siv, state| ui_grow_items_pane(siv, state, -5))
+    });
+    siv.add_global_callback('>', |siv| {
+        do_app(siv, |siv, state| ui_grow_items_pane(siv, state, 5))
+    });
+    #[cfg(feature = "preview")]
+    siv.add_global_callback('j', jobs::ui_view);
+    siv.add_global_callback(Event::WindowResize, |siv| do_app(siv, ui_resize_panes));
+    if print_selection {
+        siv.add_global_callback(Key::Enter, |siv| do_app(siv, pick_selection));
+    }
 
-    siv.add_layer(ui_input_dialog(
-        "Items directory:",
-        "itemdir",
-        "all",
-        ui_submit_itemdir,
-    ));
+    match (items, tags) {
+        _ if matches.is_present("tutorial") => tutorial::ui_start(&mut siv),
+        (Some(_), Some(tags)) => load_tagdir(&mut siv, tags),
+        (Some(_), None) => ui_ask_tagdir(&mut siv),
+        (None, None) => match session::load() {
+            Some(session) => session::ui_offer_restore(&mut siv, session),
+            None => recent::ui_pick(&mut siv),
+        },
+        (None, Some(_)) => ui_ask_itemdir(&mut siv),
+    }
+    if let Some(Err(e)) = theme_result {
+        ui_error(&mut siv, e);
+    }
+    if let Err(e) = i18n_result {
+        ui_error(&mut siv, e);
+    }
+    if let Err(e) = quit_key {
+        ui_error(&mut siv, e);
+    }
+    #[cfg(feature = "ipc")]
+    {
+        if let Some(Err(ref e)) = ipc_server {
+            ui_error(&mut siv, tr!("cannot listen on socket: {}", e));
+        }
+    }
+    #[cfg(feature = "dbus")]
+    {
+        if matches.is_present("dbus") {
+            match dbus::start(&siv) {
+                Ok(bus) => do_app(&mut siv, |_, state| state.dbus = Some(bus)),
+                Err(e) => ui_error(&mut siv, tr!("cannot register on D-Bus: {}", e)),
+            }
+        }
+    }
 
     siv.run();
+    // removes the socket
+    #[cfg(feature = "ipc")]
+    drop(ipc_server);
+
+    let state: AppState = siv.take_user_data().unwrap();
+    // restores the terminal
+    drop(siv);
+
+    if let Some(saved) = stdout_saved {
+        tty::restore(libc::STDOUT_FILENO, saved).expect("cannot restore stdout");
+        if !state.picked {
+            std::process::exit(1);
+        }
+
+        let mut paths: Vec<_> = state.sel.iter().collect();
+        paths.sort();
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        for p in paths {
+            out.write_all(p.as_os_str().as_bytes()).expect("cannot write to stdout");
+            out.write_all(b"\n").expect("cannot write to stdout");
+        }
+    }
 }
@@ -0,0 +1,274 @@
+//! `linkorgasm maintain`
+//!
+//! One round of upkeep, for a systemd timer or a cron job:
+//!
+//! 1. links to moved items are repaired: those found by inode while
+//!    loading (with `identity = "inode"`), and with `--repair-by-name`,
+//!    dangling links whose target had the file name, size and
+//!    modification time of exactly one item at the last run
+//! 2. with `--remove-dangling`, the dangling links left are removed, like
+//!    with `linkorgasm gc`, and the empty tags too with `--empty-tags`
+//! 3. the descriptions of changed images are refreshed, if a
+//!    `describe-command` is configured (see `describe`)
+//! 4. the library is reported on, like with `linkorgasm stats`
+//!
+//! The steps changing links are skipped if no items were found or most
+//! links are dangling, which is what an unmounted items disk looks like.
+//!
+//! Running it again right away changes nothing. The summary is printed
+//! for humans, or with `--format json` as one JSON object. The exit code
+//! tells whether anything failed or is still broken (see `exit`).
+
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::ArgMatches;
+use hashbrown::HashMap;
+use serde::Serialize;
+
+use crate::cli;
+use crate::describe;
+use crate::exit;
+use crate::gc;
+use crate::stats::{self, Stats};
+use crate::{lexical_path, load_exclusive, tag_item, AppState, SharedPath, META_DIR};
+
+/// Name of the file in the metadata dir with the sizes and modification
+/// times the tagged items had at the last run
+pub static STAMPS_FILE: &str = "stamps";
+
+/// Size and modification time (in seconds) of an item
+type Stamp = (u64, u64);
+
+fn stamps_path(tags_path: &Path) -> PathBuf {
+    tags_path.join(META_DIR).join(STAMPS_FILE)
+}
+
+/// Stamp of an item with the given size and modification time
+fn stamp(size: u64, mtime: Option<SystemTime>) -> Option<Stamp> {
+    let secs = mtime?.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    Some((size, secs))
+}
+
+/// Read the stamps of the items at the last run, by path
+///
+/// Lines are `SIZE MTIME PATH`. A missing or broken file gives nothing.
+fn load_stamps(tags_path: &Path) -> HashMap<PathBuf, Stamp> {
+    let bytes = match fs::read(stamps_path(tags_path)) {
+        Ok(bytes) => bytes,
+        Err(_) => return HashMap::default(),
+    };
+    let mut stamps = HashMap::default();
+    for line in bytes.split(|&b| b == b'\n') {
+        let mut fields = line.splitn(3, |&b| b == b' ');
+        let size = fields.next().and_then(|f| std::str::from_utf8(f).ok()?.parse().ok());
+        let mtime = fields.next().and_then(|f| std::str::from_utf8(f).ok()?.parse().ok());
+        let path = fields.next().map(|f| PathBuf::from(OsStr::from_bytes(f)));
+        if let (Some(size), Some(mtime), Some(path)) = (size, mtime, path) {
+            stamps.insert(path, (size, mtime));
+        }
+    }
+    stamps
+}
+
+/// Write the stamps of the tagged items, and keep those of the targets of
+/// the links still dangling, unless nothing changed
+fn save_stamps(state: &mut AppState, old: &HashMap<PathBuf, Stamp>) -> std::io::Result<()> {
+    let mut stamps: Vec<(PathBuf, Stamp)> = state
+        .items_all
+        .iter()
+        .filter(|(_, item)| !item.tags.is_empty())
+        .filter_map(|(ip, item)| Some((ip.to_path_buf(), stamp(item.size, item.mtime)?)))
+        .collect();
+    for f in state.foreign.iter().filter(|f| f.target.is_none()) {
+        let target = match fs::read_link(&f.link) {
+            Ok(target) => lexical_path(&f.tag, &target),
+            Err(_) => continue,
+        };
+        if let Some(stamp) = old.get(&target) {
+            stamps.push((target, *stamp));
+        }
+    }
+    stamps.sort();
+    stamps.dedup();
+    if stamps.len() == old.len() && stamps.iter().all(|(p, st)| old.get(p) == Some(st)) {
+        return Ok(());
+    }
+
+    let mut contents = Vec::new();
+    for (path, (size, mtime)) in stamps {
+        contents.extend(format!("{} {} ", size, mtime).as_bytes());
+        contents.extend(path.as_os_str().as_bytes());
+        contents.push(b'\n');
+    }
+    let path = stamps_path(&state.tags_path);
+    state.fs.create_meta_dir(path.parent().unwrap())?;
+    state.fs.write_file(&path, contents)
+}
+
+#[derive(Debug, Serialize)]
+struct Summary {
+    /// dangling links pointed at the item their target was, by file name
+    repaired_links: usize,
+    /// dangling links removed
+    removed_links: usize,
+    /// empty tags removed
+    removed_tags: usize,
+    /// images described
+    described: usize,
+    /// whether this was a dry run
+    dry_run: bool,
+    /// changes not made, in dry-run mode
+    planned: Vec<String>,
+    errors: Vec<String>,
+    stats: Stats,
+}
+
+/// Point dangling links at the item with the file name of their target,
+/// if exactly one item has it, and the size and modification time the
+/// target had at the last run; returns how many were, and the errors
+fn repair_by_name(state: &mut AppState, stamps: &HashMap<PathBuf, Stamp>) -> (usize, Vec<String>) {
+    let dangling: Vec<(PathBuf, PathBuf)> = state
+        .foreign
+        .iter()
+        .filter(|f| f.target.is_none())
+        .map(|f| (f.link.clone(), f.tag.clone()))
+        .collect();
+    let mut repaired = 0;
+    let mut errors = Vec::new();
+    for (link, tp) in dangling {
+        let target = match fs::read_link(&link) {
+            Ok(target) => lexical_path(&tp, &target),
+            Err(_) => continue,
+        };
+        let (name, was) = match (target.file_name(), stamps.get(&target)) {
+            (Some(name), Some(was)) => (name.to_owned(), Some(*was)),
+            _ => continue,
+        };
+        let found: Vec<SharedPath> = state
+            .items_all
+            .iter()
+            .filter(|(_, item)| item.filename == name && stamp(item.size, item.mtime) == was)
+            .map(|(ip, _)| ip.clone())
+            .collect();
+        if found.len() != 1 || !state.tags.contains_key(tp.as_path()) {
+            continue;
+        }
+        let res = state
+            .fs
+            .remove_file(&link)
+            .and_then(|_| tag_item(state, &tp, &found[0]));
+        match res {
+            Ok(()) => {
+                repaired += 1;
+                state.foreign.retain(|f| f.link != link);
+            }
+            Err(e) => errors.push(format!("{}: {}", link.display(), e)),
+        }
+    }
+    (repaired, errors)
+}
+
+/// Print the summary for humans
+fn print_plain(summary: &Summary) {
+    for op in summary.planned.iter() {
+        println!("{}", op);
+    }
+    if summary.dry_run {
+        println!("dry run, to be done:");
+    }
+    println!("repaired links: {}", summary.repaired_links);
+    println!("removed links:  {}", summary.removed_links);
+    println!("removed tags:   {}", summary.removed_tags);
+    println!("described:      {}", summary.described);
+    println!("errors:         {}", summary.errors.len());
+    println!();
+    stats::print_plain(&summary.stats);
+}
+
+/// Entry point for `linkorgasm maintain`, returns the exit code
+pub fn run(m: &ArgMatches) -> i32 {
+    let (items, tags) = match (cli::items_dir(m), cli::tags_dir(m)) {
+        (Some(items), Some(tags)) => (items, tags),
+        _ => {
+            eprintln!("both an items dir and a tags dir are required");
+            return exit::USAGE;
+        }
+    };
+    let mut state = match load_exclusive(m, Some(&items), &tags) {
+        Ok(state) => state,
+        Err(e) => return exit::load_failed(&tags, &e),
+    };
+
+    let stamps = load_stamps(&tags);
+    let mut errors = Vec::new();
+    let (mut repaired_links, mut removed_links) = (0, 0);
+    let by_name = m.is_present("repair-by-name");
+    let dangling = m.is_present("remove-dangling");
+    match gc::check_dangling(&state) {
+        Err(e) if by_name || dangling => errors.push(e),
+        _ => {
+            if by_name {
+                let (n, failed) = repair_by_name(&mut state, &stamps);
+                repaired_links = n;
+                errors.extend(failed);
+            }
+            if dangling {
+                let (n, failed) = gc::remove_dangling(&mut state);
+                removed_links = n;
+                for (link, e) in failed {
+                    errors.push(format!("{}: {}", link.display(), e));
+                }
+            }
+        }
+    }
+
+    let mut removed_tags = 0;
+    if m.is_present("empty-tags") {
+        for tp in gc::empty_tags(&state) {
+            match gc::remove_tag(&mut state, &tp) {
+                Ok(()) => removed_tags += 1,
+                Err(e) => errors.push(format!("{}: {}", tp.display(), e)),
+            }
+        }
+    }
+
+    let mut described = 0;
+    if let Some(cmdline) = state.config.describe_command.clone() {
+        let (n, failed) = describe::describe_images(&mut state, &cmdline, false);
+        described = n;
+        errors.extend(failed);
+    }
+
+    if !state.items_all.is_empty() {
+        if let Err(e) = save_stamps(&mut state, &stamps) {
+            errors.push(format!("{}: {}", stamps_path(&tags).display(), e));
+        }
+    }
+
+    let summary = Summary {
+        repaired_links,
+        removed_links,
+        removed_tags,
+        described,
+        dry_run: state.fs.is_dry_run(),
+        planned: state.fs.log().to_vec(),
+        errors,
+        stats: stats::collect(&state),
+    };
+    let json = m.value_of("format") == Some("json");
+    if !json {
+        for e in summary.errors.iter() {
+            eprintln!("{}", e);
+        }
+    }
+    if json {
+        println!("{}", serde_json::to_string(&summary).expect("cannot serialize summary"));
+    } else if !cli::is_quiet(m) {
+        print_plain(&summary);
+    }
+    exit::checked(&state, summary.errors.len())
+}
@@ -0,0 +1,222 @@
+//! Tag manifests, for tags trees kept in sync by tools that drop symlinks
+//!
+//! Some sync tools and cloud drives don't carry symlinks over. With the
+//! `tag-manifests` setting, each tag dir also gets a `.manifest` file
+//! listing its items, one path per line (relative to the items dir, if
+//! there is one). Every change to the links is written to the manifests
+//! too, and `linkorgasm materialize` makes the symlinks match the
+//! manifests: the manifests travel, and the symlinks are rebuilt from them
+//! locally. That is never done on loading, as a scan of only some of the
+//! items (like `--items -`) would drop the links to all the others.
+//!
+//! Lines for items that don't exist here (yet) are kept, so machines with
+//! different parts of a library don't drop each other's tags.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+use hashbrown::HashSet;
+
+use crate::cli;
+use crate::exit;
+use crate::{
+    item_key, lexical_path, link_target, load_headless, tag_item, untag_item, AppState, SharedPath,
+};
+
+/// Name of the manifest file in a tag directory
+pub static MANIFEST_FILE: &str = ".manifest";
+
+/// Line of a manifest for an item
+fn line(state: &AppState, ip: &Path) -> Vec<u8> {
+    let rel = state
+        .items_path
+        .as_ref()
+        .and_then(|items| ip.strip_prefix(items).ok())
+        .unwrap_or(ip);
+    rel.as_os_str().as_bytes().to_vec()
+}
+
+/// Path of the item a manifest line stands for
+fn item_path(state: &AppState, line: &[u8]) -> PathBuf {
+    let p = Path::new(OsStr::from_bytes(line));
+    match state.items_path {
+        Some(ref items) => items.join(p),
+        None => p.to_path_buf(),
+    }
+}
+
+/// The lines of a manifest file
+fn read_lines(path: &Path) -> io::Result<Vec<Vec<u8>>> {
+    let bytes = fs::read(path)?;
+    Ok(bytes
+        .split(|&b| b == b'\n')
+        .filter(|l| !l.is_empty())
+        .map(<[u8]>::to_vec)
+        .collect())
+}
+
+/// Lines for every symlink in a tag dir
+///
+/// Not only the links to the items scanned, which may be some of them;
+/// dangling links are listed with the path they point to.
+fn read_links(state: &AppState, tp: &Path) -> io::Result<Vec<Vec<u8>>> {
+    let mut lines = Vec::new();
+    for entry in fs::read_dir(tp)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_symlink() {
+            continue;
+        }
+        let link = entry.path();
+        let target = match link_target(state, &link, tp) {
+            Some(target) => target,
+            None => lexical_path(tp, &fs::read_link(&link)?),
+        };
+        lines.push(line(state, &target));
+    }
+    Ok(lines)
+}
+
+/// Lines of a tag's manifest, and whether it exists
+///
+/// A tag without a manifest gets one listing the symlinks in its dir.
+fn load(state: &AppState, tp: &Path) -> io::Result<(Vec<Vec<u8>>, bool)> {
+    match read_lines(&tp.join(MANIFEST_FILE)) {
+        Ok(lines) => Ok((lines, true)),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok((read_links(state, tp)?, false)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Write a tag's manifest, sorted
+fn store(state: &mut AppState, tp: &Path, mut lines: Vec<Vec<u8>>) -> io::Result<()> {
+    lines.sort();
+    lines.dedup();
+    let mut contents = Vec::new();
+    for l in lines {
+        contents.extend(l);
+        contents.push(b'\n');
+    }
+    state.fs.write_file(&tp.join(MANIFEST_FILE), contents)
+}
+
+/// Whether a tag dir has a manifest without any items
+pub fn is_empty(tp: &Path) -> bool {
+    read_lines(&tp.join(MANIFEST_FILE)).map_or(false, |lines| lines.is_empty())
+}
+
+/// List an item in a tag's manifest, if manifests are kept
+pub fn add(state: &mut AppState, tp: &Path, ip: &Path) -> io::Result<()> {
+    if !state.config.tag_manifests {
+        return Ok(());
+    }
+    let (mut lines, exists) = load(state, tp)?;
+    let l = line(state, ip);
+    if lines.contains(&l) && exists {
+        return Ok(());
+    }
+    lines.push(l);
+    store(state, tp, lines)
+}
+
+/// Drop an item from a tag's manifest, if manifests are kept
+pub fn remove(state: &mut AppState, tp: &Path, ip: &Path) -> io::Result<()> {
+    if !state.config.tag_manifests {
+        return Ok(());
+    }
+    let (mut lines, exists) = load(state, tp)?;
+    let l = line(state, ip);
+    let count = lines.len();
+    lines.retain(|x| *x != l);
+    if lines.len() == count && exists {
+        return Ok(());
+    }
+    store(state, tp, lines)
+}
+
+/// Make the symlinks of every tag match its manifest
+///
+/// Tags without a manifest get one from their symlinks. Needs every item
+/// scanned, as links to the items not scanned are removed. Returns the
+/// errors.
+fn materialize(state: &mut AppState) -> Vec<String> {
+    if !state.config.tag_manifests {
+        return Vec::new();
+    }
+    let mut tags: Vec<SharedPath> = state.tags.keys().cloned().collect();
+    tags.sort();
+    let mut errors = Vec::new();
+    for tp in tags.iter() {
+        let (lines, exists) = match load(state, tp) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                errors.push(format!("{}: {}", tp.join(MANIFEST_FILE).display(), e));
+                continue;
+            }
+        };
+        if !exists {
+            if let Err(e) = store(state, tp, lines) {
+                errors.push(format!("{}: {}", tp.join(MANIFEST_FILE).display(), e));
+            }
+            continue;
+        }
+
+        let listed: HashSet<SharedPath> = lines
+            .iter()
+            .filter_map(|l| item_key(state, &item_path(state, l)))
+            .collect();
+        let linked: Vec<SharedPath> = state.tags[tp].items.keys().cloned().collect();
+        let mut missing: Vec<&SharedPath> = listed
+            .iter()
+            .filter(|ip| !state.tags[tp].items.contains_key(*ip))
+            .collect();
+        missing.sort();
+        for ip in missing {
+            if let Err(e) = tag_item(state, tp, ip) {
+                errors.push(format!("{}: {}", tp.join(ip.file_name().unwrap()).display(), e));
+            }
+        }
+        for ip in linked.iter().filter(|ip| !listed.contains(*ip)) {
+            let name = state.tags[tp].items[ip].clone();
+            if let Err(e) = untag_item(state, tp, ip) {
+                errors.push(format!("{}: {}", tp.join(name).display(), e));
+            }
+        }
+    }
+    errors
+}
+
+/// Entry point for `linkorgasm materialize`, returns the exit code
+pub fn run(m: &ArgMatches) -> i32 {
+    let (items, tags) = match (cli::items_dir(m), cli::tags_dir(m)) {
+        (Some(items), Some(tags)) => (items, tags),
+        _ => {
+            eprintln!("both an items dir and a tags dir are required");
+            return exit::USAGE;
+        }
+    };
+    if items == Path::new("-") {
+        eprintln!("materialize needs the whole items dir, not a list of items");
+        return exit::USAGE;
+    }
+    let mut state = match load_headless(m, Some(&items), &tags) {
+        Ok(state) => state,
+        Err(e) => return exit::load_failed(&tags, &e),
+    };
+    if !state.config.tag_manifests {
+        eprintln!("tag-manifests is off in the config file, there are no manifests to follow");
+        return exit::USAGE;
+    }
+
+    let errors = materialize(&mut state);
+    for e in errors.iter() {
+        eprintln!("{}", e);
+    }
+    for op in state.fs.log() {
+        println!("{}", op);
+    }
+    exit::from_errors(errors.len())
+}
@@ -0,0 +1,68 @@
+//! Natural sort order for names
+//!
+//! With the `natural-sort` config setting, items and tags are sorted
+//! case-insensitively, with runs of digits compared as numbers, so `img2`
+//! comes before `img10` and `Zebra` after `apple`. Letters are compared by
+//! their lowercase forms, which is no full locale collation, but puts
+//! accented letters next to each other.
+
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Take a run of ASCII digits
+fn take_number(chars: &mut Peekable<Chars>) -> String {
+    let mut number = String::new();
+    while let Some(&c) = chars.peek() {
+        if !c.is_ascii_digit() {
+            break;
+        }
+        number.push(c);
+        chars.next();
+    }
+    number
+}
+
+/// Compare runs of digits by their values
+fn compare_numbers(a: &str, b: &str) -> Ordering {
+    let a = a.trim_start_matches('0');
+    let b = b.trim_start_matches('0');
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+/// Compare two names in natural order
+///
+/// Names that only differ in case or leading zeros are ordered by code
+/// points, so the order is total.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    let mut ia = a.chars().peekable();
+    let mut ib = b.chars().peekable();
+    loop {
+        let ord = match (ia.peek().copied(), ib.peek().copied()) {
+            (None, None) => break,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                compare_numbers(&take_number(&mut ia), &take_number(&mut ib))
+            }
+            (Some(x), Some(y)) => {
+                ia.next();
+                ib.next();
+                x.to_lowercase().cmp(y.to_lowercase())
+            }
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    a.cmp(b)
+}
+
+/// Compare two names in the configured order
+pub fn compare_names(natural: bool, a: &str, b: &str) -> Ordering {
+    if natural {
+        compare(a, b)
+    } else {
+        a.cmp(b)
+    }
+}
@@ -0,0 +1,123 @@
+//! `linkorgasm normalize-links`
+//!
+//! Tags trees made by older versions or other tools mix relative and
+//! absolute symlinks. This rewrites every symlink in the tags tree in the
+//! configured `link-style` (or the one given with `--style`), but only if
+//! the rewritten link resolves to the same canonical path as before.
+//! Dangling symlinks are left alone.
+//!
+//! With an items dir, links to items are made the way tagging makes them,
+//! from the item's path. Other links keep the path they lead through, only
+//! in the new style, rather than the canonical path of their target (which
+//! may go through another symlink or mount point).
+
+use std::io;
+use std::path::PathBuf;
+
+use clap::ArgMatches;
+
+use crate::cli;
+use crate::exit;
+use crate::linkstyle::{self, LinkStyle};
+use crate::{lexical_path, load_exclusive};
+
+/// Link styles, by the name given to `--style`
+pub static STYLES: &[&str] = &["auto", "relative", "absolute"];
+
+/// Entry point for `linkorgasm normalize-links`, returns the exit code
+pub fn run(m: &ArgMatches) -> i32 {
+    let tags = match cli::tags_dir(m) {
+        Some(tags) => tags,
+        None => {
+            eprintln!("a tags dir is required");
+            return exit::USAGE;
+        }
+    };
+    // without items, every symlink in the tags tree is a foreign one
+    let items = cli::items_dir(m);
+    let mut state = match load_exclusive(m, items.as_deref(), &tags) {
+        Ok(state) => state,
+        Err(e) => return exit::load_failed(&tags, &e),
+    };
+    let style = match m.value_of("style") {
+        Some("auto") => LinkStyle::Auto,
+        Some("relative") => LinkStyle::Relative,
+        Some("absolute") => LinkStyle::Absolute,
+        _ => state.config.link_style,
+    };
+    // (link, tag dir, path of the item, if known, and canonical target)
+    let mut links: Vec<(PathBuf, PathBuf, Option<PathBuf>, PathBuf)> = Vec::new();
+    for (tp, tag) in state.tags.iter() {
+        for (ip, name) in tag.items.iter() {
+            links.push((tp.join(name), tp.to_path_buf(), Some(ip.to_path_buf()), ip.to_path_buf()));
+        }
+    }
+    for f in state.foreign.iter() {
+        if let Some(ref target) = f.target {
+            links.push((f.link.clone(), f.tag.clone(), None, target.clone()));
+        }
+    }
+    links.sort();
+    let dangling = state.foreign.iter().filter(|f| f.target.is_none()).count();
+
+    let (mut rewritten, mut errors) = (0, 0);
+    for (link, tag, item, target) in links.iter() {
+        let old = match link.read_link() {
+            Ok(old) => old,
+            Err(e) => {
+                eprintln!("{}: {}", link.display(), e);
+                errors += 1;
+                continue;
+            }
+        };
+        let path = match item {
+            Some(ip) => ip.clone(),
+            None => lexical_path(tag, &old),
+        };
+        let new = linkstyle::target(style, tag, &path);
+        if new == old {
+            continue;
+        }
+        // only rewrite links whose new target is the same item
+        let resolved = lexical_path(tag, &new).canonicalize().ok();
+        if resolved.as_ref() != Some(target) {
+            eprintln!(
+                "{}: {} doesn't lead to {}, left alone",
+                link.display(),
+                new.display(),
+                target.display()
+            );
+            errors += 1;
+            continue;
+        }
+
+        let is_dir = target.is_dir();
+        let res: io::Result<()> = state
+            .fs
+            .remove_symlink(link, is_dir)
+            .and_then(|_| state.fs.symlink(&new, link, is_dir));
+        match res {
+            Ok(()) => rewritten += 1,
+            Err(e) => {
+                eprintln!("{}: {}", link.display(), e);
+                errors += 1;
+            }
+        }
+    }
+
+    for op in state.fs.log() {
+        println!("{}", op);
+    }
+    let verb = if state.fs.is_dry_run() {
+        "to rewrite"
+    } else {
+        "rewritten"
+    };
+    if !cli::is_quiet(m) {
+        println!(
+            "{} symlinks {}, {} dangling ones skipped, {} errors",
+            rewritten, verb, dangling, errors
+        );
+    }
+    exit::from_errors(errors)
+}
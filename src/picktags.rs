@@ -0,0 +1,99 @@
+//! `linkorgasm pick-tags FILE`
+//!
+//! Pops up the tag editor checklist for a single file and exits when it is
+//! applied or cancelled, so files can be tagged from a file manager like
+//! ranger, nnn or lf by binding this to a key.
+
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+use cursive::event::Event;
+use cursive::views::Dialog;
+use cursive::Cursive;
+
+use crate::backend;
+use crate::cli;
+use crate::i18n;
+use crate::{
+    item_key, load_headless_with, quit_event, scan_items_list, tageditor, theme, tty, ui_error,
+    AppState,
+};
+
+/// Quit once the tag editor is closed, after showing the errors if any
+fn done(siv: &mut Cursive, errors: &[String]) {
+    if errors.is_empty() {
+        return siv.quit();
+    }
+    siv.add_layer(
+        Dialog::text(errors.join("\n"))
+            .title(tr!("ERROR"))
+            .button(tr!("Quit"), |siv| siv.quit()),
+    );
+}
+
+/// Entry point for `linkorgasm pick-tags`, returns the exit code
+pub fn run(m: &ArgMatches) -> i32 {
+    let tags = match cli::tags_dir(m) {
+        Some(tags) => tags,
+        None => {
+            eprintln!("a tags dir is required");
+            return 1;
+        }
+    };
+    let file = Path::new(m.value_of_os("file").unwrap());
+    let state = load_headless_with(m, &tags, false, |state| {
+        scan_items_list(state, file.as_os_str().as_bytes());
+    });
+    let state = match state {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    // the file is the only item, unless include patterns skipped it
+    let ip = file.canonicalize().ok().and_then(|p| item_key(&state, &p));
+    let ip = match ip {
+        Some(ip) => ip,
+        None => {
+            eprintln!("cannot tag {}", file.display());
+            return 1;
+        }
+    };
+
+    let theme = state.config.theme.clone();
+    let i18n_result = i18n::init(state.config.language.as_ref().map(String::as_str));
+    let quit_key = quit_event(&state.config.quit_key);
+    tty::install_panic_hook(m.value_of_os("log-file").map(PathBuf::from));
+    let mut siv = match backend::init(state.config.backend.as_ref().map(String::as_str)) {
+        Ok(siv) => siv,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    siv.set_user_data(state);
+    let theme_result = theme.map(|name| theme::apply(&mut siv, &name));
+    siv.add_global_callback(quit_key.clone().unwrap_or(Event::Char('q')), |siv| siv.quit());
+
+    tageditor::ui_edit(&mut siv, ip, done);
+    if let Some(Err(e)) = theme_result {
+        ui_error(&mut siv, e);
+    }
+    if let Err(e) = i18n_result {
+        ui_error(&mut siv, e);
+    }
+    if let Err(e) = quit_key {
+        ui_error(&mut siv, e);
+    }
+    siv.run();
+
+    let state: AppState = siv.take_user_data().unwrap();
+    // restores the terminal
+    drop(siv);
+    for op in state.fs.log() {
+        println!("{}", op);
+    }
+    0
+}
@@ -0,0 +1,109 @@
+//! Exporting items as `.m3u8` playlists
+//!
+//! The items of the highlighted tag ('P' in the tags view), or the items
+//! shown in the items view, e.g. the result of a query ('P' there), are
+//! written as canonical paths to a playlist any media player can play.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use cursive::views::Dialog;
+use cursive::Cursive;
+use hashbrown::HashSet;
+
+use crate::browser::expand;
+use crate::checklist::CheckList;
+use crate::lazylist::LazyList;
+use crate::{do_app, ui_error, ui_input_dialog, SharedPath};
+
+/// First line of an extended M3U playlist
+static HEADER: &[u8] = b"#EXTM3U\n";
+
+/// Write a playlist of the given items, in order
+///
+/// An existing file is only replaced if it is a playlist itself.
+pub fn write(path: &Path, items: &[SharedPath]) -> io::Result<()> {
+    if let Ok(old) = fs::read(path) {
+        if !old.starts_with(HEADER) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "file exists and is not a playlist",
+            ));
+        }
+    }
+    let mut data = HEADER.to_vec();
+    for p in items {
+        data.extend_from_slice(p.as_os_str().as_bytes());
+        data.push(b'\n');
+    }
+    fs::write(path, data)
+}
+
+/// Display UI Dialog asking where to write a playlist
+///
+/// `name` is the suggested file name, without extension.
+fn ui_ask_path(siv: &mut Cursive, name: &str, items: Vec<SharedPath>) {
+    if items.is_empty() {
+        return siv.add_layer(Dialog::info(tr!("There are no items to export.")));
+    }
+    let name = name.replace(|c: char| "/:=".contains(c), "-");
+    let default = env::current_dir()
+        .unwrap_or_default()
+        .join(format!("{}.m3u8", name));
+    let dialog = ui_input_dialog(
+        "Write playlist to:",
+        "playlistpath",
+        &default.to_string_lossy(),
+        move |siv, text| {
+            let path = expand(text);
+            if let Err(e) = write(&path, &items) {
+                return ui_error(siv, format!("{}: {}", path.display(), e));
+            }
+            siv.pop_layer();
+            siv.add_layer(Dialog::info(tr!(
+                "Wrote {} items to {}",
+                items.len(),
+                path.display()
+            )));
+        },
+    );
+    siv.add_layer(dialog);
+}
+
+/// UI callback to export the items of the highlighted tag
+pub fn ui_export_tag(siv: &mut Cursive) {
+    let tp = siv
+        .call_on_id("tagsview", |v: &mut CheckList<PathBuf>| v.selection())
+        .and_then(|tp| tp);
+    let tp = match tp {
+        Some(tp) => tp,
+        None => return,
+    };
+    let (name, items) = do_app(siv, |_, state| {
+        let tag = &state.tags[tp.as_path()];
+        let mut items: Vec<SharedPath> = tag.items.keys().cloned().collect();
+        items.sort();
+        (tag.name.clone(), items)
+    });
+    ui_ask_path(siv, &name, items);
+}
+
+/// UI callback to export the items shown in the items view, in its order
+///
+/// When the items are grouped by tag, each is written once, where it is
+/// first listed.
+pub fn ui_export_shown(siv: &mut Cursive) {
+    let items = siv
+        .call_on_id("itemview", |v: &mut LazyList<SharedPath>| {
+            let mut seen = HashSet::new();
+            (0..v.len())
+                .filter_map(|i| v.get_item(i).map(|(_, p)| p.clone()))
+                .filter(|p| seen.insert(p.clone()))
+                .collect()
+        })
+        .unwrap_or_default();
+    ui_ask_path(siv, "playlist", items);
+}
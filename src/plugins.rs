@@ -0,0 +1,241 @@
+//! Plugin scripts
+//!
+//! Custom commands (auto-taggers, exporters, bulk renames) can be written
+//! as [Rhai](https://rhai.rs) scripts in the `plugins` dir of the config
+//! dir (`~/.config/linkorgasm/plugins/` on Linux). ':' in the items view
+//! lists them by file name and runs the chosen one. Scripts see the items
+//! and tags through these functions (items are canonical paths):
+//!
+//! - `items()`, `shown()`, `selection()`: all, shown and selected items
+//! - `tags()`: names of all tags; `tags_of(item)`: names of an item's tags
+//! - `tag(item, name)`, `untag(item, name)`: change the tags of an item
+//!   (missing tags are created, tagging twice does nothing)
+//! - `select(item)`, `deselect(item)`: change the selection
+//! - `rename(item, name)`: rename an item like F2
+//! - `message(text)`: show text when the script is done
+//!
+//! A script is stopped after `MAX_OPERATIONS` operations, so one stuck in
+//! an endless loop doesn't hang the UI.
+//!
+//! Only compiled with the `plugins` feature.
+
+use std::cell::RefCell;
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use cursive::views::{Dialog, ScrollView, SelectView, TextView};
+use cursive::Cursive;
+use rhai::{Array, Dynamic, Engine, EvalAltResult};
+
+use crate::config::{config_dir, Config};
+use crate::{
+    do_app, find_or_create_tag, find_tag, rename, sorted_tags, tag_item, ui_refresh_itemview,
+    untag_item, AppState, SharedPath,
+};
+
+/// Operations a script may run, plenty for going through a big collection
+const MAX_OPERATIONS: u64 = 100_000_000;
+
+type Shared = Rc<RefCell<AppState>>;
+type FnResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Dir holding the plugin scripts
+fn plugins_dir() -> Option<PathBuf> {
+    config_dir().map(|p| p.join("plugins"))
+}
+
+/// Paths of the plugin scripts, by name
+fn list() -> Vec<PathBuf> {
+    let entries = match plugins_dir().and_then(|dir| fs::read_dir(dir).ok()) {
+        Some(entries) => entries,
+        None => return Vec::new(),
+    };
+    let mut scripts: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().map_or(false, |e| e == "rhai"))
+        .collect();
+    scripts.sort();
+    scripts
+}
+
+/// Sorted paths as a script array
+fn path_array<'a>(paths: impl Iterator<Item = &'a SharedPath>) -> Array {
+    let mut paths: Vec<&SharedPath> = paths.collect();
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|p| Dynamic::from(p.to_string_lossy().into_owned()))
+        .collect()
+}
+
+/// Key of a known item, or a script error
+fn find_item(state: &AppState, item: &str) -> FnResult<SharedPath> {
+    crate::find_item(state, item).map_err(Into::into)
+}
+
+/// Make the model and actions available to scripts
+fn register(engine: &mut Engine, shared: &Shared, messages: &Rc<RefCell<Vec<String>>>) {
+    let s = Rc::clone(shared);
+    engine.register_fn("items", move || path_array(s.borrow().items_all.keys()));
+    let s = Rc::clone(shared);
+    engine.register_fn("shown", move || path_array(s.borrow().items_vis.iter()));
+    let s = Rc::clone(shared);
+    engine.register_fn("selection", move || path_array(s.borrow().sel.iter()));
+
+    let s = Rc::clone(shared);
+    engine.register_fn("tags", move || -> Array {
+        let state = s.borrow();
+        sorted_tags(&state)
+            .into_iter()
+            .map(|(_, t)| Dynamic::from(t.name.clone()))
+            .collect()
+    });
+    let s = Rc::clone(shared);
+    engine.register_fn("tags_of", move |item: &str| -> FnResult<Array> {
+        let state = s.borrow();
+        let ip = find_item(&state, item)?;
+        let mut names: Vec<String> = state.items_all[&ip]
+            .tags
+            .iter()
+            .map(|tp| state.tags[tp].name.clone())
+            .collect();
+        names.sort();
+        Ok(names.into_iter().map(Dynamic::from).collect())
+    });
+
+    let s = Rc::clone(shared);
+    engine.register_fn("tag", move |item: &str, name: &str| -> FnResult<()> {
+        let mut state = s.borrow_mut();
+        let ip = find_item(&state, item)?;
+        let tp = find_or_create_tag(&mut state, name)?;
+        if state.items_all[&ip].tags.contains(&tp) {
+            return Ok(());
+        }
+        tag_item(&mut state, &tp, &ip).map_err(|e| e.to_string().into())
+    });
+    let s = Rc::clone(shared);
+    engine.register_fn("untag", move |item: &str, name: &str| -> FnResult<()> {
+        let mut state = s.borrow_mut();
+        let ip = find_item(&state, item)?;
+        let tp = find_tag(&state, name)?;
+        if !state.items_all[&ip].tags.contains(&tp) {
+            return Ok(());
+        }
+        untag_item(&mut state, &tp, &ip).map_err(|e| e.to_string().into())
+    });
+
+    let s = Rc::clone(shared);
+    engine.register_fn("select", move |item: &str| -> FnResult<()> {
+        let mut state = s.borrow_mut();
+        let ip = find_item(&state, item)?;
+        if state.items_vis.contains(&ip) {
+            state.sel.insert(ip);
+        }
+        Ok(())
+    });
+    let s = Rc::clone(shared);
+    engine.register_fn("deselect", move |item: &str| -> FnResult<()> {
+        let mut state = s.borrow_mut();
+        let ip = find_item(&state, item)?;
+        state.sel.remove(&ip);
+        Ok(())
+    });
+    let s = Rc::clone(shared);
+    engine.register_fn("rename", move |item: &str, name: &str| -> FnResult<()> {
+        let mut state = s.borrow_mut();
+        let ip = find_item(&state, item)?;
+        if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+            return Err(format!("invalid file name: {}", name).into());
+        }
+        let to = ip.with_file_name(name);
+        rename::move_item(&mut state, &ip, &to).map_err(|e| e.to_string().into())
+    });
+
+    let m = Rc::clone(messages);
+    engine.register_fn("message", move |text: &str| {
+        m.borrow_mut().push(text.to_owned());
+    });
+}
+
+/// Run a plugin script on the app state
+///
+/// Returns the messages of the script and its error, if it failed.
+fn run(state: &mut AppState, script: &Path) -> (Vec<String>, Option<String>) {
+    // scripts hold on to the state while they run, it is put back after
+    let shared: Shared = Rc::new(RefCell::new(mem::replace(
+        state,
+        AppState::new(Config::default()),
+    )));
+    let messages = Rc::new(RefCell::new(Vec::new()));
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    register(&mut engine, &shared, &messages);
+    let result = engine.run_file(script.to_path_buf());
+    drop(engine);
+
+    *state = match Rc::try_unwrap(shared) {
+        Ok(cell) => cell.into_inner(),
+        Err(_) => unreachable!("the script engine is gone"),
+    };
+    let messages = messages.borrow().clone();
+    (messages, result.err().map(|e| e.to_string()))
+}
+
+/// UI callback running the chosen plugin
+fn ui_run(siv: &mut Cursive, script: &PathBuf) {
+    siv.pop_layer();
+    let (messages, error) = do_app(siv, |siv, state| {
+        let result = run(state, script);
+        // scripts may have renamed items, created tags and changed the selection
+        ui_refresh_itemview(siv, state);
+        result
+    });
+
+    let name = script.file_stem().unwrap().to_string_lossy().into_owned();
+    if let Some(e) = error {
+        return siv.add_layer(
+            Dialog::text(format!("{}\n\n{}", messages.join("\n"), e).trim_start().to_owned())
+                .title(tr!("ERROR in {}", name))
+                .button(tr!("Ok"), |siv| {
+                    siv.pop_layer();
+                }),
+        );
+    }
+    if !messages.is_empty() {
+        siv.add_layer(
+            Dialog::around(ScrollView::new(TextView::new(messages.join("\n"))))
+                .title(name)
+                .button(tr!("Ok"), |siv| {
+                    siv.pop_layer();
+                }),
+        );
+    }
+}
+
+/// Display UI Dialog listing the plugins to run
+pub fn ui_build(siv: &mut Cursive) {
+    let scripts = list();
+    if scripts.is_empty() {
+        let dir = plugins_dir().unwrap_or_default();
+        return siv.add_layer(Dialog::info(tr!(
+            "No plugins found. Put Rhai scripts (*.rhai) into {}.",
+            dir.display()
+        )));
+    }
+
+    let mut list = SelectView::<PathBuf>::new().on_submit(ui_run);
+    for script in scripts {
+        let name = script.file_stem().unwrap().to_string_lossy().into_owned();
+        list.add_item(name, script);
+    }
+    siv.add_layer(
+        Dialog::around(ScrollView::new(list))
+            .title(tr!("Plugins"))
+            .button(tr!("Cancel"), |siv| {
+                siv.pop_layer();
+            }),
+    );
+}
@@ -0,0 +1,140 @@
+//! Opening/previewing items with external commands
+//!
+//! Only compiled with the `preview` feature.
+
+use std::process::Command;
+
+use std::path::{Path, PathBuf};
+
+use cursive::Cursive;
+
+use crate::checklist::CheckList;
+use crate::config::Config;
+use crate::handle::AppHandle;
+use crate::{do_app, ui_error, ui_input_dialog, AppState, SharedPath};
+
+/// Open files with provided command
+///
+/// Each item gets its own job (see `jobs`).
+fn cmdexec(siv: &mut Cursive, state: &mut AppState, cmd: &str, items: &[SharedPath]) {
+    siv.pop_layer();
+    for item in items.iter() {
+        let label = format!("{} {}", cmd, item.display());
+        let mut cmd = Command::new(cmd);
+        cmd.arg(item.as_os_str());
+        if let Err(e) = state.jobs.spawn(cmd, label) {
+            ui_error(siv, e);
+        }
+    }
+}
+
+/// Command configured in `open-with` for a file name's extension
+fn open_command<'a>(config: &'a Config, filename: &Path) -> Option<&'a str> {
+    let ext = filename.extension()?.to_string_lossy().to_lowercase();
+    config
+        .open_with
+        .iter()
+        .find(|(exts, _)| {
+            exts.split(',')
+                .any(|e| e.trim().trim_start_matches('.').to_lowercase() == ext)
+        })
+        .map(|(_, cmd)| cmd.as_str())
+}
+
+/// Open selected items with the commands configured for their extensions
+///
+/// Items without one are opened with a command asked for, like with 'e'.
+pub fn open_selection(siv: &mut Cursive, state: &mut AppState) {
+    let mut items: Vec<SharedPath> = state.sel.iter().cloned().collect();
+    items.sort();
+    let mut unknown = Vec::new();
+    for item in items {
+        let filename = Path::new(&state.items_all[&item].filename);
+        let cmdline = match open_command(&state.config, filename) {
+            Some(cmdline) => cmdline,
+            None => {
+                unknown.push(item);
+                continue;
+            }
+        };
+        let mut words = cmdline.split_whitespace();
+        let mut cmd = match words.next() {
+            Some(prog) => Command::new(prog),
+            None => continue,
+        };
+        cmd.args(words).arg(item.as_os_str());
+        let label = format!("{} {}", cmdline, item.display());
+        if let Err(e) = state.jobs.spawn(cmd, label) {
+            ui_error(siv, e);
+        }
+    }
+    if !unknown.is_empty() {
+        ui_ask_command(siv, "Open the other items with:", unknown);
+    }
+}
+
+/// Display UI Dialog for providing command to open given items with
+fn ui_ask_command(siv: &mut Cursive, title: &str, items: Vec<SharedPath>) {
+    siv.add_layer(ui_input_dialog(title, "cmd", "", move |siv, x| {
+        do_app(siv, |siv, state| cmdexec(siv, state, x, &items))
+    }));
+}
+
+/// Run provided command line once, on all selected files, and show its output
+fn cmdrun(siv: &mut Cursive, state: &mut AppState, cmdline: &str) {
+    let mut words = cmdline.split_whitespace();
+    let mut cmd = match words.next() {
+        Some(prog) => Command::new(prog),
+        None => return,
+    };
+    siv.pop_layer();
+    if state.sel.is_empty() {
+        return ui_error(siv, tr!("no items selected"));
+    }
+    let mut items: Vec<_> = state.sel.iter().collect();
+    items.sort();
+    cmd.args(words).args(items.iter().map(|p| p.as_os_str()));
+
+    let label = format!("{} ({} items)", cmdline, items.len());
+    let app = AppHandle::new(siv);
+    if let Err(e) = state.jobs.spawn_shown(cmd, label, app) {
+        ui_error(siv, e);
+    }
+}
+
+/// Display UI Dialog for providing command to open selected items with
+pub fn ui_build_cmdexec(siv: &mut Cursive) {
+    let items = do_app(siv, |_, state| state.sel.iter().cloned().collect());
+    ui_ask_command(siv, "Open selection with:", items);
+}
+
+/// Display UI Dialog for providing command to run on the selection
+pub fn ui_build_cmdrun(siv: &mut Cursive) {
+    siv.add_layer(ui_input_dialog(
+        "Run on selection and show output:",
+        "cmd",
+        "",
+        |siv, x| do_app(siv, |siv, state| cmdrun(siv, state, x)),
+    ));
+}
+
+/// Open the highlighted tag's directory with the configured file manager
+pub fn open_tagdir(siv: &mut Cursive, state: &mut AppState) {
+    let tp = siv
+        .call_on_id("tagsview", |v: &mut CheckList<PathBuf>| v.selection())
+        .unwrap();
+    let tp = match tp {
+        Some(tp) => tp,
+        None => return,
+    };
+
+    let mut words = state.config.file_manager.split_whitespace();
+    let mut cmd = match words.next() {
+        Some(prog) => Command::new(prog),
+        None => return ui_error(siv, tr!("no file manager configured")),
+    };
+    cmd.args(words).arg(tp.as_path());
+    if let Err(e) = cmd.spawn() {
+        ui_error(siv, e);
+    }
+}
@@ -0,0 +1,235 @@
+//! Query language for filtering items
+//!
+//! A query is a list of terms separated by whitespace. An item is shown if
+//! it matches all of them:
+//!
+//! - `name`: the item has the tag `name`
+//! - `-name`: the item doesn't have the tag `name`
+//! - `key<value`, `key<=value`, `key>value`, `key>=value`, `key=value`:
+//!   the item has a key/value tag with the key, and its value compares as
+//!   given (as numbers, if both sides are numbers)
+//! - `key!=value`: the item doesn't have the tag `key=value`
+//! - `size>10M`: the file size compares as given (with the same
+//!   operators), in bytes or with a unit: `K`, `M`, `G` or `T`
+//! - `mtime<2022-01-01`: the day the file was last modified compares as
+//!   given
+//! - `type:image`, `-type:image`: the item is (not) of a type, see
+//!   `filetype`
+//! - `text:word`, `-text:word`: the contents of the item (don't) contain
+//!   the word, ignoring case, see `content`
+//!
+//! `size` and `mtime` terms whose values aren't sizes or dates compare
+//! key/value tags, like other keys.
+//!
+//! Whitespace can be quoted: `artist="Boards of Canada"`.
+
+use std::cmp::Ordering;
+use std::time::SystemTime;
+
+use chrono::{DateTime, Local, NaiveDate};
+
+use crate::content;
+use crate::filetype::{self, Kind};
+use crate::{AppState, Item, SharedPath};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug)]
+enum Term {
+    Tag(String),
+    NotTag(String),
+    Compare(String, Op, String),
+    Size(Op, u64),
+    Modified(Op, NaiveDate),
+    Type(Kind),
+    NotType(Kind),
+    /// a lowercase word
+    Text(String),
+    NotText(String),
+}
+
+#[derive(Debug)]
+pub struct Query {
+    terms: Vec<Term>,
+}
+
+/// Split a query into words, honoring double quotes
+fn tokenize(q: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut quoted = false;
+    for c in q.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            c => token.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quote".to_owned());
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// Parse a size like `10M`: a number with an optional unit
+fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim_end_matches(|c| c == 'B' || c == 'b');
+    let units = ["K", "M", "G", "T"];
+    let (number, unit) = match units.iter().position(|u| s.to_uppercase().ends_with(u)) {
+        Some(unit) => (&s[..s.len() - 1], unit as i32 + 1),
+        None => (s, 0),
+    };
+    let number: f64 = number.parse().ok()?;
+    if number < 0.0 {
+        return None;
+    }
+    Some((number * 1024f64.powi(unit)) as u64)
+}
+
+fn parse_type(name: &str) -> Result<Kind, String> {
+    Kind::from_name(name).ok_or_else(|| format!("unknown type: {}", name))
+}
+
+fn parse_term(token: &str) -> Result<Term, String> {
+    if let Some(word) = token.strip_prefix("-text:") {
+        return Ok(Term::NotText(word.to_lowercase()));
+    }
+    if let Some(word) = token.strip_prefix("text:") {
+        return Ok(Term::Text(word.to_lowercase()));
+    }
+    if let Some(name) = token.strip_prefix("-type:") {
+        return Ok(Term::NotType(parse_type(name)?));
+    }
+    if let Some(name) = token.strip_prefix("type:") {
+        return Ok(Term::Type(parse_type(name)?));
+    }
+    if let Some(name) = token.strip_prefix('-') {
+        if name.is_empty() {
+            return Err("missing tag name after '-'".to_owned());
+        }
+        return Ok(Term::NotTag(name.to_owned()));
+    }
+
+    let pos = match token.find(|c| "!<>=".contains(c)) {
+        Some(pos) => pos,
+        None => return Ok(Term::Tag(token.to_owned())),
+    };
+    let (key, rest) = token.split_at(pos);
+    // two-character operators first
+    let ops = [
+        ("!=", Op::Ne),
+        ("<=", Op::Le),
+        (">=", Op::Ge),
+        ("<", Op::Lt),
+        (">", Op::Gt),
+        ("=", Op::Eq),
+    ];
+    let (op, value) = ops
+        .iter()
+        .find_map(|&(s, op)| rest.strip_prefix(s).map(|value| (op, value)))
+        .ok_or_else(|| format!("invalid term: {}", token))?;
+    if key.is_empty() || value.is_empty() {
+        return Err(format!("invalid term: {}", token));
+    }
+    match key {
+        "size" => {
+            if let Some(size) = parse_size(value) {
+                return Ok(Term::Size(op, size));
+            }
+        }
+        "mtime" => {
+            if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+                return Ok(Term::Modified(op, date));
+            }
+        }
+        _ => {}
+    }
+    Ok(Term::Compare(key.to_owned(), op, value.to_owned()))
+}
+
+/// Whether an ordering satisfies a comparison (`!=` as not equal)
+fn test(ord: Ordering, op: Op) -> bool {
+    match op {
+        Op::Eq => ord == Ordering::Equal,
+        Op::Ne => ord != Ordering::Equal,
+        Op::Lt => ord == Ordering::Less,
+        Op::Le => ord != Ordering::Greater,
+        Op::Gt => ord == Ordering::Greater,
+        Op::Ge => ord != Ordering::Less,
+    }
+}
+
+/// Day of a modification time, in local time
+fn local_date(mtime: SystemTime) -> NaiveDate {
+    DateTime::<Local>::from(mtime).date_naive()
+}
+
+/// Compare values as numbers if both are numbers, as strings otherwise
+fn compare(a: &str, b: &str) -> Ordering {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        _ => a.cmp(b),
+    }
+}
+
+impl Query {
+    pub fn parse(q: &str) -> Result<Query, String> {
+        let mut terms = tokenize(q)?
+            .iter()
+            .map(|t| parse_term(t))
+            .collect::<Result<Vec<Term>, String>>()?;
+        // contents are only read for items matching the other terms
+        terms.sort_by_key(|t| matches!(t, Term::Text(_) | Term::NotText(_)));
+        Ok(Query { terms })
+    }
+
+    pub fn matches(&self, state: &AppState, ip: &SharedPath, item: &Item) -> bool {
+        let tags: Vec<_> = item
+            .tags
+            .iter()
+            .filter_map(|tp| state.tags.get(tp))
+            .collect();
+
+        self.terms.iter().all(|term| match term {
+            Term::Tag(name) => tags.iter().any(|t| t.name == *name),
+            Term::NotTag(name) => !tags.iter().any(|t| t.name == *name),
+            Term::Compare(key, op, value) => {
+                // `key!=value` is the opposite of `key=value`
+                let tag_op = if *op == Op::Ne { Op::Eq } else { *op };
+                let found = tags.iter().any(|t| match t.kv {
+                    Some((ref k, ref v)) if k == key => test(compare(v, value), tag_op),
+                    _ => false,
+                });
+                if *op == Op::Ne {
+                    !found
+                } else {
+                    found
+                }
+            }
+            Term::Size(op, size) => test(item.size.cmp(size), *op),
+            Term::Modified(op, date) => match item.mtime {
+                Some(mtime) => test(local_date(mtime).cmp(date), *op),
+                None => false,
+            },
+            Term::Type(kind) => filetype::of(item) == *kind,
+            Term::NotType(kind) => filetype::of(item) != *kind,
+            Term::Text(word) => content::contains(state, ip, item, word),
+            Term::NotText(word) => !content::contains(state, ip, item, word),
+        })
+    }
+}
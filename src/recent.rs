@@ -0,0 +1,194 @@
+//! Recently used directories
+//!
+//! The items and tags directories of every session are remembered in
+//! `recent.toml` in the data directory (`~/.local/share/linkorgasm/` on
+//! Linux), most recent first. At startup they are offered in a list, and
+//! the number keys pick one right away.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use cursive::traits::*;
+use cursive::views::{Dialog, OnEventView, SelectView};
+use cursive::Cursive;
+
+use serde::{Deserialize, Serialize};
+
+use crate::tutorial;
+use crate::{do_app, load_tagdir, scan_items, ui_ask_itemdir};
+
+/// Recent file format version understood by this binary
+pub const VERSION: u32 = 1;
+
+/// Number of directory pairs remembered
+const MAX_RECENT: usize = 9;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Dirs {
+    /// canonical path of the items dir
+    items: PathBuf,
+    /// canonical path of the tags dir
+    tags: PathBuf,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Recent {
+    /// format version
+    version: u32,
+    /// most recent first
+    #[serde(default)]
+    dirs: Vec<Dirs>,
+}
+
+/// Path to the recent file
+pub fn recent_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|p| p.join("linkorgasm").join("recent.toml"))
+}
+
+/// Load the recent directories, if there is a usable file
+fn load() -> Vec<Dirs> {
+    let text = match recent_path().and_then(|path| fs::read_to_string(path).ok()) {
+        Some(text) => text,
+        None => return Vec::new(),
+    };
+    match toml::from_str::<Recent>(&text) {
+        Ok(recent) if recent.version == VERSION => recent.dirs,
+        _ => Vec::new(),
+    }
+}
+
+/// Upgrade a recent document by one version
+///
+/// There has only ever been one format so far, so there is nothing to
+/// change.
+pub fn migrate(_doc: &mut toml::Value, _from: u32) {}
+
+fn write(path: &Path, recent: &Recent) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let text = toml::to_string(recent).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    fs::write(path, text)
+}
+
+/// Move a pair of directories to the top of the recent list
+///
+/// `items` and `tags` are canonical paths.
+pub fn remember(items: &Path, tags: &Path) {
+    let new = Dirs {
+        items: items.to_path_buf(),
+        tags: tags.to_path_buf(),
+    };
+    let mut dirs = load();
+    dirs.retain(|d| *d != new);
+    dirs.insert(0, new);
+    dirs.truncate(MAX_RECENT);
+    if let Some(path) = recent_path() {
+        // remembering is a convenience, not worth bothering the user about
+        let _ = write(
+            &path,
+            &Recent {
+                version: VERSION,
+                dirs,
+            },
+        );
+    }
+}
+
+/// The most recently used tags dir, if there is one
+pub fn last_tags() -> Option<PathBuf> {
+    load().into_iter().next().map(|d| d.tags)
+}
+
+/// An entry of the startup list
+#[derive(Clone)]
+enum Choice {
+    /// recently used directories
+    Recent(Dirs),
+    /// a profile from the config, by name, with its directories
+    Profile(String, Dirs),
+    /// ask for the directories
+    Other,
+    /// start the tutorial
+    Tutorial,
+}
+
+/// UI callback to start a session with a picked pair of directories
+fn pick(siv: &mut Cursive, choice: &Choice) {
+    siv.pop_layer();
+    match *choice {
+        Choice::Recent(ref dirs) => {
+            do_app(siv, |_, state| scan_items(state, &dirs.items));
+            load_tagdir(siv, &dirs.tags);
+        }
+        Choice::Profile(ref name, ref dirs) => {
+            do_app(siv, |_, state| {
+                state.config.use_profile(name);
+                scan_items(state, &dirs.items);
+            });
+            load_tagdir(siv, &dirs.tags);
+        }
+        Choice::Other => ui_ask_itemdir(siv),
+        Choice::Tutorial => tutorial::ui_start(siv),
+    }
+}
+
+/// Offer the recent directories that still exist, the profiles of the
+/// config, other directories and the tutorial
+///
+/// On the first run, only the last two are there.
+pub fn ui_pick(siv: &mut Cursive) {
+    let recent: Vec<Dirs> = load()
+        .into_iter()
+        .filter(|d| d.items.is_dir() && d.tags.is_dir())
+        .collect();
+    let profiles: Vec<(String, Dirs)> = do_app(siv, |_, state| {
+        state
+            .config
+            .profile
+            .iter()
+            .filter_map(|(name, profile)| {
+                let items = profile.items.clone()?;
+                let tags = profile.tags.clone()?;
+                Some((name.clone(), Dirs { items, tags }))
+            })
+            .collect()
+    });
+
+    let mut list = SelectView::<Choice>::new().on_submit(pick);
+    for (n, dirs) in recent.into_iter().enumerate() {
+        let label = format!(
+            "{}  {}  ->  {}",
+            n + 1,
+            dirs.items.display(),
+            dirs.tags.display()
+        );
+        list.add_item(label, Choice::Recent(dirs));
+    }
+    for (name, dirs) in profiles {
+        let label = format!("   {}", tr!("Profile {}: {}", name, dirs.items.display()));
+        list.add_item(label, Choice::Profile(name, dirs));
+    }
+    list.add_item(format!("   {}", tr!("Other directories...")), Choice::Other);
+    list.add_item(
+        format!("   {}", tr!("Tutorial (sample files in a sandbox)")),
+        Choice::Tutorial,
+    );
+
+    let mut list = OnEventView::new(list.with_id("recentview"));
+    for n in 0..MAX_RECENT {
+        let key = std::char::from_digit(n as u32 + 1, 10).unwrap();
+        list = list.on_event(key, move |siv| {
+            let choice = siv
+                .call_on_id("recentview", |v: &mut SelectView<Choice>| {
+                    v.get_item(n).map(|(_, choice)| choice.clone())
+                })
+                .unwrap();
+            if let Some(choice @ Choice::Recent(_)) = choice {
+                pick(siv, &choice);
+            }
+        });
+    }
+    siv.add_layer(Dialog::around(list).title(tr!("Start")));
+}
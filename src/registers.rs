@@ -0,0 +1,103 @@
+//! Named selections ("registers")
+//!
+//! The current selection can be saved under a name and recalled later in
+//! the session, replacing or merging into the selection at that time. This
+//! allows building up selections across several filters.
+
+use cursive::traits::*;
+use cursive::views::{Dialog, SelectView};
+use cursive::Cursive;
+
+use crate::{
+    do_app, prune_selection, ui_error, ui_input_dialog, ui_mark_itemview, ui_mark_tagsview,
+};
+
+/// UI callback for the save selection dialog
+fn ui_submit_save(siv: &mut Cursive, name: &str) {
+    let name = name.trim();
+    if name.is_empty() {
+        return;
+    }
+    siv.pop_layer();
+    do_app(siv, |siv, state| {
+        if state.sel.is_empty() {
+            return ui_error(siv, tr!("nothing is selected"));
+        }
+        state.registers.insert(name.to_owned(), state.sel.clone());
+    });
+}
+
+/// Display UI Dialog for saving the selection under a name
+pub fn ui_build_save(siv: &mut Cursive) {
+    siv.add_layer(ui_input_dialog(
+        "Save selection as:",
+        "regname",
+        "",
+        ui_submit_save,
+    ));
+}
+
+#[derive(Clone, Copy)]
+enum Recall {
+    /// selection becomes the saved one
+    Replace,
+    /// saved selection is added to the current one
+    Merge,
+    /// forget the saved selection
+    Delete,
+}
+
+/// UI callback for the recall dialog buttons
+fn ui_recall(siv: &mut Cursive, how: Recall) {
+    let name = siv
+        .call_on_id("registers", |v: &mut SelectView<String>| v.selection())
+        .unwrap();
+    let name = match name {
+        Some(name) => name,
+        None => return,
+    };
+
+    siv.pop_layer();
+    do_app(siv, |siv, state| {
+        match how {
+            Recall::Replace => state.sel = state.registers[name.as_str()].clone(),
+            Recall::Merge => {
+                let saved = &state.registers[name.as_str()];
+                state.sel.extend(saved.iter().cloned());
+            }
+            Recall::Delete => {
+                state.registers.remove(name.as_str());
+            }
+        }
+        prune_selection(state);
+        ui_mark_itemview(siv, state);
+        ui_mark_tagsview(siv, state);
+    });
+}
+
+/// Display UI Dialog for recalling a saved selection
+pub fn ui_build_recall(siv: &mut Cursive) {
+    let mut list = SelectView::<String>::new();
+    let empty = do_app(siv, |_, state| {
+        for (name, sel) in state.registers.iter() {
+            list.add_item(format!("{} ({} items)", name, sel.len()), name.clone());
+        }
+        state.registers.is_empty()
+    });
+    if empty {
+        return ui_error(siv, tr!("no saved selections"));
+    }
+    list.sort_by_label();
+
+    siv.add_layer(
+        Dialog::around(list.with_id("registers"))
+            .title(tr!("Saved selections"))
+            .button(tr!("Replace"), |siv| ui_recall(siv, Recall::Replace))
+            .button(tr!("Merge"), |siv| ui_recall(siv, Recall::Merge))
+            .button(tr!("Delete"), |siv| ui_recall(siv, Recall::Delete))
+            .button(tr!("Cancel"), |siv| {
+                siv.pop_layer();
+            }),
+    );
+}
+
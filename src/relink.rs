@@ -0,0 +1,94 @@
+//! `linkorgasm relink --from OLD --to NEW`
+//!
+//! After the items dir has been moved, the symlinks in the tags tree point
+//! at the old location. This rewrites every symlink whose target is under
+//! the old location to point at the same path under the new one. Each
+//! link is replaced in one rename, so an interrupted run leaves no link
+//! missing.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+
+use crate::cli;
+use crate::exit;
+use crate::linkstyle;
+use crate::{lexical_path, load_exclusive};
+
+/// Entry point for `linkorgasm relink`, returns the exit code
+pub fn run(m: &ArgMatches) -> i32 {
+    let tags = match cli::tags_dir(m) {
+        Some(tags) => tags,
+        None => {
+            eprintln!("a tags dir is required");
+            return exit::USAGE;
+        }
+    };
+    // the old location usually doesn't exist anymore, so can't be canonicalized
+    let cwd = env::current_dir().expect("cannot access current dir");
+    let old = lexical_path(&cwd, Path::new(m.value_of_os("from").unwrap()));
+    let new = match Path::new(m.value_of_os("to").unwrap()).canonicalize() {
+        Ok(new) => new,
+        Err(e) => {
+            eprintln!("cannot access new items dir: {}", e);
+            return exit::USAGE;
+        }
+    };
+    let absolute = m.is_present("absolute");
+
+    // without items, every symlink in the tags tree is a foreign one
+    let mut state = match load_exclusive(m, None, &tags) {
+        Ok(state) => state,
+        Err(e) => return exit::load_failed(&tags, &e),
+    };
+    let links: Vec<(PathBuf, PathBuf)> = state
+        .foreign
+        .iter()
+        .map(|f| (f.link.clone(), f.tag.clone()))
+        .collect();
+
+    let (mut relinked, mut errors) = (0, 0);
+    for (link, tag) in links.iter() {
+        let target = match link.read_link() {
+            Ok(target) => lexical_path(tag, &target),
+            Err(e) => {
+                eprintln!("{}: {}", link.display(), e);
+                errors += 1;
+                continue;
+            }
+        };
+        let rel = match target.strip_prefix(&old) {
+            Ok(rel) => rel,
+            Err(_) => continue,
+        };
+        let item = new.join(rel);
+        let is_dir = item.is_dir();
+        let target = if absolute {
+            item.clone()
+        } else {
+            linkstyle::target(state.config.link_style, tag, &item)
+        };
+
+        match state.fs.replace_symlink(&target, link, is_dir) {
+            Ok(()) => relinked += 1,
+            Err(e) => {
+                eprintln!("{}: {}", link.display(), e);
+                errors += 1;
+            }
+        }
+    }
+
+    for op in state.fs.log() {
+        println!("{}", op);
+    }
+    let verb = if state.fs.is_dry_run() {
+        "to rewrite"
+    } else {
+        "rewritten"
+    };
+    if !cli::is_quiet(m) {
+        println!("{} symlinks {}, {} errors", relinked, verb, errors);
+    }
+    exit::from_errors(errors)
+}
@@ -0,0 +1,171 @@
+//! Renaming and moving items
+//!
+//! An item renamed outside of linkorgasm loses its tags, as the symlinks
+//! in the tag dirs keep pointing at the old path. Renaming ('F2') or moving
+//! ('M') it from the items view also rewrites every symlink to it, and
+//! updates the indexes.
+
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use cursive::Cursive;
+
+use crate::escape::display_name;
+use crate::lazylist::LazyList;
+use crate::linkstyle;
+use crate::manifest;
+use crate::{
+    do_app, ui_error, ui_fill_itemview, ui_input_dialog, ui_mark_tagsview, ui_select_item,
+    AppState, SharedPath,
+};
+
+/// Move an item to a new path and point its symlinks there
+///
+/// `ip` is the canonical path of a known item, `to` the canonical path to
+/// move it to. The symlinks are named like the item, so nothing is changed
+/// if that name is taken in any of its tag dirs.
+pub fn move_item(state: &mut AppState, ip: &Path, to: &Path) -> io::Result<()> {
+    let filename = match to.file_name() {
+        Some(filename) => filename.to_owned(),
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid item path: {}", to.display()),
+            ))
+        }
+    };
+    if to.symlink_metadata().is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", to.display()),
+        ));
+    }
+    let tags: Vec<SharedPath> = state.items_all[ip].tags.iter().cloned().collect();
+    for tp in tags.iter() {
+        let link = tp.join(&filename);
+        let own = state.tags[tp].items.get(ip) == Some(&filename);
+        if !own && link.symlink_metadata().is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", link.display()),
+            ));
+        }
+    }
+
+    state.fs.rename(ip, to)?;
+    let mut item = state.items_all.remove(ip).unwrap();
+    let is_dir = item.is_dir;
+    let new: SharedPath = to.into();
+    item.name = display_name(&filename, state.config.escape_names);
+    item.filename = filename.clone();
+    state.items_all.insert(Rc::clone(&new), item);
+    if state.items_vis.remove(ip) {
+        state.items_vis.insert(Rc::clone(&new));
+    }
+    if state.sel.remove(ip) {
+        state.sel.insert(Rc::clone(&new));
+    }
+    for sel in state.registers.values_mut() {
+        if sel.remove(ip) {
+            sel.insert(Rc::clone(&new));
+        }
+    }
+
+    for tp in tags.iter() {
+        let tag = state.tags.get_mut(tp).unwrap();
+        let name = tag.items.remove(ip).unwrap();
+        tag.items.insert(Rc::clone(&new), filename.clone());
+        state.fs.remove_symlink(&tp.join(name), is_dir)?;
+        let target = linkstyle::target(state.config.link_style, tp, to);
+        state.fs.symlink(&target, &tp.join(&filename), is_dir)?;
+        manifest::remove(state, tp, ip)?;
+        manifest::add(state, tp, to)?;
+    }
+    Ok(())
+}
+
+/// Rebuild the items view after items were moved, keeping the selection
+fn ui_refresh_moved(siv: &mut Cursive, state: &mut AppState, cursor: &Path) {
+    ui_fill_itemview(siv, state);
+    ui_mark_tagsview(siv, state);
+    ui_select_item(siv, cursor);
+}
+
+/// UI callback for the rename dialog
+fn ui_submit_rename(siv: &mut Cursive, ip: &Path, name: &str) {
+    if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+        return ui_error(siv, tr!("invalid file name: {}", name));
+    }
+    siv.pop_layer();
+    let to = ip.with_file_name(name);
+    do_app(siv, |siv, state| {
+        if let Err(e) = move_item(state, ip, &to) {
+            return ui_error(siv, e);
+        }
+        ui_refresh_moved(siv, state, &to);
+    });
+}
+
+/// Display UI Dialog for renaming the highlighted item
+pub fn ui_build_rename(siv: &mut Cursive) {
+    let ip = siv
+        .call_on_id("itemview", |v: &mut LazyList<SharedPath>| v.selection())
+        .and_then(|ip| ip);
+    let ip = match ip {
+        Some(ip) => ip,
+        None => return,
+    };
+    let name = ip
+        .file_name()
+        .map(OsStr::to_string_lossy)
+        .unwrap_or_default()
+        .into_owned();
+    let dialog = ui_input_dialog("Rename to:", "renameto", &name, move |siv, x| {
+        ui_submit_rename(siv, &ip, x)
+    });
+    siv.add_layer(dialog);
+}
+
+/// UI callback for the move dialog
+fn ui_submit_move(siv: &mut Cursive, dir: &str) {
+    let dir = match Path::new(dir).canonicalize() {
+        Ok(dir) if dir.is_dir() => dir,
+        _ => return ui_error(siv, tr!("not a directory: {}", dir)),
+    };
+    siv.pop_layer();
+    do_app(siv, |siv, state| {
+        let mut sel: Vec<SharedPath> = state.sel.iter().cloned().collect();
+        sel.sort();
+        let mut last = None;
+        for ip in sel.iter() {
+            let to: PathBuf = dir.join(ip.file_name().unwrap());
+            if let Err(e) = move_item(state, ip, &to) {
+                ui_refresh_moved(siv, state, last.as_ref().unwrap_or(&to));
+                return ui_error(siv, format!("{}: {}", ip.display(), e));
+            }
+            last = Some(to);
+        }
+        if let Some(last) = last {
+            ui_refresh_moved(siv, state, &last);
+        }
+    });
+}
+
+/// Display UI Dialog for moving the selected items to another directory
+pub fn ui_build_move(siv: &mut Cursive) {
+    let dir = do_app(siv, |_, state| {
+        state
+            .items_path
+            .as_ref()
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    });
+    siv.add_layer(ui_input_dialog(
+        "Move selected items to directory:",
+        "moveto",
+        &dir,
+        ui_submit_move,
+    ));
+}
@@ -0,0 +1,145 @@
+//! JSON API of `linkorgasm serve`
+//!
+//! - `GET /items?query=Q`: the items matching a query (all without one)
+//! - `GET /items/ID`: one item
+//! - `POST /items/ID/tags` with `{"tag": NAME}`: tag an item, creating a
+//!   missing tag
+//! - `DELETE /items/ID/tags/NAME`: untag an item
+//! - `GET /tags`: the tags, with their number of items
+//!
+//! Items are given as `{"id", "path", "name", "tags"}` objects. The ID of
+//! an item is its path, percent-encoded with '/' as `%2F`; any path to it
+//! works. Tag names in paths are percent-encoded too. Changes answer with
+//! the item as it is afterwards, errors with `{"error": MESSAGE}`.
+//!
+//! Pages of other sites can't make a browser send a `DELETE`, or a `POST`
+//! with `Content-Type: application/json`, without asking the server first
+//! (which it doesn't answer), so they can't change tags through the API.
+//! That is why tagging must be sent as JSON; untagging has no body and
+//! needs no content type. Like all changes, both must also come from the
+//! server's own origin (see `serve`).
+
+use serde_json::{json, Value};
+
+use crate::query::Query;
+use crate::serve::{self, Request, Response};
+use crate::{find_item, find_tag, natsort, sorted_tags, AppState, SharedPath};
+
+/// Status and message of a failed request
+type ApiResult = Result<Value, (&'static str, String)>;
+
+fn bad_request(msg: String) -> (&'static str, String) {
+    ("400 Bad Request", msg)
+}
+
+fn not_found(msg: String) -> (&'static str, String) {
+    ("404 Not Found", msg)
+}
+
+fn item_json(state: &AppState, ip: &SharedPath) -> Value {
+    let item = &state.items_all[ip];
+    let path = ip.to_string_lossy();
+    let mut tags: Vec<&str> = item
+        .tags
+        .iter()
+        .map(|tp| state.tags[tp].name.as_str())
+        .collect();
+    tags.sort();
+    json!({
+        "id": serve::encode(&path),
+        "path": path,
+        "name": item.name,
+        "tags": tags,
+    })
+}
+
+fn list_items(state: &AppState, query: &str) -> ApiResult {
+    let query = if query.trim().is_empty() {
+        None
+    } else {
+        Some(Query::parse(query).map_err(|e| bad_request(e.to_string()))?)
+    };
+    let natural = state.config.natural_sort;
+    let mut items: Vec<&SharedPath> = state
+        .items_all
+        .iter()
+        .filter(|(ip, i)| query.as_ref().map_or(true, |q| q.matches(state, ip, i)))
+        .map(|(ip, _)| ip)
+        .collect();
+    items.sort_by(|a, b| {
+        natsort::compare_names(natural, &state.items_all[*a].name, &state.items_all[*b].name)
+    });
+    Ok(items.into_iter().map(|ip| item_json(state, ip)).collect())
+}
+
+fn list_tags(state: &AppState) -> Value {
+    sorted_tags(state)
+        .into_iter()
+        .map(|(_, tag)| json!({ "name": tag.name, "items": tag.items.len() }))
+        .collect()
+}
+
+/// Refuse a change not sent as JSON
+fn check_json(req: &Request) -> Result<(), (&'static str, String)> {
+    let mime = req.header("content-type").split(';').next().unwrap_or("");
+    if mime.trim().eq_ignore_ascii_case("application/json") {
+        Ok(())
+    } else {
+        let msg = "Content-Type must be application/json".to_owned();
+        Err(("415 Unsupported Media Type", msg))
+    }
+}
+
+fn add_tag(state: &mut AppState, id: &str, body: &[u8]) -> ApiResult {
+    let ip = find_item(state, id).map_err(not_found)?;
+    let body: Value = serde_json::from_slice(body)
+        .map_err(|e| bad_request(format!("invalid JSON: {}", e)))?;
+    let name = body
+        .get("tag")
+        .and_then(Value::as_str)
+        .ok_or_else(|| bad_request("missing tag".to_owned()))?;
+    serve::tag(state, &ip, name).map_err(bad_request)?;
+    Ok(item_json(state, &ip))
+}
+
+fn remove_tag(state: &mut AppState, id: &str, name: &str) -> ApiResult {
+    let ip = find_item(state, id).map_err(not_found)?;
+    find_tag(state, name).map_err(not_found)?;
+    serve::untag(state, &ip, name).map_err(bad_request)?;
+    Ok(item_json(state, &ip))
+}
+
+/// Answer a request to the API, `None` for other paths
+pub fn handle(state: &mut AppState, req: &Request) -> Option<Response> {
+    let segments: Vec<String> = req
+        .path
+        .trim_start_matches('/')
+        .split('/')
+        .map(|s| serve::decode(s, false))
+        .collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    let res = match (req.method.as_str(), segments.as_slice()) {
+        ("GET", ["items"]) => list_items(state, req.param("query")),
+        ("GET", ["items", id]) => find_item(state, id)
+            .map(|ip| item_json(state, &ip))
+            .map_err(not_found),
+        ("POST", ["items", id, "tags"]) => {
+            check_json(req).and_then(|_| add_tag(state, id, &req.body))
+        }
+        ("DELETE", ["items", id, "tags", name]) => remove_tag(state, id, name),
+        ("GET", ["tags"]) => Ok(list_tags(state)),
+        (_, ["items"])
+        | (_, ["items", _])
+        | (_, ["items", _, "tags"])
+        | (_, ["items", _, "tags", _])
+        | (_, ["tags"]) => Err((
+            "405 Method Not Allowed",
+            format!("{} not allowed here", req.method),
+        )),
+        _ => return None,
+    };
+    Some(match res {
+        Ok(value) => Response::json("200 OK", &value),
+        Err((status, msg)) => Response::json(status, &json!({ "error": msg })),
+    })
+}
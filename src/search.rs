@@ -0,0 +1,82 @@
+//! `linkorgasm query EXPR`
+//!
+//! Prints the canonical paths of the items matching a query (see `query`),
+//! for scripts: one per line (`plain`), separated by NUL bytes (`null` or
+//! `-0`, for `xargs -0`), or as a JSON array of strings (`json`, paths that
+//! are not UTF-8 are converted lossily).
+
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+
+use clap::ArgMatches;
+
+use crate::cli;
+use crate::exit;
+use crate::query::Query;
+use crate::{load_readonly, SharedPath};
+
+/// Output formats, by the name given to `--format`
+pub static FORMATS: &[&str] = &["plain", "json", "null"];
+
+/// Write the paths in the given format
+fn write_paths(out: &mut impl Write, paths: &[SharedPath], format: &str) -> io::Result<()> {
+    if format == "json" {
+        let paths: Vec<String> = paths
+            .iter()
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect();
+        serde_json::to_writer_pretty(&mut *out, &paths)?;
+        return out.write_all(b"\n");
+    }
+    let sep = if format == "null" { b'\0' } else { b'\n' };
+    for p in paths {
+        out.write_all(p.as_os_str().as_bytes())?;
+        out.write_all(&[sep])?;
+    }
+    Ok(())
+}
+
+/// Entry point for `linkorgasm query`, returns the exit code
+pub fn run(m: &ArgMatches) -> i32 {
+    let (items, tags) = match (cli::items_dir(m), cli::tags_dir(m)) {
+        (Some(items), Some(tags)) => (items, tags),
+        _ => {
+            eprintln!("both an items dir and a tags dir are required");
+            return exit::USAGE;
+        }
+    };
+    let query = match Query::parse(m.value_of("expr").unwrap()) {
+        Ok(query) => query,
+        Err(e) => {
+            eprintln!("invalid query: {}", e);
+            return exit::USAGE;
+        }
+    };
+    let state = match load_readonly(m, Some(&items), &tags) {
+        Ok(state) => state,
+        Err(e) => return exit::load_failed(&tags, &e),
+    };
+
+    let mut matching: Vec<SharedPath> = state
+        .items_all
+        .iter()
+        .filter(|(ip, i)| query.matches(&state, ip, i))
+        .map(|(p, _)| p.clone())
+        .collect();
+    matching.sort();
+
+    let format = if m.is_present("null") {
+        "null"
+    } else {
+        m.value_of("format").unwrap()
+    };
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    if let Err(e) = write_paths(&mut out, &matching, format) {
+        if e.kind() != io::ErrorKind::BrokenPipe {
+            eprintln!("{}", e);
+            return exit::FAILED;
+        }
+    }
+    exit::OK
+}
@@ -0,0 +1,249 @@
+//! `linkorgasm self-check`
+//!
+//! Every file linkorgasm keeps state in carries a version number: TOML
+//! files in a `version` key, files of lines (the journal and inodes of a
+//! tags dir) in a header line naming the file and its version. This compares
+//! them against the format versions this binary understands and migrates
+//! older files explicitly, instead of silently misreading them. The files
+//! in a tags dir are checked if one is given.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str;
+
+use clap::ArgMatches;
+
+use crate::{cli, config, exit, identity, journal, recent, session};
+
+/// How a state file records its version, and how to upgrade it by one
+/// version, from the given one
+enum Format {
+    /// a TOML document with a `version` key
+    Toml(fn(&mut toml::Value, u32)),
+    /// lines after a header line of the name and the version
+    Lines(&'static str, fn(&mut Vec<u8>, u32)),
+}
+
+/// A versioned state file
+struct StateFile {
+    /// what the file is, for messages
+    what: &'static str,
+    /// where it lives (if it can be located at all)
+    path: Option<PathBuf>,
+    /// format version understood by this binary
+    current: u32,
+    format: Format,
+}
+
+fn state_files(tags: Option<&Path>) -> Vec<StateFile> {
+    vec![
+        StateFile {
+            what: "config",
+            path: config::config_path(),
+            current: config::VERSION,
+            format: Format::Toml(config::migrate),
+        },
+        StateFile {
+            what: "session",
+            path: session::session_path(),
+            current: session::VERSION,
+            format: Format::Toml(session::migrate),
+        },
+        StateFile {
+            what: "recent directories",
+            path: recent::recent_path(),
+            current: recent::VERSION,
+            format: Format::Toml(recent::migrate),
+        },
+        StateFile {
+            what: "journal",
+            path: tags.map(journal::journal_path),
+            current: journal::VERSION,
+            format: Format::Lines(journal::HEADER, journal::migrate),
+        },
+        StateFile {
+            what: "inodes",
+            path: tags.map(identity::inodes_path),
+            current: identity::VERSION,
+            format: Format::Lines(identity::HEADER, identity::migrate),
+        },
+    ]
+}
+
+/// The header line of a file of lines
+pub fn header(name: &str, version: u32) -> String {
+    format!("{} {}\n", name, version)
+}
+
+/// The version in the header line of a file of lines, and the lines after
+/// it
+///
+/// Files without the header predate it, and have no version.
+pub fn split_header<'a>(bytes: &'a [u8], name: &str) -> (Option<u32>, &'a [u8]) {
+    let end = bytes
+        .iter()
+        .position(|&b| b == b'\n')
+        .unwrap_or(bytes.len());
+    let version = str::from_utf8(&bytes[..end]).ok().and_then(|line| {
+        let mut words = line.split(' ');
+        if words.next()? != name {
+            return None;
+        }
+        words.next()?.parse().ok()
+    });
+    match version {
+        Some(v) => (Some(v), &bytes[(end + 1).min(bytes.len())..]),
+        None => (None, bytes),
+    }
+}
+
+/// Outcome of checking a single file
+enum Check {
+    Missing,
+    Current,
+    Migrated(Option<u32>),
+    TooNew(u32),
+}
+
+fn check(f: &StateFile, path: &Path) -> Result<Check, String> {
+    match f.format {
+        Format::Toml(migrate) => check_toml(f, migrate, path),
+        Format::Lines(name, migrate) => check_lines(f, name, migrate, path),
+    }
+}
+
+/// Keep a copy of a file before migrating it
+fn backup(path: &Path) -> Result<(), String> {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    fs::copy(path, &backup).map_err(|e| format!("cannot create backup: {}", e))?;
+    Ok(())
+}
+
+fn check_toml(
+    f: &StateFile,
+    migrate: fn(&mut toml::Value, u32),
+    path: &Path,
+) -> Result<Check, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(_) => return Ok(Check::Missing),
+    };
+    let mut doc: toml::Value = text.parse().map_err(|e| format!("cannot parse: {}", e))?;
+    let found = doc
+        .get("version")
+        .and_then(|v| v.as_integer())
+        .map(|v| v as u32);
+
+    match found {
+        Some(v) if v == f.current => return Ok(Check::Current),
+        Some(v) if v > f.current => return Ok(Check::TooNew(v)),
+        _ => {}
+    }
+
+    // unversioned files predate versioning and are in the first format
+    let mut v = found.unwrap_or(1);
+    while v < f.current {
+        migrate(&mut doc, v);
+        v += 1;
+    }
+    if let Some(table) = doc.as_table_mut() {
+        table.insert("version".to_owned(), toml::Value::Integer(i64::from(f.current)));
+    }
+
+    backup(path)?;
+    let text = toml::to_string(&doc).map_err(|e| format!("cannot serialize: {}", e))?;
+    fs::write(path, text).map_err(|e| format!("cannot write: {}", e))?;
+
+    Ok(Check::Migrated(found))
+}
+
+fn check_lines(
+    f: &StateFile,
+    name: &str,
+    migrate: fn(&mut Vec<u8>, u32),
+    path: &Path,
+) -> Result<Check, String> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(_) => return Ok(Check::Missing),
+    };
+    let (found, lines) = split_header(&bytes, name);
+    match found {
+        Some(v) if v == f.current => return Ok(Check::Current),
+        Some(v) if v > f.current => return Ok(Check::TooNew(v)),
+        _ => {}
+    }
+
+    // unversioned files predate versioning and are in the first format
+    let mut lines = lines.to_vec();
+    let mut v = found.unwrap_or(1);
+    while v < f.current {
+        migrate(&mut lines, v);
+        v += 1;
+    }
+
+    backup(path)?;
+    let mut contents = header(name, f.current).into_bytes();
+    contents.extend(lines);
+    fs::write(path, contents).map_err(|e| format!("cannot write: {}", e))?;
+
+    Ok(Check::Migrated(found))
+}
+
+/// Run the self-check, returning the process exit code
+///
+/// With `--quiet`, only migrations and problems are printed.
+pub fn run(m: &ArgMatches) -> i32 {
+    let quiet = cli::is_quiet(m);
+    let mut failed = false;
+
+    let tags = cli::tags_dir(m);
+    for f in state_files(tags.as_deref()) {
+        let path = match f.path {
+            Some(ref path) => path,
+            None => {
+                if !quiet {
+                    println!("{}: location unknown, skipped", f.what);
+                }
+                continue;
+            }
+        };
+
+        match check(&f, path) {
+            Ok(Check::Missing) | Ok(Check::Current) if quiet => {}
+            Ok(Check::Missing) => println!("{}: {} does not exist", f.what, path.display()),
+            Ok(Check::Current) => {
+                println!("{}: {} is up to date (version {})", f.what, path.display(), f.current)
+            }
+            Ok(Check::Migrated(from)) => println!(
+                "{}: {} migrated from {} to version {}",
+                f.what,
+                path.display(),
+                from.map(|v| format!("version {}", v))
+                    .unwrap_or_else(|| "unversioned".to_owned()),
+                f.current
+            ),
+            Ok(Check::TooNew(v)) => {
+                failed = true;
+                println!(
+                    "{}: {} has version {}, but this binary only understands up to {}",
+                    f.what,
+                    path.display(),
+                    v,
+                    f.current
+                );
+            }
+            Err(e) => {
+                failed = true;
+                println!("{}: {}: {}", f.what, path.display(), e);
+            }
+        }
+    }
+
+    if failed {
+        exit::FAILED
+    } else {
+        exit::OK
+    }
+}
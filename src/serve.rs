@@ -0,0 +1,603 @@
+//! `linkorgasm serve`: web interface
+//!
+//! Serves a page listing the items, filtered with a query like 'f' in the
+//! items view, with forms to tag and untag them, so a collection on a
+//! headless machine can be tagged from a browser. Tagging goes through the
+//! same code as the UI: missing tags are created, hooks run, changes are
+//! logged, and `--dry-run` applies.
+//!
+//! The server is plain HTTP/1.1 without TLS, and listens on 127.0.0.1
+//! unless told otherwise. Each client is read from and written to on a
+//! thread of its own, with a time limit for the whole request, and the
+//! requests are handled one at a time. With `--token`, every request needs
+//! the token, as a bearer token or as the password of HTTP basic auth (for
+//! browsers). The items and tags are scanned once, at start (the tags dir
+//! is locked while it runs).
+//!
+//! Browsers send the basic auth password along with requests other sites
+//! make them send, so changes need more: a request changing something must
+//! name the server in its `Host` header (and `Origin`, if it has one), and
+//! the forms carry a token made at start, which other sites can't read.
+//!
+//! Scripts use the JSON API, see `rest`.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::ArgMatches;
+use hashbrown::HashMap;
+
+use crate::exit;
+use crate::html::escape;
+use crate::journal;
+use crate::query::Query;
+use crate::rest;
+use crate::{
+    cli, find_item, find_or_create_tag, find_tag, load_headless, natsort, sorted_tags, tag_item,
+    untag_item, AppState, Item, SharedPath,
+};
+
+/// Address listened on without `--listen`
+pub static DEFAULT_LISTEN: &str = "127.0.0.1:8080";
+
+/// Longest request accepted, headers and form included
+const MAX_REQUEST: u64 = 64 * 1024;
+
+/// Items shown on a page at most
+const MAX_ITEMS: usize = 1000;
+
+/// Time a client gets to send its request
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Clients served at the same time at most
+const MAX_CLIENTS: usize = 32;
+
+static STYLE: &str = "body { font-family: sans-serif; margin: 1em; } \
+    form { display: inline; } \
+    td { padding: 0.2em 0.5em; vertical-align: top; } \
+    tr:nth-child(even) { background: #f0f0f0; } \
+    .tags a { margin-right: 0.6em; }";
+
+/// A parsed request
+pub struct Request {
+    pub method: String,
+    /// path of the target, still percent-encoded
+    pub path: String,
+    /// headers, by lowercase name
+    headers: HashMap<String, String>,
+    /// parameters from the query string and the form, decoded
+    params: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn param(&self, name: &str) -> &str {
+        self.params.get(name).map_or("", String::as_str)
+    }
+
+    pub fn header(&self, name: &str) -> &str {
+        self.headers.get(name).map_or("", String::as_str)
+    }
+
+    /// Whether the request may change something
+    fn is_change(&self) -> bool {
+        !["GET", "HEAD", "OPTIONS"].contains(&self.method.as_str())
+    }
+}
+
+/// What the server knows about itself
+pub struct Server {
+    /// address listened on
+    addr: SocketAddr,
+    /// token the forms carry, new for every run
+    csrf: String,
+}
+
+impl Server {
+    /// Whether a `Host` header names this server: as the address it
+    /// listens on, or as `localhost` if that is a loopback address
+    ///
+    /// Listening on all addresses (like 0.0.0.0), any name will do.
+    fn is_own_host(&self, host: &str) -> bool {
+        let (name, port) = match host.rfind(':') {
+            Some(i) if !host[i..].contains(']') => (&host[..i], &host[i + 1..]),
+            _ => (host, "80"),
+        };
+        if port.parse() != Ok(self.addr.port()) {
+            return false;
+        }
+        let ip = self.addr.ip();
+        let name = name.trim_start_matches('[').trim_end_matches(']');
+        ip.is_unspecified()
+            || name.parse() == Ok(ip)
+            || (ip.is_loopback() && name.eq_ignore_ascii_case("localhost"))
+    }
+
+    /// Whether a request comes from a page of this server (or from no page
+    /// at all, like one from a script)
+    ///
+    /// Requests changing something are refused otherwise.
+    pub fn is_same_origin(&self, req: &Request) -> bool {
+        let host = req.header("host");
+        if !self.is_own_host(host) {
+            return false;
+        }
+        match req.headers.get("origin") {
+            Some(origin) => *origin == format!("http://{}", host),
+            None => true,
+        }
+    }
+}
+
+/// A response: status line, extra headers and body
+pub struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+impl Response {
+    fn page(body: String) -> Self {
+        Response {
+            status: "200 OK",
+            content_type: "text/html; charset=utf-8",
+            headers: Vec::new(),
+            body,
+        }
+    }
+
+    pub fn json(status: &'static str, value: &serde_json::Value) -> Self {
+        Response {
+            status,
+            content_type: "application/json",
+            headers: Vec::new(),
+            body: value.to_string(),
+        }
+    }
+
+    fn error(status: &'static str, msg: &str) -> Self {
+        Response {
+            status,
+            content_type: "text/html; charset=utf-8",
+            headers: Vec::new(),
+            body: format!(
+                "<!DOCTYPE html>\n<title>{}</title>\n<p>{}</p>\n<p><a href=\"/\">Back</a></p>\n",
+                status,
+                escape(msg)
+            ),
+        }
+    }
+
+    /// Send the browser back to the list, filtered as before
+    fn back(q: &str) -> Self {
+        let location = if q.is_empty() {
+            "/".to_owned()
+        } else {
+            format!("/?q={}", encode(q))
+        };
+        Response {
+            status: "303 See Other",
+            content_type: "text/html; charset=utf-8",
+            headers: vec![("Location", location)],
+            body: String::new(),
+        }
+    }
+
+    /// Ask for the token, with a login prompt in browsers
+    fn unauthorized() -> Self {
+        let mut res = Response::error("401 Unauthorized", "a token is required");
+        res.headers
+            .push(("WWW-Authenticate", "Basic realm=\"linkorgasm\"".to_owned()));
+        res
+    }
+}
+
+/// Decode a percent-encoded URL component, with '+' for space in forms
+pub fn decode(s: &str, form: bool) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' if form => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(b) => {
+                        out.push(b);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Percent-encode a string for a URL, '/' included
+pub fn encode(s: &str) -> String {
+    let mut out = String::new();
+    for &b in s.as_bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+/// Add the `name=value` pairs of a query string or form to `params`
+fn parse_params(s: &str, params: &mut HashMap<String, String>) {
+    for pair in s.split('&').filter(|p| !p.is_empty()) {
+        let mut kv = pair.splitn(2, '=');
+        let name = decode(kv.next().unwrap(), true);
+        let value = decode(kv.next().unwrap_or(""), true);
+        params.insert(name, value);
+    }
+}
+
+/// Reads from a client until a deadline, however slowly it sends
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    until: Instant,
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.until.saturating_duration_since(Instant::now());
+        if left == Duration::from_secs(0) {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "request too slow"));
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        (&*self.stream).read(buf)
+    }
+}
+
+/// Read a request: the request line, the headers and the form, if any
+///
+/// The whole request must come within `TIMEOUT`.
+fn read_request(stream: &TcpStream) -> io::Result<Request> {
+    let bad = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_owned());
+    let deadline = Deadline {
+        stream,
+        until: Instant::now() + TIMEOUT,
+    };
+    let mut reader = BufReader::new(deadline.take(MAX_REQUEST));
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().ok_or_else(|| bad("empty request"))?.to_owned();
+    let target = parts.next().ok_or_else(|| bad("no request target"))?.to_owned();
+
+    let mut headers = HashMap::default();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(bad("incomplete request"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let mut kv = header.splitn(2, ':');
+        let name = kv.next().unwrap().to_ascii_lowercase();
+        headers.insert(name, kv.next().unwrap_or("").trim().to_owned());
+    }
+
+    let length: usize = match headers.get("content-length") {
+        Some(n) => n.parse().map_err(|_| bad("bad content length"))?,
+        None => 0,
+    };
+    if length as u64 > MAX_REQUEST {
+        return Err(bad("request too long"));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    let mut params = HashMap::default();
+    let mut target = target.splitn(2, '?');
+    let path = target.next().unwrap().to_owned();
+    parse_params(target.next().unwrap_or(""), &mut params);
+    let form = headers
+        .get("content-type")
+        .map_or(false, |t| t.starts_with("application/x-www-form-urlencoded"));
+    if form {
+        parse_params(&String::from_utf8_lossy(&body), &mut params);
+    }
+    Ok(Request {
+        method,
+        path,
+        headers,
+        params,
+        body,
+    })
+}
+
+fn write_response(mut stream: &TcpStream, res: &Response) -> io::Result<()> {
+    let mut head = format!("HTTP/1.1 {}\r\n", res.status);
+    for (name, value) in res.headers.iter() {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str(&format!("Content-Type: {}\r\n", res.content_type));
+    head.push_str(&format!("Content-Length: {}\r\n", res.body.len()));
+    head.push_str("Connection: close\r\n\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(res.body.as_bytes())?;
+    stream.flush()
+}
+
+/// Whether two secrets are the same, compared in constant time, so they
+/// can't be guessed byte by byte
+fn same_secret(given: &str, secret: &str) -> bool {
+    given.len() == secret.len()
+        && given
+            .bytes()
+            .zip(secret.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// A new random token, in hex
+fn random_token() -> io::Result<String> {
+    let mut bytes = [0u8; 16];
+    File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Whether a request carries the token
+fn authorized(req: &Request, token: &str) -> bool {
+    let auth = req.header("authorization");
+    let given = if auth.starts_with("Bearer ") {
+        auth["Bearer ".len()..].trim().to_owned()
+    } else if auth.starts_with("Basic ") {
+        // any user name, the token as password
+        let decoded = base64::decode(auth["Basic ".len()..].trim()).unwrap_or_default();
+        let decoded = String::from_utf8_lossy(&decoded).into_owned();
+        decoded.splitn(2, ':').nth(1).unwrap_or("").to_owned()
+    } else {
+        return false;
+    };
+    same_secret(&given, token)
+}
+
+/// Hidden form fields
+fn hidden(fields: &[(&str, &str)]) -> String {
+    fields
+        .iter()
+        .map(|(name, value)| {
+            format!(
+                "<input type=\"hidden\" name=\"{}\" value=\"{}\">",
+                name,
+                escape(value)
+            )
+        })
+        .collect()
+}
+
+/// The list of items matching the query `q`, with forms carrying `csrf`
+fn page(state: &AppState, q: &str, csrf: &str) -> Result<String, String> {
+    let query = if q.trim().is_empty() {
+        None
+    } else {
+        Some(Query::parse(q).map_err(|e| format!("invalid query: {}", e))?)
+    };
+    let natural = state.config.natural_sort;
+    let mut items: Vec<(&SharedPath, &Item)> = state
+        .items_all
+        .iter()
+        .filter(|(ip, i)| query.as_ref().map_or(true, |q| q.matches(state, ip, i)))
+        .collect();
+    items.sort_by(|a, b| natsort::compare_names(natural, &a.1.name, &b.1.name));
+    let tags = sorted_tags(state);
+
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str("<meta name=\"viewport\" content=\"width=device-width\">\n");
+    out.push_str(&format!("<title>linkorgasm</title>\n<style>{}</style>\n", STYLE));
+    out.push_str("</head>\n<body>\n");
+    out.push_str(&format!(
+        "<form method=\"get\" action=\"/\"><input name=\"q\" value=\"{}\" size=\"40\" \
+         placeholder=\"query, e.g. jazz -live\"> <button>Filter</button></form>\n",
+        escape(q)
+    ));
+    out.push_str("<p class=\"tags\">");
+    for (_, tag) in tags.iter() {
+        out.push_str(&format!(
+            "<a href=\"/?q={}\">{}</a>",
+            encode(&tag.name),
+            escape(&tag.name)
+        ));
+    }
+    out.push_str("</p>\n<datalist id=\"tags\">");
+    for (_, tag) in tags.iter() {
+        out.push_str(&format!("<option value=\"{}\">", escape(&tag.name)));
+    }
+    out.push_str("</datalist>\n");
+
+    out.push_str(&format!("<p>{} of {} items", items.len(), state.items_all.len()));
+    if items.len() > MAX_ITEMS {
+        out.push_str(&format!(", showing the first {}", MAX_ITEMS));
+    }
+    out.push_str("</p>\n<table>\n");
+    for (ip, item) in items.into_iter().take(MAX_ITEMS) {
+        let path = ip.to_string_lossy().into_owned();
+        out.push_str(&format!("<tr><td>{}</td><td>", escape(&item.name)));
+        let mut names: Vec<&str> = item
+            .tags
+            .iter()
+            .map(|tp| state.tags[tp].name.as_str())
+            .collect();
+        names.sort();
+        for name in names {
+            out.push_str(&format!(
+                "<form method=\"post\" action=\"/untag\">{}<button title=\"untag\">{} \
+                 &times;</button></form> ",
+                hidden(&[("item", path.as_str()), ("tag", name), ("q", q), ("csrf", csrf)]),
+                escape(name)
+            ));
+        }
+        out.push_str(&format!(
+            "</td><td><form method=\"post\" action=\"/tag\">{}<input name=\"tag\" \
+             list=\"tags\" size=\"12\"> <button>Tag</button></form></td></tr>\n",
+            hidden(&[("item", path.as_str()), ("q", q), ("csrf", csrf)])
+        ));
+    }
+    out.push_str("</table>\n</body>\n</html>\n");
+    Ok(out)
+}
+
+/// Tag an item, creating a missing tag
+pub fn tag(state: &mut AppState, ip: &SharedPath, name: &str) -> Result<(), String> {
+    let tp = find_or_create_tag(state, name)?;
+    if !state.items_all[ip].tags.contains(&tp) {
+        tag_item(state, &tp, ip).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Untag an item
+pub fn untag(state: &mut AppState, ip: &SharedPath, name: &str) -> Result<(), String> {
+    let tp = find_tag(state, name)?;
+    if state.items_all[ip].tags.contains(&tp) {
+        untag_item(state, &tp, ip).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Tag or untag the item of a form
+fn change(state: &mut AppState, req: &Request) -> Result<(), String> {
+    let ip = find_item(state, req.param("item"))?;
+    let name = req.param("tag").trim();
+    if req.path == "/tag" {
+        tag(state, &ip, name)
+    } else {
+        untag(state, &ip, name)
+    }
+}
+
+fn handle(state: &mut AppState, server: &Server, req: &Request) -> Response {
+    if req.is_change() && !server.is_same_origin(req) {
+        return Response::error("403 Forbidden", "request from another site");
+    }
+    journal::sync(state);
+    if let Some(res) = rest::handle(state, req) {
+        return res;
+    }
+    let q = req.param("q");
+    match (req.method.as_str(), req.path.as_str()) {
+        ("GET", "/") => match page(state, q, &server.csrf) {
+            Ok(body) => Response::page(body),
+            Err(e) => Response::error("400 Bad Request", &e),
+        },
+        ("POST", "/tag") | ("POST", "/untag") if !same_secret(req.param("csrf"), &server.csrf) => {
+            Response::error("403 Forbidden", "form expired, reload the page")
+        }
+        ("POST", "/tag") | ("POST", "/untag") => match change(state, req) {
+            Ok(()) => Response::back(q),
+            Err(e) => Response::error("400 Bad Request", &e),
+        },
+        (_, "/") | (_, "/tag") | (_, "/untag") => {
+            Response::error("405 Method Not Allowed", "method not allowed")
+        }
+        _ => Response::error("404 Not Found", "not found"),
+    }
+}
+
+/// Entry point for `linkorgasm serve`, runs until killed
+pub fn run(m: &ArgMatches) -> i32 {
+    let (items, tags) = match (cli::items_dir(m), cli::tags_dir(m)) {
+        (Some(items), Some(tags)) => (items, tags),
+        _ => {
+            eprintln!("both an items dir and a tags dir are required");
+            return exit::USAGE;
+        }
+    };
+    let mut state = match load_headless(m, Some(&items), &tags) {
+        Ok(state) => state,
+        Err(e) => return exit::load_failed(&tags, &e),
+    };
+    let listen = m.value_of("listen").unwrap();
+    let listener = match TcpListener::bind(listen) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("cannot listen on {}: {}", listen, e);
+            return exit::FAILED;
+        }
+    };
+    let server = match (listener.local_addr(), random_token()) {
+        (Ok(addr), Ok(csrf)) => Server { addr, csrf },
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("cannot start the server: {}", e);
+            return exit::FAILED;
+        }
+    };
+    let token = m.value_of("token").map(str::to_owned);
+    if !cli::is_quiet(m) {
+        eprintln!("serving on http://{}/", listen);
+    }
+
+    let (requests, received) = mpsc::channel();
+    thread::spawn(move || accept(listener, token, requests));
+    for (req, reply) in received {
+        let res = handle(&mut state, &server, &req);
+        let _ = reply.send(res);
+    }
+    0
+}
+
+/// A request for the main thread, and where to send the response
+type Job = (Request, Sender<Response>);
+
+/// Accept clients, each on a thread of its own, and pass their requests on
+fn accept(listener: TcpListener, token: Option<String>, requests: Sender<Job>) {
+    let clients = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        if clients.fetch_add(1, Ordering::SeqCst) >= MAX_CLIENTS {
+            clients.fetch_sub(1, Ordering::SeqCst);
+            let _ = write_response(&stream, &Response::error("503 Service Unavailable", "busy"));
+            continue;
+        }
+        let clients = Arc::clone(&clients);
+        let token = token.clone();
+        let requests = requests.clone();
+        thread::spawn(move || {
+            serve_client(&stream, token.as_ref().map(String::as_str), &requests);
+            clients.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+}
+
+/// Read a client's request, have it handled and send the response
+fn serve_client(stream: &TcpStream, token: Option<&str>, requests: &Sender<Job>) {
+    let _ = stream.set_write_timeout(Some(TIMEOUT));
+    let res = match read_request(stream) {
+        Ok(ref req) if token.map_or(false, |t| !authorized(req, t)) => Response::unauthorized(),
+        Ok(req) => {
+            let (reply, response) = mpsc::channel();
+            if requests.send((req, reply)).is_err() {
+                return;
+            }
+            match response.recv() {
+                Ok(res) => res,
+                Err(_) => return,
+            }
+        }
+        Err(e) => Response::error("400 Bad Request", &e.to_string()),
+    };
+    let _ = write_response(stream, &res);
+}
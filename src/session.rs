@@ -0,0 +1,164 @@
+//! Saving and restoring sessions
+//!
+//! On quit, the directories, active filter, selection and cursor positions
+//! are saved to `session.toml` in the data directory
+//! (`~/.local/share/linkorgasm/` on Linux). On the next launch without
+//! directories given on the command line, the user is offered to restore it.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use cursive::views::Dialog;
+use cursive::Cursive;
+
+use serde::{Deserialize, Serialize};
+
+use crate::checklist::CheckList;
+use crate::lazylist::LazyList;
+use crate::recent;
+use crate::{
+    do_app, load_tagdir, run_filter, scan_items, ui_ask_itemdir, ui_error, ui_mark_itemview,
+    ui_mark_tagsview, ui_refresh_itemview, ui_select_item, ui_select_value, Filter, SharedPath,
+};
+
+/// Session file format version understood by this binary
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Session {
+    /// format version
+    version: u32,
+    /// canonical path of the items dir
+    items: PathBuf,
+    /// canonical path of the tags dir
+    tags: PathBuf,
+    /// selected items
+    #[serde(default)]
+    selection: Vec<PathBuf>,
+    /// highlighted item
+    item_cursor: Option<PathBuf>,
+    /// highlighted tag
+    tag_cursor: Option<PathBuf>,
+    /// active filter (last, because it may be a TOML table)
+    filter: Filter,
+}
+
+/// Path to the session file
+pub fn session_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|p| p.join("linkorgasm").join("session.toml"))
+}
+
+/// Load the saved session, if there is a usable one
+pub fn load() -> Option<Session> {
+    let text = fs::read_to_string(session_path()?).ok()?;
+    let session: Session = toml::from_str(&text).ok()?;
+    if session.version != VERSION {
+        return None;
+    }
+    Some(session)
+}
+
+/// Upgrade a session document by one version
+///
+/// There has only ever been one format so far, so there is nothing to
+/// change.
+pub fn migrate(_doc: &mut toml::Value, _from: u32) {}
+
+fn write(path: &Path, session: &Session) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let text = toml::to_string(session).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    fs::write(path, text)
+}
+
+/// Save the current session, if the main UI is up
+pub fn save(siv: &mut Cursive) {
+    let item_cursor = siv
+        .call_on_id("itemview", |v: &mut LazyList<SharedPath>| v.selection())
+        .and_then(|p| p)
+        .map(|p| p.to_path_buf());
+    let tag_cursor = siv
+        .call_on_id("tagsview", |v: &mut CheckList<PathBuf>| v.selection())
+        .and_then(|p| p)
+        .map(|p| p.to_path_buf());
+
+    let session = do_app(siv, |_, state| {
+        // the tutorial sandbox is not worth coming back to
+        if state.tutorial.is_some() {
+            return None;
+        }
+        let items = state.items_path.clone()?;
+        let tags = state.tags_path.canonicalize().ok()?;
+        let mut selection: Vec<_> = state.sel.iter().map(|p| p.to_path_buf()).collect();
+        selection.sort();
+        Some(Session {
+            version: VERSION,
+            items,
+            tags,
+            selection,
+            item_cursor,
+            tag_cursor,
+            filter: state.filter.clone(),
+        })
+    });
+
+    if let (Some(session), Some(path)) = (session, session_path()) {
+        // nothing sensible to do about errors while quitting
+        let _ = write(&path, &session);
+    }
+}
+
+/// Restore a saved session
+fn restore(siv: &mut Cursive, session: &Session) {
+    if !session.items.is_dir() || !session.tags.is_dir() {
+        ui_ask_itemdir(siv);
+        return ui_error(siv, tr!("the directories of the saved session are gone"));
+    }
+
+    do_app(siv, |_, state| scan_items(state, &session.items));
+    load_tagdir(siv, &session.tags);
+
+    do_app(siv, |siv, state| {
+        state.filter = session.filter.clone();
+        run_filter(state);
+        ui_refresh_itemview(siv, state);
+        for p in session.selection.iter() {
+            if let Some(p) = state.items_vis.get(p.as_path()) {
+                state.sel.insert(p.clone());
+            }
+        }
+        ui_mark_itemview(siv, state);
+        ui_mark_tagsview(siv, state);
+    });
+
+    if let Some(ref p) = session.item_cursor {
+        ui_select_item(siv, p);
+    }
+    if let Some(ref p) = session.tag_cursor {
+        ui_select_value(siv, "tagsview", p);
+    }
+}
+
+/// Ask whether to restore the saved session or start a new one
+pub fn ui_offer_restore(siv: &mut Cursive, session: Session) {
+    let text = tr!(
+        "Restore the previous session?\n\nItems: {}\nTags:  {}",
+        session.items.display(),
+        session.tags.display()
+    );
+    siv.add_layer(
+        Dialog::text(text)
+            .title(tr!("Session"))
+            .button(tr!("Restore"), move |siv| {
+                siv.pop_layer();
+                restore(siv, &session);
+            })
+            .button(tr!("New session"), |siv| {
+                siv.pop_layer();
+                recent::ui_pick(siv);
+            }),
+    );
+}
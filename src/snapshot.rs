@@ -0,0 +1,486 @@
+//! `linkorgasm snapshot`, `linkorgasm restore` and `linkorgasm diff`
+//!
+//! A snapshot records which items every tag has, in a TOML file:
+//!
+//! ```toml
+//! version = 1
+//! created = "2024-05-01T03:00:00+02:00"
+//! items = "/data/music"
+//! tags = "/data/tags"
+//!
+//! [links]
+//! "genre/jazz" = ["Kind of Blue.flac"]
+//! unsorted = []
+//! ```
+//!
+//! Item paths are relative to the items dir. By default, snapshots are
+//! kept in `.linkorgasm/snapshots` in the tags dir, named by the time they
+//! were taken. Restoring one creates the tags and links missing from the
+//! tags dir, and with `--exact` also removes the links and tags it doesn't
+//! have, so the tags dir can be rebuilt from a snapshot after a mistake or
+//! a loss. The items themselves are not part of it, and must be in an
+//! items dir (not a list from stdin) for their paths to be recorded.
+//!
+//! Taking a snapshot only reads the tags dir, so it doesn't lock it.
+//!
+//! Diffing two snapshots, or a snapshot and the tags dir as it is, lists
+//! the tags and links added and removed in between.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use clap::ArgMatches;
+use hashbrown::HashSet;
+use serde::{Deserialize, Serialize};
+
+use crate::apply::find_tag;
+use crate::cli;
+use crate::exit;
+use crate::fsops;
+use crate::gc;
+use crate::{
+    create_tag, item_key, load_exclusive, load_readonly, tag_item, untag_item, AppState,
+    SharedPath, META_DIR,
+};
+
+/// Snapshot file format version understood by this binary
+pub const VERSION: u32 = 1;
+
+/// Dir of the snapshots in the metadata dir
+pub static SNAPSHOTS_DIR: &str = "snapshots";
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Snapshot {
+    /// format version
+    version: u32,
+    /// when it was taken (RFC 3339)
+    created: String,
+    /// canonical path of the items dir
+    items: PathBuf,
+    /// canonical path of the tags dir
+    tags: PathBuf,
+    /// items of each tag, by tag name (last, because it is a TOML table)
+    links: BTreeMap<String, Vec<PathBuf>>,
+}
+
+/// What restoring a snapshot did
+#[derive(Default)]
+struct Summary {
+    created: usize,
+    deleted: usize,
+    added: usize,
+    removed: usize,
+    missing: usize,
+    errors: usize,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} tags created, {} tags deleted, {} links added, {} links removed, \
+             {} items missing, {} errors",
+            self.created, self.deleted, self.added, self.removed, self.missing, self.errors
+        )
+    }
+}
+
+/// Changes from one snapshot to another
+#[derive(Default)]
+struct Changes {
+    tags_added: usize,
+    tags_removed: usize,
+    links_added: usize,
+    links_removed: usize,
+}
+
+impl fmt::Display for Changes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} tags added, {} tags removed, {} links added, {} links removed",
+            self.tags_added, self.tags_removed, self.links_added, self.links_removed
+        )
+    }
+}
+
+/// Dir of the snapshots of a tags dir
+fn snapshots_dir(tags: &Path) -> PathBuf {
+    tags.join(META_DIR).join(SNAPSHOTS_DIR)
+}
+
+/// The most recent snapshot of a tags dir
+fn latest(tags: &Path) -> Option<PathBuf> {
+    fs::read_dir(snapshots_dir(tags))
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|p| p.extension().map_or(false, |ext| ext == "toml"))
+        .max()
+}
+
+/// Path of an item relative to the items dir, if it is in there
+fn relative(state: &AppState, ip: &Path) -> PathBuf {
+    state
+        .items_path
+        .as_ref()
+        .and_then(|items| ip.strip_prefix(items).ok())
+        .unwrap_or(ip)
+        .to_path_buf()
+}
+
+/// Record the items of every tag
+fn take(state: &AppState) -> Snapshot {
+    let links = state
+        .tags
+        .values()
+        .map(|tag| {
+            let mut items: Vec<PathBuf> =
+                tag.items.keys().map(|ip| relative(state, ip)).collect();
+            items.sort();
+            (tag.name.clone(), items)
+        })
+        .collect();
+    Snapshot {
+        version: VERSION,
+        created: Local::now().to_rfc3339(),
+        items: state.items_path.clone().unwrap_or_default(),
+        tags: state.tags_path.clone(),
+        links,
+    }
+}
+
+fn load_snapshot(path: &Path) -> Result<Snapshot, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("cannot read snapshot: {}", e))?;
+    let snapshot: Snapshot =
+        toml::from_str(&text).map_err(|e| format!("cannot parse snapshot: {}", e))?;
+    if snapshot.version > VERSION {
+        return Err(format!(
+            "snapshot has version {}, but this binary only understands up to {}",
+            snapshot.version, VERSION
+        ));
+    }
+    Ok(snapshot)
+}
+
+/// Give a tag the items of the snapshot, and with `exact` only those
+fn restore_tag(
+    state: &mut AppState,
+    name: &str,
+    items: &[PathBuf],
+    exact: bool,
+    s: &mut Summary,
+) {
+    let known = find_tag(state, name).is_ok();
+    let tp = match create_tag(state, name) {
+        Ok(tp) => tp,
+        Err(e) => {
+            eprintln!("{}: {}", name, e);
+            s.errors += 1;
+            return;
+        }
+    };
+    if !known {
+        s.created += 1;
+    }
+
+    let base = state.items_path.clone().unwrap_or_default();
+    let mut listed = Vec::new();
+    for item in items.iter() {
+        let ip = base.join(item).canonicalize().ok();
+        match ip.and_then(|ip| item_key(state, &ip)) {
+            Some(ip) => listed.push(ip),
+            None => {
+                eprintln!("missing item: {}", item.display());
+                s.missing += 1;
+            }
+        }
+    }
+    listed.sort();
+    listed.dedup();
+    for ip in listed.iter() {
+        if state.tags[tp.as_path()].items.contains_key(ip) {
+            continue;
+        }
+        match tag_item(state, &tp, ip) {
+            Ok(()) => s.added += 1,
+            Err(e) => {
+                eprintln!("{}: {}: {}", ip.display(), name, e);
+                s.errors += 1;
+            }
+        }
+    }
+    if !exact {
+        return;
+    }
+    let mut extra: Vec<SharedPath> = state.tags[tp.as_path()]
+        .items
+        .keys()
+        .filter(|ip| listed.binary_search(*ip).is_err())
+        .cloned()
+        .collect();
+    extra.sort();
+    for ip in extra {
+        match untag_item(state, &tp, &ip) {
+            Ok(()) => s.removed += 1,
+            Err(e) => {
+                eprintln!("{}: {}: {}", ip.display(), name, e);
+                s.errors += 1;
+            }
+        }
+    }
+}
+
+/// Empty the tags the snapshot doesn't have and delete them, for `--exact`
+fn remove_other_tags(state: &mut AppState, snapshot: &Snapshot, s: &mut Summary) {
+    let mut other: Vec<(SharedPath, String)> = state
+        .tags
+        .iter()
+        .filter(|(_, tag)| !snapshot.links.contains_key(&tag.name))
+        .map(|(tp, tag)| (tp.clone(), tag.name.clone()))
+        .collect();
+    // nested tags first, so their parents are empty when it's their turn
+    other.sort();
+    for (tp, name) in other.into_iter().rev() {
+        let mut items: Vec<SharedPath> = state.tags[&tp].items.keys().cloned().collect();
+        items.sort();
+        for ip in items {
+            match untag_item(state, &tp, &ip) {
+                Ok(()) => s.removed += 1,
+                Err(e) => {
+                    eprintln!("{}: {}: {}", ip.display(), name, e);
+                    s.errors += 1;
+                }
+            }
+        }
+        match gc::remove_tag(state, &tp) {
+            Ok(()) => s.deleted += 1,
+            Err(e) => {
+                eprintln!("{}: {}", name, e);
+                s.errors += 1;
+            }
+        }
+    }
+}
+
+/// Whether the items are a list from stdin, which a snapshot can't record
+/// paths relative to
+fn is_stdin(items: &Path) -> bool {
+    if items == Path::new("-") {
+        eprintln!("snapshots need an items dir, not a list of items from stdin");
+        return true;
+    }
+    false
+}
+
+/// Write the tags (`+ name/`) and links (`+ name: item`) added to and
+/// removed (`-`) from `old` in `new`, by tag name
+fn write_diff(out: &mut impl Write, old: &Snapshot, new: &Snapshot) -> io::Result<Changes> {
+    let mut changes = Changes::default();
+    let none = Vec::new();
+    let names: BTreeSet<&String> = old.links.keys().chain(new.links.keys()).collect();
+    for name in names {
+        let (before, after) = (old.links.get(name), new.links.get(name));
+        match (before, after) {
+            (None, Some(_)) => {
+                writeln!(out, "+ {}/", name)?;
+                changes.tags_added += 1;
+            }
+            (Some(_), None) => {
+                writeln!(out, "- {}/", name)?;
+                changes.tags_removed += 1;
+            }
+            _ => {}
+        }
+        let before = before.unwrap_or(&none);
+        let after = after.unwrap_or(&none);
+        let (in_before, in_after): (HashSet<&PathBuf>, HashSet<&PathBuf>) =
+            (before.iter().collect(), after.iter().collect());
+        for item in before.iter().filter(|item| !in_after.contains(item)) {
+            writeln!(out, "- {}: {}", name, item.display())?;
+            changes.links_removed += 1;
+        }
+        for item in after.iter().filter(|item| !in_before.contains(item)) {
+            writeln!(out, "+ {}: {}", name, item.display())?;
+            changes.links_added += 1;
+        }
+    }
+    Ok(changes)
+}
+
+/// Entry point for `linkorgasm snapshot`, returns the exit code
+pub fn run(m: &ArgMatches) -> i32 {
+    let (items, tags) = match (cli::items_dir(m), cli::tags_dir(m)) {
+        (Some(items), Some(tags)) => (items, tags),
+        _ => {
+            eprintln!("both an items dir and a tags dir are required");
+            return exit::USAGE;
+        }
+    };
+    if is_stdin(&items) {
+        return exit::USAGE;
+    }
+    let mut state = match load_readonly(m, Some(&items), &tags) {
+        Ok(state) => state,
+        Err(e) => return exit::load_failed(&tags, &e),
+    };
+    // the snapshot is only added, so it is written without the lock
+    state.fs = match cli::fs_ops(m) {
+        Ok(fs) => fs,
+        Err(e) => {
+            eprintln!("cannot open dry-run log: {}", e);
+            return exit::FAILED;
+        }
+    };
+    if !fsops::is_writable(&tags) {
+        state.fs.set_read_only(true);
+    }
+
+    let snapshot = take(&state);
+    let text = match toml::to_string(&snapshot) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("cannot write snapshot: {}", e);
+            return exit::FAILED;
+        }
+    };
+    let out = match m.value_of_os("out") {
+        Some(out) => PathBuf::from(out),
+        None => {
+            let name = Local::now().format("%Y-%m-%d-%H%M%S.toml").to_string();
+            snapshots_dir(&state.tags_path).join(name)
+        }
+    };
+    if out.symlink_metadata().is_ok() {
+        eprintln!("{} already exists", out.display());
+        return exit::USAGE;
+    }
+    let res = match out.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => state.fs.create_meta_dir(dir),
+        _ => Ok(()),
+    };
+    if let Err(e) = res.and_then(|()| state.fs.write_file(&out, text)) {
+        eprintln!("{}: {}", out.display(), e);
+        return exit::FAILED;
+    }
+    for op in state.fs.log() {
+        println!("{}", op);
+    }
+    if state.fs.is_dry_run() {
+        return exit::OK;
+    }
+    if !cli::is_quiet(m) {
+        let links: usize = snapshot.links.values().map(Vec::len).sum();
+        println!(
+            "{} tags with {} links recorded in {}",
+            snapshot.links.len(),
+            links,
+            out.display()
+        );
+    }
+    exit::OK
+}
+
+/// Entry point for `linkorgasm restore`, returns the exit code
+pub fn restore(m: &ArgMatches) -> i32 {
+    let path = match m.value_of_os("snapshot") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => cli::tags_dir(m).and_then(|tags| latest(&tags)),
+    };
+    let path = match path {
+        Some(path) => path,
+        None => {
+            eprintln!("no snapshot given, and none found in the tags dir");
+            return exit::USAGE;
+        }
+    };
+    let snapshot = match load_snapshot(&path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            return exit::USAGE;
+        }
+    };
+
+    let items = cli::items_dir(m).unwrap_or_else(|| snapshot.items.clone());
+    let tags = cli::tags_dir(m).unwrap_or_else(|| snapshot.tags.clone());
+    if is_stdin(&items) {
+        return exit::USAGE;
+    }
+    let mut state = match load_exclusive(m, Some(&items), &tags) {
+        Ok(state) => state,
+        Err(e) => return exit::load_failed(&tags, &e),
+    };
+
+    let exact = m.is_present("exact");
+    let mut summary = Summary::default();
+    for (name, items) in snapshot.links.iter() {
+        restore_tag(&mut state, name, items, exact, &mut summary);
+    }
+    if exact {
+        remove_other_tags(&mut state, &snapshot, &mut summary);
+    }
+
+    for op in state.fs.log() {
+        println!("{}", op);
+    }
+    if !cli::is_quiet(m) {
+        println!("{}", summary);
+    }
+    exit::from_errors(summary.errors + summary.missing)
+}
+
+/// Entry point for `linkorgasm diff`, returns the exit code
+///
+/// Without a second snapshot, the first one is compared to the tags dir.
+pub fn diff(m: &ArgMatches) -> i32 {
+    let path = Path::new(m.value_of_os("old").unwrap());
+    let old = match load_snapshot(path) {
+        Ok(old) => old,
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            return exit::USAGE;
+        }
+    };
+    let new = match m.value_of_os("new").map(Path::new) {
+        Some(path) => match load_snapshot(path) {
+            Ok(new) => new,
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                return exit::USAGE;
+            }
+        },
+        None => {
+            let items = cli::items_dir(m).unwrap_or_else(|| old.items.clone());
+            let tags = cli::tags_dir(m).unwrap_or_else(|| old.tags.clone());
+            if is_stdin(&items) {
+                return exit::USAGE;
+            }
+            match load_readonly(m, Some(&items), &tags) {
+                Ok(state) => take(&state),
+                Err(e) => return exit::load_failed(&tags, &e),
+            }
+        }
+    };
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match write_diff(&mut out, &old, &new) {
+        Ok(changes) => {
+            if !cli::is_quiet(m) {
+                let _ = writeln!(out, "{}", changes);
+            }
+            exit::OK
+        }
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => exit::OK,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit::FAILED
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! `linkorgasm stats`
+//!
+//! Reports the health of a library: how many items there are and how big
+//! they are, how many are untagged, how many items each tag has, and the
+//! broken symlinks in the tags tree. With `--format json`, the report is
+//! one JSON object with a timestamp, to be collected by a cron job.
+
+use std::collections::BTreeMap;
+
+use chrono::Local;
+use clap::ArgMatches;
+use serde::Serialize;
+
+use crate::cli;
+use crate::columns::format_size;
+use crate::exit;
+use crate::{load_readonly, AppState};
+
+/// Output formats, by the name given to `--format`
+pub static FORMATS: &[&str] = &["plain", "json"];
+
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    /// when the report was made (RFC 3339)
+    time: String,
+    /// number of items
+    items: usize,
+    /// combined size of the items in bytes (directories without contents)
+    size: u64,
+    /// number of items without tags
+    untagged: usize,
+    /// number of tags
+    tags: usize,
+    /// number of tags without items
+    empty_tags: usize,
+    /// symlinks in the tags tree whose target doesn't exist
+    broken_links: usize,
+    /// symlinks in the tags tree to something that isn't an item
+    foreign_links: usize,
+    /// number of items of each tag, by tag name
+    tag_counts: BTreeMap<String, usize>,
+}
+
+/// Gather the numbers from a scanned library
+pub fn collect(state: &AppState) -> Stats {
+    let broken_links = state.foreign.iter().filter(|f| f.target.is_none()).count();
+    Stats {
+        time: Local::now().to_rfc3339(),
+        items: state.items_all.len(),
+        size: state.items_all.values().map(|i| i.size).sum(),
+        untagged: state.items_all.values().filter(|i| i.tags.is_empty()).count(),
+        tags: state.tags.len(),
+        empty_tags: state.tags.values().filter(|t| t.items.is_empty()).count(),
+        broken_links,
+        foreign_links: state.foreign.len() - broken_links,
+        tag_counts: state
+            .tags
+            .values()
+            .map(|t| (t.name.clone(), t.items.len()))
+            .collect(),
+    }
+}
+
+/// Print the report for humans
+pub fn print_plain(stats: &Stats) {
+    println!("items:         {} ({})", stats.items, format_size(stats.size));
+    println!("untagged:      {}", stats.untagged);
+    println!("tags:          {} ({} empty)", stats.tags, stats.empty_tags);
+    println!("broken links:  {}", stats.broken_links);
+    println!("foreign links: {}", stats.foreign_links);
+    if stats.tag_counts.is_empty() {
+        return;
+    }
+    println!();
+    let width = stats.tag_counts.keys().map(|n| n.chars().count()).max().unwrap();
+    for (name, count) in stats.tag_counts.iter() {
+        println!("{:width$}  {}", name, count, width = width);
+    }
+}
+
+/// Entry point for `linkorgasm stats`, returns the exit code
+pub fn run(m: &ArgMatches) -> i32 {
+    let (items, tags) = match (cli::items_dir(m), cli::tags_dir(m)) {
+        (Some(items), Some(tags)) => (items, tags),
+        _ => {
+            eprintln!("both an items dir and a tags dir are required");
+            return exit::USAGE;
+        }
+    };
+    let state = match load_readonly(m, Some(&items), &tags) {
+        Ok(state) => state,
+        Err(e) => return exit::load_failed(&tags, &e),
+    };
+
+    let stats = collect(&state);
+    if m.value_of("format") == Some("json") {
+        println!("{}", serde_json::to_string(&stats).expect("cannot serialize stats"));
+    } else {
+        print_plain(&stats);
+    }
+    exit::checked(&state, 0)
+}
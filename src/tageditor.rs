@@ -0,0 +1,126 @@
+//! Editing all tags of one item at once
+//!
+//! Enter in the items view opens a checklist of all tags, with those of
+//! the highlighted item checked. Space checks and unchecks tags; nothing
+//! is changed until the checklist is applied.
+
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use cursive::theme::Color;
+use cursive::traits::*;
+use cursive::views::{Dialog, OnEventView, ScrollView};
+use cursive::Cursive;
+use hashbrown::HashSet;
+
+use crate::checklist::{Check, CheckList};
+use crate::lazylist::LazyList;
+use crate::{
+    do_app, sorted_tags, tag_item, tag_label, ui_error, ui_label, ui_mark_itemview,
+    ui_update_tagsview, untag_item, AppState, SharedPath,
+};
+
+/// Give an item exactly the checked tags, returns the errors
+fn apply(state: &mut AppState, ip: &Path, checked: &HashSet<PathBuf>) -> Vec<String> {
+    let mut errors = Vec::new();
+    let current: Vec<SharedPath> = state.items_all[ip].tags.iter().cloned().collect();
+    for tp in current.iter() {
+        if !checked.contains(&**tp) {
+            if let Err(e) = untag_item(state, tp, ip) {
+                errors.push(format!("{}: {}", tp.display(), e));
+            }
+        }
+    }
+    for tp in checked.iter() {
+        let known = state.tags.contains_key(tp.as_path());
+        if known && !state.items_all[ip].tags.contains(tp.as_path()) {
+            if let Err(e) = tag_item(state, tp, ip) {
+                errors.push(format!("{}: {}", tp.display(), e));
+            }
+        }
+    }
+    errors
+}
+
+/// Display UI Dialog for editing the tags of the highlighted item
+pub fn ui_build(siv: &mut Cursive) {
+    let ip = siv
+        .call_on_id("itemview", |v: &mut LazyList<SharedPath>| v.selection())
+        .and_then(|ip| ip);
+    if let Some(ip) = ip {
+        ui_edit(siv, ip, |siv, errors| {
+            if !errors.is_empty() {
+                ui_error(siv, errors.join("\n"));
+            }
+        });
+    }
+}
+
+/// Display UI Dialog for editing the tags of an item
+///
+/// `done` is called when the dialog is closed, with the errors of applying
+/// the changes (none if cancelled).
+pub fn ui_edit(siv: &mut Cursive, ip: SharedPath, done: fn(&mut Cursive, &[String])) {
+    let (name, rows, checked) = do_app(siv, |_, state| {
+        let item = &state.items_all[&ip];
+        let checked: HashSet<PathBuf> = item.tags.iter().map(|tp| tp.to_path_buf()).collect();
+        let rows: Vec<(String, Option<Color>, PathBuf)> = sorted_tags(state)
+            .into_iter()
+            .map(|(tp, t)| (tag_label(state, tp, t), t.color, tp.to_path_buf()))
+            .collect();
+        (item.name.clone(), rows, checked)
+    });
+    if rows.is_empty() {
+        let dialog = Dialog::text(tr!("There are no tags yet.")).button(tr!("Ok"), move |siv| {
+            siv.pop_layer();
+            done(siv, &[]);
+        });
+        return siv.add_layer(dialog);
+    }
+
+    let mut list = CheckList::<PathBuf>::new();
+    for (i, (label, color, tp)) in rows.into_iter().enumerate() {
+        let on = checked.contains(&tp);
+        list.add_item(ui_label(label, color), tp);
+        list.set_check(i, Check::from(on));
+    }
+    let checked = Rc::new(RefCell::new(checked));
+
+    let toggled = Rc::clone(&checked);
+    let list = OnEventView::new(list.with_id("tageditor")).on_event(' ', move |siv| {
+        siv.call_on_id("tageditor", |v: &mut CheckList<PathBuf>| {
+            let i = match v.selected_id() {
+                Some(i) => i,
+                None => return,
+            };
+            let tp = v.get_item(i).unwrap().1.clone();
+            let mut checked = toggled.borrow_mut();
+            let on = !checked.remove(&tp);
+            if on {
+                checked.insert(tp);
+            }
+            v.set_check(i, Check::from(on));
+        });
+    });
+
+    siv.add_layer(
+        Dialog::around(ScrollView::new(list).max_height(20))
+            .title(tr!("Tags of {}", name))
+            .button(tr!("Apply"), move |siv| {
+                let checked = checked.borrow().clone();
+                siv.pop_layer();
+                let errors = do_app(siv, |siv, state| {
+                    let errors = apply(state, &ip, &checked);
+                    ui_mark_itemview(siv, state);
+                    ui_update_tagsview(siv, state);
+                    errors
+                });
+                done(siv, &errors);
+            })
+            .button(tr!("Cancel"), move |siv| {
+                siv.pop_layer();
+                done(siv, &[]);
+            }),
+    );
+}
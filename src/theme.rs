@@ -0,0 +1,66 @@
+//! Color themes
+//!
+//! Besides the built-in `dark` and `light` themes, cursive theme files are
+//! loaded from the `themes` subdirectory of the config directory (by name,
+//! without the `.toml` extension) or from an explicit path.
+
+use std::path::PathBuf;
+
+use cursive::Cursive;
+
+use crate::config;
+
+static DARK: &str = r##"
+shadow = false
+borders = "simple"
+
+[colors]
+background = "#1c1c1c"
+view = "#262626"
+shadow = "#000000"
+primary = "#d0d0d0"
+secondary = "#87afd7"
+tertiary = "#808080"
+title_primary = "#ffd75f"
+title_secondary = "#d7af5f"
+highlight = "#005f87"
+highlight_inactive = "#3a3a3a"
+"##;
+
+static LIGHT: &str = r##"
+shadow = false
+borders = "simple"
+
+[colors]
+background = "#e4e4e4"
+view = "#ffffff"
+shadow = "#a8a8a8"
+primary = "#1c1c1c"
+secondary = "#005f87"
+tertiary = "#808080"
+title_primary = "#870000"
+title_secondary = "#875f00"
+highlight = "#5f87d7"
+highlight_inactive = "#d0d0d0"
+"##;
+
+/// Resolve a theme name to a file path
+fn theme_path(name: &str) -> Option<PathBuf> {
+    if name.contains('/') || name.ends_with(".toml") {
+        return Some(PathBuf::from(name));
+    }
+    config::config_dir().map(|d| d.join("themes").join(format!("{}.toml", name)))
+}
+
+/// Load and apply a theme by name or path
+pub fn apply(siv: &mut Cursive, name: &str) -> Result<(), String> {
+    let result = match name {
+        "dark" => siv.load_toml(DARK),
+        "light" => siv.load_toml(LIGHT),
+        _ => match theme_path(name) {
+            Some(path) => siv.load_theme_file(path),
+            None => return Err(format!("cannot locate theme {}", name)),
+        },
+    };
+    result.map_err(|e| format!("cannot load theme {}: {:?}", name, e))
+}
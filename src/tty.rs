@@ -0,0 +1,89 @@
+//! Terminal plumbing for running inside shell pipelines
+//!
+//! When linkorgasm is used as a picker (`$(linkorgasm --print-selection)`),
+//! the standard streams are pipes, but the UI still has to talk to the
+//! terminal. These helpers temporarily point a stream at `/dev/tty`.
+//!
+//! The UI also puts the terminal in raw mode on the alternate screen; the
+//! panic hook puts it back before a panic is reported.
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::panic;
+use std::path::PathBuf;
+
+use log::error;
+
+/// Reset attributes, show the cursor, stop mouse reporting and leave the
+/// alternate screen
+static RESET: &[u8] = b"\x1b[0m\x1b[?25h\x1b[?1000l\x1b[?1002l\x1b[?1006l\x1b[?1015l\x1b[?1049l";
+
+/// Point the given standard stream at the controlling terminal
+///
+/// Returns a duplicate of the original stream, for `restore`.
+pub fn redirect_to_tty(fd: RawFd) -> io::Result<RawFd> {
+    let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+    unsafe {
+        let saved = libc::dup(fd);
+        if saved < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if libc::dup2(tty.as_raw_fd(), fd) < 0 {
+            let e = io::Error::last_os_error();
+            libc::close(saved);
+            return Err(e);
+        }
+        Ok(saved)
+    }
+}
+
+/// Undo `redirect_to_tty`
+pub fn restore(fd: RawFd, saved: RawFd) -> io::Result<()> {
+    unsafe {
+        let r = libc::dup2(saved, fd);
+        libc::close(saved);
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Make panics leave the terminal usable and their message visible
+///
+/// Must be called before the UI starts, to save the terminal settings it
+/// changes. The panic is logged too, and the message points to the log
+/// file (if there is one).
+pub fn install_panic_hook(log_file: Option<PathBuf>) {
+    let saved = OpenOptions::new()
+        .read(true)
+        .open("/dev/tty")
+        .ok()
+        .and_then(|tty| unsafe {
+            let mut termios: libc::termios = mem::zeroed();
+            if libc::tcgetattr(tty.as_raw_fd(), &mut termios) == 0 {
+                Some(termios)
+            } else {
+                None
+            }
+        });
+    let default = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if let Ok(mut tty) = OpenOptions::new().read(true).write(true).open("/dev/tty") {
+            let _ = tty.write_all(RESET);
+            if let Some(ref termios) = saved {
+                unsafe {
+                    libc::tcsetattr(tty.as_raw_fd(), libc::TCSANOW, termios);
+                }
+            }
+        }
+        error!("{}", info);
+        default(info);
+        match log_file {
+            Some(ref path) => eprintln!("See the log file for more: {}", path.display()),
+            None => eprintln!("Run with `--log-file FILE -vv` to log more about what happened."),
+        }
+    }));
+}
@@ -0,0 +1,216 @@
+//! Guided first run in a sandbox
+//!
+//! `linkorgasm --tutorial` (or "Tutorial" in the startup list) creates an
+//! items dir with a few sample files and a tags dir next to it in a new
+//! private dir in the temporary directory, and opens them. Hints walk through selecting,
+//! tagging and filtering; each one is shown once the previous step has
+//! been done, and shows what tagging did to the tags dir. Sandbox sessions
+//! are not remembered, so they don't clutter the recent list.
+
+use std::fs;
+use std::io;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+
+use cursive::views::Dialog;
+use cursive::Cursive;
+
+use crate::fsops;
+use crate::{do_app, load_tagdir, scan_items, ui_error, AppState, Filter};
+
+/// Sample items and their contents
+static SAMPLES: &[(&str, &str)] = &[
+    ("pancakes.txt", "Flour, milk, eggs. Tagged 'recipes' already.\n"),
+    ("lasagna.txt", "Takes two hours, worth it.\n"),
+    ("tax-return-2019.txt", "Due in May.\n"),
+    ("meeting-notes.txt", "Ask about the new printer.\n"),
+    ("holiday-plan.txt", "Train tickets, then the coast.\n"),
+    ("song-ideas.txt", "Something in D minor.\n"),
+];
+
+/// Tags of the sample tags dir
+static TAGS: &[&str] = &["todo", "recipes", "work"];
+
+/// The tag the hints ask to use
+static TAG: &str = "todo";
+
+/// Progress through the hints
+#[derive(Debug)]
+pub struct Tutorial {
+    /// the sandbox, holding `items` and `tags`
+    dir: PathBuf,
+    /// index of the current step in `STEPS`
+    step: usize,
+    /// whether the main UI is up and the hints are being shown
+    started: bool,
+}
+
+/// One step: the hint, and whether it has been followed
+struct Step {
+    hint: fn(&AppState, &Path) -> String,
+    done: fn(&AppState) -> bool,
+}
+
+static STEPS: &[Step] = &[
+    Step {
+        hint: |_, dir| {
+            tr!(
+                "Welcome! This is a sandbox in {}: the items are the files in \
+                 'items', the tags are the directories in 'tags'. Nothing in it \
+                 is yours, so try anything.\n\n\
+                 The left pane lists the items. Move with the arrow keys and \
+                 press Space on two of them to select them.",
+                dir.display()
+            )
+        },
+        done: |state| state.sel.len() >= 2,
+    },
+    Step {
+        hint: |_, _| {
+            tr!(
+                "The tags pane now shows which tags all selected items have.\n\n\
+                 Press Tab to go to the tags pane, move to '{}' and press Space \
+                 to give the tag to the selected items.",
+                TAG
+            )
+        },
+        done: |state| tag_size(state) >= 2,
+    },
+    Step {
+        hint: |state, _| {
+            tr!(
+                "Tagging an item puts a symlink to it into the tag's directory. \
+                 '{}' now holds:\n\n{}\n\n\
+                 Any program can follow those links. Press Tab to go back to \
+                 the items, then 'f' and enter '{}' to show only the items \
+                 tagged '{}'.",
+                TAG,
+                links(state),
+                TAG,
+                TAG
+            )
+        },
+        done: |state| matches!(state.filter, Filter::Query(_)),
+    },
+    Step {
+        hint: |_, _| {
+            tr!(
+                "Queries combine tags: 'todo -work' shows items tagged 'todo' but \
+                 not 'work'. Press 'u' to show only items without tags, and 'r' \
+                 to show all items again."
+            )
+        },
+        done: |state| matches!(state.filter, Filter::All),
+    },
+    Step {
+        hint: |_, dir| {
+            tr!(
+                "That's the gist. Press 'h' for all the keys, and 'q' to quit \
+                 when you're done. The sandbox stays in {} until your system \
+                 cleans up its temporary files.",
+                dir.display()
+            )
+        },
+        done: |_| false,
+    },
+];
+
+/// Number of items with the tag the hints ask to use
+fn tag_size(state: &AppState) -> usize {
+    state
+        .tags
+        .values()
+        .find(|t| t.name == TAG)
+        .map_or(0, |t| t.items.len())
+}
+
+/// The symlinks in the tag's directory, like `ls -l` shows them
+fn links(state: &AppState) -> String {
+    let dir = state.tags_path.join(TAG);
+    let mut lines: Vec<String> = fs::read_dir(&dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let target = fs::read_link(e.path()).ok()?;
+            Some(format!(
+                "  {}/{} -> {}",
+                TAG,
+                e.file_name().to_string_lossy(),
+                target.display()
+            ))
+        })
+        .collect();
+    lines.sort();
+    lines.join("\n")
+}
+
+/// Create a sandbox with sample items and tags, returns its path
+fn create_sandbox() -> io::Result<PathBuf> {
+    let dir = fsops::create_temp_dir("linkorgasm-tutorial")?;
+    let items = dir.join("items");
+    let tags = dir.join("tags");
+    fs::create_dir_all(&items)?;
+    for (name, contents) in SAMPLES {
+        fs::write(items.join(name), contents)?;
+    }
+    for tag in TAGS {
+        fs::create_dir_all(tags.join(tag))?;
+    }
+    let first = SAMPLES[0].0;
+    symlink(
+        Path::new("../../items").join(first),
+        tags.join("recipes").join(first),
+    )?;
+    Ok(dir)
+}
+
+/// Show the hint of the current step
+fn ui_hint(siv: &mut Cursive, state: &AppState, tutorial: &Tutorial) {
+    let text = (STEPS[tutorial.step].hint)(state, &tutorial.dir);
+    let title = tr!("Tutorial ({}/{})", tutorial.step + 1, STEPS.len());
+    siv.add_layer(Dialog::info(text).title(title));
+}
+
+/// Start the tutorial in a new sandbox
+pub fn ui_start(siv: &mut Cursive) {
+    let dir = match create_sandbox() {
+        Ok(dir) => dir,
+        Err(e) => return ui_error(siv, tr!("cannot create the tutorial sandbox: {}", e)),
+    };
+    do_app(siv, |_, state| {
+        scan_items(state, dir.join("items"));
+        state.tutorial = Some(Tutorial {
+            dir: dir.clone(),
+            step: 0,
+            started: false,
+        });
+    });
+    load_tagdir(siv, dir.join("tags"));
+}
+
+/// Show the first hint, once the main UI is built
+pub fn ui_begin(siv: &mut Cursive, state: &mut AppState) {
+    if let Some(mut tutorial) = state.tutorial.take() {
+        if !tutorial.started {
+            tutorial.started = true;
+            ui_hint(siv, state, &tutorial);
+        }
+        state.tutorial = Some(tutorial);
+    }
+}
+
+/// Move on to the next hint if the current step has been done
+///
+/// Called after every action (see `do_app`).
+pub fn ui_check(siv: &mut Cursive, state: &mut AppState) {
+    let mut tutorial = match state.tutorial.take() {
+        Some(tutorial) => tutorial,
+        None => return,
+    };
+    if tutorial.started && (STEPS[tutorial.step].done)(state) {
+        tutorial.step += 1;
+        ui_hint(siv, state, &tutorial);
+    }
+    state.tutorial = Some(tutorial);
+}